@@ -0,0 +1,926 @@
+//! Typechecking function bodies.
+//!
+//! Bodies are checked when the program is compiled (by [`crate::types::typeck`][]),
+//! producing a [`TypedBody`][] for every function: each variable and expression
+//! knows its type, field accesses know which field they read, and references are
+//! taken and looked through explicitly. Both the interpreter ([`crate::eval`][])
+//! and backends that lower bodies to real code (like abi-cafe's) work from this,
+//! so they can't disagree about what a body means.
+//!
+//! Function bodies can't use puns: a body's result has to be the same in every
+//! language, so a function with a body can't have a pun anywhere in its signature
+//! (and neither can anything the body builds).
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use miette::SourceSpan;
+
+use crate::eval::{int_from_i64, int_info, is_float, round_float};
+use crate::parse::{CallExpr, CtorExpr, Expr, Ident, Literal, LiteralExpr, PathExpr, Stmt};
+use crate::spanned::Spanned;
+use crate::types::{FieldTy, FuncIdx, KdlScriptTypeError, PrimitiveTy, Ty, TyIdx, TypedProgram};
+use crate::Result;
+
+/// A variable id (an index into [`TypedBody::vars`][])
+pub type VarIdx = usize;
+
+/// A function's body, with everything resolved.
+#[derive(Debug, Clone, Default)]
+pub struct TypedBody {
+    /// Every variable: the function's inputs (in order), followed by each `let`
+    pub vars: Vec<BodyVar>,
+    /// The statements, in order (nothing follows a `return`)
+    pub stmts: Vec<TypedStmt>,
+}
+
+/// A variable in a function body.
+#[derive(Debug, Clone)]
+pub struct BodyVar {
+    /// The variable's name (later `let`s may shadow it)
+    pub name: Ident,
+    /// The variable's type
+    pub ty: TyIdx,
+}
+
+/// A statement.
+#[derive(Debug, Clone)]
+pub enum TypedStmt {
+    /// `let "x" <expr>` (the var is None for `let "_"`)
+    Let(Option<VarIdx>, TypedExpr),
+    /// `return <expr>`
+    Return(TypedExpr),
+    /// `print <expr>`
+    Print(TypedExpr),
+}
+
+/// An expression and its type.
+#[derive(Debug, Clone)]
+pub struct TypedExpr {
+    /// The type of the expression (nominal types built by the body are resolved
+    /// through aliases, everything else is the type it was declared with)
+    pub ty: TyIdx,
+    /// Where the expression came from
+    pub span: SourceSpan,
+    /// What the expression does
+    pub kind: ExprKind,
+}
+
+/// The kinds of [`TypedExpr`][].
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    /// Read a variable
+    Var(VarIdx),
+    /// An integer (or `ptr`) literal, as its bits zero-extended to 128
+    Int(PrimitiveTy, u128),
+    /// A float literal (already rounded to the precision of its type)
+    Float(PrimitiveTy, f64),
+    /// A bool literal
+    Bool(bool),
+    /// Read the field of a struct (by index)
+    Field(Box<TypedExpr>, usize),
+    /// Read the field of a union (by index), which must be the one that was set
+    UnionField(Box<TypedExpr>, usize),
+    /// Read a field (by index) of a tagged union's variant (by index),
+    /// which must be the active variant
+    VariantField(Box<TypedExpr>, usize, usize),
+    /// Read an element of an array (the index is always in bounds)
+    Index(Box<TypedExpr>, usize),
+    /// Look through a reference
+    Deref(Box<TypedExpr>),
+    /// Take a reference to a place (always built from a path, so it's never a temporary)
+    Ref(Box<TypedExpr>),
+    /// A case of a c-style enum (by index)
+    EnumCase(usize),
+    /// Build a struct, with its fields in declaration order
+    Struct(Vec<TypedExpr>),
+    /// Build a union by setting one of its fields (by index)
+    Union(usize, Box<TypedExpr>),
+    /// Build a case (by index) of a tagged union, with its fields in declaration order
+    Tagged(usize, Vec<TypedExpr>),
+    /// Build an array
+    Array(Vec<TypedExpr>),
+    /// Call a function that has exactly one output (or none, making this `()`)
+    Call(FuncIdx, Vec<TypedExpr>),
+    /// Apply an arithmetic builtin to two integers or floats of the same type
+    Arith(ArithOp, Box<TypedExpr>, Box<TypedExpr>),
+}
+
+/// The arithmetic builtins.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArithOp {
+    /// `+` (wrapping for integers)
+    Add,
+    /// `-` (wrapping for integers)
+    Sub,
+    /// `*` (wrapping for integers)
+    Mul,
+}
+
+impl ArithOp {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "+" => Some(Self::Add),
+            "-" => Some(Self::Sub),
+            "*" => Some(Self::Mul),
+            _ => None,
+        }
+    }
+
+    /// The name of the builtin (which is also its operator in C and Rust).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+        }
+    }
+}
+
+impl TypedExpr {
+    /// Render a path expression the way it was written (for errors), like `x.Some.field0`.
+    pub fn path_text(&self, body: &TypedBody, program: &TypedProgram) -> String {
+        let base_ty = |base: &TypedExpr| program.realize_ty(resolve_alias(program, base.ty));
+        match &self.kind {
+            ExprKind::Var(var) => body.vars[*var].name.to_string(),
+            ExprKind::Field(base, idx) => {
+                let Ty::Struct(struct_ty) = base_ty(base) else {
+                    unreachable!("Internal Compiler Error: field of non-struct");
+                };
+                let base = base.path_text(body, program);
+                format!("{base}.{}", struct_ty.fields[*idx].ident)
+            }
+            ExprKind::UnionField(base, idx) => {
+                let Ty::Union(union_ty) = base_ty(base) else {
+                    unreachable!("Internal Compiler Error: field of non-union");
+                };
+                let base = base.path_text(body, program);
+                format!("{base}.{}", union_ty.fields[*idx].ident)
+            }
+            ExprKind::VariantField(base, variant, idx) => {
+                let Ty::Tagged(tagged_ty) = base_ty(base) else {
+                    unreachable!("Internal Compiler Error: variant of non-tagged");
+                };
+                let variant = &tagged_ty.variants[*variant];
+                let field = &variant.fields.as_deref().unwrap_or_default()[*idx];
+                let base = base.path_text(body, program);
+                format!("{base}.{}.{}", variant.name, field.ident)
+            }
+            ExprKind::Index(base, idx) => format!("{}.{idx}", base.path_text(body, program)),
+            ExprKind::Deref(base) => base.path_text(body, program),
+            ExprKind::Ref(base) => format!("&{}", base.path_text(body, program)),
+            _ => program.format_ty(self.ty),
+        }
+    }
+}
+
+/// Look through aliases (but not puns, which bodies can't have).
+pub fn resolve_alias(program: &TypedProgram, mut ty: TyIdx) -> TyIdx {
+    while let Ty::Alias(alias_ty) = program.realize_ty(ty) {
+        ty = alias_ty.real;
+    }
+    ty
+}
+
+/// Typecheck a function's body.
+pub(crate) fn typeck_body(
+    program: &TypedProgram,
+    func: FuncIdx,
+    stmts: &[Stmt],
+) -> Result<TypedBody> {
+    let decl = program.realize_func(func);
+    if stmts.is_empty() {
+        return Ok(TypedBody::default());
+    }
+    let mut cx = BodyCx {
+        program,
+        body: TypedBody::default(),
+        scope: HashMap::new(),
+    };
+
+    let name_span = Spanned::span(&decl.name.val);
+    for arg in decl.inputs.iter().chain(&decl.outputs) {
+        if let Some(pun) = cx.find_pun(arg.ty, &mut HashSet::new()) {
+            return Err(cx
+                .error(
+                    format!(
+                        "{} has a body, so its signature can't use puns (it uses {})",
+                        decl.name,
+                        program.format_ty(pun)
+                    ),
+                    name_span,
+                    Some("a body has to compute the same thing in every language".to_owned()),
+                )
+                .into());
+        }
+    }
+    for arg in &decl.inputs {
+        cx.push_var(arg.name.clone(), arg.ty);
+    }
+
+    let mut returned = false;
+    for stmt in stmts {
+        if returned {
+            let span = match stmt {
+                Stmt::Let(stmt) => Spanned::span(&stmt.expr),
+                Stmt::Return(stmt) => Spanned::span(&stmt.expr),
+                Stmt::Print(stmt) => Spanned::span(&stmt.expr),
+            };
+            return Err(cx
+                .error("nothing can come after `return`".to_owned(), span, None)
+                .into());
+        }
+        match stmt {
+            Stmt::Let(stmt) => {
+                let expr = cx.check_expr(&stmt.expr, None)?;
+                let var = stmt
+                    .var
+                    .as_ref()
+                    .map(|var| cx.push_var(var.clone(), expr.ty));
+                cx.body.stmts.push(TypedStmt::Let(var, expr));
+            }
+            Stmt::Return(stmt) => {
+                let [output] = &decl.outputs[..] else {
+                    return Err(cx
+                        .error(
+                            format!(
+                                "{} has {} outputs, but `return` produces one value",
+                                decl.name,
+                                decl.outputs.len()
+                            ),
+                            Spanned::span(&stmt.expr),
+                            None,
+                        )
+                        .into());
+                };
+                let expr = cx.check_expr(&stmt.expr, Some(output.ty))?;
+                cx.body.stmts.push(TypedStmt::Return(expr));
+                returned = true;
+            }
+            Stmt::Print(stmt) => {
+                let expr = cx.check_expr(&stmt.expr, None)?;
+                cx.body.stmts.push(TypedStmt::Print(expr));
+            }
+        }
+    }
+
+    if !decl.outputs.is_empty() && !returned {
+        return Err(cx
+            .error(
+                format!("{} never returns a value", decl.name),
+                name_span,
+                Some("add a `return` statement to its body".to_owned()),
+            )
+            .into());
+    }
+    Ok(cx.body)
+}
+
+/// The state of typechecking a body.
+struct BodyCx<'a> {
+    program: &'a TypedProgram,
+    body: TypedBody,
+    /// The variable each name currently refers to
+    scope: HashMap<String, VarIdx>,
+}
+
+impl BodyCx<'_> {
+    fn push_var(&mut self, name: Ident, ty: TyIdx) -> VarIdx {
+        let var = self.body.vars.len();
+        self.scope.insert(name.to_string(), var);
+        self.body.vars.push(BodyVar { name, ty });
+        var
+    }
+
+    /// Check an expression, making sure it has the expected type (if any).
+    fn check_expr(&mut self, expr: &Spanned<Expr>, expected: Option<TyIdx>) -> Result<TypedExpr> {
+        let span = Spanned::span(expr);
+        let mut typed = match &**expr {
+            Expr::Literal(expr) => return self.literal(expr, expected, span),
+            Expr::Path(expr) => self.path(expr, span)?,
+            Expr::Ctor(expr) => self.ctor(expr, span)?,
+            Expr::Call(expr) => self.call(expr, expected, span)?,
+        };
+        if let Some(ty) = expected {
+            // Look through references if a value is wanted
+            let wants_ref = matches!(self.realize(ty, span)?, Ty::Ref(_));
+            while let (Ty::Ref(ref_ty), false) = (self.realize(typed.ty, span)?, wants_ref) {
+                typed = TypedExpr {
+                    ty: ref_ty.pointee_ty,
+                    span,
+                    kind: ExprKind::Deref(Box::new(typed)),
+                };
+            }
+            if !self.same_ty(typed.ty, ty, span)? {
+                return Err(self.error(
+                    format!(
+                        "expected {}, found {}",
+                        self.program.format_ty(ty),
+                        self.program.format_ty(typed.ty)
+                    ),
+                    span,
+                    None,
+                ))?;
+            }
+        }
+        Ok(typed)
+    }
+
+    /// Check a literal, giving it the expected type (if any).
+    fn literal(
+        &self,
+        expr: &LiteralExpr,
+        expected: Option<TyIdx>,
+        span: SourceSpan,
+    ) -> Result<TypedExpr> {
+        let prim = match expected {
+            Some(ty) => match self.realize(ty, span)? {
+                Ty::Primitive(prim) => Some(*prim),
+                _ => None,
+            },
+            None => None,
+        };
+        if let Some(prim) = prim {
+            self.check_prim_supported(prim, span)?;
+        }
+        let kind = match (&expr.val, expected, prim) {
+            (Literal::Int(val), None, _) => ExprKind::Int(PrimitiveTy::I64, *val as u64 as u128),
+            (Literal::Float(val), None, _) => ExprKind::Float(PrimitiveTy::F64, *val),
+            (Literal::Bool(val), None, _) | (Literal::Bool(val), _, Some(PrimitiveTy::Bool)) => {
+                ExprKind::Bool(*val)
+            }
+            (Literal::Int(val), _, Some(prim)) if int_info(prim).is_some() => {
+                let Some(bits) = int_from_i64(prim, *val) else {
+                    return Err(self.error(
+                        format!("{val} doesn't fit in {}", self.prim_name(prim)),
+                        span,
+                        None,
+                    ))?;
+                };
+                ExprKind::Int(prim, bits)
+            }
+            (Literal::Int(val), _, Some(prim)) if is_float(prim) => {
+                ExprKind::Float(prim, round_float(prim, *val as f64))
+            }
+            (Literal::Float(val), _, Some(prim)) if is_float(prim) => {
+                ExprKind::Float(prim, round_float(prim, *val))
+            }
+            (_, Some(ty), _) => {
+                let kind = match expr.val {
+                    Literal::Int(_) => "an integer",
+                    Literal::Float(_) => "a float",
+                    Literal::Bool(_) => "a bool",
+                };
+                return Err(self.error(
+                    format!("expected {}, found {kind}", self.program.format_ty(ty)),
+                    span,
+                    None,
+                ))?;
+            }
+        };
+        let ty = match &kind {
+            ExprKind::Int(prim, _) | ExprKind::Float(prim, _) => self.program.prim_ty(*prim),
+            _ => self.program.prim_ty(PrimitiveTy::Bool),
+        };
+        Ok(TypedExpr { ty, span, kind })
+    }
+
+    /// Check a path like `x.field.0`.
+    fn path(&self, expr: &PathExpr, span: SourceSpan) -> Result<TypedExpr> {
+        let (by_ref, root) = match expr.var.strip_prefix('&') {
+            Some(root) => (true, root),
+            None => (false, expr.var.as_str()),
+        };
+
+        let mut typed = match self.scope.get(root) {
+            Some(&var) => TypedExpr {
+                ty: self.body.vars[var].ty,
+                span,
+                kind: ExprKind::Var(var),
+            },
+            None => self.enum_case(root, &expr.path, span)?,
+        };
+        let parts = if matches!(typed.kind, ExprKind::EnumCase(_)) {
+            &[][..]
+        } else {
+            &expr.path[..]
+        };
+
+        let mut parts = parts.iter();
+        let mut path_so_far = root.to_owned();
+        while let Some(part) = parts.next() {
+            while let Ty::Ref(ref_ty) = self.realize(typed.ty, span)? {
+                typed = TypedExpr {
+                    ty: ref_ty.pointee_ty,
+                    span,
+                    kind: ExprKind::Deref(Box::new(typed)),
+                };
+            }
+            let no_field = || {
+                self.error(
+                    format!(
+                        "{path_so_far} is {}, which has no field {part}",
+                        self.program.format_ty(typed.ty)
+                    ),
+                    span,
+                    None,
+                )
+            };
+            let (ty, kind) = match self.realize(typed.ty, span)? {
+                Ty::Struct(struct_ty) => {
+                    let Some(idx) = field_idx(&struct_ty.fields, part) else {
+                        return Err(no_field())?;
+                    };
+                    let ty = struct_ty.fields[idx].ty;
+                    (ty, ExprKind::Field(Box::new(typed), idx))
+                }
+                Ty::Union(union_ty) => {
+                    let Some(idx) = field_idx(&union_ty.fields, part) else {
+                        return Err(no_field())?;
+                    };
+                    let ty = union_ty.fields[idx].ty;
+                    (ty, ExprKind::UnionField(Box::new(typed), idx))
+                }
+                Ty::Tagged(tagged_ty) => {
+                    let Some(variant_idx) = tagged_ty
+                        .variants
+                        .iter()
+                        .position(|v| v.name.as_str() == part.as_str())
+                    else {
+                        return Err(self.error(
+                            format!("{} has no case named {part}", tagged_ty.name),
+                            span,
+                            None,
+                        ))?;
+                    };
+                    let Some(field) = parts.next() else {
+                        return Err(self.error(
+                            format!("expected a field of {part} after {path_so_far}.{part}"),
+                            span,
+                            None,
+                        ))?;
+                    };
+                    let variant = &tagged_ty.variants[variant_idx];
+                    let variant_fields = variant.fields.as_deref().unwrap_or_default();
+                    let Some(idx) = field_idx(variant_fields, field) else {
+                        return Err(self.error(
+                            format!("{}::{} has no field {field}", tagged_ty.name, variant.name),
+                            span,
+                            None,
+                        ))?;
+                    };
+                    path_so_far = format!("{path_so_far}.{part}");
+                    let ty = variant_fields[idx].ty;
+                    (
+                        ty,
+                        ExprKind::VariantField(Box::new(typed), variant_idx, idx),
+                    )
+                }
+                Ty::Array(array_ty) => {
+                    let Ok(idx) = part.parse::<usize>() else {
+                        return Err(self.error(
+                            format!("{path_so_far} is an array, so {part} should be an index"),
+                            span,
+                            None,
+                        ))?;
+                    };
+                    if idx as u64 >= array_ty.len {
+                        return Err(self.error(
+                            format!(
+                                "index {idx} is out of bounds for {path_so_far}, which has length {}",
+                                array_ty.len
+                            ),
+                            span,
+                            None,
+                        ))?;
+                    }
+                    (array_ty.elem_ty, ExprKind::Index(Box::new(typed), idx))
+                }
+                _ => {
+                    return Err(self.error(
+                        format!(
+                            "{path_so_far} is {}, which has no fields",
+                            self.program.format_ty(typed.ty)
+                        ),
+                        span,
+                        None,
+                    ))?;
+                }
+            };
+            typed = TypedExpr { ty, span, kind };
+            path_so_far = format!("{path_so_far}.{part}");
+        }
+
+        if by_ref {
+            let Some(ty) = self.program.ref_ty(typed.ty) else {
+                return Err(self.error(
+                    format!(
+                        "&{} doesn't appear in any signature",
+                        self.program.format_ty(typed.ty)
+                    ),
+                    span,
+                    Some("bodies can only make references to types the program uses".to_owned()),
+                ))?;
+            };
+            typed = TypedExpr {
+                ty,
+                span,
+                kind: ExprKind::Ref(Box::new(typed)),
+            };
+        }
+        Ok(typed)
+    }
+
+    /// Check a path that isn't a variable, which had better be `MyEnum.Case`.
+    fn enum_case(&self, root: &str, path: &[Ident], span: SourceSpan) -> Result<TypedExpr> {
+        if let (Some(ty), [case]) = (self.program.ty_by_name(root), path) {
+            let ty = self.resolve(ty, span)?;
+            if let Ty::Enum(enum_ty) = self.program.realize_ty(ty) {
+                let Some(idx) = enum_ty
+                    .variants
+                    .iter()
+                    .position(|v| v.name.as_str() == case.as_str())
+                else {
+                    return Err(self.error(
+                        format!("{} has no case named {case}", enum_ty.name),
+                        span,
+                        None,
+                    ))?;
+                };
+                return Ok(TypedExpr {
+                    ty,
+                    span,
+                    kind: ExprKind::EnumCase(idx),
+                });
+            }
+        }
+        Err(self.error(format!("there's no variable named {root}"), span, None))?
+    }
+
+    /// Check a constructor like `"Point" { x 1; y 2; }`.
+    fn ctor(&mut self, expr: &CtorExpr, span: SourceSpan) -> Result<TypedExpr> {
+        let (ty_name, case) = match expr.ty.split_once('.') {
+            Some((ty_name, case)) => (ty_name, Some(case)),
+            None => (expr.ty.as_str(), None),
+        };
+        let tydent = Spanned::new(ty_name.to_owned(), span);
+        let Some(ty) = crate::parse::parse_tydent(&tydent)
+            .and_then(|tydent| self.program.ty_by_tydent(&tydent))
+        else {
+            return Err(self.error(
+                format!("I don't know what the type {ty_name} is"),
+                span,
+                Some("constructed types must be declared or used in a signature".to_owned()),
+            ))?;
+        };
+        let ty = self.resolve(ty, span)?;
+        let ty_desc = self.program.format_ty(ty);
+
+        if let (Some(case), false) = (case, matches!(self.program.realize_ty(ty), Ty::Tagged(_))) {
+            return Err(self.error(
+                format!("{ty_desc} isn't a tagged union, so it has no case {case}"),
+                span,
+                None,
+            ))?;
+        }
+        let kind = match self.program.realize_ty(ty) {
+            Ty::Struct(struct_ty) => {
+                ExprKind::Struct(self.ctor_fields(&ty_desc, &struct_ty.fields, expr, span)?)
+            }
+            Ty::Union(union_ty) => {
+                let [val] = &expr.vals[..] else {
+                    return Err(self.error(
+                        format!("constructing {ty_desc} needs exactly one field"),
+                        span,
+                        None,
+                    ))?;
+                };
+                let Some(idx) = val
+                    .var
+                    .as_ref()
+                    .and_then(|var| field_idx(&union_ty.fields, var))
+                else {
+                    return Err(self.error(
+                        format!("{ty_desc} has no field {}", field_name(val.var.as_ref())),
+                        Spanned::span(val),
+                        None,
+                    ))?;
+                };
+                let inner = self.check_expr(&val.expr, Some(union_ty.fields[idx].ty))?;
+                ExprKind::Union(idx, Box::new(inner))
+            }
+            Ty::Tagged(tagged_ty) => {
+                let Some(case) = case else {
+                    return Err(self.error(
+                        format!("which case of {ty_desc} should this be?"),
+                        span,
+                        Some(format!("try {ty_desc}.{}", tagged_ty.variants[0].name)),
+                    ))?;
+                };
+                let Some(idx) = tagged_ty.variants.iter().position(|v| v.name == *case) else {
+                    return Err(self.error(
+                        format!("{ty_desc} has no case named {case}"),
+                        span,
+                        None,
+                    ))?;
+                };
+                let variant = &tagged_ty.variants[idx];
+                let variant_fields = variant.fields.as_deref().unwrap_or_default();
+                let ty_desc = format!("{ty_desc}::{}", variant.name);
+                ExprKind::Tagged(idx, self.ctor_fields(&ty_desc, variant_fields, expr, span)?)
+            }
+            Ty::Array(array_ty) => {
+                if expr.vals.len() as u64 != array_ty.len {
+                    return Err(self.error(
+                        format!(
+                            "{ty_desc} needs {} elements, but {} were given",
+                            array_ty.len,
+                            expr.vals.len()
+                        ),
+                        span,
+                        None,
+                    ))?;
+                }
+                let elem_ty = array_ty.elem_ty;
+                let mut elems = vec![];
+                for val in &expr.vals {
+                    if let Some(var) = &val.var {
+                        return Err(self.error(
+                            format!("array elements don't have names (found {var})"),
+                            Spanned::span(val),
+                            Some("use _ for each element".to_owned()),
+                        ))?;
+                    }
+                    elems.push(self.check_expr(&val.expr, Some(elem_ty))?);
+                }
+                ExprKind::Array(elems)
+            }
+            _ => {
+                return Err(self.error(
+                    format!("{ty_desc} can't be built with a constructor"),
+                    span,
+                    Some("use a literal, a path like MyEnum.Case, or &x instead".to_owned()),
+                ))?;
+            }
+        };
+        Ok(TypedExpr { ty, span, kind })
+    }
+
+    /// Check the fields of a struct-like constructor, putting them in declaration order.
+    ///
+    /// Fields can be given by name, or positionally with `_`.
+    fn ctor_fields(
+        &mut self,
+        ty_desc: &str,
+        fields: &[FieldTy],
+        expr: &CtorExpr,
+        span: SourceSpan,
+    ) -> Result<Vec<TypedExpr>> {
+        let mut vals = vec![None; fields.len()];
+        for (pos, val) in expr.vals.iter().enumerate() {
+            let idx = match &val.var {
+                Some(var) => field_idx(fields, var),
+                None => (pos < fields.len()).then_some(pos),
+            };
+            let Some(idx) = idx else {
+                return Err(self.error(
+                    format!("{ty_desc} has no field {}", field_name(val.var.as_ref())),
+                    Spanned::span(val),
+                    None,
+                ))?;
+            };
+            if vals[idx].is_some() {
+                return Err(self.error(
+                    format!("{} was set twice", fields[idx].ident),
+                    Spanned::span(val),
+                    None,
+                ))?;
+            }
+            vals[idx] = Some(self.check_expr(&val.expr, Some(fields[idx].ty))?);
+        }
+        fields
+            .iter()
+            .zip(vals)
+            .map(|(field, val)| {
+                val.ok_or_else(|| {
+                    self.error(
+                        format!("{ty_desc} is missing a value for {}", field.ident),
+                        span,
+                        None,
+                    )
+                    .into()
+                })
+            })
+            .collect()
+    }
+
+    /// Check a call to a function or a builtin.
+    fn call(
+        &mut self,
+        expr: &CallExpr,
+        expected: Option<TyIdx>,
+        span: SourceSpan,
+    ) -> Result<TypedExpr> {
+        let name = expr.func.as_str();
+        if let Some(op) = ArithOp::from_name(name) {
+            let [lhs, rhs] = &expr.args[..] else {
+                return Err(self.error(
+                    format!("{name} takes 2 inputs, but was given {}", expr.args.len()),
+                    span,
+                    None,
+                ))?;
+            };
+            // Let a literal on either side take the type of the other side
+            let (lhs, rhs) = if expected.is_none() && matches!(**lhs, Expr::Literal(_)) {
+                let rhs = self.check_expr(rhs, None)?;
+                let lhs = self.check_expr(lhs, self.prim_ty_of(&rhs, span)?)?;
+                (lhs, rhs)
+            } else {
+                let lhs = self.check_expr(lhs, expected)?;
+                let rhs_ty = match expected {
+                    Some(ty) => Some(ty),
+                    None => self.prim_ty_of(&lhs, span)?,
+                };
+                let rhs = self.check_expr(rhs, rhs_ty)?;
+                (lhs, rhs)
+            };
+            let lhs_ty = self.resolve(lhs.ty, span)?;
+            let rhs_ty = self.resolve(rhs.ty, span)?;
+            let arith_ok = match self.program.realize_ty(lhs_ty) {
+                Ty::Primitive(prim) => {
+                    let numeric = int_info(*prim).is_some() && *prim != PrimitiveTy::Ptr;
+                    lhs_ty == rhs_ty && (numeric || is_float(*prim))
+                }
+                _ => false,
+            };
+            if !arith_ok {
+                return Err(self.error(
+                    format!(
+                        "can't apply {name} to {} and {}",
+                        self.program.format_ty(lhs.ty),
+                        self.program.format_ty(rhs.ty)
+                    ),
+                    span,
+                    None,
+                ))?;
+            }
+            if let Ty::Primitive(prim) = self.program.realize_ty(lhs_ty) {
+                self.check_prim_supported(*prim, span)?;
+            }
+            return Ok(TypedExpr {
+                ty: lhs.ty,
+                span,
+                kind: ExprKind::Arith(op, Box::new(lhs), Box::new(rhs)),
+            });
+        }
+
+        let Some(func) = self
+            .program
+            .all_funcs()
+            .find(|&func| self.program.realize_func(func).name == *name)
+        else {
+            return Err(self.error(format!("there's no function named {name}"), span, None))?;
+        };
+        let decl = self.program.realize_func(func);
+        if decl.inputs.len() != expr.args.len() {
+            return Err(self.error(
+                format!(
+                    "{name} takes {} inputs, but was given {}",
+                    decl.inputs.len(),
+                    expr.args.len()
+                ),
+                span,
+                None,
+            ))?;
+        }
+        let args = decl
+            .inputs
+            .iter()
+            .zip(&expr.args)
+            .map(|(arg, expr)| self.check_expr(expr, Some(arg.ty)))
+            .collect::<Result<Vec<_>>>()?;
+        let ty = match &decl.outputs[..] {
+            [] => self
+                .program
+                .ty_by_name("()")
+                .expect("Internal Compiler Error: () isn't a type"),
+            [output] => output.ty,
+            outputs => {
+                return Err(self.error(
+                    format!(
+                        "{name} has {} outputs, so it can't be used as a value",
+                        outputs.len()
+                    ),
+                    span,
+                    None,
+                ))?;
+            }
+        };
+        Ok(TypedExpr {
+            ty,
+            span,
+            kind: ExprKind::Call(func, args),
+        })
+    }
+
+    /// Whether two types are the same (looking through aliases).
+    fn same_ty(&self, lhs: TyIdx, rhs: TyIdx, span: SourceSpan) -> Result<bool> {
+        let lhs = self.resolve(lhs, span)?;
+        let rhs = self.resolve(rhs, span)?;
+        if lhs == rhs {
+            return Ok(true);
+        }
+        let same = match (self.program.realize_ty(lhs), self.program.realize_ty(rhs)) {
+            (Ty::Array(lhs), Ty::Array(rhs)) => {
+                lhs.len == rhs.len && self.same_ty(lhs.elem_ty, rhs.elem_ty, span)?
+            }
+            (Ty::Ref(lhs), Ty::Ref(rhs)) => self.same_ty(lhs.pointee_ty, rhs.pointee_ty, span)?,
+            _ => false,
+        };
+        Ok(same)
+    }
+
+    /// Look through aliases, rejecting puns.
+    fn resolve(&self, ty: TyIdx, span: SourceSpan) -> Result<TyIdx> {
+        let ty = resolve_alias(self.program, ty);
+        if let Ty::Pun(pun_ty) = self.program.realize_ty(ty) {
+            return Err(self.error(
+                format!("function bodies can't use puns (found {})", pun_ty.name),
+                span,
+                Some("a body has to compute the same thing in every language".to_owned()),
+            ))?;
+        }
+        Ok(ty)
+    }
+
+    fn realize(&self, ty: TyIdx, span: SourceSpan) -> Result<&Ty> {
+        Ok(self.program.realize_ty(self.resolve(ty, span)?))
+    }
+
+    /// Find a pun anywhere inside a type.
+    fn find_pun(&self, ty: TyIdx, visited: &mut HashSet<TyIdx>) -> Option<TyIdx> {
+        if !visited.insert(ty) {
+            return None;
+        }
+        let fields = |fields: &[FieldTy], visited: &mut HashSet<TyIdx>| {
+            fields
+                .iter()
+                .find_map(|field| self.find_pun(field.ty, visited))
+        };
+        match self.program.realize_ty(ty) {
+            Ty::Pun(_) => Some(ty),
+            Ty::Alias(alias_ty) => self.find_pun(alias_ty.real, visited),
+            Ty::Struct(struct_ty) => fields(&struct_ty.fields, visited),
+            Ty::Union(union_ty) => fields(&union_ty.fields, visited),
+            Ty::Tagged(tagged_ty) => tagged_ty
+                .variants
+                .iter()
+                .find_map(|variant| fields(variant.fields.as_deref().unwrap_or_default(), visited)),
+            Ty::Array(array_ty) => self.find_pun(array_ty.elem_ty, visited),
+            Ty::Ref(ref_ty) => self.find_pun(ref_ty.pointee_ty, visited),
+            Ty::Primitive(_) | Ty::Enum(_) | Ty::Empty => None,
+        }
+    }
+
+    /// The type a literal should take to match this expression (if it's a primitive).
+    fn prim_ty_of(&self, expr: &TypedExpr, span: SourceSpan) -> Result<Option<TyIdx>> {
+        Ok(match self.realize(expr.ty, span)? {
+            Ty::Primitive(_) => Some(expr.ty),
+            _ => None,
+        })
+    }
+
+    fn prim_name(&self, prim: PrimitiveTy) -> String {
+        self.program.format_ty(self.program.prim_ty(prim))
+    }
+
+    fn check_prim_supported(&self, prim: PrimitiveTy, span: SourceSpan) -> Result<()> {
+        use PrimitiveTy::*;
+        if matches!(prim, I256 | U256 | F16 | F128) {
+            return Err(self.error(
+                format!("function bodies don't support {}", self.prim_name(prim)),
+                span,
+                None,
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: String, span: SourceSpan, help: Option<String>) -> KdlScriptTypeError {
+        KdlScriptTypeError {
+            message,
+            src: self.program.src().clone(),
+            span,
+            help,
+        }
+    }
+}
+
+/// Find a field by name.
+fn field_idx(fields: &[FieldTy], name: &str) -> Option<usize> {
+    fields.iter().position(|field| field.ident == *name)
+}
+
+fn field_name(var: Option<&Ident>) -> &str {
+    var.map(|var| var.as_str()).unwrap_or("_")
+}
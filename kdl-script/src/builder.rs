@@ -0,0 +1,534 @@
+//! A typed API for building KdlScript programs without writing KDL.
+//!
+//! This is useful for tools that generate programs (like test generators):
+//! rather than pasting strings together and hoping they parse, you can
+//! directly construct a [`ParsedProgram`][] and then hand it to
+//! [`Compiler::compile_parsed`][crate::Compiler::compile_parsed] to typecheck it.
+//!
+//! ```
+//! use kdl_script::builder::{FuncBuilder, ProgramBuilder, StructBuilder};
+//! use kdl_script::parse::{IntExpr, Tydent};
+//!
+//! let mut program = ProgramBuilder::new();
+//! program.add_const("N", 4).unwrap();
+//! program
+//!     .add_ty(
+//!         StructBuilder::new("Point")
+//!             .field("x", Tydent::named("f32"))
+//!             .field("y", Tydent::named("f32")),
+//!     )
+//!     .unwrap();
+//! program.add_func(
+//!     FuncBuilder::new("points")
+//!         .input("arg", Tydent::array(Tydent::named("Point"), "N".parse::<IntExpr>().unwrap()))
+//!         .output("out", Tydent::reference(Tydent::named("Point"))),
+//! );
+//!
+//! let program = program.build();
+//! println!("{}", program.to_kdl().unwrap());
+//!
+//! let mut compiler = kdl_script::Compiler::new();
+//! compiler.compile_parsed(program).unwrap();
+//! ```
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::parse::*;
+use crate::spanned::Spanned;
+
+/// An item couldn't be added to a [`ProgramBuilder`][].
+#[derive(Debug, Error, Diagnostic)]
+pub enum BuilderError {
+    #[error("duplicate definition of const {0}")]
+    DuplicateConst(String),
+    #[error("duplicate definition of type {0}")]
+    DuplicateTy(String),
+}
+
+/// Builds up a [`ParsedProgram`][] item by item.
+#[derive(Debug, Clone)]
+pub struct ProgramBuilder {
+    program: ParsedProgram,
+}
+
+impl ProgramBuilder {
+    /// Make an empty program.
+    pub fn new() -> Self {
+        Self {
+            program: ParsedProgram {
+                tys: StableMap::new(),
+                funcs: StableMap::new(),
+                consts: StableMap::new(),
+                builtin_funcs_start: 0,
+            },
+        }
+    }
+
+    /// Start from an existing program (minus any builtins it contains).
+    ///
+    /// This lets you parse some type definitions from source and then
+    /// programmatically add more items to them.
+    pub fn from_parsed(program: &ParsedProgram) -> Self {
+        let mut program = program.clone();
+        program.funcs = program
+            .funcs
+            .into_iter()
+            .take(program.builtin_funcs_start)
+            .collect();
+        program.builtin_funcs_start = program.funcs.len();
+        Self { program }
+    }
+
+    /// Add a named constant.
+    pub fn add_const(
+        &mut self,
+        name: &str,
+        val: impl Into<IntExpr>,
+    ) -> Result<&mut Self, BuilderError> {
+        let name = ident(name);
+        if self.program.consts.contains_key(&name) {
+            return Err(BuilderError::DuplicateConst(name.to_string()));
+        }
+        self.program.consts.insert(
+            name.clone(),
+            ConstDecl {
+                name,
+                val: val.into(),
+            },
+        );
+        Ok(self)
+    }
+
+    /// Add a type definition.
+    pub fn add_ty(&mut self, ty: impl Into<TyDecl>) -> Result<&mut Self, BuilderError> {
+        let ty = ty.into();
+        if self.program.tys.contains_key(ty.name()) {
+            return Err(BuilderError::DuplicateTy(ty.name().to_string()));
+        }
+        self.program.tys.insert(ty.name().clone(), ty);
+        Ok(self)
+    }
+
+    /// Add a function definition.
+    pub fn add_func(&mut self, func: impl Into<FuncDecl>) -> &mut Self {
+        let func = func.into();
+        self.program.funcs.insert(func.name.clone(), func);
+        self
+    }
+
+    /// Get the finished program, ready to be typechecked.
+    pub fn build(&self) -> ParsedProgram {
+        let mut program = self.program.clone();
+        // Anything added after this point is a builtin!
+        program.builtin_funcs_start = program.funcs.len();
+        #[cfg(feature = "eval")]
+        program.add_builtin_funcs();
+        program
+    }
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TyDecl {
+    /// The name of the type this declares.
+    pub fn name(&self) -> &Ident {
+        match self {
+            TyDecl::Struct(decl) => &decl.name,
+            TyDecl::Union(decl) => &decl.name,
+            TyDecl::Enum(decl) => &decl.name,
+            TyDecl::Tagged(decl) => &decl.name,
+            TyDecl::Alias(decl) => &decl.name,
+            TyDecl::Pun(decl) => &decl.name,
+        }
+    }
+}
+
+impl Tydent {
+    /// A type referred to by name, like `u32` or `MyStruct`.
+    pub fn named(name: &str) -> Self {
+        Tydent::Name(ident(name))
+    }
+
+    /// A fixed length array, like `[u32; 4]`.
+    pub fn array(elem_ty: Tydent, len: impl Into<IntExpr>) -> Self {
+        Tydent::Array(Box::new(Spanned::from(elem_ty)), len.into())
+    }
+
+    /// A reference to another type, like `&u32`.
+    pub fn reference(pointee_ty: Tydent) -> Self {
+        Tydent::Ref(Box::new(Spanned::from(pointee_ty)))
+    }
+}
+
+/// Builds a [`StructDecl`][].
+#[derive(Debug, Clone)]
+pub struct StructBuilder {
+    decl: StructDecl,
+}
+
+impl StructBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: StructDecl {
+                name: ident(name),
+                fields: vec![],
+                attrs: vec![],
+            },
+        }
+    }
+
+    /// Add a field (use `_` as the name for a positional field).
+    pub fn field(mut self, name: &str, ty: Tydent) -> Self {
+        self.decl.fields.push(typed_var(name, ty));
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+}
+
+impl From<StructBuilder> for TyDecl {
+    fn from(builder: StructBuilder) -> Self {
+        TyDecl::Struct(builder.decl)
+    }
+}
+
+/// Builds a [`UnionDecl`][].
+#[derive(Debug, Clone)]
+pub struct UnionBuilder {
+    decl: UnionDecl,
+}
+
+impl UnionBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: UnionDecl {
+                name: ident(name),
+                fields: vec![],
+                attrs: vec![],
+            },
+        }
+    }
+
+    /// Add a field (use `_` as the name for a positional field).
+    pub fn field(mut self, name: &str, ty: Tydent) -> Self {
+        self.decl.fields.push(typed_var(name, ty));
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+}
+
+impl From<UnionBuilder> for TyDecl {
+    fn from(builder: UnionBuilder) -> Self {
+        TyDecl::Union(builder.decl)
+    }
+}
+
+/// Builds an [`EnumDecl`][].
+#[derive(Debug, Clone)]
+pub struct EnumBuilder {
+    decl: EnumDecl,
+}
+
+impl EnumBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: EnumDecl {
+                name: ident(name),
+                variants: vec![],
+                attrs: vec![],
+            },
+        }
+    }
+
+    /// Add a variant with an implicit value.
+    pub fn variant(mut self, name: &str) -> Self {
+        self.decl.variants.push(EnumVariant {
+            name: ident(name),
+            val: None,
+        });
+        self
+    }
+
+    /// Add a variant with an explicit value.
+    pub fn variant_with_val(mut self, name: &str, val: impl Into<IntExpr>) -> Self {
+        self.decl.variants.push(EnumVariant {
+            name: ident(name),
+            val: Some(val.into()),
+        });
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+}
+
+impl From<EnumBuilder> for TyDecl {
+    fn from(builder: EnumBuilder) -> Self {
+        TyDecl::Enum(builder.decl)
+    }
+}
+
+/// Builds a [`TaggedDecl`][].
+#[derive(Debug, Clone)]
+pub struct TaggedBuilder {
+    decl: TaggedDecl,
+}
+
+impl TaggedBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: TaggedDecl {
+                name: ident(name),
+                variants: vec![],
+                attrs: vec![],
+            },
+        }
+    }
+
+    /// Add a variant with no fields (`None`).
+    pub fn unit_variant(mut self, name: &str) -> Self {
+        self.decl.variants.push(TaggedVariant {
+            name: ident(name),
+            fields: None,
+        });
+        self
+    }
+
+    /// Add a variant with fields (`Some { x: u32 }`).
+    ///
+    /// As with structs, fields named `_` are positional.
+    pub fn variant<'a>(
+        mut self,
+        name: &str,
+        fields: impl IntoIterator<Item = (&'a str, Tydent)>,
+    ) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|(name, ty)| typed_var(name, ty))
+            .collect();
+        self.decl.variants.push(TaggedVariant {
+            name: ident(name),
+            fields: Some(fields),
+        });
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+}
+
+impl From<TaggedBuilder> for TyDecl {
+    fn from(builder: TaggedBuilder) -> Self {
+        TyDecl::Tagged(builder.decl)
+    }
+}
+
+/// Builds an [`AliasDecl`][].
+#[derive(Debug, Clone)]
+pub struct AliasBuilder {
+    decl: AliasDecl,
+}
+
+impl AliasBuilder {
+    pub fn new(name: &str, alias: Tydent) -> Self {
+        Self {
+            decl: AliasDecl {
+                name: ident(name),
+                alias: Spanned::from(alias),
+                attrs: vec![],
+            },
+        }
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+}
+
+impl From<AliasBuilder> for TyDecl {
+    fn from(builder: AliasBuilder) -> Self {
+        TyDecl::Alias(builder.decl)
+    }
+}
+
+/// Builds a [`PunDecl`][].
+///
+/// Each block must declare a type with the same name as the pun.
+#[derive(Debug, Clone)]
+pub struct PunBuilder {
+    decl: PunDecl,
+}
+
+impl PunBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: PunDecl {
+                name: ident(name),
+                blocks: vec![],
+                attrs: vec![],
+            },
+        }
+    }
+
+    /// Add a block that applies to any of the given languages.
+    pub fn lang(mut self, langs: &[&str], decl: impl Into<TyDecl>) -> Self {
+        let selector = PunSelector::Any(
+            langs
+                .iter()
+                .map(|&lang| PunSelector::Lang(Spanned::from(lang.to_owned())))
+                .collect(),
+        );
+        self.push_block(selector, decl.into());
+        self
+    }
+
+    /// Add the fallback block.
+    pub fn default(mut self, decl: impl Into<TyDecl>) -> Self {
+        self.push_block(PunSelector::Default, decl.into());
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+
+    fn push_block(&mut self, selector: PunSelector, decl: TyDecl) {
+        assert!(
+            decl.name() == &self.decl.name,
+            "pun declared a type other than what it should have"
+        );
+        self.decl.blocks.push(PunBlock { selector, decl });
+    }
+}
+
+impl From<PunBuilder> for TyDecl {
+    fn from(builder: PunBuilder) -> Self {
+        TyDecl::Pun(builder.decl)
+    }
+}
+
+/// Builds a [`FuncDecl`][].
+#[derive(Debug, Clone)]
+pub struct FuncBuilder {
+    decl: FuncDecl,
+}
+
+impl FuncBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            decl: FuncDecl {
+                name: ident(name),
+                inputs: vec![],
+                outputs: vec![],
+                attrs: vec![],
+                vals: vec![],
+                #[cfg(feature = "eval")]
+                body: vec![],
+            },
+        }
+    }
+
+    /// Add an input (use `_` as the name for an anonymous arg).
+    pub fn input(mut self, name: &str, ty: Tydent) -> Self {
+        self.decl.inputs.push(typed_var(name, ty));
+        self
+    }
+
+    /// Add an output (use `_` as the name for an anonymous output).
+    pub fn output(mut self, name: &str, ty: Tydent) -> Self {
+        self.decl.outputs.push(typed_var(name, ty));
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.decl.attrs.push(attr);
+        self
+    }
+
+    /// Give part of an argument an explicit value (see the `vals` block).
+    pub fn val(mut self, path: &str, val: ValLiteral) -> Self {
+        self.decl.vals.push(ValDecl {
+            path: Spanned::from(path.to_owned()),
+            val: Spanned::from(val),
+        });
+        self
+    }
+
+    /// Add a statement to the body.
+    #[cfg(feature = "eval")]
+    pub fn stmt(mut self, stmt: Stmt) -> Self {
+        self.decl.body.push(stmt);
+        self
+    }
+}
+
+impl From<FuncBuilder> for FuncDecl {
+    fn from(builder: FuncBuilder) -> Self {
+        builder.decl
+    }
+}
+
+impl Attr {
+    /// `@packed`
+    pub fn packed() -> Self {
+        Attr::Packed(AttrPacked {})
+    }
+
+    /// `@align N`
+    pub fn align(align: impl Into<IntExpr>) -> Self {
+        Attr::Align(AttrAligned {
+            align: align.into(),
+        })
+    }
+
+    /// `@repr "c"` and friends
+    pub fn repr(reprs: Vec<Repr>) -> Self {
+        Attr::Repr(AttrRepr { reprs })
+    }
+
+    /// `@ "whatever"`
+    pub fn passthrough(attr: &str) -> Self {
+        Attr::Passthrough(AttrPassthrough {
+            selector: PunSelector::Default,
+            attr: Spanned::from(attr.to_owned()),
+        })
+    }
+
+    /// `@c "whatever"`, `@rust "whatever"`
+    pub fn lang_passthrough(lang: LangRepr, attr: &str) -> Self {
+        Attr::Passthrough(AttrPassthrough {
+            selector: PunSelector::Lang(Spanned::from(lang.to_string())),
+            attr: Spanned::from(attr.to_owned()),
+        })
+    }
+}
+
+fn ident(name: &str) -> Ident {
+    Ident::from(name.to_owned())
+}
+
+fn typed_var(name: &str, ty: Tydent) -> TypedVar {
+    TypedVar {
+        name: if name == "_" { None } else { Some(ident(name)) },
+        ty: Spanned::from(ty),
+        attrs: vec![],
+    }
+}
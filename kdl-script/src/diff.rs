@@ -0,0 +1,356 @@
+//! Comparing two versions of a program for ABI compatibility.
+//!
+//! Given the old and new versions of an API, [`abi_diff`][] reports every change
+//! that would break code built against the old version: removed functions or types,
+//! changed signatures, fields that were reordered/renamed/retyped, and types whose
+//! size or alignment changed. Purely additive changes (new functions, new types,
+//! new enum cases) are reported too, but aren't considered breaking.
+//!
+//! Types are compared structurally (a [`TyIdx`][] is only meaningful within its
+//! own program), after resolving aliases and puns. Nominal types are matched up by
+//! name, starting from the types in the old program's [`DefinitionGraph`][crate::DefinitionGraph].
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::layout::DataLayout;
+use crate::types::{Arg, FieldTy, Ty, TyIdx};
+use crate::{Definition, PunEnv, Result, TypedProgram};
+
+/// All the differences between two versions of a program.
+#[derive(Debug, Clone, Default)]
+pub struct AbiDiff {
+    pub changes: Vec<AbiChange>,
+}
+
+impl AbiDiff {
+    /// Whether any of the changes break ABI compatibility.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    /// The changes that break ABI compatibility.
+    pub fn breaking(&self) -> impl Iterator<Item = &AbiChange> {
+        self.changes.iter().filter(|change| change.breaking)
+    }
+}
+
+/// A single difference between two versions of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiChange {
+    /// Whether this breaks code built against the old version.
+    pub breaking: bool,
+    /// The item that changed (like `Point.x` or `send(packet)`).
+    pub item: String,
+    /// What happened to it.
+    pub message: String,
+}
+
+impl fmt::Display for AbiChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.breaking { "breaking" } else { "added" };
+        write!(f, "{kind}: {}: {}", self.item, self.message)
+    }
+}
+
+/// Compare two versions of a program.
+///
+/// Puns are resolved with `env`, and sizes are compared on `target`.
+pub fn abi_diff(
+    old: &TypedProgram,
+    new: &TypedProgram,
+    env: &PunEnv,
+    target: &DataLayout,
+) -> Result<AbiDiff> {
+    let mut differ = Differ {
+        old,
+        new,
+        env,
+        target,
+        visited: HashSet::new(),
+        diff: AbiDiff::default(),
+    };
+
+    // Types, in definition order (matched up by name)
+    let old_tys = differ.nominal_tys(old)?;
+    let new_tys = differ.nominal_tys(new)?;
+    for &old_ty in &old_tys {
+        let name = old.format_ty(old_ty);
+        match new.ty_by_name(&name) {
+            Some(new_ty) => differ.compare_ty(&name, old_ty, new_ty)?,
+            None => differ.breaking(&name, "type was removed".to_owned()),
+        }
+    }
+    for &new_ty in &new_tys {
+        let name = new.format_ty(new_ty);
+        if old.ty_by_name(&name).is_none() {
+            differ.added(&name, "type was added".to_owned());
+        }
+    }
+
+    // Functions (matched up by name)
+    for old_func in old.all_funcs().map(|func| old.realize_func(func)) {
+        let name = old_func.name.as_str();
+        let new_func = new
+            .all_funcs()
+            .map(|func| new.realize_func(func))
+            .find(|new_func| new_func.name.as_str() == name);
+        let Some(new_func) = new_func else {
+            differ.breaking(name, "function was removed".to_owned());
+            continue;
+        };
+        differ.compare_args(name, "input", &old_func.inputs, &new_func.inputs)?;
+        differ.compare_args(name, "output", &old_func.outputs, &new_func.outputs)?;
+    }
+    for new_func in new.all_funcs().map(|func| new.realize_func(func)) {
+        let name = new_func.name.as_str();
+        let exists = old
+            .all_funcs()
+            .any(|func| old.realize_func(func).name.as_str() == name);
+        if !exists {
+            differ.added(name, "function was added".to_owned());
+        }
+    }
+
+    Ok(differ.diff)
+}
+
+struct Differ<'a> {
+    old: &'a TypedProgram,
+    new: &'a TypedProgram,
+    env: &'a PunEnv,
+    target: &'a DataLayout,
+    /// Pairs of nominal types we've already compared (also prevents infinite recursion).
+    visited: HashSet<(TyIdx, TyIdx)>,
+    diff: AbiDiff,
+}
+
+impl Differ<'_> {
+    fn breaking(&mut self, item: &str, message: String) {
+        self.diff.changes.push(AbiChange {
+            breaking: true,
+            item: item.to_owned(),
+            message,
+        });
+    }
+
+    fn added(&mut self, item: &str, message: String) {
+        self.diff.changes.push(AbiChange {
+            breaking: false,
+            item: item.to_owned(),
+            message,
+        });
+    }
+
+    /// The nominal types a program defines for its functions.
+    fn nominal_tys(&self, program: &TypedProgram) -> Result<Vec<TyIdx>> {
+        let graph = program.definition_graph(self.env)?;
+        let tys = graph
+            .definitions(program.all_funcs())
+            .into_iter()
+            .filter_map(|def| match def {
+                Definition::DefineTy(ty) => Some(ty),
+                _ => None,
+            })
+            .filter(|&ty| {
+                matches!(
+                    program.realize_ty(ty),
+                    Ty::Struct(_) | Ty::Union(_) | Ty::Enum(_) | Ty::Tagged(_)
+                )
+            })
+            .collect();
+        Ok(tys)
+    }
+
+    /// Look through aliases and puns, since they don't affect the ABI.
+    fn resolve(&self, program: &TypedProgram, ty: TyIdx) -> Result<TyIdx> {
+        match program.realize_ty(ty) {
+            Ty::Alias(alias_ty) => self.resolve(program, alias_ty.real),
+            Ty::Pun(pun_ty) => {
+                let real = program.resolve_pun(pun_ty, self.env)?;
+                self.resolve(program, real)
+            }
+            _ => Ok(ty),
+        }
+    }
+
+    fn compare_args(&mut self, func: &str, kind: &str, old: &[Arg], new: &[Arg]) -> Result<()> {
+        if old.len() != new.len() {
+            self.breaking(
+                func,
+                format!(
+                    "number of {kind}s changed from {} to {}",
+                    old.len(),
+                    new.len()
+                ),
+            );
+        }
+        for (old_arg, new_arg) in old.iter().zip(new) {
+            let item = format!("{func}({kind} {})", old_arg.name);
+            self.compare_ty(&item, old_arg.ty, new_arg.ty)?;
+        }
+        Ok(())
+    }
+
+    fn compare_ty(&mut self, item: &str, old_ty: TyIdx, new_ty: TyIdx) -> Result<()> {
+        let (old, new) = (self.old, self.new);
+        let old_ty = self.resolve(self.old, old_ty)?;
+        let new_ty = self.resolve(self.new, new_ty)?;
+        // Each pair of nominal types only needs to be compared (and reported) once
+        if old.realize_ty(old_ty).is_nominal() && !self.visited.insert((old_ty, new_ty)) {
+            return Ok(());
+        }
+
+        match (old.realize_ty(old_ty), new.realize_ty(new_ty)) {
+            (Ty::Primitive(old_prim), Ty::Primitive(new_prim)) if old_prim == new_prim => {}
+            (Ty::Empty, Ty::Empty) => {}
+            (Ty::Ref(old_ref), Ty::Ref(new_ref)) => {
+                self.compare_ty(item, old_ref.pointee_ty, new_ref.pointee_ty)?;
+            }
+            (Ty::Array(old_array), Ty::Array(new_array)) => {
+                if old_array.len != new_array.len {
+                    self.breaking(
+                        item,
+                        format!(
+                            "array length changed from {} to {}",
+                            old_array.len, new_array.len
+                        ),
+                    );
+                }
+                self.compare_ty(item, old_array.elem_ty, new_array.elem_ty)?;
+            }
+            (Ty::Struct(old_struct), Ty::Struct(new_struct)) => {
+                self.compare_fields(item, &old_struct.fields, &new_struct.fields)?;
+                self.compare_layout(item, old_ty, new_ty);
+            }
+            (Ty::Union(old_union), Ty::Union(new_union)) => {
+                self.compare_fields(item, &old_union.fields, &new_union.fields)?;
+                self.compare_layout(item, old_ty, new_ty);
+            }
+            (Ty::Enum(old_enum), Ty::Enum(new_enum)) => {
+                let old_vals = enum_values(old_enum);
+                let new_vals = enum_values(new_enum);
+                for (name, old_val) in &old_vals {
+                    match new_vals.iter().find(|(new_name, _)| new_name == name) {
+                        Some((_, new_val)) if new_val != old_val => self.breaking(
+                            &format!("{item}::{name}"),
+                            format!("value changed from {old_val} to {new_val}"),
+                        ),
+                        Some(_) => {}
+                        None => {
+                            self.breaking(&format!("{item}::{name}"), "case was removed".to_owned())
+                        }
+                    }
+                }
+                for (name, _) in &new_vals {
+                    if !old_vals.iter().any(|(old_name, _)| old_name == name) {
+                        self.added(&format!("{item}::{name}"), "case was added".to_owned());
+                    }
+                }
+                self.compare_layout(item, old_ty, new_ty);
+            }
+            (Ty::Tagged(old_tagged), Ty::Tagged(new_tagged)) => {
+                for (idx, old_variant) in old_tagged.variants.iter().enumerate() {
+                    let variant_item = format!("{item}::{}", old_variant.name);
+                    let Some(new_variant) = new_tagged.variants.get(idx) else {
+                        self.breaking(&variant_item, "variant was removed".to_owned());
+                        continue;
+                    };
+                    if old_variant.name.as_str() != new_variant.name.as_str() {
+                        self.breaking(
+                            &variant_item,
+                            format!("variant {idx} is now {}", new_variant.name),
+                        );
+                        continue;
+                    }
+                    let old_fields = old_variant.fields.clone().unwrap_or_default();
+                    let new_fields = new_variant.fields.clone().unwrap_or_default();
+                    self.compare_fields(&variant_item, &old_fields, &new_fields)?;
+                }
+                for new_variant in new_tagged.variants.iter().skip(old_tagged.variants.len()) {
+                    self.added(
+                        &format!("{item}::{}", new_variant.name),
+                        "variant was added".to_owned(),
+                    );
+                }
+                self.compare_layout(item, old_ty, new_ty);
+            }
+            _ => {
+                self.breaking(
+                    item,
+                    format!(
+                        "type changed from {} to {}",
+                        old.format_ty(old_ty),
+                        new.format_ty(new_ty)
+                    ),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Fields are part of the ABI by position, so any change to them is breaking.
+    fn compare_fields(&mut self, item: &str, old: &[FieldTy], new: &[FieldTy]) -> Result<()> {
+        for (idx, old_field) in old.iter().enumerate() {
+            let name = old_field.ident.as_str();
+            let field_item = format!("{item}.{name}");
+            let new_idx = new.iter().position(|field| field.ident.as_str() == name);
+            match new_idx {
+                None => self.breaking(&field_item, "field was removed".to_owned()),
+                Some(new_idx) if new_idx != idx => self.breaking(
+                    &field_item,
+                    format!("field moved from position {idx} to {new_idx}"),
+                ),
+                Some(new_idx) => self.compare_ty(&field_item, old_field.ty, new[new_idx].ty)?,
+            }
+        }
+        for new_field in new {
+            let name = new_field.ident.as_str();
+            if !old.iter().any(|field| field.ident.as_str() == name) {
+                self.breaking(&format!("{item}.{name}"), "field was added".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    fn compare_layout(&mut self, item: &str, old_ty: TyIdx, new_ty: TyIdx) {
+        // Types without a specified layout (repr(rust)) can't be compared
+        let old_layout = self.old.layout_of(old_ty, self.env, self.target);
+        let new_layout = self.new.layout_of(new_ty, self.env, self.target);
+        let (Ok(old_layout), Ok(new_layout)) = (old_layout, new_layout) else {
+            return;
+        };
+        if old_layout.size != new_layout.size {
+            self.breaking(
+                item,
+                format!(
+                    "size changed from {} to {}",
+                    old_layout.size, new_layout.size
+                ),
+            );
+        }
+        if old_layout.align != new_layout.align {
+            self.breaking(
+                item,
+                format!(
+                    "alignment changed from {} to {}",
+                    old_layout.align, new_layout.align
+                ),
+            );
+        }
+    }
+}
+
+/// The value of each case of a C-style enum (unspecified values count up from the previous one).
+fn enum_values(enum_ty: &crate::types::EnumTy) -> Vec<(String, i64)> {
+    let mut next = 0;
+    enum_ty
+        .variants
+        .iter()
+        .map(|variant| {
+            let val = variant.val.unwrap_or(next);
+            next = val.wrapping_add(1);
+            (variant.name.to_string(), val)
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+//! Emitting declarations for a [`TypedProgram`][] in real languages.
+//!
+//! This is the declaration half of a codegen backend: given a [`DefinitionGraph`][]
+//! it produces the type definitions and function signatures a language needs
+//! to talk about the program's functions. It's shared by the `kdl-script emit`
+//! subcommand and abi-cafe's toolchains, which layer their own value/harness
+//! logic on top.
+//!
+//! Each language has its own emitter ([`c::CDecls`][], [`rust::RustDecls`][])
+//! which interns the language's name for every type as it goes, so that later
+//! codegen can look those names up again.
+//!
+//! kdl-script identifiers aren't necessarily valid in every language (a field
+//! named `type` or `int`), so each emitter also has an `ident` method that spells
+//! a name for its language. Escaped names have to stay unique, so names that share
+//! C's global namespace (and function names, which are link symbols shared between
+//! languages) are all spelled up front by [`GlobalIdents`][].
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::types::{Func, FuncIdx, Ty};
+use crate::{KdlScriptError, PunEnv, TypedProgram};
+
+pub mod c;
+pub mod rust;
+
+const INDENT: &str = "    ";
+
+/// An error while emitting declarations.
+#[derive(Debug, Error, Diagnostic)]
+pub enum EmitError {
+    /// The program uses something the target language can't express.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    #[error(transparent)]
+    Fmt(#[from] std::fmt::Error),
+}
+
+/// A language we know how to emit declarations for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmitLang {
+    C,
+    Rust,
+}
+
+impl EmitLang {
+    /// The [`PunEnv`][] puns should be resolved with for this language.
+    pub fn pun_env(&self) -> PunEnv {
+        PunEnv {
+            lang: self.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for EmitLang {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            EmitLang::C => "c",
+            EmitLang::Rust => "rust",
+        };
+        s.fmt(f)
+    }
+}
+
+impl FromStr for EmitLang {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "c" => Ok(Self::C),
+            "rust" => Ok(Self::Rust),
+            _ => Err(format!("unknown emit lang {s}")),
+        }
+    }
+}
+
+/// Emit the declarations needed to call the given functions
+/// (or all of them, if `funcs` is empty) as a standalone source file.
+pub fn emit_declarations(
+    program: &Arc<TypedProgram>,
+    lang: EmitLang,
+    funcs: &[FuncIdx],
+) -> std::result::Result<String, KdlScriptError> {
+    let env = Arc::new(lang.pun_env());
+    let defs = program.definition_graph(&env)?;
+    let funcs = if funcs.is_empty() {
+        program.all_funcs().collect()
+    } else {
+        funcs.to_vec()
+    };
+
+    let mut out = String::new();
+    match lang {
+        EmitLang::C => {
+            let mut decls = c::CDecls::new(program.clone(), env);
+            decls.generate_includes(&mut out)?;
+            decls.generate_definitions(&mut out, &defs, &funcs)?;
+            decls.generate_prototypes(&mut out, "", &funcs)?;
+        }
+        EmitLang::Rust => {
+            let mut decls = rust::RustDecls::new(program.clone(), env);
+            decls.generate_definitions(&mut out, &defs, &funcs)?;
+            decls.generate_externs(&mut out, "C", &funcs)?;
+        }
+    }
+    Ok(out)
+}
+
+/// Something in C's global namespace.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Global {
+    Ty(String),
+    Func(String),
+    /// A variant of a c-like enum, which C spells `Enum_Variant`
+    Variant(String, String),
+}
+
+/// How to spell every name that shares C's global namespace: types, functions,
+/// and the variants of c-like enums.
+///
+/// A caller in one language links against a callee in another, so function names
+/// that any language would need to escape (or that are `reserved`) are escaped
+/// the same way everywhere, and every emitter spells functions with this.
+#[derive(Debug, Clone)]
+pub struct GlobalIdents {
+    names: HashMap<Global, String>,
+}
+
+impl GlobalIdents {
+    pub fn new(types: &TypedProgram, reserved: &[&str]) -> Self {
+        let mut globals = vec![];
+        for ty in types.all_tys() {
+            let name = match types.realize_ty(ty) {
+                Ty::Struct(struct_ty) => &struct_ty.name,
+                Ty::Union(union_ty) => &union_ty.name,
+                Ty::Tagged(tagged_ty) => &tagged_ty.name,
+                Ty::Alias(alias_ty) => &alias_ty.name,
+                Ty::Pun(pun_ty) => &pun_ty.name,
+                Ty::Enum(enum_ty) => {
+                    let name = &enum_ty.name;
+                    // Variants are prefixed with the enum's name, escaped like any other
+                    let prefix = if c::is_keyword(name) || reserved.contains(&name.as_str()) {
+                        format!("{name}_")
+                    } else {
+                        name.to_string()
+                    };
+                    for variant in &enum_ty.variants {
+                        let global = Global::Variant(name.to_string(), variant.name.to_string());
+                        globals.push((global, format!("{prefix}_{}", variant.name)));
+                    }
+                    name
+                }
+                Ty::Primitive(_) | Ty::Empty | Ty::Array(_) | Ty::Ref(_) => continue,
+            };
+            globals.push((Global::Ty(name.to_string()), name.to_string()));
+        }
+        for func in types.all_funcs() {
+            let name = &types.realize_func(func).name;
+            globals.push((Global::Func(name.to_string()), name.to_string()));
+        }
+        // (the blocks of a pun all have the same name)
+        let mut seen = HashSet::new();
+        globals.retain(|(global, _)| seen.insert(global.clone()));
+
+        let names = escape_scope(globals, |global, name| {
+            let is_keyword = match global {
+                Global::Func(_) => c::is_keyword(name) || rust::is_keyword(name),
+                Global::Ty(_) | Global::Variant(..) => c::is_keyword(name),
+            };
+            is_keyword || reserved.contains(&name)
+        });
+        Self { names }
+    }
+
+    /// How C spells the name of a type.
+    pub fn ty<'a>(&'a self, name: &'a str) -> &'a str {
+        self.get(Global::Ty(name.to_owned()), name)
+    }
+
+    /// How every language spells the name of a function.
+    pub fn func<'a>(&'a self, name: &'a str) -> &'a str {
+        self.get(Global::Func(name.to_owned()), name)
+    }
+
+    /// How C spells a variant of a c-like enum (`Enum_Variant`).
+    pub fn variant(&self, enum_name: &str, variant_name: &str) -> String {
+        let global = Global::Variant(enum_name.to_owned(), variant_name.to_owned());
+        self.names
+            .get(&global)
+            .cloned()
+            .unwrap_or_else(|| format!("{enum_name}_{variant_name}"))
+    }
+
+    fn get<'a>(&'a self, global: Global, name: &'a str) -> &'a str {
+        self.names.get(&global).map_or(name, |name| name)
+    }
+}
+
+/// The name backends give a variable in a function body.
+///
+/// Inputs keep their names, and `let`s are numbered so that shadowing
+/// doesn't turn into a redeclaration (which C doesn't allow).
+/// This is the kdl-script name, so it still needs to be spelled for the language.
+#[cfg(feature = "eval")]
+pub fn body_var_name(function: &Func, var: crate::body::VarIdx) -> String {
+    match function.inputs.get(var) {
+        Some(arg) => arg.name.to_string(),
+        None => format!("{}_{var}", function.body.vars[var].name),
+    }
+}
+
+/// Every local a function's definition declares: its inputs and outputs,
+/// and the variables its body `let`s (see [`body_var_name`][]).
+pub fn func_locals(function: &Func) -> Vec<String> {
+    let args = function.inputs.iter().chain(&function.outputs);
+    let locals = args.map(|arg| arg.name.to_string());
+    #[cfg(feature = "eval")]
+    let locals = locals.chain(
+        (function.inputs.len()..function.body.vars.len()).map(|var| body_var_name(function, var)),
+    );
+    locals.collect()
+}
+
+/// Spell every name in a scope, giving the ones that `needs_escape` `_` suffixes
+/// until they don't clash with anything else in the scope.
+///
+/// Names that don't need escaping always keep their spelling.
+pub(crate) fn escape_scope<K: Hash + Eq>(
+    names: Vec<(K, String)>,
+    needs_escape: impl Fn(&K, &str) -> bool,
+) -> HashMap<K, String> {
+    let mut taken = names
+        .iter()
+        .filter(|(key, name)| !needs_escape(key, name))
+        .map(|(_, name)| name.clone())
+        .collect::<HashSet<_>>();
+    let mut spellings = HashMap::new();
+    for (key, name) in names {
+        let mut ident = name.clone();
+        if needs_escape(&key, &name) {
+            ident.push('_');
+            while needs_escape(&key, &ident) || taken.contains(&ident) {
+                ident.push('_');
+            }
+            taken.insert(ident.clone());
+        }
+        spellings.insert(key, ident);
+    }
+    spellings
+}
+
+/// Check that a function's outputs are something we know how to return.
+///
+/// Outputs containing references would need to be outparams, and multiple
+/// outputs would need to be packed into a struct, neither of which we do.
+pub fn check_returns(types: &TypedProgram, function: &Func) -> Result<(), EmitError> {
+    let has_outparams = function
+        .outputs
+        .iter()
+        .any(|arg| types.ty_contains_ref(arg.ty));
+    if has_outparams {
+        return Err(EmitError::Unsupported(
+            "outparams (outputs containing references) aren't supported".to_owned(),
+        ));
+    }
+    if function.outputs.len() > 1 {
+        return Err(EmitError::Unsupported(
+            "multiple returns (should this be a struct?)".to_owned(),
+        ));
+    }
+    Ok(())
+}
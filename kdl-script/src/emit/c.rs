@@ -0,0 +1,593 @@
+//! C declarations.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+use tracing::debug;
+
+use super::{escape_scope, func_locals, EmitError, GlobalIdents, INDENT};
+use crate::parse::{Attr, AttrAligned, AttrRepr, LangRepr, Repr};
+use crate::types::{AliasTy, ArrayTy, FieldTy, Func, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use crate::{Definition, DefinitionGraph, PunEnv, TypedProgram};
+
+type Result<T> = std::result::Result<T, EmitError>;
+
+/// Words C won't let us use as identifiers: keywords (up to C23), and the
+/// names the headers we include define.
+const KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Bool",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+    "alignas",
+    "alignof",
+    "constexpr",
+    "nullptr",
+    "static_assert",
+    "thread_local",
+    "typeof",
+    "typeof_unqual",
+    "bool",
+    "true",
+    "false",
+    "int8_t",
+    "int16_t",
+    "int32_t",
+    "int64_t",
+    "uint8_t",
+    "uint16_t",
+    "uint32_t",
+    "uint64_t",
+    "__int128_t",
+    "__uint128_t",
+    "_Float16",
+    "_Float128",
+];
+
+/// Whether `name` can't be used as-is as a C identifier.
+pub fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+/// Emits C type definitions and function prototypes.
+pub struct CDecls {
+    pub types: Arc<TypedProgram>,
+    pub env: Arc<PunEnv>,
+    /// The repr to use for types that don't specify one.
+    pub repr: LangRepr,
+    /// How to spell `f16`, or why it can't be used.
+    ///
+    /// Compilers don't agree on this, so the default (`_Float16`) may need overriding.
+    pub f16: std::result::Result<&'static str, String>,
+    /// How to spell `f128`, or why it can't be used.
+    ///
+    /// Compilers don't agree on this, so the default (`_Float128`) may need overriding.
+    pub f128: std::result::Result<&'static str, String>,
+    /// The name of each interned type, split into the parts that go
+    /// before and after the name of a variable of that type
+    /// (`int32_t (*` and `)[4]` for a pointer to an array).
+    pub tynames: HashMap<TyIdx, (String, String)>,
+    /// Names the surrounding code uses for itself, which [`CDecls::ident`][] will avoid.
+    ///
+    /// Set this before emitting anything: global names are only spelled once.
+    pub reserved: &'static [&'static str],
+    globals: OnceLock<GlobalIdents>,
+}
+
+impl CDecls {
+    pub fn new(types: Arc<TypedProgram>, env: Arc<PunEnv>) -> Self {
+        Self {
+            types,
+            env,
+            repr: LangRepr::C,
+            f16: Ok("_Float16 "),
+            f128: Ok("_Float128 "),
+            tynames: Default::default(),
+            reserved: &[],
+            globals: OnceLock::new(),
+        }
+    }
+
+    fn globals(&self) -> &GlobalIdents {
+        self.globals
+            .get_or_init(|| GlobalIdents::new(&self.types, self.reserved))
+    }
+
+    fn needs_escape(&self, name: &str) -> bool {
+        is_keyword(name) || self.reserved.contains(&name)
+    }
+
+    /// Spell a kdl-script identifier for C.
+    ///
+    /// Keywords and reserved names get a `_` suffix. Names that share a scope with
+    /// other kdl-script names should use [`CDecls::ty_ident`][], [`CDecls::field_ident`][],
+    /// [`CDecls::variant_ident`][], [`CDecls::func_ident`][], or [`CDecls::local_ident`][],
+    /// which keep them unique.
+    pub fn ident<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.needs_escape(name) {
+            Cow::Owned(format!("{name}_"))
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Spell the name of a type for C.
+    pub fn ty_ident<'a>(&'a self, name: &'a str) -> &'a str {
+        self.globals().ty(name)
+    }
+
+    /// Spell the name of the `idx`th of some struct (or union) fields for C,
+    /// keeping it distinct from the other fields.
+    pub fn field_ident<'a>(&self, fields: &'a [FieldTy], idx: usize) -> Cow<'a, str> {
+        let name = &fields[idx].ident;
+        if !self.needs_escape(name) {
+            return Cow::Borrowed(name);
+        }
+        let names = fields
+            .iter()
+            .enumerate()
+            .map(|(idx, field)| (idx, field.ident.to_string()))
+            .collect();
+        let mut names = escape_scope(names, |_, name| self.needs_escape(name));
+        Cow::Owned(names.remove(&idx).unwrap())
+    }
+
+    /// Spell one of a function's locals (an argument, or a variable in its body)
+    /// for C, keeping it distinct from the function's other locals (see [`func_locals`][]).
+    pub fn local_ident<'a>(&self, function: &Func, name: &'a str) -> Cow<'a, str> {
+        if !self.needs_escape(name) {
+            return Cow::Borrowed(name);
+        }
+        let mut seen = HashSet::new();
+        let names = func_locals(function)
+            .into_iter()
+            .filter(|local| seen.insert(local.clone()))
+            .map(|local| (local.clone(), local))
+            .collect();
+        let mut names = escape_scope(names, |_, name| self.needs_escape(name));
+        Cow::Owned(names.remove(name).unwrap_or_else(|| format!("{name}_")))
+    }
+
+    /// Spell a variant of a c-like enum, which C puts in the global namespace
+    /// as `EnumName_Variant`.
+    pub fn variant_ident(&self, enum_name: &str, variant_name: &str) -> String {
+        self.globals().variant(enum_name, variant_name)
+    }
+
+    /// Spell a function name for C (see [`GlobalIdents`][]).
+    pub fn func_ident<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let ident = self.globals().func(name);
+        if ident == name {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(ident.to_owned())
+        }
+    }
+
+    /// Emit the headers the emitted declarations rely on.
+    pub fn generate_includes(&self, f: &mut dyn Write) -> Result<()> {
+        writeln!(f, "#include <stdint.h>")?;
+        writeln!(f, "#include <stdbool.h>")?;
+        writeln!(f)?;
+        Ok(())
+    }
+
+    /// Emit the definitions of every type the given functions need.
+    pub fn generate_definitions(
+        &mut self,
+        f: &mut dyn Write,
+        defs: &DefinitionGraph,
+        funcs: &[FuncIdx],
+    ) -> Result<()> {
+        for def in defs.definitions(funcs.iter().copied()) {
+            match def {
+                Definition::DeclareTy(ty) => {
+                    debug!("declare ty {}", self.types.format_ty(ty));
+                    self.generate_forward_decl(f, ty)?;
+                }
+                Definition::DefineTy(ty) => {
+                    debug!("define ty {}", self.types.format_ty(ty));
+                    self.generate_tydef(f, ty)?;
+                }
+                Definition::DefineFunc(_) => {
+                    // Prototypes are emitted separately, once all the types exist
+                }
+                Definition::DeclareFunc(_) => {
+                    // nothing to do, executable kdl-script isn't real and can't hurt us
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit a prototype for each of the given functions.
+    pub fn generate_prototypes(
+        &self,
+        f: &mut dyn Write,
+        convention: &str,
+        funcs: &[FuncIdx],
+    ) -> Result<()> {
+        for &func in funcs {
+            self.generate_signature(f, convention, func)?;
+            writeln!(f, ";")?;
+        }
+        writeln!(f)?;
+        Ok(())
+    }
+
+    pub fn intern_tyname(&mut self, ty: TyIdx) -> Result<()> {
+        // Don't double-intern
+        if self.tynames.contains_key(&ty) {
+            return Ok(());
+        }
+
+        let (prefix, suffix) = match self.types.realize_ty(ty) {
+            // Structural types that don't need definitions but we should
+            // intern the name of
+            Ty::Primitive(prim) => {
+                let name = match prim {
+                    PrimitiveTy::I8 => "int8_t ",
+                    PrimitiveTy::I16 => "int16_t ",
+                    PrimitiveTy::I32 => "int32_t ",
+                    PrimitiveTy::I64 => "int64_t ",
+                    PrimitiveTy::I128 => "__int128_t ",
+                    PrimitiveTy::U8 => "uint8_t ",
+                    PrimitiveTy::U16 => "uint16_t ",
+                    PrimitiveTy::U32 => "uint32_t ",
+                    PrimitiveTy::U64 => "uint64_t ",
+                    PrimitiveTy::U128 => "__uint128_t ",
+                    PrimitiveTy::F32 => "float ",
+                    PrimitiveTy::F64 => "double ",
+                    PrimitiveTy::Bool => "bool ",
+                    PrimitiveTy::Ptr => "void *",
+                    PrimitiveTy::I256 => {
+                        return Err(EmitError::Unsupported("c doesn't have i256?".to_owned()))
+                    }
+                    PrimitiveTy::U256 => {
+                        return Err(EmitError::Unsupported("c doesn't have u256?".to_owned()))
+                    }
+                    PrimitiveTy::F16 => self.f16.clone().map_err(EmitError::Unsupported)?,
+                    PrimitiveTy::F128 => self.f128.clone().map_err(EmitError::Unsupported)?,
+                };
+                (name.to_owned(), None)
+            }
+            Ty::Array(ArrayTy { elem_ty, len }) => {
+                let (pre, post) = &self.tynames[elem_ty];
+                (pre.clone(), Some(format!("[{len}]{post}")))
+            }
+            Ty::Ref(RefTy { pointee_ty }) => {
+                let (pre, post) = &self.tynames[pointee_ty];
+                // If the last type modifier was postfix (an array dimension)
+                // Then we need to introduce a set of parens to make this pointer
+                // bind more tightly
+                let was_postfix = matches!(self.types.realize_ty(*pointee_ty), Ty::Array(_));
+                if was_postfix {
+                    (format!("{pre}(*"), Some(format!("){post}")))
+                } else {
+                    (format!("{pre}*"), Some(post.clone()))
+                }
+            }
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => (format!("{} ", self.ty_ident(&struct_ty.name)), None),
+            Ty::Union(union_ty) => (format!("{} ", self.ty_ident(&union_ty.name)), None),
+            Ty::Enum(enum_ty) => (format!("{} ", self.ty_ident(&enum_ty.name)), None),
+            Ty::Tagged(tagged_ty) => (format!("{} ", self.ty_ident(&tagged_ty.name)), None),
+            Ty::Alias(alias_ty) => (format!("{} ", self.ty_ident(&alias_ty.name)), None),
+            // Puns should be evaporated
+            Ty::Pun(pun) => {
+                let real_ty = self.types.resolve_pun(pun, &self.env).unwrap();
+                let (pre, post) = self.tynames[&real_ty].clone();
+                (pre, Some(post))
+            }
+            Ty::Empty => {
+                return Err(EmitError::Unsupported(
+                    "c doesn't have empty tuples".to_owned(),
+                ))
+            }
+        };
+
+        self.tynames
+            .insert(ty, (prefix, suffix.unwrap_or_default()));
+
+        Ok(())
+    }
+
+    pub fn generate_forward_decl(&mut self, f: &mut dyn Write, ty: TyIdx) -> Result<()> {
+        // Make sure our own name is interned
+        self.intern_tyname(ty)?;
+
+        match self.types.realize_ty(ty) {
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                let ty_name = self.ty_ident(&struct_ty.name);
+                writeln!(f, "typedef struct {ty_name} {ty_name};")?;
+            }
+            Ty::Union(union_ty) => {
+                let ty_name = self.ty_ident(&union_ty.name);
+                writeln!(f, "typedef union {ty_name} {ty_name};")?;
+            }
+            Ty::Enum(enum_ty) => {
+                let ty_name = self.ty_ident(&enum_ty.name);
+                writeln!(f, "typedef enum {ty_name} {ty_name};")?;
+            }
+            Ty::Tagged(tagged_ty) => {
+                let ty_name = self.ty_ident(&tagged_ty.name);
+                writeln!(f, "typedef struct {ty_name} {ty_name};")?;
+            }
+            Ty::Alias(AliasTy { name, real, attrs }) => {
+                if !attrs.is_empty() {
+                    return Err(EmitError::Unsupported(
+                        "don't yet know how to apply attrs to aliases".to_string(),
+                    ));
+                }
+                let name = self.ty_ident(name);
+                let (pre, post) = &self.tynames[real];
+                writeln!(f, "typedef {pre}{name}{post};\n")?;
+            }
+            Ty::Pun(..) => {
+                // Puns should be evaporated by the type name interner
+            }
+            Ty::Primitive(_) | Ty::Array(_) | Ty::Ref(_) => {
+                // Builtin
+            }
+            Ty::Empty => {
+                return Err(EmitError::Unsupported(
+                    "c doesn't have empty tuples".to_owned(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generate_tydef(&mut self, f: &mut dyn Write, ty: TyIdx) -> Result<()> {
+        // Make sure our own name is interned
+        self.intern_tyname(ty)?;
+
+        match self.types.realize_ty(ty) {
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                // Emit an actual struct decl
+                let ty_name = self.ty_ident(&struct_ty.name);
+                write!(f, "typedef struct ")?;
+                self.generate_repr_attr(f, &struct_ty.attrs, "struct")?;
+                writeln!(f, "{ty_name} {{")?;
+                for (idx, field) in struct_ty.fields.iter().enumerate() {
+                    let field_name = self.field_ident(&struct_ty.fields, idx);
+                    let (pre, post) = &self.tynames[&field.ty];
+                    let (attr_pre, attr_post) = field_attrs(&field.attrs)?;
+                    writeln!(f, "{INDENT}{attr_pre}{pre}{field_name}{post}{attr_post};")?;
+                }
+                writeln!(f, "}} {ty_name};\n")?;
+            }
+            Ty::Union(union_ty) => {
+                // Emit an actual union decl
+                let ty_name = self.ty_ident(&union_ty.name);
+                write!(f, "typedef union ")?;
+                self.generate_repr_attr(f, &union_ty.attrs, "union")?;
+                writeln!(f, "{ty_name} {{")?;
+                for (idx, field) in union_ty.fields.iter().enumerate() {
+                    let field_name = self.field_ident(&union_ty.fields, idx);
+                    let (pre, post) = &self.tynames[&field.ty];
+                    let (attr_pre, attr_post) = field_attrs(&field.attrs)?;
+                    writeln!(f, "{INDENT}{attr_pre}{pre}{field_name}{post}{attr_post};")?;
+                }
+                writeln!(f, "}} {ty_name};\n")?;
+            }
+            Ty::Enum(enum_ty) => {
+                // Emit an actual enum decl
+                let ty_name = self.ty_ident(&enum_ty.name);
+                write!(f, "typedef enum ")?;
+                self.generate_repr_attr(f, &enum_ty.attrs, "enum")?;
+                writeln!(f, "{ty_name} {{")?;
+                for variant in &enum_ty.variants {
+                    let variant_name = self.variant_ident(&enum_ty.name, &variant.name);
+                    if let Some(val) = variant.val {
+                        writeln!(f, "{INDENT}{variant_name} = {val},")?;
+                    } else {
+                        writeln!(f, "{INDENT}{variant_name},")?;
+                    }
+                }
+                writeln!(f, "}} {ty_name};\n")?;
+            }
+            Ty::Tagged(_tagged_ty) => {
+                return Err(EmitError::Unsupported(
+                    "c doesn't have tagged unions impled yet".to_owned(),
+                ));
+            }
+            Ty::Alias(_) => {
+                // Just reuse the other impl
+                self.generate_forward_decl(f, ty)?;
+            }
+            Ty::Pun(..) => {
+                // Puns should be evaporated by the type name interner
+            }
+            Ty::Primitive(_) | Ty::Array(_) | Ty::Ref(_) => {
+                // Builtin
+            }
+            Ty::Empty => {
+                return Err(EmitError::Unsupported(
+                    "c doesn't have empty tuples".to_owned(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the attributes of a type, which go between `struct` (or `union`...) and its name.
+    pub fn generate_repr_attr(
+        &self,
+        f: &mut dyn Write,
+        attrs: &[Attr],
+        _ty_style: &str,
+    ) -> Result<()> {
+        let mut default_lang_repr = true;
+        let mut lang_repr = None;
+        let mut repr_attrs = vec![];
+        let mut other_attrs = vec![];
+        for attr in attrs {
+            match attr {
+                Attr::Align(_) => {
+                    return Err(EmitError::Unsupported("@align not implemented".to_owned()));
+                }
+                Attr::Packed(_) => {
+                    return Err(EmitError::Unsupported("@packed not implemented".to_owned()));
+                }
+                Attr::Passthrough(attr) => {
+                    if attr.applies_to(LangRepr::C) {
+                        other_attrs.push(&attr.attr);
+                    }
+                }
+                Attr::Repr(AttrRepr { reprs }) => {
+                    default_lang_repr = false;
+                    // Any explicit repr attributes disables default C
+                    for repr in reprs {
+                        match repr {
+                            Repr::Transparent => {
+                                return Err(EmitError::Unsupported(
+                                    "unsupport repr transparent".to_owned(),
+                                ));
+                            }
+                            Repr::Primitive(prim) => {
+                                return Err(EmitError::Unsupported(format!(
+                                    "unsupport repr {prim:?}"
+                                )));
+                            }
+                            Repr::Lang(repr) => {
+                                if let Some(old_repr) = lang_repr {
+                                    return Err(EmitError::Unsupported(format!(
+                                        "multiple lang reprs on one type ({old_repr}, {repr})"
+                                    )));
+                                }
+                                lang_repr = Some(*repr);
+                            }
+                        };
+                    }
+                }
+            }
+        }
+        if default_lang_repr && lang_repr.is_none() {
+            lang_repr = Some(self.repr);
+        }
+        if let Some(lang_repr) = lang_repr {
+            if let Some(attr) = lang_repr_decl(lang_repr)? {
+                repr_attrs.push(attr.to_owned());
+            }
+        }
+        if !repr_attrs.is_empty() {
+            return Err(EmitError::Unsupported(
+                "c doesn't implement non-trivial reprs attributes yet".to_owned(),
+            ));
+        }
+        for attr in other_attrs {
+            write!(f, "{} ", attr)?;
+        }
+        Ok(())
+    }
+
+    /// Emit `ret convention name(args)` for a function.
+    ///
+    /// `convention` is spliced in verbatim, so it should either be empty or end in a space.
+    pub fn generate_signature(
+        &self,
+        f: &mut dyn Write,
+        convention: &str,
+        func: FuncIdx,
+    ) -> Result<()> {
+        let function = self.types.realize_func(func);
+
+        let (pre, post) = if let Some(output) = function.outputs.first() {
+            let (pre, post) = &self.tynames[&output.ty];
+            (&**pre, &**post)
+        } else {
+            ("void ", "")
+        };
+        let func_name = self.func_ident(&function.name);
+        write!(f, "{pre}{convention}{func_name}{post}(")?;
+        let mut multiarg = false;
+        // Add inputs
+        for arg in &function.inputs {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            let arg_name = self.local_ident(function, &arg.name);
+            let (pre, post) = &self.tynames[&arg.ty];
+            write!(f, "{pre}{}{post}", arg_name)?;
+        }
+        write!(f, ")")?;
+        Ok(())
+    }
+}
+
+/// The parts of a field declaration that go before and after it to apply its attributes.
+fn field_attrs(attrs: &[Attr]) -> Result<(String, String)> {
+    let mut pre = String::new();
+    let mut post = String::new();
+    for attr in attrs {
+        match attr {
+            Attr::Align(AttrAligned { align }) => write!(pre, "_Alignas({}) ", align.val())?,
+            Attr::Packed(_) => write!(post, " __attribute__((packed))")?,
+            Attr::Passthrough(attr) => {
+                if attr.applies_to(LangRepr::C) {
+                    write!(pre, "{} ", attr.attr)?;
+                }
+            }
+            Attr::Repr(_) => {
+                return Err(EmitError::Unsupported("fields can't have reprs".to_owned()))
+            }
+        }
+    }
+    Ok((pre, post))
+}
+
+/// The attribute needed to get a given layout, if any.
+pub fn lang_repr_decl(repr: LangRepr) -> Result<Option<&'static str>> {
+    match repr {
+        LangRepr::Rust => Err(EmitError::Unsupported(
+            "c doesn't support repr rust".to_owned(),
+        )),
+        LangRepr::C => Ok(None),
+    }
+}
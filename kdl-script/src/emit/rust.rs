@@ -0,0 +1,673 @@
+//! Rust declarations.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+use tracing::debug;
+
+use super::{check_returns, EmitError, GlobalIdents, INDENT};
+use crate::layout::is_packed;
+use crate::parse::{Attr, AttrAligned, AttrPacked, AttrRepr, LangRepr, Repr};
+use crate::types::{AliasTy, ArrayTy, FieldTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use crate::{Definition, DefinitionGraph, PunEnv, TypedProgram};
+
+type Result<T> = std::result::Result<T, EmitError>;
+
+/// Rust's keywords (strict and reserved, up to the 2024 edition).
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "typeof", "unsized", "virtual", "yield", "try", "gen",
+];
+
+/// Keywords that can't be written as raw identifiers.
+const NOT_RAW: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Names that aren't keywords, but that emitted code relies on meaning what they normally do.
+const BUILTINS: &[&str] = &[
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "usize",
+    "f16",
+    "f32",
+    "f64",
+    "f128",
+    "bool",
+    "char",
+    "str",
+    "Option",
+    "Some",
+    "None",
+    "Copy",
+    "Clone",
+    "Debug",
+    "PartialEq",
+];
+
+/// Whether `name` can't be used as-is as a Rust identifier.
+pub fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+/// A newtype a field is wrapped in to give it the layout of a C field with attributes.
+///
+/// Rust can't align or pack a single field, so a field with `@align N` is declared
+/// as a `FieldAlignN<T>` (a `#[repr(C, align(N))]` newtype) and one with `@packed`
+/// as a `FieldPacked<T>` (a `#[repr(C, packed)]` one). Wrapping pads the field out
+/// to its alignment, so typechecking rejects aligned fields where that would change
+/// the layout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FieldWrapper {
+    Align(u64),
+    Packed,
+}
+
+impl std::fmt::Display for FieldWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldWrapper::Align(align) => write!(f, "FieldAlign{align}"),
+            FieldWrapper::Packed => write!(f, "FieldPacked"),
+        }
+    }
+}
+
+/// The newtypes a field with these attributes is wrapped in, outermost first.
+pub fn field_wrappers(attrs: &[Attr]) -> Vec<FieldWrapper> {
+    let mut wrappers = vec![];
+    let align = attrs
+        .iter()
+        .filter_map(|attr| match attr {
+            Attr::Align(AttrAligned { align }) => Some(align.val() as u64),
+            _ => None,
+        })
+        .max();
+    if let Some(align) = align {
+        wrappers.push(FieldWrapper::Align(align));
+    }
+    if attrs.iter().any(|attr| matches!(attr, Attr::Packed(_))) {
+        wrappers.push(FieldWrapper::Packed);
+    }
+    wrappers
+}
+
+/// Whether `name` is the name of a [`FieldWrapper`][].
+fn is_field_wrapper(name: &str) -> bool {
+    name == "FieldPacked"
+        || name
+            .strip_prefix("FieldAlign")
+            .is_some_and(|align| align.parse::<u64>().is_ok())
+}
+
+/// Emits Rust type definitions and function signatures.
+pub struct RustDecls {
+    pub types: Arc<TypedProgram>,
+    pub env: Arc<PunEnv>,
+    /// The repr to use for types that don't specify one.
+    pub repr: LangRepr,
+    /// Whether unstable types like `f16` can be used.
+    pub allow_unstable: bool,
+    /// The name of each interned type.
+    pub tynames: HashMap<TyIdx, String>,
+    /// The name of each interned type that contains references,
+    /// with the lifetime of those references named `'a`.
+    pub borrowed_tynames: HashMap<TyIdx, String>,
+    /// Names the surrounding code uses for itself, which [`RustDecls::ident`][] will avoid.
+    ///
+    /// Set this before emitting anything: function names are only spelled once.
+    pub reserved: &'static [&'static str],
+    globals: OnceLock<GlobalIdents>,
+    /// The [`FieldWrapper`][]s that have been defined so far.
+    field_wrappers: HashSet<FieldWrapper>,
+}
+
+impl RustDecls {
+    pub fn new(types: Arc<TypedProgram>, env: Arc<PunEnv>) -> Self {
+        Self {
+            types,
+            env,
+            repr: LangRepr::C,
+            allow_unstable: true,
+            tynames: Default::default(),
+            borrowed_tynames: Default::default(),
+            reserved: &[],
+            globals: OnceLock::new(),
+            field_wrappers: HashSet::new(),
+        }
+    }
+
+    /// Spell a kdl-script identifier for Rust.
+    ///
+    /// Keywords become raw identifiers (`r#type`), and anything that can't
+    /// (`self`, builtins, [`FieldWrapper`][]s, reserved names) gets a `_` suffix.
+    pub fn ident<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if NOT_RAW.contains(&name)
+            || BUILTINS.contains(&name)
+            || is_field_wrapper(name)
+            || self.reserved.contains(&name)
+        {
+            Cow::Owned(format!("{name}_"))
+        } else if is_keyword(name) {
+            Cow::Owned(format!("r#{name}"))
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Spell a function name for Rust (see [`GlobalIdents`][]).
+    pub fn func_ident<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let globals = self
+            .globals
+            .get_or_init(|| GlobalIdents::new(&self.types, self.reserved));
+        let ident = globals.func(name);
+        if ident == name {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(ident.to_owned())
+        }
+    }
+
+    /// Emit the definitions of every type the given functions need.
+    pub fn generate_definitions(
+        &mut self,
+        f: &mut dyn Write,
+        defs: &DefinitionGraph,
+        funcs: &[FuncIdx],
+    ) -> Result<()> {
+        for def in defs.definitions(funcs.iter().copied()) {
+            match def {
+                Definition::DeclareTy(ty) => {
+                    debug!("declare ty {}", self.types.format_ty(ty));
+                    self.intern_tyname(ty)?;
+                }
+                Definition::DefineTy(ty) => {
+                    debug!("define ty {}", self.types.format_ty(ty));
+                    self.generate_tydef(f, ty)?;
+                }
+                Definition::DefineFunc(_) => {
+                    // Signatures are emitted separately, once all the types exist
+                }
+                Definition::DeclareFunc(_) => {
+                    // nothing to do, executable kdl-script isn't real and can't hurt us
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit an `extern` block declaring the given functions.
+    pub fn generate_externs(
+        &self,
+        f: &mut dyn Write,
+        convention: &str,
+        funcs: &[FuncIdx],
+    ) -> Result<()> {
+        writeln!(f, "extern \"{convention}\" {{",)?;
+        for &func in funcs {
+            write!(f, "{INDENT}")?;
+            self.generate_signature(f, func)?;
+            writeln!(f, ";")?;
+        }
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+
+    pub fn intern_tyname(&mut self, ty: TyIdx) -> Result<()> {
+        // Don't double-intern
+        if self.tynames.contains_key(&ty) {
+            return Ok(());
+        }
+
+        let has_borrows = self.types.ty_contains_ref(ty);
+        let (tyname, borrowed_tyname) = match self.types.realize_ty(ty) {
+            // Structural types that don't need definitions but we should
+            // intern the name of
+            Ty::Primitive(prim) => {
+                let name = match prim {
+                    PrimitiveTy::I8 => "i8",
+                    PrimitiveTy::I16 => "i16",
+                    PrimitiveTy::I32 => "i32",
+                    PrimitiveTy::I64 => "i64",
+                    PrimitiveTy::I128 => "i128",
+                    PrimitiveTy::U8 => "u8",
+                    PrimitiveTy::U16 => "u16",
+                    PrimitiveTy::U32 => "u32",
+                    PrimitiveTy::U64 => "u64",
+                    PrimitiveTy::U128 => "u128",
+                    PrimitiveTy::F32 => "f32",
+                    PrimitiveTy::F64 => "f64",
+                    PrimitiveTy::Bool => "bool",
+                    PrimitiveTy::Ptr => "*mut ()",
+                    PrimitiveTy::I256 => {
+                        return Err(EmitError::Unsupported("rust doesn't have i256".to_owned()))
+                    }
+                    PrimitiveTy::U256 => {
+                        return Err(EmitError::Unsupported("rust doesn't have u256".to_owned()))
+                    }
+                    PrimitiveTy::F16 => {
+                        if self.allow_unstable {
+                            "f16"
+                        } else {
+                            return Err(EmitError::Unsupported(
+                                "f16 is an unstable rust feature, requires nightly".to_owned(),
+                            ));
+                        }
+                    }
+                    PrimitiveTy::F128 => {
+                        if self.allow_unstable {
+                            "f128"
+                        } else {
+                            return Err(EmitError::Unsupported(
+                                "f128 is an unstable rust feature, requires nightly".to_owned(),
+                            ));
+                        }
+                    }
+                };
+                (name.to_owned(), None)
+            }
+            Ty::Array(ArrayTy { elem_ty, len }) => {
+                let elem_tyname = &self.tynames[elem_ty];
+                let borrowed_tyname = self
+                    .borrowed_tynames
+                    .get(elem_ty)
+                    .map(|elem_tyname| format!("[{elem_tyname}; {len}]"));
+                (format!("[{elem_tyname}; {len}]"), borrowed_tyname)
+            }
+            Ty::Ref(RefTy { pointee_ty }) => {
+                let pointee_tyname = &self.tynames[pointee_ty];
+                let borrowed_pointee_tyname = self
+                    .borrowed_tynames
+                    .get(pointee_ty)
+                    .unwrap_or(pointee_tyname);
+                (
+                    format!("&mut {pointee_tyname}"),
+                    Some(format!("&'a mut {borrowed_pointee_tyname}")),
+                )
+            }
+            Ty::Empty => ("()".to_owned(), None),
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                let name = self.ident(&struct_ty.name);
+                let borrowed_tyname = has_borrows.then(|| format!("{name}<'a>"));
+                (name.into_owned(), borrowed_tyname)
+            }
+            Ty::Union(union_ty) => {
+                let name = self.ident(&union_ty.name);
+                let borrowed_tyname = has_borrows.then(|| format!("{name}<'a>"));
+                (name.into_owned(), borrowed_tyname)
+            }
+            Ty::Enum(enum_ty) => (self.ident(&enum_ty.name).into_owned(), None),
+            Ty::Tagged(tagged_ty) => {
+                let name = self.ident(&tagged_ty.name);
+                let borrowed_tyname = has_borrows.then(|| format!("{name}<'a>"));
+                (name.into_owned(), borrowed_tyname)
+            }
+            Ty::Alias(alias_ty) => {
+                let name = self.ident(&alias_ty.name);
+                let borrowed_tyname = has_borrows.then(|| format!("{name}<'a>"));
+                (name.into_owned(), borrowed_tyname)
+            }
+            // Puns should be evaporated
+            Ty::Pun(pun) => {
+                let real_ty = self.types.resolve_pun(pun, &self.env).unwrap();
+                (
+                    self.tynames[&real_ty].clone(),
+                    self.borrowed_tynames.get(&real_ty).cloned(),
+                )
+            }
+        };
+
+        self.tynames.insert(ty, tyname);
+        if let Some(borrowed) = borrowed_tyname {
+            self.borrowed_tynames.insert(ty, borrowed);
+        }
+
+        Ok(())
+    }
+
+    /// The name to use for a type when it's a field of another type
+    /// (which will have a lifetime if the field does).
+    fn field_tyname(&self, ty: TyIdx) -> &str {
+        self.borrowed_tynames.get(&ty).unwrap_or(&self.tynames[&ty])
+    }
+
+    /// Spell the place of `base`'s field, reaching through any [`FieldWrapper`][]s.
+    pub fn field_place(&self, base: &str, field: &FieldTy) -> String {
+        let mut place = format!("{base}.{}", self.ident(&field.ident));
+        for _ in field_wrappers(&field.attrs) {
+            place.push_str(".0");
+        }
+        place
+    }
+
+    /// Like [`RustDecls::field_place`][], but copies packed fields out,
+    /// since Rust won't take a reference to them.
+    pub fn field_value(&self, base: &str, field: &FieldTy) -> String {
+        let place = self.field_place(base, field);
+        if field_wrappers(&field.attrs).contains(&FieldWrapper::Packed) {
+            format!("{{ {place} }}")
+        } else {
+            place
+        }
+    }
+
+    /// The text to put before and after the value of a field
+    /// to wrap it in any [`FieldWrapper`][]s it needs.
+    pub fn field_val_wrapping(&self, field: &FieldTy) -> (String, String) {
+        let wrappers = field_wrappers(&field.attrs);
+        let pre = wrappers.iter().map(|w| format!("{w}(")).collect();
+        let post = ")".repeat(wrappers.len());
+        (pre, post)
+    }
+
+    /// The types to declare some fields with, defining any [`FieldWrapper`][]s they need.
+    fn generate_field_tynames(
+        &mut self,
+        f: &mut dyn Write,
+        fields: &[FieldTy],
+        packed: bool,
+    ) -> Result<Vec<String>> {
+        let mut tynames = vec![];
+        for field in fields {
+            let wrappers = field_wrappers(&field.attrs);
+            if packed && wrappers.iter().any(|w| matches!(w, FieldWrapper::Align(_))) {
+                return Err(EmitError::Unsupported(
+                    "rust can't align a field of a packed type".to_owned(),
+                ));
+            }
+            if wrappers.contains(&FieldWrapper::Packed) && self.types.ty_contains_ref(field.ty) {
+                return Err(EmitError::Unsupported(
+                    "rust can't read references out of a packed field".to_owned(),
+                ));
+            }
+            let mut tyname = self.field_tyname(field.ty).to_owned();
+            for wrapper in wrappers.into_iter().rev() {
+                self.generate_field_wrapper(f, wrapper)?;
+                tyname = format!("{wrapper}<{tyname}>");
+            }
+            tynames.push(tyname);
+        }
+        Ok(tynames)
+    }
+
+    /// Define a [`FieldWrapper`][], if it hasn't been already.
+    fn generate_field_wrapper(&mut self, f: &mut dyn Write, wrapper: FieldWrapper) -> Result<()> {
+        if !self.field_wrappers.insert(wrapper) {
+            return Ok(());
+        }
+        match wrapper {
+            FieldWrapper::Align(align) => writeln!(f, "#[repr(C, align({align}))]")?,
+            FieldWrapper::Packed => writeln!(f, "#[repr(C, packed)]")?,
+        }
+        writeln!(f, "#[derive(Copy, Clone)]")?;
+        writeln!(f, "struct {wrapper}<T>(T);\n")?;
+        Ok(())
+    }
+
+    pub fn generate_tydef(&mut self, f: &mut dyn Write, ty: TyIdx) -> Result<()> {
+        // Make sure our own name is interned
+        self.intern_tyname(ty)?;
+
+        let has_borrows = self.types.ty_contains_ref(ty);
+        let types = self.types.clone();
+        match types.realize_ty(ty) {
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                let packed = is_packed(&struct_ty.attrs);
+                let field_tynames = self.generate_field_tynames(f, &struct_ty.fields, packed)?;
+                // Emit an actual struct decl
+                self.generate_repr_attr(f, &struct_ty.attrs, "struct")?;
+                let ty_name = self.ident(&struct_ty.name);
+                if has_borrows {
+                    writeln!(f, "struct {ty_name}<'a> {{")?;
+                } else {
+                    writeln!(f, "#[derive(Copy, Clone)]")?;
+                    writeln!(f, "struct {ty_name} {{")?;
+                }
+                for (field, field_tyname) in struct_ty.fields.iter().zip(field_tynames) {
+                    let field_name = self.ident(&field.ident);
+                    generate_field_attrs(f, &field.attrs)?;
+                    writeln!(f, "{INDENT}{field_name}: {field_tyname},")?;
+                }
+                writeln!(f, "}}\n")?;
+            }
+            Ty::Union(union_ty) => {
+                let packed = is_packed(&union_ty.attrs);
+                let field_tynames = self.generate_field_tynames(f, &union_ty.fields, packed)?;
+                // Emit an actual union decl
+                self.generate_repr_attr(f, &union_ty.attrs, "union")?;
+                let ty_name = self.ident(&union_ty.name);
+                if has_borrows {
+                    writeln!(f, "union {ty_name}<'a> {{")?;
+                } else {
+                    writeln!(f, "#[derive(Copy, Clone)]")?;
+                    writeln!(f, "union {ty_name} {{")?;
+                }
+                for (field, field_tyname) in union_ty.fields.iter().zip(field_tynames) {
+                    let field_name = self.ident(&field.ident);
+                    generate_field_attrs(f, &field.attrs)?;
+                    writeln!(f, "{INDENT}{field_name}: {field_tyname},")?;
+                }
+                writeln!(f, "}}\n")?;
+            }
+            Ty::Enum(enum_ty) => {
+                // Emit an actual enum decl
+                self.generate_repr_attr(f, &enum_ty.attrs, "enum")?;
+                writeln!(f, "#[derive(Debug, Copy, Clone, PartialEq)]")?;
+                writeln!(f, "enum {} {{", self.ident(&enum_ty.name))?;
+                for variant in &enum_ty.variants {
+                    let variant_name = self.ident(&variant.name);
+                    if let Some(val) = variant.val {
+                        writeln!(f, "{INDENT}{variant_name} = {val},")?;
+                    } else {
+                        writeln!(f, "{INDENT}{variant_name},")?;
+                    }
+                }
+                writeln!(f, "}}\n")?;
+            }
+            Ty::Tagged(tagged_ty) => {
+                // Emit an actual enum decl
+                self.generate_repr_attr(f, &tagged_ty.attrs, "tagged")?;
+                let ty_name = self.ident(&tagged_ty.name);
+                if has_borrows {
+                    writeln!(f, "enum {ty_name}<'a> {{")?;
+                } else {
+                    writeln!(f, "#[derive(Copy, Clone)]")?;
+                    writeln!(f, "enum {ty_name} {{")?;
+                }
+                for variant in &tagged_ty.variants {
+                    let variant_name = self.ident(&variant.name);
+                    if let Some(fields) = &variant.fields {
+                        writeln!(f, "{INDENT}{variant_name} {{")?;
+                        for field in fields {
+                            let field_name = self.ident(&field.ident);
+                            let field_tyname = self.field_tyname(field.ty);
+                            writeln!(f, "{INDENT}{INDENT}{field_name}: {field_tyname},")?;
+                        }
+                        writeln!(f, "{INDENT}}},")?;
+                    } else {
+                        writeln!(f, "{INDENT}{variant_name},")?;
+                    }
+                }
+                writeln!(f, "}}\n")?;
+            }
+            Ty::Alias(AliasTy { name, real, attrs }) => {
+                if !attrs.is_empty() {
+                    return Err(EmitError::Unsupported(
+                        "don't yet know how to apply attrs to aliases".to_string(),
+                    ));
+                }
+
+                // Emit an actual type alias decl
+                let name = self.ident(name);
+                if let Some(real_tyname) = self.borrowed_tynames.get(real) {
+                    writeln!(f, "type {name}<'a> = {real_tyname};\n")?;
+                } else {
+                    let real_tyname = &self.tynames[real];
+                    writeln!(f, "type {name} = {real_tyname};\n")?;
+                }
+            }
+            Ty::Pun(..) => {
+                // Puns should be evaporated by the type name interner
+            }
+            Ty::Primitive(_) | Ty::Array(_) | Ty::Ref(_) | Ty::Empty => {
+                // Builtin
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generate_repr_attr(
+        &self,
+        f: &mut dyn Write,
+        attrs: &[Attr],
+        _ty_style: &str,
+    ) -> Result<()> {
+        let mut default_lang_repr = true;
+        let mut lang_repr = None;
+        let mut repr_attrs = vec![];
+        let mut other_attrs = vec![];
+        for attr in attrs {
+            match attr {
+                Attr::Align(AttrAligned { align }) => {
+                    repr_attrs.push(format!("align({})", align.val()));
+                }
+                Attr::Packed(AttrPacked {}) => {
+                    repr_attrs.push("packed".to_owned());
+                }
+                Attr::Passthrough(attr) => {
+                    if attr.applies_to(LangRepr::Rust) {
+                        other_attrs.push(attr.attr.to_string());
+                    }
+                }
+                Attr::Repr(AttrRepr { reprs }) => {
+                    default_lang_repr = false;
+                    // Any explicit repr attributes disables default C
+                    for repr in reprs {
+                        let val = match repr {
+                            Repr::Primitive(prim) => match prim {
+                                PrimitiveTy::I8 => "i8",
+                                PrimitiveTy::I16 => "i16",
+                                PrimitiveTy::I32 => "i32",
+                                PrimitiveTy::I64 => "i64",
+                                PrimitiveTy::I128 => "i128",
+                                PrimitiveTy::U8 => "u8",
+                                PrimitiveTy::U16 => "u16",
+                                PrimitiveTy::U32 => "u32",
+                                PrimitiveTy::U64 => "u64",
+                                PrimitiveTy::U128 => "u128",
+                                PrimitiveTy::I256
+                                | PrimitiveTy::U256
+                                | PrimitiveTy::F16
+                                | PrimitiveTy::F32
+                                | PrimitiveTy::F64
+                                | PrimitiveTy::F128
+                                | PrimitiveTy::Bool
+                                | PrimitiveTy::Ptr => {
+                                    return Err(EmitError::Unsupported(format!(
+                                        "unsupport repr({prim:?})"
+                                    )));
+                                }
+                            },
+                            Repr::Lang(repr) => {
+                                if let Some(old_repr) = lang_repr {
+                                    return Err(EmitError::Unsupported(format!(
+                                        "multiple lang reprs on one type ({old_repr}, {repr})"
+                                    )));
+                                }
+                                lang_repr = Some(*repr);
+                                continue;
+                            }
+                            Repr::Transparent => "transparent",
+                        };
+                        repr_attrs.push(val.to_owned());
+                    }
+                }
+            }
+        }
+        if default_lang_repr && lang_repr.is_none() {
+            lang_repr = Some(self.repr);
+        }
+        if let Some(attr) = lang_repr.and_then(lang_repr_decl) {
+            repr_attrs.push(attr.to_owned());
+        }
+        if !repr_attrs.is_empty() {
+            writeln!(f, "#[repr({})]", repr_attrs.join(", "))?;
+        }
+        for attr in other_attrs {
+            writeln!(f, "{}", attr)?;
+        }
+        Ok(())
+    }
+
+    /// Emit `fn name(args) -> output` for a function.
+    pub fn generate_signature(&self, f: &mut dyn Write, func: FuncIdx) -> Result<()> {
+        let function = self.types.realize_func(func);
+        check_returns(&self.types, function)?;
+
+        write!(f, "fn {}(", self.func_ident(&function.name))?;
+        let mut multiarg = false;
+        // Add inputs
+        for arg in &function.inputs {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            let arg_name = self.ident(&arg.name);
+            let arg_ty = &self.tynames[&arg.ty];
+            write!(f, "{}: {}", arg_name, arg_ty)?;
+        }
+        // Add normal returns
+        if let Some(arg) = function.outputs.first() {
+            let arg_ty = &self.tynames[&arg.ty];
+            write!(f, ") -> {arg_ty}")?;
+        } else {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Emit the attributes of a field.
+///
+/// Only passthroughs become attributes: Rust aligns or packs a field by wrapping
+/// its type in a newtype instead (see [`FieldWrapper`][]).
+fn generate_field_attrs(f: &mut dyn Write, attrs: &[Attr]) -> Result<()> {
+    for attr in attrs {
+        match attr {
+            Attr::Passthrough(attr) => {
+                if attr.applies_to(LangRepr::Rust) {
+                    writeln!(f, "{INDENT}{}", attr.attr)?;
+                }
+            }
+            Attr::Align(_) | Attr::Packed(_) => {
+                // The field's type is wrapped instead (see `FieldWrapper`)
+            }
+            Attr::Repr(_) => {
+                return Err(EmitError::Unsupported("fields can't have reprs".to_owned()))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The contents of `#[repr(...)]` needed to get a given layout, if any.
+pub fn lang_repr_decl(repr: LangRepr) -> Option<&'static str> {
+    match repr {
+        LangRepr::Rust => None,
+        LangRepr::C => Some("C"),
+    }
+}
@@ -1,154 +1,496 @@
-use std::{collections::HashMap, sync::Arc};
+//! An interpreter for function bodies.
+//!
+//! This runs the [`TypedBody`][]s of a [`TypedProgram`][], so every value knows
+//! its type: integers wrap at their declared width, and structs, unions, enums,
+//! tagged unions, arrays, and references are all values. Most mistakes (a missing
+//! field, a literal that doesn't fit, ...) are type errors caught when the program
+//! is compiled (see [`crate::body`][]), so the only things that can go wrong here
+//! are reading the wrong variant of a tagged union or the wrong field of a union,
+//! and infinite recursion. Those are reported as a [`KdlScriptEvalError`][]
+//! pointing at the offending expression.
+//!
+//! The syntax is still tiny:
+//!
+//! * statements: `let "x" <expr>`, `return <expr>`, `print <expr>`
+//! * calls: `"func:" "arg1" "arg2"`, including the builtins `+:`, `-:`, and `*:`
+//! * paths: `"x.field"`, `"arr.0"`, `"opt.Some.field0"` (references are looked through),
+//!   `"&x"` to take a reference, and `"MyEnum.Case"`
+//! * constructors: `"MyStruct" { field <expr>; }`, `"MyTagged.Case" { ... }`,
+//!   `"MyUnion" { field <expr>; }` (exactly one field), and `"[u32; 3]" { _ 1; _ 2; _ 3; }`
+//! * literals: ints, floats, and bools
+//!
+//! Literals take on the type they're used as (defaulting to `i64` and `f64`),
+//! and so does the other operand of an arithmetic builtin. References are
+//! dereferenced when a value of the pointee's type is wanted.
+//!
+//! `i256`, `u256`, `f16`, and `f128` aren't supported.
 
-use miette::NamedSource;
+use std::sync::Arc;
 
-use crate::{
-    parse::{Expr, FuncDecl, Literal, ParsedProgram, Stmt},
-    spanned::Spanned,
-    Result,
-};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
-enum Val {
-    Struct(HashMap<String, Val>),
-    Int(i64),
-    Float(f64),
-    Bool(bool),
+use crate::body::{resolve_alias, ArithOp, ExprKind, TypedBody, TypedExpr, TypedStmt};
+use crate::spanned::Spanned;
+use crate::types::{FieldTy, FuncIdx, PrimitiveTy, Ty, TyIdx, TypedProgram};
+use crate::Result;
+
+/// How deep calls can go before we assume the program recurses forever
+/// (there's no control flow, so any recursion is infinite).
+const MAX_CALL_DEPTH: usize = 128;
+
+/// An error that occured while evaluating a program.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+#[diagnostic(code(kdl_script::eval_error))]
+pub struct KdlScriptEvalError {
+    pub message: String,
+    #[source_code]
+    pub src: Arc<NamedSource>,
+    #[label]
+    pub span: SourceSpan,
+    #[diagnostic(help)]
+    pub help: Option<String>,
 }
 
-pub fn eval_kdl_script(_src: &Arc<NamedSource>, program: &ParsedProgram) -> Result<i64> {
-    let main = lookup_func(program, "main");
+/// A value computed by the interpreter.
+///
+/// Nominal values hold the [`TyIdx`][] of their type (with aliases resolved).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val {
+    /// An integer (or `ptr`), as its bits zero-extended to 128
+    Int(PrimitiveTy, u128),
+    /// A float (`f32`s are rounded to `f32` after every operation)
+    Float(PrimitiveTy, f64),
+    /// A bool
+    Bool(bool),
+    /// A struct, with its fields in declaration order
+    Struct(TyIdx, Vec<Val>),
+    /// A union, with the index of the field that was written
+    Union(TyIdx, usize, Box<Val>),
+    /// A c-style enum, with the index of its case
+    Enum(TyIdx, usize),
+    /// A tagged union, with the index of its case and that case's fields
+    Tagged(TyIdx, usize, Vec<Val>),
+    /// An array
+    Array(Vec<Val>),
+    /// A reference to a value
+    Ref(Box<Val>),
+    /// Empty tuple -- `()`
+    Empty,
+}
 
-    let val = eval_call(program, main, HashMap::default());
+/// Run the program's `main` function (if it has one), returning its output.
+pub fn eval_kdl_script(program: &TypedProgram) -> Result<Option<Val>> {
+    let Some(main) = program
+        .all_funcs()
+        .find(|&func| program.realize_func(func).name == *"main")
+    else {
+        return Ok(None);
+    };
+    let mut outputs = program.eval_func(main, vec![])?;
+    Ok(Some(outputs.pop().unwrap_or(Val::Empty)))
+}
 
-    match val {
-        Val::Int(val) => Ok(val),
-        Val::Float(val) => Ok(val as i64),
-        Val::Bool(val) => Ok(val as i64),
-        Val::Struct(_) => {
-            unreachable!("main returned struct!")
+impl TypedProgram {
+    /// Call a function with these inputs, returning its outputs.
+    ///
+    /// The function needs a body (unless it has no outputs).
+    pub fn eval_func(&self, func: FuncIdx, inputs: Vec<Val>) -> Result<Vec<Val>> {
+        let mut interp = Interp {
+            program: self,
+            depth: 0,
+        };
+        let decl = self.realize_func(func);
+        let span = Spanned::span(&decl.name.val);
+        if decl.inputs.len() != inputs.len() {
+            return Err(interp.error(
+                format!(
+                    "{} takes {} inputs, but was given {}",
+                    decl.name,
+                    decl.inputs.len(),
+                    inputs.len()
+                ),
+                span,
+                None,
+            ))?;
         }
+        for (arg, val) in decl.inputs.iter().zip(&inputs) {
+            interp.check_val(val, arg.ty, span)?;
+        }
+        interp.call(func, inputs, span)
     }
-}
 
-fn eval_call(program: &ParsedProgram, func: &FuncDecl, mut vars: HashMap<String, Val>) -> Val {
-    for stmt in &func.body {
-        match stmt {
-            Stmt::Let(stmt) => {
-                let temp = eval_expr(program, &stmt.expr, &vars);
-                if let Some(var) = &stmt.var {
-                    vars.insert(var.to_string(), temp);
-                }
-            }
-            Stmt::Return(stmt) => {
-                let temp = eval_expr(program, &stmt.expr, &vars);
-                return temp;
+    /// Render a value like `Point { x: 1, y: 2 }`.
+    pub fn format_val(&self, val: &Val) -> String {
+        let fields = |fields: &[FieldTy], vals: &[Val]| {
+            let fields = fields
+                .iter()
+                .zip(vals)
+                .map(|(field, val)| format!("{}: {}", field.ident, self.format_val(val)))
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                String::new()
+            } else {
+                format!(" {{ {} }}", fields.join(", "))
             }
-            Stmt::Print(stmt) => {
-                let temp = eval_expr(program, &stmt.expr, &vars);
-                print_val(&temp);
+        };
+        match val {
+            Val::Int(prim, bits) => match int_info(*prim) {
+                Some((_, true)) => sign_extend(*prim, *bits).to_string(),
+                _ => bits.to_string(),
+            },
+            Val::Float(_, val) => val.to_string(),
+            Val::Bool(val) => val.to_string(),
+            Val::Struct(ty, vals) => match self.realize_ty(*ty) {
+                Ty::Struct(struct_ty) => {
+                    format!("{}{}", struct_ty.name, fields(&struct_ty.fields, vals))
+                }
+                _ => unreachable!("Internal Compiler Error: struct value of non-struct type"),
+            },
+            Val::Union(ty, idx, val) => match self.realize_ty(*ty) {
+                Ty::Union(union_ty) => format!(
+                    "{}{}",
+                    union_ty.name,
+                    fields(&union_ty.fields[*idx..][..1], std::slice::from_ref(val))
+                ),
+                _ => unreachable!("Internal Compiler Error: union value of non-union type"),
+            },
+            Val::Enum(ty, idx) => match self.realize_ty(*ty) {
+                Ty::Enum(enum_ty) => format!("{}::{}", enum_ty.name, enum_ty.variants[*idx].name),
+                _ => unreachable!("Internal Compiler Error: enum value of non-enum type"),
+            },
+            Val::Tagged(ty, idx, vals) => match self.realize_ty(*ty) {
+                Ty::Tagged(tagged_ty) => {
+                    let variant = &tagged_ty.variants[*idx];
+                    let variant_fields = variant.fields.as_deref().unwrap_or_default();
+                    format!(
+                        "{}::{}{}",
+                        tagged_ty.name,
+                        variant.name,
+                        fields(variant_fields, vals)
+                    )
+                }
+                _ => unreachable!("Internal Compiler Error: tagged value of non-tagged type"),
+            },
+            Val::Array(vals) => {
+                let vals = vals
+                    .iter()
+                    .map(|val| self.format_val(val))
+                    .collect::<Vec<_>>();
+                format!("[{}]", vals.join(", "))
             }
+            Val::Ref(val) => format!("&{}", self.format_val(val)),
+            Val::Empty => "()".to_owned(),
         }
     }
-    unreachable!("function didn't return!");
 }
 
-fn eval_expr(program: &ParsedProgram, expr: &Spanned<Expr>, vars: &HashMap<String, Val>) -> Val {
-    match &**expr {
-        Expr::Call(expr) => {
-            let func = lookup_func(program, &expr.func);
-            assert_eq!(
-                func.inputs.len(),
-                expr.args.len(),
-                "function {} had wrong number of args",
-                &**expr.func
-            );
-            let input = func
-                .inputs
-                .iter()
-                .zip(expr.args.iter())
-                .map(|(var, expr)| {
-                    let val = eval_expr(program, expr, vars);
-                    let var = var.name.as_ref().unwrap().to_string();
-                    (var, val)
-                })
-                .collect();
-
-            match func.name.as_str() {
-                "+" => eval_add(input),
-                _ => eval_call(program, func, input),
+/// The state of an evaluation.
+struct Interp<'a> {
+    program: &'a TypedProgram,
+    depth: usize,
+}
+
+impl Interp<'_> {
+    /// Run a function's body.
+    fn call(&mut self, func: FuncIdx, inputs: Vec<Val>, span: SourceSpan) -> Result<Vec<Val>> {
+        let decl = self.program.realize_func(func);
+        let body = &decl.body;
+        if body.stmts.is_empty() && !decl.outputs.is_empty() {
+            return Err(self.error(
+                format!("{} has no body, so it can't be run", decl.name),
+                span,
+                None,
+            ))?;
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(self.error(
+                format!("{} recursed too deeply", decl.name),
+                span,
+                Some("there's no control flow, so recursion never terminates".to_owned()),
+            ))?;
+        }
+        self.depth += 1;
+
+        let mut vars = inputs.into_iter().map(Some).collect::<Vec<_>>();
+        vars.resize(body.vars.len(), None);
+        let mut outputs = vec![];
+        for stmt in &body.stmts {
+            match stmt {
+                TypedStmt::Let(var, expr) => {
+                    let val = self.eval_expr(body, expr, &vars)?;
+                    if let Some(var) = var {
+                        vars[*var] = Some(val);
+                    }
+                }
+                TypedStmt::Return(expr) => {
+                    outputs.push(self.eval_expr(body, expr, &vars)?);
+                }
+                TypedStmt::Print(expr) => {
+                    let val = self.eval_expr(body, expr, &vars)?;
+                    println!("{}", self.program.format_val(&val));
+                }
             }
         }
-        Expr::Path(expr) => {
-            let mut sub_val = vars
-                .get(&**expr.var)
-                .unwrap_or_else(|| panic!("couldn't find var {}", &**expr.var));
-            for field in &expr.path {
-                if let Val::Struct(val) = sub_val {
-                    sub_val = val
-                        .get(field.as_str())
-                        .unwrap_or_else(|| panic!("couldn't find field {}", &**field));
-                } else {
-                    panic!("tried to get .{} on primitive", &**field);
+        self.depth -= 1;
+        Ok(outputs)
+    }
+
+    /// Evaluate an expression.
+    fn eval_expr(
+        &mut self,
+        body: &TypedBody,
+        expr: &TypedExpr,
+        vars: &[Option<Val>],
+    ) -> Result<Val> {
+        let val = match &expr.kind {
+            ExprKind::Var(var) => vars[*var]
+                .clone()
+                .expect("Internal Compiler Error: variable used before it was set"),
+            ExprKind::Int(prim, bits) => Val::Int(*prim, *bits),
+            ExprKind::Float(prim, val) => Val::Float(*prim, *val),
+            ExprKind::Bool(val) => Val::Bool(*val),
+            ExprKind::Field(base, idx) | ExprKind::Index(base, idx) => {
+                match self.eval_expr(body, base, vars)? {
+                    Val::Struct(_, mut vals) | Val::Array(mut vals) => vals.swap_remove(*idx),
+                    _ => unreachable!("Internal Compiler Error: field of a non-struct value"),
+                }
+            }
+            ExprKind::UnionField(base, idx) => {
+                let Val::Union(ty, active, inner) = self.eval_expr(body, base, vars)? else {
+                    unreachable!("Internal Compiler Error: field of a non-union value");
+                };
+                if active != *idx {
+                    let Ty::Union(union_ty) = self.program.realize_ty(ty) else {
+                        unreachable!("Internal Compiler Error: union value of non-union type");
+                    };
+                    return Err(self.error(
+                        format!(
+                            "{} isn't the field of the union that was set",
+                            expr.path_text(body, self.program)
+                        ),
+                        expr.span,
+                        Some(format!("it holds {}", union_ty.fields[active].ident)),
+                    ))?;
                 }
+                *inner
+            }
+            ExprKind::VariantField(base, variant, idx) => {
+                let Val::Tagged(ty, case, mut vals) = self.eval_expr(body, base, vars)? else {
+                    unreachable!("Internal Compiler Error: variant of a non-tagged value");
+                };
+                if case != *variant {
+                    let Ty::Tagged(tagged_ty) = self.program.realize_ty(ty) else {
+                        unreachable!("Internal Compiler Error: tagged value of non-tagged type");
+                    };
+                    return Err(self.error(
+                        format!(
+                            "{} is {}::{}, not {}",
+                            base.path_text(body, self.program),
+                            tagged_ty.name,
+                            tagged_ty.variants[case].name,
+                            tagged_ty.variants[*variant].name,
+                        ),
+                        expr.span,
+                        None,
+                    ))?;
+                }
+                vals.swap_remove(*idx)
+            }
+            ExprKind::Deref(base) => match self.eval_expr(body, base, vars)? {
+                Val::Ref(pointee) => *pointee,
+                _ => unreachable!("Internal Compiler Error: deref of a non-ref value"),
+            },
+            ExprKind::Ref(base) => Val::Ref(Box::new(self.eval_expr(body, base, vars)?)),
+            ExprKind::EnumCase(idx) => Val::Enum(expr.ty, *idx),
+            ExprKind::Struct(fields) => Val::Struct(expr.ty, self.eval_exprs(body, fields, vars)?),
+            ExprKind::Union(idx, val) => {
+                Val::Union(expr.ty, *idx, Box::new(self.eval_expr(body, val, vars)?))
             }
-            sub_val.clone()
+            ExprKind::Tagged(idx, fields) => {
+                Val::Tagged(expr.ty, *idx, self.eval_exprs(body, fields, vars)?)
+            }
+            ExprKind::Array(elems) => Val::Array(self.eval_exprs(body, elems, vars)?),
+            ExprKind::Call(func, args) => {
+                let inputs = self.eval_exprs(body, args, vars)?;
+                self.call(*func, inputs, expr.span)?
+                    .pop()
+                    .unwrap_or(Val::Empty)
+            }
+            ExprKind::Arith(op, lhs, rhs) => {
+                let lhs = self.eval_expr(body, lhs, vars)?;
+                let rhs = self.eval_expr(body, rhs, vars)?;
+                arith(*op, lhs, rhs)
+            }
+        };
+        Ok(val)
+    }
+
+    fn eval_exprs(
+        &mut self,
+        body: &TypedBody,
+        exprs: &[TypedExpr],
+        vars: &[Option<Val>],
+    ) -> Result<Vec<Val>> {
+        exprs
+            .iter()
+            .map(|expr| self.eval_expr(body, expr, vars))
+            .collect()
+    }
+
+    /// Check that a value has the given type.
+    fn check_val(&self, val: &Val, ty: TyIdx, span: SourceSpan) -> Result<()> {
+        if !self.val_has_ty(val, ty) {
+            return Err(self.error(
+                format!(
+                    "expected {}, found {}",
+                    self.program.format_ty(ty),
+                    self.describe_val(val)
+                ),
+                span,
+                None,
+            ))?;
         }
-        Expr::Ctor(expr) => {
-            let fields = expr
-                .vals
-                .iter()
-                .map(|stmt| {
-                    let val = eval_expr(program, &stmt.expr, vars);
-                    let var = stmt.var.as_ref().unwrap().to_string();
-                    (var, val)
-                })
-                .collect();
-            Val::Struct(fields)
+        Ok(())
+    }
+
+    fn val_has_ty(&self, val: &Val, ty: TyIdx) -> bool {
+        let ty = resolve_alias(self.program, ty);
+        match (val, self.program.realize_ty(ty)) {
+            (Val::Int(prim, _) | Val::Float(prim, _), Ty::Primitive(ty_prim)) => prim == ty_prim,
+            (Val::Bool(_), Ty::Primitive(PrimitiveTy::Bool)) => true,
+            (
+                Val::Struct(val_ty, _)
+                | Val::Union(val_ty, ..)
+                | Val::Enum(val_ty, _)
+                | Val::Tagged(val_ty, ..),
+                _,
+            ) => *val_ty == ty,
+            (Val::Array(elems), Ty::Array(array_ty)) => {
+                elems.len() as u64 == array_ty.len
+                    && elems
+                        .iter()
+                        .all(|elem| self.val_has_ty(elem, array_ty.elem_ty))
+            }
+            (Val::Ref(pointee), Ty::Ref(ref_ty)) => self.val_has_ty(pointee, ref_ty.pointee_ty),
+            (Val::Empty, Ty::Empty) => true,
+            _ => false,
         }
-        Expr::Literal(expr) => match expr.val {
-            Literal::Float(val) => Val::Float(val),
-            Literal::Int(val) => Val::Int(val),
-            Literal::Bool(val) => Val::Bool(val),
-        },
     }
-}
 
-fn print_val(val: &Val) {
-    match val {
-        Val::Struct(vals) => {
-            println!("{{");
-            for (k, v) in vals {
-                print!("  {k}: ");
-                print_val(v);
+    /// Describe the type of a value (for errors).
+    fn describe_val(&self, val: &Val) -> String {
+        match val {
+            Val::Int(prim, _) | Val::Float(prim, _) => {
+                self.program.format_ty(self.program.prim_ty(*prim))
+            }
+            Val::Bool(_) => "bool".to_owned(),
+            Val::Struct(ty, _) | Val::Union(ty, ..) | Val::Enum(ty, _) | Val::Tagged(ty, ..) => {
+                self.program.format_ty(*ty)
             }
-            println!("}}");
+            Val::Array(elems) => match elems.first() {
+                Some(elem) => format!("[{}; {}]", self.describe_val(elem), elems.len()),
+                None => "[_; 0]".to_owned(),
+            },
+            Val::Ref(pointee) => format!("&{}", self.describe_val(pointee)),
+            Val::Empty => "()".to_owned(),
+        }
+    }
+
+    fn error(&self, message: String, span: SourceSpan, help: Option<String>) -> KdlScriptEvalError {
+        KdlScriptEvalError {
+            message,
+            src: self.program.src().clone(),
+            span,
+            help,
         }
-        Val::Int(val) => println!("{val}"),
-        Val::Float(val) => println!("{val}"),
-        Val::Bool(val) => println!("{val}"),
     }
 }
 
-fn eval_add(input: HashMap<String, Val>) -> Val {
-    let lhs = input.get("lhs").unwrap();
-    let rhs = input.get("rhs").unwrap();
+/// Apply an arithmetic builtin (wrapping for integers).
+fn arith(op: ArithOp, lhs: Val, rhs: Val) -> Val {
     match (lhs, rhs) {
-        (Val::Int(lhs), Val::Int(rhs)) => Val::Int(lhs + rhs),
-        (Val::Float(lhs), Val::Float(rhs)) => Val::Float(lhs + rhs),
-        _ => {
-            panic!("unsupported addition pair");
+        (Val::Int(prim, lhs), Val::Int(_, rhs)) => {
+            let val = match op {
+                ArithOp::Add => lhs.wrapping_add(rhs),
+                ArithOp::Sub => lhs.wrapping_sub(rhs),
+                ArithOp::Mul => lhs.wrapping_mul(rhs),
+            };
+            Val::Int(prim, truncate(prim, val))
         }
+        (Val::Float(prim, lhs), Val::Float(_, rhs)) => {
+            let val = match op {
+                ArithOp::Add => lhs + rhs,
+                ArithOp::Sub => lhs - rhs,
+                ArithOp::Mul => lhs * rhs,
+            };
+            Val::Float(prim, round_float(prim, val))
+        }
+        _ => unreachable!("Internal Compiler Error: arithmetic on non-numbers"),
+    }
+}
+
+/// The width in bits and signedness of an integer type (`ptr` is a `u64`).
+pub(crate) fn int_info(prim: PrimitiveTy) -> Option<(u32, bool)> {
+    use PrimitiveTy::*;
+    let info = match prim {
+        I8 => (8, true),
+        I16 => (16, true),
+        I32 => (32, true),
+        I64 => (64, true),
+        I128 => (128, true),
+        U8 => (8, false),
+        U16 => (16, false),
+        U32 => (32, false),
+        U64 | Ptr => (64, false),
+        U128 => (128, false),
+        I256 | U256 | F16 | F32 | F64 | F128 | Bool => return None,
+    };
+    Some(info)
+}
+
+pub(crate) fn is_float(prim: PrimitiveTy) -> bool {
+    use PrimitiveTy::*;
+    matches!(prim, F16 | F32 | F64 | F128)
+}
+
+/// Wrap an integer to the width of its type.
+pub(crate) fn truncate(prim: PrimitiveTy, bits: u128) -> u128 {
+    let (width, _) = int_info(prim).expect("Internal Compiler Error: not an integer");
+    if width == 128 {
+        bits
+    } else {
+        bits & ((1 << width) - 1)
     }
 }
 
-fn lookup_func<'a>(program: &'a ParsedProgram, func_name: &str) -> &'a FuncDecl {
-    let func = program
-        .funcs
-        .iter()
-        .find(|(name, _f)| name.as_str() == func_name);
-    if func.is_none() {
-        panic!("couldn't find {func_name} function");
+/// Interpret the bits of a signed integer.
+pub(crate) fn sign_extend(prim: PrimitiveTy, bits: u128) -> i128 {
+    let (width, _) = int_info(prim).expect("Internal Compiler Error: not an integer");
+    let shift = 128 - width;
+    ((bits << shift) as i128) >> shift
+}
+
+/// Get the bits of an integer literal, if it fits in the type.
+pub(crate) fn int_from_i64(prim: PrimitiveTy, val: i64) -> Option<u128> {
+    let (width, signed) = int_info(prim)?;
+    let val = i128::from(val);
+    let (min, max) = match (signed, width) {
+        (_, 128) if signed => (i128::MIN, i128::MAX),
+        (_, 128) => (0, i128::MAX),
+        (true, _) => (-(1 << (width - 1)), (1 << (width - 1)) - 1),
+        (false, _) => (0, (1 << width) - 1),
+    };
+    (min..=max)
+        .contains(&val)
+        .then(|| truncate(prim, val as u128))
+}
+
+/// Round a float to the precision of its type.
+pub(crate) fn round_float(prim: PrimitiveTy, val: f64) -> f64 {
+    match prim {
+        PrimitiveTy::F32 => val as f32 as f64,
+        _ => val,
     }
-    func.unwrap().1
 }
@@ -0,0 +1,507 @@
+//! Computing the memory layout of types for a particular target.
+//!
+//! A kdl-script program doesn't know what platform it will run on, so layouts
+//! are computed relative to a [`DataLayout`][] that describes the parts of the
+//! target we care about (pointer size, how aligned each primitive is).
+//! [`TypedProgram::layout_of`][] then produces the size, alignment, and field
+//! offsets of a type using C's layout rules, which is also what Rust's
+//! `repr(C)` (and `repr(C)`/`repr(int)` tagged unions) follow.
+//!
+//! Types with `@repr "rust"` have an unspecified layout, so asking for their
+//! layout is an error. [`TypedProgram::layout_of_with_repr`][] can also treat
+//! types without a `@repr` as `repr(Rust)`, the way abi-cafe's `--reprs rust` does.
+//! Puns are resolved with a [`PunEnv`][] first, since each language may pick a
+//! different type.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::parse::{Attr, AttrAligned, AttrRepr, LangRepr, Repr};
+use crate::types::{FieldTy, PrimitiveTy, Ty, TyCtx, TyIdx};
+use crate::{PunEnv, Result, TypedProgram};
+
+/// The properties of a target that determine the layout of types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataLayout {
+    /// The size (and alignment) of pointers, in bytes.
+    pub pointer_size: u64,
+    /// The alignment of primitives that aren't aligned to their size.
+    pub prim_aligns: HashMap<PrimitiveTy, u64>,
+    /// The integer type C uses for enums without an explicit repr.
+    pub c_enum: PrimitiveTy,
+}
+
+impl DataLayout {
+    /// A made-up target where every primitive is aligned to its size and
+    /// pointers are 8 bytes.
+    pub fn natural() -> Self {
+        Self {
+            pointer_size: 8,
+            prim_aligns: HashMap::new(),
+            c_enum: PrimitiveTy::I32,
+        }
+    }
+
+    /// x86_64 (System V and Windows agree on these).
+    pub fn x86_64() -> Self {
+        Self::natural()
+    }
+
+    /// aarch64 (Linux, macOS, and Windows agree on these).
+    pub fn aarch64() -> Self {
+        Self::natural()
+    }
+
+    /// 32-bit x86 on Linux, where 8-byte scalars are only 4-byte aligned.
+    pub fn i686() -> Self {
+        Self {
+            pointer_size: 4,
+            prim_aligns: [
+                (PrimitiveTy::I64, 4),
+                (PrimitiveTy::U64, 4),
+                (PrimitiveTy::F64, 4),
+            ]
+            .into_iter()
+            .collect(),
+            c_enum: PrimitiveTy::I32,
+        }
+    }
+
+    /// The target this was compiled for.
+    ///
+    /// Pointers and the primitives whose alignment varies between targets are
+    /// measured with the compiler's own `size_of`/`align_of`.
+    pub fn host() -> Self {
+        use std::mem::{align_of, size_of};
+        let aligns = [
+            (PrimitiveTy::I64, align_of::<i64>()),
+            (PrimitiveTy::U64, align_of::<u64>()),
+            (PrimitiveTy::F64, align_of::<f64>()),
+            (PrimitiveTy::I128, align_of::<i128>()),
+            (PrimitiveTy::U128, align_of::<u128>()),
+        ];
+        let mut layout = Self {
+            pointer_size: size_of::<usize>() as u64,
+            prim_aligns: HashMap::new(),
+            c_enum: PrimitiveTy::I32,
+        };
+        for (prim, align) in aligns {
+            let align = align as u64;
+            if layout.primitive(prim).1 != align {
+                layout.prim_aligns.insert(prim, align);
+            }
+        }
+        layout
+    }
+
+    /// The size and alignment of a primitive.
+    pub fn primitive(&self, prim: PrimitiveTy) -> (u64, u64) {
+        let size = match prim {
+            PrimitiveTy::I8 | PrimitiveTy::U8 | PrimitiveTy::Bool => 1,
+            PrimitiveTy::I16 | PrimitiveTy::U16 | PrimitiveTy::F16 => 2,
+            PrimitiveTy::I32 | PrimitiveTy::U32 | PrimitiveTy::F32 => 4,
+            PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::F64 => 8,
+            PrimitiveTy::I128 | PrimitiveTy::U128 | PrimitiveTy::F128 => 16,
+            PrimitiveTy::I256 | PrimitiveTy::U256 => 32,
+            PrimitiveTy::Ptr => self.pointer_size,
+        };
+        let align = self.prim_aligns.get(&prim).copied().unwrap_or(size);
+        (size, align)
+    }
+}
+
+impl FromStr for DataLayout {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "natural" => Ok(Self::natural()),
+            "host" => Ok(Self::host()),
+            "x86_64" => Ok(Self::x86_64()),
+            "aarch64" => Ok(Self::aarch64()),
+            "i686" => Ok(Self::i686()),
+            _ => Err(format!(
+                "unknown target {s} (expected natural, host, x86_64, aarch64, or i686)"
+            )),
+        }
+    }
+}
+
+/// The layout of a type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TyLayout {
+    pub size: u64,
+    pub align: u64,
+    /// The fields of a struct or union, in declaration order.
+    pub fields: Vec<FieldLayout>,
+    /// The tag of a tagged union.
+    pub tag: Option<FieldLayout>,
+    /// The variants of a tagged union, in declaration order.
+    pub variants: Vec<VariantLayout>,
+}
+
+impl TyLayout {
+    fn scalar(size: u64, align: u64) -> Self {
+        Self {
+            size,
+            align,
+            fields: vec![],
+            tag: None,
+            variants: vec![],
+        }
+    }
+}
+
+/// Where a field lives in its parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: TyIdx,
+    /// The offset of the field from the start of the outermost type, in bytes.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The layout of a variant of a tagged union.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantLayout {
+    pub name: String,
+    /// The variant's fields (not including the tag), with offsets from the
+    /// start of the tagged union.
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Lays out fields one after another, C-style.
+struct FieldPlacer {
+    packed: bool,
+    size: u64,
+    align: u64,
+    fields: Vec<FieldLayout>,
+}
+
+impl FieldPlacer {
+    fn new(packed: bool) -> Self {
+        Self {
+            packed,
+            size: 0,
+            align: 1,
+            fields: vec![],
+        }
+    }
+
+    /// Add a field after all the previous ones, returning None on overflow.
+    fn place(&mut self, name: String, ty: TyIdx, layout: &TyLayout, attrs: &[Attr]) -> Option<u64> {
+        let align = field_align(layout.align, self.packed, attrs);
+        let offset = self.size.checked_next_multiple_of(align)?;
+        self.size = offset.checked_add(layout.size)?;
+        self.align = self.align.max(align);
+        self.fields.push(FieldLayout {
+            name,
+            ty,
+            offset,
+            size: layout.size,
+        });
+        Some(offset)
+    }
+}
+
+impl TypedProgram {
+    /// Compute the layout of a type on the given target.
+    ///
+    /// This fails for types with an unspecified layout (`@repr "rust"`),
+    /// and if a pun can't be resolved with the given [`PunEnv`][].
+    pub fn layout_of(&self, ty: TyIdx, env: &PunEnv, target: &DataLayout) -> Result<TyLayout> {
+        self.layout_of_with_repr(ty, env, target, LangRepr::C)
+    }
+
+    /// Compute the layout of a type on the given target, where types without a `@repr`
+    /// get `default_repr` (like the `--reprs` of abi-cafe).
+    pub fn layout_of_with_repr(
+        &self,
+        ty: TyIdx,
+        env: &PunEnv,
+        target: &DataLayout,
+        default_repr: LangRepr,
+    ) -> Result<TyLayout> {
+        self.tcx()
+            .layout_of_with_repr(ty, Some(env), target, default_repr)
+    }
+}
+
+impl TyCtx {
+    /// The layout engine behind [`TypedProgram::layout_of`][] (and `sizeof`/`alignof`,
+    /// which have no [`PunEnv`][] and so can't look through puns).
+    pub(crate) fn layout_of_with_repr(
+        &self,
+        ty: TyIdx,
+        env: Option<&PunEnv>,
+        target: &DataLayout,
+        default_repr: LangRepr,
+    ) -> Result<TyLayout> {
+        let layout_of = |ty| self.layout_of_with_repr(ty, env, target, default_repr);
+        let check_layout_repr = |attrs| self.check_layout_repr(ty, attrs, default_repr);
+        let layout = match self.realize_ty(ty) {
+            Ty::Primitive(prim) => {
+                let (size, align) = target.primitive(*prim);
+                TyLayout::scalar(size, align)
+            }
+            Ty::Empty => TyLayout::scalar(0, 1),
+            Ty::Ref(_) => TyLayout::scalar(target.pointer_size, target.pointer_size),
+            Ty::Alias(alias_ty) => layout_of(alias_ty.real)?,
+            Ty::Pun(pun_ty) => {
+                let Some(env) = env else {
+                    let message = format!(
+                        "{} is a pun, so its layout depends on the language",
+                        self.format_ty(ty)
+                    );
+                    return Err(self.ty_error(ty, message, None))?;
+                };
+                let real = self.resolve_pun(pun_ty, env)?;
+                layout_of(real)?
+            }
+            Ty::Array(array_ty) => {
+                let elem = layout_of(array_ty.elem_ty)?;
+                let Some(size) = elem.size.checked_mul(array_ty.len) else {
+                    return Err(self.too_big(ty))?;
+                };
+                TyLayout::scalar(size, elem.align)
+            }
+            Ty::Enum(enum_ty) => {
+                check_layout_repr(&enum_ty.attrs)?;
+                let prim = repr_prim(&enum_ty.attrs).unwrap_or(target.c_enum);
+                let (size, align) = target.primitive(prim);
+                finish_aggregate(TyLayout::scalar(size, align), &enum_ty.attrs)
+            }
+            Ty::Struct(struct_ty) => {
+                check_layout_repr(&struct_ty.attrs)?;
+                if is_transparent(&struct_ty.attrs) {
+                    self.transparent_layout(&struct_ty.fields, layout_of)?
+                } else {
+                    let mut placer = FieldPlacer::new(is_packed(&struct_ty.attrs));
+                    for field in &struct_ty.fields {
+                        let layout = layout_of(field.ty)?;
+                        placer
+                            .place(field.ident.to_string(), field.ty, &layout, &field.attrs)
+                            .ok_or_else(|| self.too_big(ty))?;
+                    }
+                    let layout = TyLayout {
+                        fields: placer.fields,
+                        ..TyLayout::scalar(placer.size, placer.align)
+                    };
+                    finish_aggregate(layout, &struct_ty.attrs)
+                }
+            }
+            Ty::Union(union_ty) => {
+                check_layout_repr(&union_ty.attrs)?;
+                if is_transparent(&union_ty.attrs) {
+                    self.transparent_layout(&union_ty.fields, layout_of)?
+                } else {
+                    let packed = is_packed(&union_ty.attrs);
+                    let mut layout = TyLayout::scalar(0, 1);
+                    for field in &union_ty.fields {
+                        let field_layout = layout_of(field.ty)?;
+                        layout.size = layout.size.max(field_layout.size);
+                        layout.align =
+                            layout
+                                .align
+                                .max(field_align(field_layout.align, packed, &field.attrs));
+                        layout.fields.push(FieldLayout {
+                            name: field.ident.to_string(),
+                            ty: field.ty,
+                            offset: 0,
+                            size: field_layout.size,
+                        });
+                    }
+                    finish_aggregate(layout, &union_ty.attrs)
+                }
+            }
+            Ty::Tagged(tagged_ty) => {
+                check_layout_repr(&tagged_ty.attrs)?;
+                let prim = repr_prim(&tagged_ty.attrs);
+                let tag_prim = prim.unwrap_or(target.c_enum);
+                let tag_ty = self.prim_ty(tag_prim);
+                let tag_layout = layout_of(tag_ty)?;
+                let variant_fields = |variant: &crate::types::TaggedVariantTy| {
+                    variant.fields.clone().unwrap_or_default()
+                };
+
+                if prim.is_some() && !has_c_repr(&tagged_ty.attrs) {
+                    // `repr(int)`: a union of structs that each start with the tag
+                    let mut layout = TyLayout::scalar(tag_layout.size, tag_layout.align);
+                    for variant in &tagged_ty.variants {
+                        let mut placer = FieldPlacer::new(false);
+                        placer.place("tag".to_owned(), tag_ty, &tag_layout, &[]);
+                        for field in variant_fields(variant) {
+                            let field_layout = layout_of(field.ty)?;
+                            placer
+                                .place(field.ident.to_string(), field.ty, &field_layout, &[])
+                                .ok_or_else(|| self.too_big(ty))?;
+                        }
+                        layout.size = layout.size.max(placer.size);
+                        layout.align = layout.align.max(placer.align);
+                        layout.variants.push(VariantLayout {
+                            name: variant.name.to_string(),
+                            fields: placer.fields.split_off(1),
+                        });
+                    }
+                    layout.tag = Some(FieldLayout {
+                        name: "tag".to_owned(),
+                        ty: tag_ty,
+                        offset: 0,
+                        size: tag_layout.size,
+                    });
+                    finish_aggregate(layout, &tagged_ty.attrs)
+                } else {
+                    // `repr(C)`: a struct of the tag followed by a union of structs
+                    let mut payload = TyLayout::scalar(0, 1);
+                    for variant in &tagged_ty.variants {
+                        let mut placer = FieldPlacer::new(false);
+                        for field in variant_fields(variant) {
+                            let field_layout = layout_of(field.ty)?;
+                            placer
+                                .place(field.ident.to_string(), field.ty, &field_layout, &[])
+                                .ok_or_else(|| self.too_big(ty))?;
+                        }
+                        payload.size = payload.size.max(placer.size);
+                        payload.align = payload.align.max(placer.align);
+                        payload.variants.push(VariantLayout {
+                            name: variant.name.to_string(),
+                            fields: placer.fields,
+                        });
+                    }
+                    let payload_size = payload.size.next_multiple_of(payload.align);
+
+                    let mut placer = FieldPlacer::new(false);
+                    placer.place("tag".to_owned(), tag_ty, &tag_layout, &[]);
+                    let payload_offset = placer
+                        .place(
+                            "payload".to_owned(),
+                            ty,
+                            &TyLayout::scalar(payload_size, payload.align),
+                            &[],
+                        )
+                        .ok_or_else(|| self.too_big(ty))?;
+                    for variant in &mut payload.variants {
+                        for field in &mut variant.fields {
+                            field.offset += payload_offset;
+                        }
+                    }
+                    let layout = TyLayout {
+                        tag: placer.fields.into_iter().next(),
+                        variants: payload.variants,
+                        ..TyLayout::scalar(placer.size, placer.align)
+                    };
+                    finish_aggregate(layout, &tagged_ty.attrs)
+                }
+            }
+        };
+        Ok(layout)
+    }
+
+    /// A transparent type has the layout of its one field.
+    fn transparent_layout(
+        &self,
+        fields: &[FieldTy],
+        layout_of: impl Fn(TyIdx) -> Result<TyLayout>,
+    ) -> Result<TyLayout> {
+        let Some(field) = fields.first() else {
+            return Ok(TyLayout::scalar(0, 1));
+        };
+        let layout = layout_of(field.ty)?;
+        Ok(TyLayout {
+            fields: vec![FieldLayout {
+                name: field.ident.to_string(),
+                ty: field.ty,
+                offset: 0,
+                size: layout.size,
+            }],
+            ..TyLayout::scalar(layout.size, layout.align)
+        })
+    }
+
+    /// Reject types with a `@repr "rust"` (or no `@repr` when that's the default),
+    /// since their layout is unspecified.
+    fn check_layout_repr(&self, ty: TyIdx, attrs: &[Attr], default_repr: LangRepr) -> Result<()> {
+        let is_rust = attrs.iter().any(|attr| {
+            matches!(attr, Attr::Repr(AttrRepr { reprs }) if reprs.contains(&Repr::Lang(LangRepr::Rust)))
+        });
+        let has_repr = attrs.iter().any(|attr| matches!(attr, Attr::Repr(_)));
+        let reason = if is_rust {
+            "has @repr \"rust\""
+        } else if !has_repr && default_repr == LangRepr::Rust {
+            "has no @repr and defaults to \"rust\""
+        } else {
+            return Ok(());
+        };
+        Err(self.ty_error(
+            ty,
+            format!(
+                "{} {reason}, so its layout is unspecified",
+                self.format_ty(ty)
+            ),
+            None,
+        ))?
+    }
+
+    fn too_big(&self, ty: TyIdx) -> crate::KdlScriptTypeError {
+        self.ty_error(
+            ty,
+            format!("{} is too big to lay out", self.format_ty(ty)),
+            None,
+        )
+    }
+}
+
+/// Get the primitive from a `@repr`, if there is one.
+fn repr_prim(attrs: &[Attr]) -> Option<PrimitiveTy> {
+    attrs.iter().find_map(|attr| match attr {
+        Attr::Repr(AttrRepr { reprs }) => reprs.iter().find_map(|repr| match repr {
+            Repr::Primitive(prim) => Some(*prim),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+fn has_c_repr(attrs: &[Attr]) -> bool {
+    attrs.iter().any(|attr| {
+        matches!(attr, Attr::Repr(AttrRepr { reprs }) if reprs.contains(&Repr::Lang(LangRepr::C)))
+    })
+}
+
+fn is_transparent(attrs: &[Attr]) -> bool {
+    attrs.iter().any(
+        |attr| matches!(attr, Attr::Repr(AttrRepr { reprs }) if reprs.contains(&Repr::Transparent)),
+    )
+}
+
+/// Check if these attributes include `@packed`.
+pub(crate) fn is_packed(attrs: &[Attr]) -> bool {
+    attrs.iter().any(|attr| matches!(attr, Attr::Packed(_)))
+}
+
+/// The alignment of a field: its type's alignment unless it (or the type it's in)
+/// is packed, raised to any `@align` on the field.
+pub(crate) fn field_align(ty_align: u64, packed: bool, attrs: &[Attr]) -> u64 {
+    let mut align = if packed || is_packed(attrs) {
+        1
+    } else {
+        ty_align
+    };
+    for attr in attrs {
+        if let Attr::Align(AttrAligned { align: field_align }) = attr {
+            align = align.max(field_align.val() as u64);
+        }
+    }
+    align
+}
+
+/// Apply any `@align` to the layout of an aggregate, and pad it out to its alignment.
+fn finish_aggregate(mut layout: TyLayout, attrs: &[Attr]) -> TyLayout {
+    for attr in attrs {
+        if let Attr::Align(AttrAligned { align }) = attr {
+            layout.align = layout.align.max(align.val() as u64);
+        }
+    }
+    layout.size = layout.size.next_multiple_of(layout.align);
+    layout
+}
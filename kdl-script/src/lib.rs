@@ -6,16 +6,28 @@ use kdl::KdlDocument;
 use miette::{Diagnostic, NamedSource};
 use thiserror::Error;
 
+pub use lint::KdlScriptTypeWarning;
 pub use parse::{KdlScriptParseError, ParsedProgram, PunEnv};
 pub use types::{Definition, DefinitionGraph, KdlScriptTypeError, TypedProgram};
 
+#[cfg(feature = "eval")]
+pub mod body;
+pub mod builder;
+pub mod diff;
+pub mod emit;
 #[cfg(feature = "eval")]
 pub mod eval;
+pub mod layout;
+pub mod lint;
+#[cfg(feature = "cli")]
+pub mod lsp;
 pub mod parse;
+pub mod pretty;
 pub mod spanned;
 #[cfg(test)]
 mod tests;
 pub mod types;
+pub mod vals;
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum KdlScriptError {
@@ -33,25 +45,129 @@ pub enum KdlScriptError {
     #[error(transparent)]
     #[diagnostic(transparent)]
     Type(#[from] KdlScriptTypeError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Emit(#[from] emit::EmitError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Builder(#[from] builder::BuilderError),
+
+    #[cfg(feature = "eval")]
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Eval(#[from] eval::KdlScriptEvalError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Multiple(#[from] KdlScriptErrors),
 }
 
+/// Several errors, gathered up by [`ErrorMode::Gather`][].
+#[derive(Debug, Error, Diagnostic)]
+#[error("found {} errors", .errors.len())]
+#[diagnostic(code(kdl_script::errors))]
+pub struct KdlScriptErrors {
+    #[related]
+    pub errors: Vec<KdlScriptError>,
+}
+
+/// How the [`Compiler`][] should handle and present errors.
 pub struct ErrorHandler {
     pub error_style: ErrorStyle,
     pub error_mode: ErrorMode,
 }
 
+/// What to do when the [`Compiler`][] finds an error.
 pub enum ErrorMode {
+    /// Keep going as long as possible, collecting up all the errors.
+    ///
+    /// Each top-level item is checked independently, so one broken item
+    /// doesn't hide the problems in the others. If any errors are found
+    /// they're all returned together as [`KdlScriptErrors`][] at the end of
+    /// the phase (parsing or typechecking) that found them.
     Gather(Vec<KdlScriptError>),
+    /// Immediately return the first error.
     Scream,
 }
 
+/// How errors should be rendered by [`ErrorHandler::render`][].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ErrorStyle {
+    /// Pretty terminal output with source snippets.
     Human,
+    /// JSON with the message, code, help, and labeled spans of each error
+    /// (see [`miette::JSONReportHandler`][]).
     Json,
 }
 
+impl std::str::FromStr for ErrorStyle {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown error style {s}")),
+        }
+    }
+}
+
+impl ErrorHandler {
+    /// Report an error the compiler can recover from.
+    ///
+    /// In [`ErrorMode::Scream`][] this just hands the error back to be returned,
+    /// in [`ErrorMode::Gather`][] it gets stashed and the compiler keeps going.
+    pub fn report(&mut self, err: impl Into<KdlScriptError>) -> Result<()> {
+        match &mut self.error_mode {
+            ErrorMode::Gather(errors) => {
+                errors.push(err.into());
+                Ok(())
+            }
+            ErrorMode::Scream => Err(err.into()),
+        }
+    }
+
+    /// Return all the errors gathered so far (if any) as one error.
+    ///
+    /// A lone error is returned as-is, several get wrapped up in [`KdlScriptErrors`][].
+    pub fn take_errors(&mut self) -> Result<()> {
+        let ErrorMode::Gather(errors) = &mut self.error_mode else {
+            return Ok(());
+        };
+        let mut errors = std::mem::take(errors);
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(KdlScriptErrors { errors })?,
+        }
+    }
+
+    /// Render an error in the [`ErrorStyle`][] of this handler.
+    pub fn render(&self, err: &dyn Diagnostic) -> String {
+        let mut out = String::new();
+        let res = match self.error_style {
+            ErrorStyle::Human => miette::GraphicalReportHandler::new().render_report(&mut out, err),
+            ErrorStyle::Json => miette::JSONReportHandler::new().render_report(&mut out, err),
+        };
+        res.expect("writing to a String can't fail");
+        out
+    }
+}
+
+impl Default for ErrorHandler {
+    fn default() -> Self {
+        Self {
+            error_style: ErrorStyle::Human,
+            error_mode: ErrorMode::Scream,
+        }
+    }
+}
+
 pub struct Compiler {
-    // error_handler: ErrorHandler,
+    pub error_handler: ErrorHandler,
+    /// The target `sizeof` and `alignof` measure types for (defaults to the host).
+    pub target: layout::DataLayout,
     pub source: Option<Arc<NamedSource>>,
     pub parsed: Option<Arc<ParsedProgram>>,
     pub typed: Option<Arc<TypedProgram>>,
@@ -61,13 +177,30 @@ pub type Result<T> = std::result::Result<T, KdlScriptError>;
 
 impl Compiler {
     pub fn new() -> Self {
+        Self::with_error_handler(ErrorHandler::default())
+    }
+
+    /// Make a Compiler that handles errors in a particular way.
+    ///
+    /// ```
+    /// use kdl_script::{Compiler, ErrorHandler, ErrorMode, ErrorStyle};
+    ///
+    /// let mut compiler = Compiler::with_error_handler(ErrorHandler {
+    ///     error_style: ErrorStyle::Json,
+    ///     error_mode: ErrorMode::Gather(vec![]),
+    /// });
+    /// let program = r#"
+    ///     struct "A" { x "nope"; }
+    ///     struct "B" { y "also_nope"; }
+    /// "#;
+    /// let err = compiler.compile_string("test.kdl", program.to_owned()).unwrap_err();
+    /// let json = compiler.error_handler.render(&err);
+    /// assert!(json.contains("nope") && json.contains("also_nope"));
+    /// ```
+    pub fn with_error_handler(error_handler: ErrorHandler) -> Self {
         Self {
-            /*
-            error_handler: ErrorHandler {
-                error_mode: ErrorMode::Scream,
-                error_style: ErrorStyle::Human,
-            },
-             */
+            error_handler,
+            target: layout::DataLayout::host(),
             source: None,
             parsed: None,
             typed: None,
@@ -92,28 +225,123 @@ impl Compiler {
         input_name: &str,
         input_string: String,
     ) -> std::result::Result<Arc<TypedProgram>, KdlScriptError> {
+        let parsed = self.parse_string(input_name, input_string)?;
+        self.typecheck(parsed)
+    }
+
+    /// Parse a program without typechecking it.
+    ///
+    /// This is useful if you want to modify the program with a
+    /// [`builder::ProgramBuilder`][] before handing it to [`Compiler::compile_derived`][].
+    pub fn parse_string(
+        &mut self,
+        input_name: &str,
+        input_string: String,
+    ) -> std::result::Result<Arc<ParsedProgram>, KdlScriptError> {
         let input_string = Arc::new(input_string);
 
         let src = Arc::new(miette::NamedSource::new(input_name, input_string.clone()));
         self.source = Some(src.clone());
 
-        let kdl_doc: KdlDocument = input_string.parse::<kdl::KdlDocument>()?;
-        let parsed = Arc::new(parse::parse_kdl_script(self, src, &kdl_doc)?);
+        let parsed = if parse::compact::is_compact_path(input_name) {
+            parse::compact::parse_compact_script(self, src, &input_string)?
+        } else {
+            let kdl_doc: KdlDocument = input_string.parse::<kdl::KdlDocument>()?;
+            parse::parse_kdl_script(self, src, &kdl_doc)?
+        };
+        self.error_handler.take_errors()?;
+        let parsed = Arc::new(parsed);
         self.parsed = Some(parsed.clone());
-        let typed = Arc::new(types::typeck(self, &parsed)?);
+
+        Ok(parsed)
+    }
+
+    /// Pretty-print a program in a canonical style, preserving comments.
+    ///
+    /// The program only needs to parse, not typecheck.
+    pub fn format_string(
+        &mut self,
+        input_name: &str,
+        input_string: String,
+    ) -> std::result::Result<String, KdlScriptError> {
+        let parsed = self.parse_string(input_name, input_string.clone())?;
+        if parse::compact::is_compact_path(input_name) {
+            return Err(KdlScriptParseError {
+                message: "only the kdl syntax can be formatted".to_string(),
+                src: self.source.clone().unwrap(),
+                span: (0..0).into(),
+                help: Some("ParsedProgram::to_kdl can convert this program to kdl".to_owned()),
+            })?;
+        }
+        if let Some(span) = pretty::find_slashdash(&input_string) {
+            return Err(KdlScriptParseError {
+                message: "formatting would delete this /- comment".to_string(),
+                src: self.source.clone().unwrap(),
+                span,
+                help: Some("remove it or convert it to a // comment".to_owned()),
+            })?;
+        }
+        Ok(parsed.to_kdl_with_comments(&input_string)?)
+    }
+
+    /// Typecheck a program that was built programmatically.
+    ///
+    /// Diagnostics will point into the pretty-printed form of the program. If the
+    /// program was derived from one we parsed, use [`Compiler::compile_derived`][]
+    /// so they point into that source instead.
+    pub fn compile_parsed(
+        &mut self,
+        parsed: ParsedProgram,
+    ) -> std::result::Result<Arc<TypedProgram>, KdlScriptError> {
+        let src = Arc::new(miette::NamedSource::new(
+            "<generated>.kdl",
+            parsed.to_kdl()?,
+        ));
+        self.source = Some(src);
+
+        let parsed = Arc::new(parsed);
+        self.parsed = Some(parsed.clone());
+        self.typecheck(parsed)
+    }
+
+    /// Typecheck a program derived from the last one this compiler parsed with
+    /// [`Compiler::parse_string`][] (like one with more items added by a
+    /// [`builder::ProgramBuilder`][]).
+    ///
+    /// Diagnostics will point into the source we parsed, so anything in the program
+    /// that didn't come from it won't have a useful location.
+    pub fn compile_derived(
+        &mut self,
+        parsed: ParsedProgram,
+    ) -> std::result::Result<Arc<TypedProgram>, KdlScriptError> {
+        if self.source.is_none() {
+            return self.compile_parsed(parsed);
+        }
+
+        let parsed = Arc::new(parsed);
+        self.parsed = Some(parsed.clone());
+        self.typecheck(parsed)
+    }
+
+    fn typecheck(
+        &mut self,
+        parsed: Arc<ParsedProgram>,
+    ) -> std::result::Result<Arc<TypedProgram>, KdlScriptError> {
+        let typed = types::typeck(self, &parsed)?;
+        self.error_handler.take_errors()?;
+        let typed = Arc::new(typed);
         self.typed = Some(typed.clone());
 
         Ok(typed)
     }
 
-    pub fn eval(&mut self) -> std::result::Result<Option<i64>, KdlScriptError> {
-        if let (Some(src), Some(parsed)) = (&self.source, &self.parsed) {
-            if parsed.funcs.contains_key("main") {
-                let val = eval::eval_kdl_script(src, parsed)?;
-                return Ok(Some(val));
-            }
-        }
-        Ok(None)
+    /// Run the compiled program's `main` function, if it has one (see [`eval`][]).
+    #[cfg(feature = "eval")]
+    pub fn eval(&mut self) -> std::result::Result<Option<eval::Val>, KdlScriptError> {
+        let Some(typed) = &self.typed else {
+            return Ok(None);
+        };
+        eval::eval_kdl_script(typed)
     }
 }
 
@@ -0,0 +1,200 @@
+//! Warnings about programs that typecheck but are probably mistakes.
+//!
+//! These are computed at the end of typechecking and can be found with
+//! [`TypedProgram::warnings`][]. They never stop a program from compiling,
+//! it's up to the user of the program to decide if they matter.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::parse::PunSelector;
+use crate::spanned::Spanned;
+use crate::types::{Ty, TyIdx, TypedProgram};
+
+/// The kinds of mistakes [`KdlScriptTypeWarning`][]s point out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A type that no function uses.
+    UnusedType,
+    /// A function with no inputs or outputs.
+    EmptyFunc,
+    /// A pun with no `default` block, so some languages can't resolve it.
+    PunWithoutDefault,
+    /// A function output that contains a reference (making it an outparam).
+    RefInOutput,
+    /// A struct with no fields, whose size depends on the language.
+    EmptyStruct,
+}
+
+impl Lint {
+    /// The name of the lint, like `unused_type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedType => "unused_type",
+            Lint::EmptyFunc => "empty_func",
+            Lint::PunWithoutDefault => "pun_without_default",
+            Lint::RefInOutput => "ref_in_output",
+            Lint::EmptyStruct => "empty_struct",
+        }
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Something suspicious about a program that still typechecked.
+#[derive(Debug, Clone, Error, Diagnostic)]
+#[error("{message}")]
+#[diagnostic(code(kdl_script::type_warning), severity(Warning))]
+pub struct KdlScriptTypeWarning {
+    pub lint: Lint,
+    pub message: String,
+    #[source_code]
+    pub src: Arc<NamedSource>,
+    #[label]
+    pub span: SourceSpan,
+    #[diagnostic(help)]
+    pub help: Option<String>,
+}
+
+/// Check a program for likely mistakes, in the order they appear in the source.
+///
+/// `user_tys` are the top-level types the program declares.
+pub(crate) fn lint_program(
+    program: &TypedProgram,
+    user_tys: &[TyIdx],
+) -> Vec<KdlScriptTypeWarning> {
+    let mut warnings = vec![];
+    let mut warn = |lint, message, span, help: Option<&str>| {
+        warnings.push(KdlScriptTypeWarning {
+            lint,
+            message,
+            src: program.src().clone(),
+            span,
+            help: help.map(ToOwned::to_owned),
+        })
+    };
+
+    for &ty in user_tys {
+        match program.realize_ty(ty) {
+            Ty::Struct(struct_ty) if struct_ty.fields.is_empty() => warn(
+                Lint::EmptyStruct,
+                format!("{} has no fields", struct_ty.name),
+                Spanned::span(&struct_ty.name),
+                Some("empty structs are size 0 in rust, but are an extension (or size 1) in c"),
+            ),
+            Ty::Pun(pun_ty)
+                if !pun_ty
+                    .blocks
+                    .iter()
+                    .any(|block| always_applies(&block.selector)) =>
+            {
+                warn(
+                    Lint::PunWithoutDefault,
+                    format!("{} has no default block", pun_ty.name),
+                    Spanned::span(&pun_ty.name),
+                    Some("languages that no block selects will fail to resolve this pun"),
+                )
+            }
+            _ => {}
+        }
+    }
+
+    for func_idx in program.all_funcs() {
+        let func = program.realize_func(func_idx);
+        #[cfg(feature = "eval")]
+        let has_body = !func.body.stmts.is_empty();
+        #[cfg(not(feature = "eval"))]
+        let has_body = false;
+        if func.inputs.is_empty() && func.outputs.is_empty() && !has_body {
+            warn(
+                Lint::EmptyFunc,
+                format!("{} has no inputs or outputs", func.name),
+                Spanned::span(&func.name),
+                Some("calling it doesn't test anything"),
+            );
+        }
+        for output in &func.outputs {
+            if program.ty_contains_ref(output.ty) {
+                warn(
+                    Lint::RefInOutput,
+                    format!(
+                        "output {} of {} contains a reference",
+                        output.name, func.name
+                    ),
+                    Spanned::span(&output.name),
+                    Some("references in outputs are outparams, consider making this an input"),
+                );
+            }
+        }
+    }
+
+    // A program with no functions is just a pile of types (like a procgen test),
+    // so there's nothing for them to be used by
+    if program.all_funcs().next().is_some() {
+        let used = used_tys(program);
+        for &ty in user_tys {
+            if !used.contains(&ty) {
+                warn(
+                    Lint::UnusedType,
+                    format!("{} is never used", program.format_ty(ty)),
+                    program.span_for_ty_decl(ty),
+                    Some("only types that functions use (directly or not) get tested"),
+                );
+            }
+        }
+    }
+
+    warnings.sort_by_key(|warning| warning.span.offset());
+    warnings
+}
+
+/// Check if a pun block would apply to every language.
+fn always_applies(selector: &PunSelector) -> bool {
+    match selector {
+        PunSelector::Default => true,
+        PunSelector::Lang(_) => false,
+        PunSelector::Any(selectors) => selectors.iter().any(always_applies),
+        PunSelector::All(selectors) => selectors.iter().all(always_applies),
+    }
+}
+
+/// Get every type the program's functions (transitively) use.
+fn used_tys(program: &TypedProgram) -> HashSet<TyIdx> {
+    let mut to_visit = vec![];
+    for func_idx in program.all_funcs() {
+        let func = program.realize_func(func_idx);
+        to_visit.extend(func.inputs.iter().chain(&func.outputs).map(|arg| arg.ty));
+        #[cfg(feature = "eval")]
+        to_visit.extend(func.body.vars.iter().map(|var| var.ty));
+    }
+
+    let mut used = HashSet::new();
+    while let Some(ty) = to_visit.pop() {
+        if !used.insert(ty) {
+            continue;
+        }
+        match program.realize_ty(ty) {
+            Ty::Primitive(_) | Ty::Empty | Ty::Enum(_) => {}
+            Ty::Struct(ty) => to_visit.extend(ty.fields.iter().map(|field| field.ty)),
+            Ty::Union(ty) => to_visit.extend(ty.fields.iter().map(|field| field.ty)),
+            Ty::Tagged(ty) => to_visit.extend(
+                ty.variants
+                    .iter()
+                    .flat_map(|variant| variant.fields.as_deref().unwrap_or_default())
+                    .map(|field| field.ty),
+            ),
+            Ty::Alias(ty) => to_visit.push(ty.real),
+            Ty::Pun(ty) => to_visit.extend(ty.blocks.iter().map(|block| block.real)),
+            Ty::Array(ty) => to_visit.push(ty.elem_ty),
+            Ty::Ref(ty) => to_visit.push(ty.pointee_ty),
+        }
+    }
+    used
+}
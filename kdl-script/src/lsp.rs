@@ -0,0 +1,588 @@
+//! A language server for kdl-script programs (`kdl-script lsp`).
+//!
+//! This speaks the Language Server Protocol over stdio, and provides:
+//!
+//! * diagnostics: every parse/type error in the document (see [`crate::ErrorMode::Gather`][])
+//! * hover: the resolved type of a type name (and what each language resolves a pun to),
+//!   or the signature of a function
+//! * go to definition: jump from a use of a type name to its declaration
+//!
+//! Every open document is recompiled from scratch whenever it changes. Programs are
+//! small enough that this is instant, and it keeps things simple.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, HoverRequest, Request as RequestTrait};
+use lsp_types::{
+    DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability,
+    Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams,
+    Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use miette::{Diagnostic, Severity, SourceSpan};
+
+use crate::parse::{TyDecl, Tydent, TypedVar};
+use crate::spanned::Spanned;
+use crate::types::{Ty, TyIdx};
+use crate::{Compiler, ErrorHandler, ErrorMode, ErrorStyle, ParsedProgram, PunEnv, TypedProgram};
+
+type LspResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// The languages we show pun resolutions for on hover.
+const PUN_LANGS: &[&str] = &["c", "rust"];
+
+/// Run a language server over stdin/stdout until the client shuts it down.
+pub fn run_stdio() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(capabilities())?;
+    connection.initialize(capabilities)?;
+    serve(&connection)?;
+    // The writer thread only exits once every sender is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// The capabilities of this server.
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Handle messages on an already-initialized connection until the client shuts it down.
+pub fn serve(connection: &Connection) -> LspResult<()> {
+    let mut server = Server::default();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(req);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(not) => {
+                for not in server.handle_notification(not)? {
+                    connection.sender.send(Message::Notification(not))?;
+                }
+            }
+            Message::Response(_) => {
+                // We never send requests, so there's nothing to do with these
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+    docs: HashMap<Url, Document>,
+}
+
+/// An open document, and everything we learned by compiling it.
+struct Document {
+    lines: LineIndex,
+    parsed: Option<Arc<ParsedProgram>>,
+    typed: Option<Arc<TypedProgram>>,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+}
+
+impl Server {
+    fn handle_request(&mut self, req: Request) -> Response {
+        let id = req.id.clone();
+        let result = match &*req.method {
+            HoverRequest::METHOD => req
+                .extract::<lsp_types::HoverParams>(HoverRequest::METHOD)
+                .map_err(|e| e.to_string())
+                .map(|(_, params)| {
+                    let pos = params.text_document_position_params;
+                    serde_json::to_value(self.hover(&pos.text_document.uri, pos.position))
+                }),
+            GotoDefinition::METHOD => req
+                .extract::<lsp_types::GotoDefinitionParams>(GotoDefinition::METHOD)
+                .map_err(|e| e.to_string())
+                .map(|(_, params)| {
+                    let pos = params.text_document_position_params;
+                    serde_json::to_value(self.definition(&pos.text_document.uri, pos.position))
+                }),
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request {method}"),
+                );
+            }
+        };
+        match result {
+            Ok(Ok(value)) => Response::new_ok(id, value),
+            Ok(Err(e)) => Response::new_err(id, ErrorCode::InternalError as i32, e.to_string()),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e),
+        }
+    }
+
+    /// Handle a notification, returning any notifications to send back.
+    fn handle_notification(&mut self, not: Notification) -> LspResult<Vec<Notification>> {
+        let uri = match &*not.method {
+            DidOpenTextDocument::METHOD => {
+                let params = not
+                    .extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)?;
+                let uri = params.text_document.uri;
+                self.update(&uri, params.text_document.text);
+                uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = not.extract::<lsp_types::DidChangeTextDocumentParams>(
+                    DidChangeTextDocument::METHOD,
+                )?;
+                // We asked for full syncing, so the last change is the whole document
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(vec![]);
+                };
+                let uri = params.text_document.uri;
+                self.update(&uri, change.text);
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = not.extract::<lsp_types::DidCloseTextDocumentParams>(
+                    DidCloseTextDocument::METHOD,
+                )?;
+                let uri = params.text_document.uri;
+                self.docs.remove(&uri);
+                // Clear out the diagnostics for the closed document
+                return Ok(vec![publish_diagnostics(uri, vec![])]);
+            }
+            _ => return Ok(vec![]),
+        };
+        let diagnostics = self.docs[&uri].diagnostics.clone();
+        Ok(vec![publish_diagnostics(uri, diagnostics)])
+    }
+
+    /// Recompile a document with new contents.
+    fn update(&mut self, uri: &Url, text: String) {
+        let lines = LineIndex::new(&text);
+        let mut compiler = Compiler::with_error_handler(ErrorHandler {
+            error_style: ErrorStyle::Human,
+            error_mode: ErrorMode::Gather(vec![]),
+        });
+        let name = uri
+            .to_file_path()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|()| uri.to_string());
+
+        let mut diagnostics = vec![];
+        match compiler.compile_string(&name, text) {
+            Ok(typed) => {
+                for warning in typed.warnings() {
+                    to_lsp_diagnostics(warning, &lines, &mut diagnostics);
+                }
+            }
+            Err(e) => to_lsp_diagnostics(&e, &lines, &mut diagnostics),
+        }
+        self.docs.insert(
+            uri.clone(),
+            Document {
+                lines,
+                parsed: compiler.parsed,
+                typed: compiler.typed,
+                diagnostics,
+            },
+        );
+    }
+
+    fn hover(&self, uri: &Url, pos: Position) -> Option<Hover> {
+        let doc = self.docs.get(uri)?;
+        let parsed = doc.parsed.as_ref()?;
+        let typed = doc.typed.as_ref()?;
+        let offset = doc.lines.offset(pos)?;
+        let symbol = symbol_at(parsed, offset)?;
+
+        let contents = match &symbol.target {
+            Target::Ty { name, .. } => describe_ty(typed, typed.ty_by_name(name)?),
+            Target::Func { name } => {
+                let func = typed
+                    .all_funcs()
+                    .find(|&func| typed.realize_func(func).name.as_str() == *name)?;
+                describe_func(typed, func)
+            }
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```\n{contents}\n```"),
+            }),
+            range: Some(doc.lines.range(symbol.span)),
+        })
+    }
+
+    fn definition(&self, uri: &Url, pos: Position) -> Option<GotoDefinitionResponse> {
+        let doc = self.docs.get(uri)?;
+        let parsed = doc.parsed.as_ref()?;
+        let offset = doc.lines.offset(pos)?;
+        let symbol = symbol_at(parsed, offset)?;
+
+        let span = match symbol.target {
+            Target::Ty {
+                local: Some(span), ..
+            } => span,
+            Target::Ty { name, local: None } => parsed
+                .tys
+                .keys()
+                .find(|decl_name| decl_name.as_str() == name)
+                .map(|decl_name| Spanned::span(decl_name))?,
+            Target::Func { name } => parsed
+                .funcs
+                .keys()
+                .find(|decl_name| decl_name.as_str() == name)
+                .map(|decl_name| Spanned::span(decl_name))?,
+        };
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: uri.clone(),
+            range: doc.lines.range(span),
+        }))
+    }
+}
+
+fn publish_diagnostics(uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Notification {
+    Notification::new(
+        PublishDiagnostics::METHOD.to_owned(),
+        PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        },
+    )
+}
+
+/// Convert an error (or warning) into LSP diagnostics, one for each of the errors it contains.
+fn to_lsp_diagnostics(
+    err: &dyn Diagnostic,
+    lines: &LineIndex,
+    out: &mut Vec<lsp_types::Diagnostic>,
+) {
+    if let Some(related) = err.related() {
+        let len = out.len();
+        for err in related {
+            to_lsp_diagnostics(err, lines, out);
+        }
+        if out.len() > len {
+            return;
+        }
+    }
+
+    let span = err
+        .labels()
+        .and_then(|mut labels| labels.next())
+        .map(|label| *label.inner())
+        .unwrap_or_else(|| SourceSpan::from(0..0));
+    let mut message = err.to_string();
+    if let Some(help) = err.help() {
+        write!(&mut message, "\nhelp: {help}").unwrap();
+    }
+    out.push(lsp_types::Diagnostic {
+        range: lines.range(span),
+        severity: Some(match err.severity() {
+            Some(Severity::Warning) => DiagnosticSeverity::WARNING,
+            Some(Severity::Advice) => DiagnosticSeverity::HINT,
+            Some(Severity::Error) | None => DiagnosticSeverity::ERROR,
+        }),
+        code: err
+            .code()
+            .map(|code| NumberOrString::String(code.to_string())),
+        source: Some("kdl-script".to_owned()),
+        message,
+        ..Default::default()
+    });
+}
+
+/// Something interesting in the source.
+struct Symbol<'a> {
+    span: SourceSpan,
+    target: Target<'a>,
+}
+
+/// What a [`Symbol`][] refers to.
+enum Target<'a> {
+    /// A type (either its declaration or a use of it).
+    Ty {
+        name: &'a str,
+        /// If this was used inside a pun block that declares a type with this name,
+        /// the declaration in that block.
+        local: Option<SourceSpan>,
+    },
+    /// A function's declaration.
+    Func { name: &'a str },
+}
+
+/// Find the innermost symbol at this byte offset.
+fn symbol_at(parsed: &ParsedProgram, offset: usize) -> Option<Symbol<'_>> {
+    let mut symbols = vec![];
+    for decl in parsed.tys.values() {
+        ty_decl_symbols(decl, None, &mut symbols);
+    }
+    for func in parsed.funcs.values() {
+        symbols.push(Symbol {
+            span: Spanned::span(&func.name),
+            target: Target::Func {
+                name: func.name.as_str(),
+            },
+        });
+        for var in func.inputs.iter().chain(&func.outputs) {
+            var_symbols(var, None, &mut symbols);
+        }
+    }
+
+    // Generated things (like builtins) have empty spans, so they can't be hovered.
+    // A cursor at the very end of a symbol still counts as being "on" it.
+    symbols
+        .into_iter()
+        .filter(|symbol| {
+            let start = symbol.span.offset();
+            let end = start + symbol.span.len();
+            !symbol.span.is_empty() && start <= offset && offset <= end
+        })
+        .min_by_key(|symbol| symbol.span.len())
+}
+
+fn ty_decl_symbols<'a>(
+    decl: &'a TyDecl,
+    local: Option<(&'a str, SourceSpan)>,
+    out: &mut Vec<Symbol<'a>>,
+) {
+    let name = match decl {
+        TyDecl::Struct(decl) => &decl.name,
+        TyDecl::Union(decl) => &decl.name,
+        TyDecl::Enum(decl) => &decl.name,
+        TyDecl::Tagged(decl) => &decl.name,
+        TyDecl::Alias(decl) => &decl.name,
+        TyDecl::Pun(decl) => &decl.name,
+    };
+    // Inside a pun block the decl's name is local to the block
+    let local_span = local.map(|_| Spanned::span(name));
+    out.push(Symbol {
+        span: Spanned::span(name),
+        target: Target::Ty {
+            name: name.as_str(),
+            local: local_span,
+        },
+    });
+
+    match decl {
+        TyDecl::Struct(decl) => {
+            for field in &decl.fields {
+                var_symbols(field, local, out);
+            }
+        }
+        TyDecl::Union(decl) => {
+            for field in &decl.fields {
+                var_symbols(field, local, out);
+            }
+        }
+        TyDecl::Enum(_) => {}
+        TyDecl::Tagged(decl) => {
+            for variant in &decl.variants {
+                for field in variant.fields.iter().flatten() {
+                    var_symbols(field, local, out);
+                }
+            }
+        }
+        TyDecl::Alias(decl) => tydent_symbols(&decl.alias, local, out),
+        TyDecl::Pun(decl) => {
+            for block in &decl.blocks {
+                let block_name = match &block.decl {
+                    TyDecl::Struct(decl) => &decl.name,
+                    TyDecl::Union(decl) => &decl.name,
+                    TyDecl::Enum(decl) => &decl.name,
+                    TyDecl::Tagged(decl) => &decl.name,
+                    TyDecl::Alias(decl) => &decl.name,
+                    TyDecl::Pun(decl) => &decl.name,
+                };
+                let local = Some((block_name.as_str(), Spanned::span(block_name)));
+                ty_decl_symbols(&block.decl, local, out);
+            }
+        }
+    }
+}
+
+fn var_symbols<'a>(
+    var: &'a TypedVar,
+    local: Option<(&'a str, SourceSpan)>,
+    out: &mut Vec<Symbol<'a>>,
+) {
+    tydent_symbols(&var.ty, local, out);
+}
+
+fn tydent_symbols<'a>(
+    ty: &'a Spanned<Tydent>,
+    local: Option<(&'a str, SourceSpan)>,
+    out: &mut Vec<Symbol<'a>>,
+) {
+    match &**ty {
+        Tydent::Name(name) => {
+            let local = local
+                .filter(|(local_name, _)| *local_name == name.as_str())
+                .map(|(_, span)| span);
+            out.push(Symbol {
+                span: Spanned::span(ty),
+                target: Target::Ty {
+                    name: name.as_str(),
+                    local,
+                },
+            });
+        }
+        Tydent::Array(elem_ty, _) => tydent_symbols(elem_ty, local, out),
+        Tydent::Ref(pointee_ty) => tydent_symbols(pointee_ty, local, out),
+        Tydent::Empty => {}
+    }
+}
+
+/// Describe a type for hover info, in a vaguely rust-like syntax.
+fn describe_ty(typed: &TypedProgram, ty: TyIdx) -> String {
+    let mut out = String::new();
+    let fields = |out: &mut String, fields: &[crate::types::FieldTy]| {
+        for field in fields {
+            writeln!(out, "    {}: {},", field.ident, typed.format_ty(field.ty)).unwrap();
+        }
+    };
+    match typed.realize_ty(ty) {
+        Ty::Struct(struct_ty) => {
+            writeln!(out, "struct {} {{", struct_ty.name).unwrap();
+            fields(&mut out, &struct_ty.fields);
+            write!(out, "}}").unwrap();
+        }
+        Ty::Union(union_ty) => {
+            writeln!(out, "union {} {{", union_ty.name).unwrap();
+            fields(&mut out, &union_ty.fields);
+            write!(out, "}}").unwrap();
+        }
+        Ty::Enum(enum_ty) => {
+            writeln!(out, "enum {} {{", enum_ty.name).unwrap();
+            for variant in &enum_ty.variants {
+                if let Some(val) = variant.val {
+                    writeln!(out, "    {} = {val},", variant.name).unwrap();
+                } else {
+                    writeln!(out, "    {},", variant.name).unwrap();
+                }
+            }
+            write!(out, "}}").unwrap();
+        }
+        Ty::Tagged(tagged_ty) => {
+            writeln!(out, "tagged {} {{", tagged_ty.name).unwrap();
+            for variant in &tagged_ty.variants {
+                if let Some(variant_fields) = &variant.fields {
+                    writeln!(out, "    {} {{", variant.name).unwrap();
+                    for field in variant_fields {
+                        let ty = typed.format_ty(field.ty);
+                        writeln!(out, "        {}: {ty},", field.ident).unwrap();
+                    }
+                    writeln!(out, "    }},").unwrap();
+                } else {
+                    writeln!(out, "    {},", variant.name).unwrap();
+                }
+            }
+            write!(out, "}}").unwrap();
+        }
+        Ty::Alias(alias_ty) => {
+            let real = typed.format_ty(alias_ty.real);
+            write!(out, "alias {} = {real}", alias_ty.name).unwrap();
+        }
+        Ty::Pun(pun_ty) => {
+            write!(out, "pun {}", pun_ty.name).unwrap();
+            for lang in PUN_LANGS {
+                let env = PunEnv {
+                    lang: lang.to_string(),
+                };
+                write!(out, "\n\n// {lang}\n").unwrap();
+                match typed.resolve_pun(pun_ty, &env) {
+                    Ok(real) => write!(out, "{}", describe_ty(typed, real)).unwrap(),
+                    Err(_) => write!(out, "(no matching block)").unwrap(),
+                }
+            }
+        }
+        Ty::Primitive(_) | Ty::Array(_) | Ty::Ref(_) | Ty::Empty => {
+            write!(out, "{}", typed.format_ty(ty)).unwrap();
+        }
+    }
+    out
+}
+
+/// Describe a function's signature for hover info.
+fn describe_func(typed: &TypedProgram, func: crate::types::FuncIdx) -> String {
+    let func = typed.realize_func(func);
+    let args = |args: &[crate::types::Arg]| {
+        args.iter()
+            .map(|arg| format!("{}: {}", arg.name, typed.format_ty(arg.ty)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut out = format!("fn {}({})", func.name, args(&func.inputs));
+    if !func.outputs.is_empty() {
+        write!(out, " -> ({})", args(&func.outputs)).unwrap();
+    }
+    out
+}
+
+/// Converts between byte offsets and LSP positions (line + UTF-16 column).
+struct LineIndex {
+    text: String,
+    /// The byte offset each line starts at.
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        Self {
+            text: text.to_owned(),
+            line_starts,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let character = self
+            .text
+            .get(line_start..offset)
+            .map(|prefix| prefix.encode_utf16().count())
+            .unwrap_or(0);
+        Position::new(line as u32, character as u32)
+    }
+
+    fn range(&self, span: SourceSpan) -> Range {
+        Range::new(
+            self.position(span.offset()),
+            self.position(span.offset() + span.len()),
+        )
+    }
+
+    fn offset(&self, pos: Position) -> Option<usize> {
+        let line_start = *self.line_starts.get(pos.line as usize)?;
+        let line_end = self
+            .line_starts
+            .get(pos.line as usize + 1)
+            .copied()
+            .unwrap_or(self.text.len());
+        let mut utf16_col = 0;
+        for (idx, c) in self.text[line_start..line_end].char_indices() {
+            if utf16_col >= pos.character as usize {
+                return Some(line_start + idx);
+            }
+            utf16_col += c.len_utf16();
+        }
+        Some(line_end)
+    }
+}
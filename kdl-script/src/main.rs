@@ -1,32 +1,163 @@
 use std::path::PathBuf;
 
-use clap::Parser;
-use kdl_script::Compiler;
+use clap::{Args, Parser, Subcommand};
+use kdl_script::emit::EmitLang;
+use kdl_script::layout::DataLayout;
+use kdl_script::{Compiler, ErrorHandler, ErrorMode, ErrorStyle};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// A program to run (or dump the types of)
+    pub src: Option<PathBuf>,
+    /// How to print errors (human or json)
+    #[arg(long, global = true, default_value = "human")]
+    pub error_style: ErrorStyle,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Rewrite programs in the canonical style
+    Fmt(FmtArgs),
+    /// Print the declarations a language needs to call a program's functions
+    Emit(EmitArgs),
+    /// Print the size, alignment, and field offsets of a program's types
+    Layout(LayoutArgs),
+    /// Report ABI-breaking changes between two versions of a program
+    Diff(DiffArgs),
+    /// Print the dependency graph between a program's types and functions (as DOT)
+    Graph(GraphArgs),
+    /// Run a language server over stdio
+    #[cfg(feature = "cli")]
+    Lsp,
+}
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// The programs to format
+    #[arg(required = true)]
+    pub srcs: Vec<PathBuf>,
+    /// Don't write anything, just fail if any program isn't already formatted
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LayoutArgs {
+    /// The program to lay out the types of
     pub src: PathBuf,
+    /// The target to lay types out for (natural, host, x86_64, aarch64, or i686)
+    #[arg(long, default_value = "host")]
+    pub target: DataLayout,
+    /// The language to resolve puns for (c or rust)
+    #[arg(long, default_value = "c")]
+    pub lang: EmitLang,
+    /// Only lay out the types with these names
+    ///
+    /// default: (every type the program's functions use)
+    #[arg(long, value_delimiter = ',')]
+    pub tys: Vec<String>,
 }
 
-fn main() -> std::result::Result<(), miette::Report> {
-    real_main()?;
-    Ok(())
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// The old version of the program
+    pub old: PathBuf,
+    /// The new version of the program
+    pub new: PathBuf,
+    /// The target to compare type layouts on (natural, host, x86_64, aarch64, or i686)
+    #[arg(long, default_value = "host")]
+    pub target: DataLayout,
+    /// The language to resolve puns for (c or rust)
+    #[arg(long, default_value = "c")]
+    pub lang: EmitLang,
 }
 
-fn real_main() -> std::result::Result<(), miette::Report> {
+#[derive(Args, Debug)]
+pub struct GraphArgs {
+    /// The program to graph
+    pub src: PathBuf,
+    /// The language to resolve puns for (c or rust)
+    #[arg(long, default_value = "c")]
+    pub lang: EmitLang,
+    /// Only graph the functions with these names (and the types they need)
+    ///
+    /// default: (every function and type the program declares)
+    #[arg(long, value_delimiter = ',')]
+    pub funcs: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct EmitArgs {
+    /// The program to emit declarations for
+    pub src: PathBuf,
+    /// The language to emit (c or rust)
+    #[arg(long)]
+    pub lang: EmitLang,
+    /// Only emit the functions with these names (and the types they need)
+    ///
+    /// default: (all of them)
+    #[arg(long, value_delimiter = ',')]
+    pub funcs: Vec<String>,
+}
+
+fn main() -> std::result::Result<(), miette::Report> {
     let cli = Cli::parse();
 
     tracing_subscriber::fmt()
         .with_max_level(tracing::level_filters::LevelFilter::WARN)
+        // stdout may be speaking a protocol (see `lsp`)
+        .with_writer(std::io::stderr)
         .init();
 
-    let mut compiler = Compiler::new();
-    let typed = compiler.compile_path(&cli.src)?;
+    let error_style = cli.error_style;
+    let res = real_main(cli);
+    if let (Err(e), ErrorStyle::Json) = (&res, error_style) {
+        // Tools want the JSON on stdout, not a human-readable report
+        let handler = ErrorHandler {
+            error_style,
+            error_mode: ErrorMode::Scream,
+        };
+        println!("{}", handler.render(&**e));
+        std::process::exit(1);
+    }
+    res
+}
+
+/// Make a Compiler that reports every error it can find (not just the first)
+fn new_compiler(error_style: ErrorStyle) -> Compiler {
+    Compiler::with_error_handler(ErrorHandler {
+        error_style,
+        error_mode: ErrorMode::Gather(vec![]),
+    })
+}
+
+fn real_main(cli: Cli) -> std::result::Result<(), miette::Report> {
+    let src = match cli.command {
+        Some(Command::Fmt(args)) => return fmt(args, cli.error_style),
+        Some(Command::Emit(args)) => return emit(args, cli.error_style),
+        Some(Command::Layout(args)) => return layout(args, cli.error_style),
+        Some(Command::Diff(args)) => return diff(args, cli.error_style),
+        Some(Command::Graph(args)) => return graph(args, cli.error_style),
+        #[cfg(feature = "cli")]
+        Some(Command::Lsp) => {
+            return kdl_script::lsp::run_stdio().map_err(|e| miette::miette!("{e}"));
+        }
+        None => cli.src.expect("clap should have required a src"),
+    };
+
+    let mut compiler = new_compiler(cli.error_style);
+    let typed = compiler.compile_path(&src)?;
+    for warning in typed.warnings() {
+        eprintln!("{}", compiler.error_handler.render(warning));
+    }
 
     // Try to eval, otherwise dump the type info / decls
     let result = compiler.eval()?;
     if let Some(result) = result {
-        println!("{}", result);
+        println!("{}", typed.format_val(&result));
     } else {
         println!("typed program:");
         println!("{:?}", typed);
@@ -57,6 +188,148 @@ fn real_main() -> std::result::Result<(), miette::Report> {
     Ok(())
 }
 
+fn fmt(args: FmtArgs, error_style: ErrorStyle) -> std::result::Result<(), miette::Report> {
+    let mut unformatted = 0;
+    for src in &args.srcs {
+        let input = std::fs::read_to_string(src)
+            .map_err(|e| miette::miette!("couldn't read {}: {e}", src.display()))?;
+        let mut compiler = new_compiler(error_style);
+        let output = compiler.format_string(&src.display().to_string(), input.clone())?;
+        if output == input {
+            continue;
+        }
+        if args.check {
+            println!("{} is not formatted", src.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(src, output)
+                .map_err(|e| miette::miette!("couldn't write {}: {e}", src.display()))?;
+        }
+    }
+    if unformatted > 0 {
+        return Err(miette::miette!("{unformatted} file(s) need formatting"));
+    }
+    Ok(())
+}
+
+fn emit(args: EmitArgs, error_style: ErrorStyle) -> std::result::Result<(), miette::Report> {
+    let mut compiler = new_compiler(error_style);
+    let typed = compiler.compile_path(&args.src)?;
+
+    let mut funcs = vec![];
+    for name in &args.funcs {
+        let func = typed
+            .all_funcs()
+            .find(|&func| *typed.realize_func(func).name == **name)
+            .ok_or_else(|| miette::miette!("no function named {name}"))?;
+        funcs.push(func);
+    }
+
+    let output = kdl_script::emit::emit_declarations(&typed, args.lang, &funcs)?;
+    print!("{output}");
+    Ok(())
+}
+
+fn graph(args: GraphArgs, error_style: ErrorStyle) -> std::result::Result<(), miette::Report> {
+    let mut compiler = new_compiler(error_style);
+    let typed = compiler.compile_path(&args.src)?;
+
+    let mut funcs = vec![];
+    for name in &args.funcs {
+        let func = typed
+            .all_funcs()
+            .find(|&func| *typed.realize_func(func).name == **name)
+            .ok_or_else(|| miette::miette!("no function named {name}"))?;
+        funcs.push(func);
+    }
+    let funcs = (!args.funcs.is_empty()).then_some(&funcs[..]);
+
+    let graph = typed.definition_graph(&args.lang.pun_env())?;
+    print!("{}", graph.to_dot(&typed, funcs));
+    Ok(())
+}
+
+fn layout(args: LayoutArgs, error_style: ErrorStyle) -> std::result::Result<(), miette::Report> {
+    let mut compiler = new_compiler(error_style);
+    // Array lengths using sizeof/alignof should agree with the layouts we print
+    compiler.target = args.target.clone();
+    let typed = compiler.compile_path(&args.src)?;
+    let env = args.lang.pun_env();
+
+    let tys = if args.tys.is_empty() {
+        let graph = typed.definition_graph(&env)?;
+        graph
+            .definitions(typed.all_funcs())
+            .into_iter()
+            .filter_map(|def| match def {
+                kdl_script::Definition::DefineTy(ty) if typed.realize_ty(ty).is_nominal() => {
+                    Some(ty)
+                }
+                _ => None,
+            })
+            .collect()
+    } else {
+        let mut tys = vec![];
+        for name in &args.tys {
+            let ty = typed
+                .ty_by_name(name)
+                .ok_or_else(|| miette::miette!("no type named {name}"))?;
+            tys.push(ty);
+        }
+        tys
+    };
+
+    for ty in tys {
+        let layout = typed.layout_of(ty, &env, &args.target)?;
+        println!(
+            "{}: size {}, align {}",
+            typed.format_ty(ty),
+            layout.size,
+            layout.align
+        );
+        let print_field = |indent: &str, field: &kdl_script::layout::FieldLayout| {
+            println!(
+                "{indent}{}: {} @ {} (size {})",
+                field.name,
+                typed.format_ty(field.ty),
+                field.offset,
+                field.size
+            );
+        };
+        for field in layout.fields.iter().chain(&layout.tag) {
+            print_field("    ", field);
+        }
+        for variant in &layout.variants {
+            println!("    {}", variant.name);
+            for field in &variant.fields {
+                print_field("        ", field);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff(args: DiffArgs, error_style: ErrorStyle) -> std::result::Result<(), miette::Report> {
+    let compile = |path: &PathBuf| {
+        let mut compiler = new_compiler(error_style);
+        compiler.target = args.target.clone();
+        compiler.compile_path(path)
+    };
+    let old = compile(&args.old)?;
+    let new = compile(&args.new)?;
+    let env = args.lang.pun_env();
+
+    let diff = kdl_script::diff::abi_diff(&old, &new, &env, &args.target)?;
+    for change in &diff.changes {
+        println!("{change}");
+    }
+    let breaking = diff.breaking().count();
+    if breaking > 0 {
+        return Err(miette::miette!("{breaking} breaking change(s)"));
+    }
+    Ok(())
+}
+
 /*
 fn backend_to_the_future(program: &Arc<TypedProgram>) {
 
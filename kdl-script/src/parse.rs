@@ -12,8 +12,8 @@ use kdl::{KdlDocument, KdlEntry, KdlNode};
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::character::complete::{alpha1, alphanumeric1};
-use nom::combinator::{all_consuming, cut, opt, recognize};
+use nom::character::complete::{alpha1, alphanumeric1, digit1, hex_digit1};
+use nom::combinator::{all_consuming, cut, map, map_res, opt, recognize};
 use nom::error::{context, VerboseError};
 use nom::multi::{many0, many0_count, separated_list1};
 use nom::sequence::{delimited, pair, preceded, separated_pair};
@@ -25,6 +25,8 @@ use crate::spanned::Spanned;
 use crate::types::{PrimitiveTy, PRIMITIVES};
 use crate::{Compiler, Result};
 
+pub mod compact;
+
 pub type StableMap<K, V> = linked_hash_map::LinkedHashMap<K, V>;
 
 /// A string that may refer to another item like a type of function
@@ -112,6 +114,7 @@ impl std::fmt::Display for Ident {
 /// An error that occured during parsing
 #[derive(Debug, Error, Diagnostic)]
 #[error("{message}")]
+#[diagnostic(code(kdl_script::parse_error))]
 pub struct KdlScriptParseError {
     pub message: String,
     #[source_code]
@@ -129,6 +132,8 @@ pub struct ParsedProgram {
     pub tys: StableMap<Ident, TyDecl>,
     /// The function definitions
     pub funcs: StableMap<Ident, FuncDecl>,
+    /// The named constants
+    pub consts: StableMap<Ident, ConstDecl>,
     /// Where in funcs builtins like `+` start (if at all).
     pub builtin_funcs_start: usize,
 }
@@ -153,18 +158,39 @@ pub enum TyDecl {
 /// A type "name" (which may be structural like `[u32; 4]`).
 ///
 /// It's like an ident but, for types -- a tydent!
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Tydent {
     /// A named type (the type checker will resolve this)
     Name(Ident),
-    /// A fixed length array
-    Array(Box<Spanned<Tydent>>, u64),
+    /// A fixed length array (the length is evaluated by the type checker)
+    Array(Box<Spanned<Tydent>>, IntExpr),
     /// A by-reference type
     Ref(Box<Spanned<Tydent>>),
     /// The empty tuple -- `()`
     Empty,
 }
 
+impl std::fmt::Display for Tydent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tydent::Name(name) => name.fmt(f),
+            Tydent::Array(elem_ty, len) => write!(f, "[{elem_ty}; {len}]"),
+            Tydent::Ref(pointee_ty) => write!(f, "&{pointee_ty}"),
+            Tydent::Empty => f.write_str("()"),
+        }
+    }
+}
+
+impl std::str::FromStr for Tydent {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (_, ty) = all_consuming(tydent)(s)
+            .finish()
+            .map_err(|_e| format!("couldn't parse type {s:?}"))?;
+        Ok(Spanned::into_inner(ty))
+    }
+}
+
 /// An attribute that can be hung off a function or type.
 ///
 /// Currently stubbed out, not really used. Potential uses:
@@ -246,8 +272,24 @@ impl std::str::FromStr for LangRepr {
 /// An attribute to passthrough to the target language.
 ///
 /// @ "whatever you want buddy"
+/// @c "__attribute__((ms_struct))"
+/// @rust "#[derive(Debug)]"
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct AttrPassthrough(pub Spanned<String>);
+pub struct AttrPassthrough {
+    /// Which languages get the attribute ([`PunSelector::Default`][] for all of them)
+    pub selector: PunSelector,
+    /// The attribute itself
+    pub attr: Spanned<String>,
+}
+
+impl AttrPassthrough {
+    /// Check if this attribute should be emitted for the given language.
+    pub fn applies_to(&self, lang: LangRepr) -> bool {
+        self.selector.matches(&PunEnv {
+            lang: lang.to_string(),
+        })
+    }
+}
 
 /// A struct decl.
 ///
@@ -375,11 +417,27 @@ pub struct AliasDecl {
     pub attrs: Vec<Attr>,
 }
 
+/// A named constant.
+///
+/// ```kdl
+/// const "N" 16
+/// const "BYTES" "N * sizeof(u32)"
+/// ```
+///
+/// Constants are global and may be declared in any order (but not cyclically).
+#[derive(Debug, Clone)]
+pub struct ConstDecl {
+    pub name: Ident,
+    pub val: IntExpr,
+}
+
 /// A (name, type) pair that occurs in many places like field/arg decls.
 #[derive(Debug, Clone)]
 pub struct TypedVar {
     pub name: Option<Ident>,
     pub ty: Spanned<Tydent>,
+    /// Attributes (only fields of structs and unions can have these).
+    pub attrs: Vec<Attr>,
 }
 
 /// A function declaration
@@ -389,24 +447,45 @@ pub struct FuncDecl {
     pub inputs: Vec<TypedVar>,
     pub outputs: Vec<TypedVar>,
     pub attrs: Vec<Attr>,
+    /// Explicit values for the arguments (the `vals` block)
+    pub vals: Vec<ValDecl>,
     #[cfg(feature = "eval")]
     pub body: Vec<Stmt>,
 }
 
+/// An explicit value for part of a function's arguments (an entry of a `vals` block).
+#[derive(Debug, Clone)]
+pub struct ValDecl {
+    /// The path to the value, named like the harness names values (`arg.field[1].*`)
+    pub path: Spanned<String>,
+    /// The value
+    pub val: Spanned<ValLiteral>,
+}
+
+/// The literal given for an explicit value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValLiteral {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// The name of a variant, or an integer too big (or too bit-pattern-y) for an `i64`
+    String(String),
+}
+
 /// The parser, used to hold onto some global state for things like diagnostic.
 struct Parser<'a> {
-    // comp: &'a mut Compiler,
+    comp: &'a mut Compiler,
     src: Arc<NamedSource>,
     ast: &'a KdlDocument,
 }
 
 /// Parse a KdlScript program!
 pub fn parse_kdl_script(
-    _comp: &mut Compiler,
+    comp: &mut Compiler,
     src: Arc<NamedSource>,
     ast: &KdlDocument,
 ) -> Result<ParsedProgram> {
-    let mut parser = Parser { src, ast };
+    let mut parser = Parser { comp, src, ast };
     parser.parse()
 }
 
@@ -417,7 +496,7 @@ impl Parser<'_> {
 
         let mut program = self.parse_module(self.ast)?;
         #[cfg(feature = "eval")]
-        program.add_builtin_funcs()?;
+        program.add_builtin_funcs();
 
         Ok(program)
     }
@@ -426,6 +505,7 @@ impl Parser<'_> {
     fn parse_module(&mut self, doc: &KdlDocument) -> Result<ParsedProgram> {
         let mut funcs = StableMap::new();
         let mut tys = StableMap::new();
+        let mut consts = StableMap::new();
 
         let mut cur_attrs = vec![];
         for node in doc.nodes() {
@@ -433,57 +513,20 @@ impl Parser<'_> {
 
             // If it's an attribute, gather it up to be given to a "real" item
             if name.starts_with('@') {
-                cur_attrs.push(self.attr(node)?);
+                match self.attr(node) {
+                    Ok(attr) => cur_attrs.push(attr),
+                    Err(e) => self.comp.error_handler.report(e)?,
+                }
                 continue;
             }
 
             // Ok it's a real item, grab all the attributes, they belong to it
             let attrs = std::mem::take(&mut cur_attrs);
 
-            // Now parse the various kinds of top-level items
-            match name {
-                "fn" => {
-                    let func = self.func_decl(node, attrs)?;
-                    funcs.insert(func.name.clone(), func);
-                }
-                "struct" => {
-                    let ty = self.struct_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Struct(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                "union" => {
-                    let ty = self.union_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Union(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                "enum" => {
-                    let ty = self.enum_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Enum(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                "tagged" => {
-                    let ty = self.tagged_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Tagged(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                "alias" => {
-                    let ty = self.alias_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Alias(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                "pun" => {
-                    let ty = self.pun_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Pun(ty));
-                    assert!(old.is_none(), "duplicate type def");
-                }
-                x => {
-                    return Err(KdlScriptParseError {
-                        message: format!("I don't know what a '{x}' is"),
-                        src: self.src.clone(),
-                        span: *node.name().span(),
-                        help: None,
-                    })?;
-                }
+            // Items are independent, so if we're gathering errors
+            // we can keep going and find problems in the rest of them
+            if let Err(e) = self.item(node, attrs, &mut funcs, &mut tys, &mut consts) {
+                self.comp.error_handler.report(e)?;
             }
         }
 
@@ -492,10 +535,70 @@ impl Parser<'_> {
         Ok(ParsedProgram {
             tys,
             funcs,
+            consts,
             builtin_funcs_start,
         })
     }
 
+    /// Parse a top-level item of a module.
+    fn item(
+        &mut self,
+        node: &KdlNode,
+        attrs: Vec<Attr>,
+        funcs: &mut StableMap<Ident, FuncDecl>,
+        tys: &mut StableMap<Ident, TyDecl>,
+        consts: &mut StableMap<Ident, ConstDecl>,
+    ) -> Result<()> {
+        let name = node.name().value();
+        // Now parse the various kinds of top-level items
+        match name {
+            "fn" => {
+                let func = self.func_decl(node, attrs)?;
+                funcs.insert(func.name.clone(), func);
+            }
+            "struct" | "union" | "enum" | "tagged" | "alias" | "pun" => {
+                let ty = match name {
+                    "struct" => TyDecl::Struct(self.struct_decl(node, attrs)?),
+                    "union" => TyDecl::Union(self.union_decl(node, attrs)?),
+                    "enum" => TyDecl::Enum(self.enum_decl(node, attrs)?),
+                    "tagged" => TyDecl::Tagged(self.tagged_decl(node, attrs)?),
+                    "alias" => TyDecl::Alias(self.alias_decl(node, attrs)?),
+                    _ => TyDecl::Pun(self.pun_decl(node, attrs)?),
+                };
+                if tys.contains_key(ty.name()) {
+                    return Err(KdlScriptParseError {
+                        message: format!("duplicate definition of type {}", ty.name()),
+                        src: self.src.clone(),
+                        span: Spanned::span(ty.name()),
+                        help: None,
+                    })?;
+                }
+                tys.insert(ty.name().clone(), ty);
+            }
+            "const" => {
+                let decl = self.const_decl(node, attrs)?;
+                if consts.contains_key(&decl.name) {
+                    return Err(KdlScriptParseError {
+                        message: format!("duplicate definition of const {}", decl.name),
+                        src: self.src.clone(),
+                        span: Spanned::span(&decl.name),
+                        help: None,
+                    })?;
+                }
+                consts.insert(decl.name.clone(), decl);
+            }
+            x => {
+                return Err(KdlScriptParseError {
+                    message: format!("I don't know what a '{x}' is"),
+                    src: self.src.clone(),
+                    span: *node.name().span(),
+                    help: None,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     /// Parse a `struct` node.
     fn struct_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<StructDecl> {
         trace!("struct decl");
@@ -629,6 +732,16 @@ impl Parser<'_> {
                 })?;
             }
 
+            // Don't want any consts either, those are global
+            if let Some((name, _const)) = defs.consts.iter().next() {
+                return Err(KdlScriptParseError {
+                    message: "puns can't contain const decls".to_string(),
+                    src: self.src.clone(),
+                    span: Spanned::span(name),
+                    help: Some("move this to the top level".to_owned()),
+                })?;
+            }
+
             let mut final_ty = None;
 
             // Only want one type declared (might loosen this later)
@@ -677,6 +790,43 @@ impl Parser<'_> {
         Ok(AliasDecl { name, alias, attrs })
     }
 
+    /// Parse a `const` node.
+    fn const_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<ConstDecl> {
+        trace!("const decl");
+        if let Some(attr) = attrs.first() {
+            return Err(KdlScriptParseError {
+                message: "consts can't have attributes".to_string(),
+                src: self.src.clone(),
+                span: *node.name().span(),
+                help: Some(format!("remove this attribute: {attr:?}")),
+            })?;
+        }
+        let name = self.string_at(node, "const name", 0)?;
+        let name = self.ident(name)?;
+        let Some(e) = node.entries().get(1) else {
+            let name_span = Spanned::span(&name);
+            let after_name = name_span.offset() + name_span.len();
+            return Err(KdlScriptParseError {
+                message: "Hey I need a value (integer expression) here!".to_string(),
+                src: self.src.clone(),
+                span: (after_name..after_name).into(),
+                help: None,
+            })?;
+        };
+        let val = self.int_expr(e)?;
+        if let Some(e) = node.entries().get(2) {
+            return Err(KdlScriptParseError {
+                message: "You have something extra after your const value".to_string(),
+                src: self.src.clone(),
+                span: *e.span(),
+                help: Some("remove this?".to_owned()),
+            })?;
+        }
+        self.no_children(node)?;
+
+        Ok(ConstDecl { name, val })
+    }
+
     /// Parse a `fn` node.
     fn func_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<FuncDecl> {
         trace!("fn");
@@ -684,12 +834,14 @@ impl Parser<'_> {
         let name = self.ident(name)?;
         let mut inputs = vec![];
         let mut outputs = vec![];
+        let mut vals = vec![];
         #[cfg(feature = "eval")]
         let mut body = vec![];
 
         let mut reached_body = false;
         let mut input_span = None;
         let mut output_span = None;
+        let mut vals_span = None;
         for stmt in node.children().into_iter().flat_map(|d| d.nodes()) {
             match stmt.name().value() {
                 "inputs" => {
@@ -746,6 +898,29 @@ impl Parser<'_> {
                     output_span = Some(*stmt.name().span());
                     continue;
                 }
+                "vals" => {
+                    trace!("fn vals");
+                    if reached_body {
+                        return Err(KdlScriptParseError {
+                            message: "vals must come before the body".to_string(),
+                            src: self.src.clone(),
+                            span: *stmt.name().span(),
+                            help: None,
+                        })?;
+                    }
+                    if let Some(_old_vals) = vals_span {
+                        return Err(KdlScriptParseError {
+                            message: "duplicate vals block".to_string(),
+                            src: self.src.clone(),
+                            span: *stmt.name().span(),
+                            help: None,
+                        })?;
+                    }
+                    self.no_args(stmt)?;
+                    vals = self.val_children(stmt)?;
+                    vals_span = Some(*stmt.name().span());
+                    continue;
+                }
                 x => {
                     #[cfg(feature = "eval")]
                     match x {
@@ -793,6 +968,7 @@ impl Parser<'_> {
             name,
             inputs,
             outputs,
+            vals,
             #[cfg(feature = "eval")]
             body,
             attrs,
@@ -854,15 +1030,44 @@ impl Parser<'_> {
             "@" => {
                 trace!("passthrough attr");
                 let val = self.one_string(attr, "attribute to pass through to target language")?;
-                Attr::Passthrough(AttrPassthrough(val))
+                Attr::Passthrough(AttrPassthrough {
+                    selector: PunSelector::Default,
+                    attr: val,
+                })
             }
             x => {
-                return Err(KdlScriptParseError {
-                    message: format!("I don't know what a '{x}' attribute is"),
-                    src: self.src.clone(),
-                    span: *attr.name().span(),
-                    help: None,
-                })?;
+                // `@lang "..."` is a passthrough for only that language
+                let is_passthrough = matches!(
+                    attr.entries().first().map(|e| e.value()),
+                    Some(kdl::KdlValue::RawString(_) | kdl::KdlValue::String(_))
+                );
+                let lang = x.strip_prefix('@').filter(|_| is_passthrough);
+                let Some(lang) = lang else {
+                    return Err(KdlScriptParseError {
+                        message: format!("I don't know what a '{x}' attribute is"),
+                        src: self.src.clone(),
+                        span: *attr.name().span(),
+                        help: None,
+                    })?;
+                };
+                if lang.parse::<LangRepr>().is_err() {
+                    return Err(KdlScriptParseError {
+                        message: format!("passthrough attribute for unknown language '{lang}'"),
+                        src: self.src.clone(),
+                        span: *attr.name().span(),
+                        help: Some(
+                            "the known languages are c and rust (or use @ for all of them)"
+                                .to_owned(),
+                        ),
+                    })?;
+                }
+                trace!("{lang} passthrough attr");
+                let val = self.one_string(attr, "attribute to pass through to target language")?;
+                let lang = Spanned::new(lang.to_owned(), *attr.name().span());
+                Attr::Passthrough(AttrPassthrough {
+                    selector: PunSelector::Lang(lang),
+                    attr: val,
+                })
             }
         };
         Ok(attr)
@@ -973,19 +1178,83 @@ impl Parser<'_> {
         Ok(())
     }
 
-    /// This node's children should be TypedVars
+    /// This node's children should be TypedVars (each possibly preceded by attributes)
     fn typed_var_children(&mut self, node: &KdlNode) -> Result<Vec<TypedVar>> {
-        node.children()
-            .into_iter()
-            .flat_map(|d| d.nodes())
-            .map(|var| {
-                let name = self.var_name_decl(var)?;
-                let ty_str = self.one_string(var, "type")?;
-                let ty = self.tydent(&ty_str)?;
-                self.no_children(var)?;
-                Ok(TypedVar { name, ty })
-            })
-            .collect()
+        let mut vars = vec![];
+        let mut cur_attrs = vec![];
+        let mut last_attr = None;
+        for var in node.children().into_iter().flat_map(|d| d.nodes()) {
+            if var.name().value().starts_with('@') {
+                cur_attrs.push(self.attr(var)?);
+                last_attr = Some(*var.span());
+                continue;
+            }
+            let name = self.var_name_decl(var)?;
+            let ty_str = self.one_string(var, "type")?;
+            let ty = self.tydent(&ty_str)?;
+            self.no_children(var)?;
+            let attrs = std::mem::take(&mut cur_attrs);
+            vars.push(TypedVar { name, ty, attrs });
+        }
+        if !cur_attrs.is_empty() {
+            return Err(KdlScriptParseError {
+                message: "attribute isn't followed by a field".to_owned(),
+                src: self.src.clone(),
+                span: last_attr.unwrap(),
+                help: Some("attributes apply to the field after them".to_owned()),
+            })?;
+        }
+        Ok(vars)
+    }
+
+    /// This node's children should be explicit values (`"path" <literal>`)
+    fn val_children(&mut self, node: &KdlNode) -> Result<Vec<ValDecl>> {
+        let mut vals = vec![];
+        for val in node.children().into_iter().flat_map(|d| d.nodes()) {
+            let path = Spanned::new(val.name().value().to_owned(), *val.name().span());
+            let entries = val.entries();
+            let [entry] = entries else {
+                return Err(KdlScriptParseError {
+                    message: "a value should have exactly one literal".to_owned(),
+                    src: self.src.clone(),
+                    span: *val.span(),
+                    help: Some("like `\"point.x\" 1.5`".to_owned()),
+                })?;
+            };
+            if entry.name().is_some() {
+                return Err(KdlScriptParseError {
+                    message: "Named values don't belong here, only literals".to_string(),
+                    src: self.src.clone(),
+                    span: *entry.span(),
+                    help: Some("try removing the name".to_owned()),
+                })?;
+            }
+            let lit = match entry.value() {
+                kdl::KdlValue::RawString(s) | kdl::KdlValue::String(s) => {
+                    ValLiteral::String(s.clone())
+                }
+                kdl::KdlValue::Base2(int)
+                | kdl::KdlValue::Base8(int)
+                | kdl::KdlValue::Base10(int)
+                | kdl::KdlValue::Base16(int) => ValLiteral::Int(*int),
+                kdl::KdlValue::Base10Float(val) => ValLiteral::Float(*val),
+                kdl::KdlValue::Bool(val) => ValLiteral::Bool(*val),
+                kdl::KdlValue::Null => {
+                    return Err(KdlScriptParseError {
+                        message: "nulls aren't supported literals".to_string(),
+                        src: self.src.clone(),
+                        span: *entry.span(),
+                        help: None,
+                    })?;
+                }
+            };
+            self.no_children(val)?;
+            vals.push(ValDecl {
+                path,
+                val: Spanned::new(lit, *entry.span()),
+            });
+        }
+        Ok(vals)
     }
 
     /// This node's children should be enum variants
@@ -1080,7 +1349,10 @@ impl Parser<'_> {
         })
     }
 
-    /// Parse an [`IntExpr`][] (literal) from this entry.
+    /// Parse an [`IntExpr`][] from this entry.
+    ///
+    /// This can either be an integer literal or a string containing
+    /// a constant expression like `"N * sizeof(u32)"`.
     fn int_expr(&mut self, entry: &KdlEntry) -> Result<IntExpr> {
         if entry.name().is_some() {
             return Err(KdlScriptParseError {
@@ -1090,11 +1362,27 @@ impl Parser<'_> {
                 help: Some("try removing the name".to_owned()),
             })?;
         }
-        let val = match entry.value() {
+        let expr = match entry.value() {
             kdl::KdlValue::Base2(int)
             | kdl::KdlValue::Base8(int)
             | kdl::KdlValue::Base10(int)
-            | kdl::KdlValue::Base16(int) => *int,
+            | kdl::KdlValue::Base16(int) => ConstExpr::Literal(*int),
+            kdl::KdlValue::RawString(s) | kdl::KdlValue::String(s) => {
+                let input = Spanned::new(s.clone(), *entry.span());
+                let (_, mut expr) = all_consuming(context(
+                    "a constant expression",
+                    delimited(many0(unicode_space), const_expr, many0(unicode_space)),
+                ))(&input)
+                .finish()
+                .map_err(|_e| KdlScriptParseError {
+                    message: String::from("couldn't parse constant expression"),
+                    src: self.src.clone(),
+                    span: *entry.span(),
+                    help: Some("try something like \"2 * N\" or \"sizeof(u32)\"".to_owned()),
+                })?;
+                inherit_const_spans(&mut expr, &input);
+                expr
+            }
             _ => {
                 return Err(KdlScriptParseError {
                     message: String::from("must be an integer"),
@@ -1106,7 +1394,7 @@ impl Parser<'_> {
         };
         Ok(IntExpr {
             span: *entry.span(),
-            val,
+            expr,
         })
     }
 }
@@ -1117,8 +1405,10 @@ fn inherit_spans(tydent: &mut Spanned<Tydent>, input: &Spanned<String>) {
         Tydent::Name(ident) => {
             Spanned::clone_span_from(&mut ident.val, input);
         }
-        Tydent::Array(elem_tydent, _) => {
+        Tydent::Array(elem_tydent, len) => {
             inherit_spans(elem_tydent, input);
+            len.span = Spanned::span(input);
+            inherit_const_spans(&mut len.expr, input);
         }
         Tydent::Ref(pointee_tydent) => {
             inherit_spans(pointee_tydent, input);
@@ -1129,6 +1419,27 @@ fn inherit_spans(tydent: &mut Spanned<Tydent>, input: &Spanned<String>) {
     }
 }
 
+fn inherit_const_spans(expr: &mut ConstExpr, input: &Spanned<String>) {
+    match expr {
+        ConstExpr::Literal(_) => {
+            // noop
+        }
+        ConstExpr::Const(ident) => {
+            Spanned::clone_span_from(&mut ident.val, input);
+        }
+        ConstExpr::Neg(inner) => {
+            inherit_const_spans(inner, input);
+        }
+        ConstExpr::Binary(_op, lhs, rhs) => {
+            inherit_const_spans(lhs, input);
+            inherit_const_spans(rhs, input);
+        }
+        ConstExpr::SizeOf(tydent) | ConstExpr::AlignOf(tydent) => {
+            inherit_spans(tydent, input);
+        }
+    }
+}
+
 // A fuckton of nom parsing for sub-syntax like Tydents.
 
 type NomResult<I, O> = IResult<I, O, VerboseError<I>>;
@@ -1138,6 +1449,14 @@ fn tydent(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     alt((tydent_ref, tydent_array, tydent_empty_tuple, tydent_named))(input)
 }
 
+/// Parse a string that should be exactly a type, like `[u32; 4]`.
+#[cfg(feature = "eval")]
+pub(crate) fn parse_tydent(input: &Spanned<String>) -> Option<Spanned<Tydent>> {
+    let (_, mut ty_ref) = all_consuming(tydent)(input).finish().ok()?;
+    inherit_spans(&mut ty_ref, input);
+    Some(ty_ref)
+}
+
 /// Matches a reference type (&T)
 fn tydent_ref(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     let (input, pointee_ty) = preceded(
@@ -1158,23 +1477,24 @@ fn tydent_array(input: &str) -> NomResult<&str, Spanned<Tydent>> {
             ),
             tag(";"),
             context(
-                "an array length (integer)",
-                delimited(many0(unicode_space), array_len, many0(unicode_space)),
+                "an array length (integer expression)",
+                delimited(many0(unicode_space), const_expr, many0(unicode_space)),
             ),
         )),
         tag("]"),
     )(input)?;
     Ok((
         input,
-        Spanned::from(Tydent::Array(Box::new(elem_ty), array_len)),
+        Spanned::from(Tydent::Array(
+            Box::new(elem_ty),
+            IntExpr {
+                span: SourceSpan::from(0..0),
+                expr: array_len,
+            },
+        )),
     ))
 }
 
-/// Matches an array length (u64)
-fn array_len(input: &str) -> NomResult<&str, u64> {
-    nom::character::complete::u64(input)
-}
-
 /// Matches the empty tuple
 fn tydent_empty_tuple(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     let (input, _tup) = tag("()")(input)?;
@@ -1215,6 +1535,140 @@ fn ident(input: &str) -> NomResult<&str, &str> {
     ))(input)
 }
 
+/// Matches a constant expression (`1 << N`, `sizeof(MyStruct) * 2`, ...)
+///
+/// Precedence follows Rust: unary `-` binds tightest, then `* / %`, `+ -`,
+/// `<< >>`, `&`, `^`, and finally `|`.
+fn const_expr(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(input, &[("|", BinOp::BitOr)], const_bitxor)
+}
+
+fn const_bitxor(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(input, &[("^", BinOp::BitXor)], const_bitand)
+}
+
+fn const_bitand(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(input, &[("&", BinOp::BitAnd)], const_shift)
+}
+
+fn const_shift(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(
+        input,
+        &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+        const_additive,
+    )
+}
+
+fn const_additive(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(input, &[("+", BinOp::Add), ("-", BinOp::Sub)], const_term)
+}
+
+fn const_term(input: &str) -> NomResult<&str, ConstExpr> {
+    const_binary(
+        input,
+        &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+        const_unary,
+    )
+}
+
+/// Matches a left-associative chain of binary operators with the given operands.
+fn const_binary<'a>(
+    input: &'a str,
+    ops: &[(&str, BinOp)],
+    operand: fn(&'a str) -> NomResult<&'a str, ConstExpr>,
+) -> NomResult<&'a str, ConstExpr> {
+    let (mut input, mut lhs) = operand(input)?;
+    'chain: loop {
+        let (rest, _) = many0(unicode_space)(input)?;
+        for &(op_str, op) in ops {
+            if let Some(rest) = rest.strip_prefix(op_str) {
+                let (rest, rhs) =
+                    context("an operand", cut(preceded(many0(unicode_space), operand)))(rest)?;
+                lhs = ConstExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+                input = rest;
+                continue 'chain;
+            }
+        }
+        return Ok((input, lhs));
+    }
+}
+
+/// Matches a negation or an atom
+fn const_unary(input: &str) -> NomResult<&str, ConstExpr> {
+    alt((
+        map(
+            preceded(
+                tag("-"),
+                context(
+                    "an operand",
+                    cut(preceded(many0(unicode_space), const_unary)),
+                ),
+            ),
+            |inner| ConstExpr::Neg(Box::new(inner)),
+        ),
+        const_atom,
+    ))(input)
+}
+
+/// Matches an integer literal, builtin, named constant, or parenthesized expression
+fn const_atom(input: &str) -> NomResult<&str, ConstExpr> {
+    alt((
+        map(const_int, ConstExpr::Literal),
+        map(const_builtin("sizeof"), |ty| {
+            ConstExpr::SizeOf(Box::new(ty))
+        }),
+        map(const_builtin("alignof"), |ty| {
+            ConstExpr::AlignOf(Box::new(ty))
+        }),
+        map(ident, |name| {
+            ConstExpr::Const(Ident {
+                val: Spanned::from(name.to_owned()),
+                was_blank: false,
+            })
+        }),
+        delimited(
+            tag("("),
+            cut(delimited(
+                many0(unicode_space),
+                const_expr,
+                many0(unicode_space),
+            )),
+            tag(")"),
+        ),
+    ))(input)
+}
+
+/// Matches a builtin that takes a type, like `sizeof(T)`
+fn const_builtin<'a>(
+    name: &'static str,
+) -> impl FnMut(&'a str) -> NomResult<&'a str, Spanned<Tydent>> {
+    preceded(
+        pair(tag(name), many0(unicode_space)),
+        delimited(
+            tag("("),
+            context(
+                "a type",
+                cut(delimited(
+                    many0(unicode_space),
+                    tydent,
+                    many0(unicode_space),
+                )),
+            ),
+            tag(")"),
+        ),
+    )
+}
+
+/// Matches an integer literal (decimal or `0x` hex)
+fn const_int(input: &str) -> NomResult<&str, i64> {
+    alt((
+        map_res(preceded(tag("0x"), hex_digit1), |digits| {
+            i64::from_str_radix(digits, 16)
+        }),
+        map_res(digit1, |digits: &str| digits.parse::<i64>()),
+    ))(input)
+}
+
 /// Matches various kinds of whitespace we allow
 fn unicode_space(input: &str) -> NomResult<&str, &str> {
     alt((
@@ -1240,7 +1694,11 @@ fn unicode_space(input: &str) -> NomResult<&str, &str> {
     ))(input)
 }
 
-/// An integer expression (literal)
+/// An integer expression, evaluated by the type checker.
+///
+/// This can be a literal (`16`) or a constant expression (`"1 << N"`).
+/// All the [`Attr`][]s in a [`crate::TypedProgram`][] have had their
+/// expressions folded to literals, so backends can just use [`IntExpr::val`][].
 ///
 /// FIXME: this should actually defer deserializing into an integer
 /// so that it can be some huge type like `u256`. Not sure who/where
@@ -1253,7 +1711,165 @@ fn unicode_space(input: &str) -> NomResult<&str, &str> {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct IntExpr {
     pub span: SourceSpan,
-    pub val: i64,
+    pub expr: ConstExpr,
+}
+
+impl IntExpr {
+    /// Get the value of an expression that has been folded by the type checker.
+    ///
+    /// Panics if the expression hasn't been folded to a literal.
+    pub fn val(&self) -> i64 {
+        match self.expr {
+            ConstExpr::Literal(val) => val,
+            _ => panic!("Internal Compiler Error: constant expression wasn't evaluated"),
+        }
+    }
+}
+
+impl std::fmt::Display for IntExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.expr.fmt(f)
+    }
+}
+
+impl std::str::FromStr for IntExpr {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (_, expr) = all_consuming(delimited(
+            many0(unicode_space),
+            const_expr,
+            many0(unicode_space),
+        ))(s)
+        .finish()
+        .map_err(|_e| format!("couldn't parse constant expression {s:?}"))?;
+        Ok(expr.into())
+    }
+}
+
+impl From<i64> for IntExpr {
+    fn from(val: i64) -> Self {
+        ConstExpr::Literal(val).into()
+    }
+}
+
+impl From<ConstExpr> for IntExpr {
+    fn from(expr: ConstExpr) -> Self {
+        Self {
+            span: SourceSpan::from(0..0),
+            expr,
+        }
+    }
+}
+
+/// The syntax tree of an [`IntExpr`][].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConstExpr {
+    /// An integer literal
+    Literal(i64),
+    /// A reference to a [`ConstDecl`][]
+    Const(Ident),
+    /// `-expr`
+    Neg(Box<ConstExpr>),
+    /// `lhs op rhs`
+    Binary(BinOp, Box<ConstExpr>, Box<ConstExpr>),
+    /// `sizeof(T)`, the size of a type in bytes
+    SizeOf(Box<Spanned<Tydent>>),
+    /// `alignof(T)`, the alignment of a type in bytes
+    AlignOf(Box<Spanned<Tydent>>),
+}
+
+/// A binary operator in a [`ConstExpr`][].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+}
+
+impl ConstExpr {
+    /// How tightly this expression binds (for deciding where parens are needed).
+    fn precedence(&self) -> u8 {
+        match self {
+            ConstExpr::Binary(op, ..) => op.precedence(),
+            ConstExpr::Neg(_) => 6,
+            // Negative literals print like a negation
+            ConstExpr::Literal(val) if *val < 0 => 6,
+            ConstExpr::Literal(_)
+            | ConstExpr::Const(_)
+            | ConstExpr::SizeOf(_)
+            | ConstExpr::AlignOf(_) => 7,
+        }
+    }
+}
+
+impl std::fmt::Display for ConstExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstExpr::Literal(val) => val.fmt(f),
+            ConstExpr::Const(name) => name.fmt(f),
+            ConstExpr::Neg(inner) => {
+                if inner.precedence() < self.precedence() {
+                    write!(f, "-({inner})")
+                } else {
+                    write!(f, "-{inner}")
+                }
+            }
+            ConstExpr::Binary(op, lhs, rhs) => {
+                // Everything is left-associative, so only the lhs can share our precedence
+                if lhs.precedence() < op.precedence() {
+                    write!(f, "({lhs})")?;
+                } else {
+                    write!(f, "{lhs}")?;
+                }
+                write!(f, " {op} ")?;
+                if rhs.precedence() <= op.precedence() {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            }
+            ConstExpr::SizeOf(ty) => write!(f, "sizeof({ty})"),
+            ConstExpr::AlignOf(ty) => write!(f, "alignof({ty})"),
+        }
+    }
+}
+
+impl BinOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 5,
+            BinOp::Add | BinOp::Sub => 4,
+            BinOp::Shl | BinOp::Shr => 3,
+            BinOp::BitAnd => 2,
+            BinOp::BitXor => 1,
+            BinOp::BitOr => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::BitAnd => "&",
+            BinOp::BitXor => "^",
+            BinOp::BitOr => "|",
+        };
+        s.fmt(f)
+    }
 }
 
 /// All of this gunk is only used for function bodies, which only
@@ -1489,33 +2105,27 @@ mod runnable {
     }
 
     impl ParsedProgram {
-        pub(crate) fn add_builtin_funcs(&mut self) -> Result<()> {
-            // Add builtins jankily
-            self.funcs.insert(
-                Ident::from(String::from("+")),
-                FuncDecl {
-                    name: Ident::from(String::from("+")),
-                    inputs: vec![
-                        TypedVar {
-                            name: Some(Ident::from(String::from("lhs"))),
-                            ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
-                        },
-                        TypedVar {
-                            name: Some(Ident::from(String::from("rhs"))),
-                            ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
-                        },
-                    ],
-                    outputs: vec![TypedVar {
-                        name: Some(Ident::from(String::from("out"))),
-                        ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
-                    }],
-                    attrs: vec![],
-
-                    body: vec![],
-                },
-            );
-
-            Ok(())
+        pub(crate) fn add_builtin_funcs(&mut self) {
+            // Add builtins jankily (the interpreter actually makes these generic)
+            let i64_var = |name: &str| TypedVar {
+                name: Some(Ident::from(String::from(name))),
+                ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
+                attrs: vec![],
+            };
+            for op in ["+", "-", "*"] {
+                self.funcs.insert(
+                    Ident::from(String::from(op)),
+                    FuncDecl {
+                        name: Ident::from(String::from(op)),
+                        inputs: vec![i64_var("lhs"), i64_var("rhs")],
+                        outputs: vec![i64_var("out")],
+                        attrs: vec![],
+                        vals: vec![],
+
+                        body: vec![],
+                    },
+                );
+            }
         }
     }
 }
@@ -0,0 +1,762 @@
+//! The compact (Rust/C-like) surface syntax.
+//!
+//! This is an alternative to writing programs as KDL nodes, used for files with
+//! the [`EXTENSION`][] extension. It parses to exactly the same [`ParsedProgram`][]
+//! so everything downstream (typechecking, puns, backends) works unchanged.
+//!
+//! ```text
+//! const N = 4;
+//!
+//! #[repr(C)]
+//! struct Point { x: f32, y: f32 }
+//! struct Meters(f32);
+//! union Bits { f: f32, u: u32 }
+//! enum Mode { Fast, Slow = 1 << 4 }
+//! enum Option { None, Some(i32) }
+//! tagged Unit { A, B }
+//! type Coords = [Point; N];
+//! pun Size {
+//!     lang(c) { type Size = u32; }
+//!     default { type Size = u64; }
+//! }
+//!
+//! fn scale(p: &Point, _: f32) -> Point;
+//! fn split(p: Point) -> (x: f32, y: f32);
+//! ```
+//!
+//! `enum`s with any payloads are tagged unions (like Rust), otherwise they're
+//! c-style enums. Use `tagged` to get a tagged union without payloads.
+//! Types and constant expressions use the same syntax as they do in KDL strings.
+
+use std::sync::Arc;
+
+use miette::{NamedSource, SourceSpan};
+use nom::Finish;
+use tracing::trace;
+
+use super::*;
+use crate::{Compiler, Result};
+
+/// The file extension for programs written in the compact syntax.
+pub const EXTENSION: &str = "kds";
+
+/// Whether a program with this name/path is written in the compact syntax.
+pub fn is_compact_path(name: &str) -> bool {
+    name.strip_suffix(EXTENSION)
+        .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Parse a program written in the compact syntax.
+pub fn parse_compact_script(
+    comp: &mut Compiler,
+    src: Arc<NamedSource>,
+    input: &str,
+) -> Result<ParsedProgram> {
+    let mut parser = CompactParser {
+        comp,
+        src,
+        input,
+        pos: 0,
+    };
+    parser.parse()
+}
+
+/// A top-level item, before it gets sorted into the program.
+enum Item {
+    Ty(TyDecl),
+    Func(FuncDecl),
+    Const(ConstDecl),
+}
+
+struct CompactParser<'a> {
+    comp: &'a mut Compiler,
+    src: Arc<NamedSource>,
+    input: &'a str,
+    /// The byte offset we're at in `input`.
+    pos: usize,
+}
+
+impl<'a> CompactParser<'a> {
+    fn parse(&mut self) -> Result<ParsedProgram> {
+        trace!("parsing compact syntax");
+        let mut funcs = StableMap::new();
+        let mut tys = StableMap::new();
+        let mut consts = StableMap::new();
+
+        loop {
+            self.skip_trivia();
+            if self.rest().is_empty() {
+                break;
+            }
+
+            // Items are independent, so if we're gathering errors
+            // we can skip to the next one and find problems there too
+            let item_start = self.pos;
+            if let Err(e) = self.item(&mut funcs, &mut tys, &mut consts) {
+                self.comp.error_handler.report(e)?;
+                self.pos = item_start;
+                self.skip_item();
+            }
+        }
+
+        #[allow(unused_mut)]
+        let mut program = ParsedProgram {
+            tys,
+            funcs,
+            consts,
+            builtin_funcs_start: 0,
+        };
+        program.builtin_funcs_start = program.funcs.len();
+        #[cfg(feature = "eval")]
+        program.add_builtin_funcs();
+        Ok(program)
+    }
+
+    /// Parse a top-level item and add it to the program.
+    fn item(
+        &mut self,
+        funcs: &mut StableMap<Ident, FuncDecl>,
+        tys: &mut StableMap<Ident, TyDecl>,
+        consts: &mut StableMap<Ident, ConstDecl>,
+    ) -> Result<()> {
+        match self.any_item()? {
+            Item::Ty(ty) => {
+                let name = ty_decl_name(&ty).clone();
+                if tys.contains_key(&name) {
+                    return Err(self.error_at(
+                        Spanned::span(&name),
+                        format!("duplicate definition of type {name}"),
+                        None,
+                    ))?;
+                }
+                tys.insert(name, ty);
+            }
+            Item::Func(func) => {
+                if funcs.contains_key(&func.name) {
+                    return Err(self.error_at(
+                        Spanned::span(&func.name),
+                        format!("duplicate definition of fn {}", func.name),
+                        None,
+                    ))?;
+                }
+                funcs.insert(func.name.clone(), func);
+            }
+            Item::Const(decl) => {
+                if consts.contains_key(&decl.name) {
+                    return Err(self.error_at(
+                        Spanned::span(&decl.name),
+                        format!("duplicate definition of const {}", decl.name),
+                        None,
+                    ))?;
+                }
+                consts.insert(decl.name.clone(), decl);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse an item (with its attributes).
+    fn any_item(&mut self) -> Result<Item> {
+        let attrs = self.attrs()?;
+        let keyword = self.word();
+        let item = match keyword.as_str() {
+            "struct" => Item::Ty(TyDecl::Struct(self.struct_decl(attrs)?)),
+            "union" => Item::Ty(TyDecl::Union(self.union_decl(attrs)?)),
+            "enum" => Item::Ty(self.enum_decl(attrs, false)?),
+            "tagged" => Item::Ty(self.enum_decl(attrs, true)?),
+            "type" => Item::Ty(TyDecl::Alias(self.alias_decl(attrs)?)),
+            "pun" => Item::Ty(TyDecl::Pun(self.pun_decl(attrs)?)),
+            "fn" => Item::Func(self.func_decl(attrs)?),
+            "const" => {
+                if !attrs.is_empty() {
+                    return Err(self.error_at(
+                        Spanned::span(&keyword),
+                        "consts can't have attributes".to_owned(),
+                        None,
+                    ))?;
+                }
+                Item::Const(self.const_decl()?)
+            }
+            "" => {
+                return Err(self.error(
+                    "expected an item".to_owned(),
+                    Some("try struct, union, enum, tagged, type, pun, fn, or const".to_owned()),
+                ))?;
+            }
+            x => {
+                return Err(self.error_at(
+                    Spanned::span(&keyword),
+                    format!("I don't know what a '{x}' is"),
+                    None,
+                ))?;
+            }
+        };
+        Ok(item)
+    }
+
+    /// `struct Name { a: T, b: U }`, `struct Name(T, U);`, or `struct Name;`
+    fn struct_decl(&mut self, attrs: Vec<Attr>) -> Result<StructDecl> {
+        trace!("struct decl");
+        let name = self.ident()?;
+        let fields = if self.eat("(") {
+            let fields = self.tuple_fields()?;
+            self.expect(";")?;
+            fields
+        } else if self.eat(";") {
+            vec![]
+        } else {
+            self.named_fields()?
+        };
+        Ok(StructDecl {
+            name,
+            fields,
+            attrs,
+        })
+    }
+
+    /// `union Name { a: T, b: U }`
+    fn union_decl(&mut self, attrs: Vec<Attr>) -> Result<UnionDecl> {
+        trace!("union decl");
+        let name = self.ident()?;
+        let fields = self.named_fields()?;
+        Ok(UnionDecl {
+            name,
+            fields,
+            attrs,
+        })
+    }
+
+    /// `enum Name { A, B = 2 }` or `enum Name { A, B(T), C { x: T } }`
+    ///
+    /// If `tagged` is set (or any variant has a payload) this is a tagged union.
+    fn enum_decl(&mut self, attrs: Vec<Attr>, tagged: bool) -> Result<TyDecl> {
+        trace!("enum decl");
+        let name = self.ident()?;
+        self.expect("{")?;
+        let mut variants = vec![];
+        while !self.eat("}") {
+            let variant_name = self.ident()?;
+            let val = if self.eat("=") {
+                Some(self.int_expr()?)
+            } else {
+                None
+            };
+            let fields = if self.eat("(") {
+                Some(self.tuple_fields()?)
+            } else if self.at("{") {
+                Some(self.named_fields()?)
+            } else {
+                None
+            };
+            variants.push((variant_name, val, fields));
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+
+        let tagged = tagged || variants.iter().any(|(_, _, fields)| fields.is_some());
+        if tagged {
+            let mut tagged_variants = vec![];
+            for (name, val, fields) in variants {
+                if let Some(val) = val {
+                    return Err(self.error_at(
+                        val.span,
+                        "variants of tagged unions can't have values".to_owned(),
+                        None,
+                    ))?;
+                }
+                tagged_variants.push(TaggedVariant { name, fields });
+            }
+            Ok(TyDecl::Tagged(TaggedDecl {
+                name,
+                variants: tagged_variants,
+                attrs,
+            }))
+        } else {
+            let variants = variants
+                .into_iter()
+                .map(|(name, val, _)| EnumVariant { name, val })
+                .collect();
+            Ok(TyDecl::Enum(EnumDecl {
+                name,
+                variants,
+                attrs,
+            }))
+        }
+    }
+
+    /// `type Name = T;`
+    fn alias_decl(&mut self, attrs: Vec<Attr>) -> Result<AliasDecl> {
+        trace!("alias decl");
+        let name = self.ident()?;
+        self.expect("=")?;
+        let alias = self.tydent()?;
+        self.expect(";")?;
+        Ok(AliasDecl { name, alias, attrs })
+    }
+
+    /// `pun Name { lang(c, rust) { ... } default { ... } }`
+    fn pun_decl(&mut self, attrs: Vec<Attr>) -> Result<PunDecl> {
+        trace!("pun decl");
+        let name = self.ident()?;
+        self.expect("{")?;
+        let mut blocks = vec![];
+        while !self.eat("}") {
+            let selector_word = self.word();
+            let selector = match selector_word.as_str() {
+                "lang" => {
+                    self.expect("(")?;
+                    let mut langs = vec![];
+                    while !self.eat(")") {
+                        let lang = self.ident()?;
+                        langs.push(PunSelector::Lang(lang.val));
+                        if !self.eat(",") {
+                            self.expect(")")?;
+                            break;
+                        }
+                    }
+                    if langs.is_empty() {
+                        return Err(self.error_at(
+                            Spanned::span(&selector_word),
+                            "Hey I need a lang name here!".to_owned(),
+                            None,
+                        ))?;
+                    }
+                    PunSelector::Any(langs)
+                }
+                "default" => PunSelector::Default,
+                x => {
+                    return Err(self.error_at(
+                        Spanned::span(&selector_word),
+                        format!("I don't know what a '{x}' is here"),
+                        Some("pun blocks start with lang(...) or default".to_owned()),
+                    ))?;
+                }
+            };
+            let block_start = self.pos;
+            self.expect("{")?;
+            let decl = match self.any_item()? {
+                Item::Ty(decl) if ty_decl_name(&decl) == &name => decl,
+                Item::Ty(decl) => {
+                    return Err(self.error_at(
+                        Spanned::span(ty_decl_name(&decl)),
+                        "pun declared a type other than what it should have".to_owned(),
+                        None,
+                    ))?;
+                }
+                Item::Func(func) => {
+                    return Err(self.error_at(
+                        Spanned::span(&func.name),
+                        "puns can't contain function decls".to_owned(),
+                        None,
+                    ))?;
+                }
+                Item::Const(decl) => {
+                    return Err(self.error_at(
+                        Spanned::span(&decl.name),
+                        "puns can't contain const decls".to_owned(),
+                        Some("move this to the top level".to_owned()),
+                    ))?;
+                }
+            };
+            if !self.eat("}") {
+                return Err(self.error_at(
+                    self.span_from(block_start),
+                    "pun blocks should only declare the type they pun".to_owned(),
+                    None,
+                ))?;
+            }
+            blocks.push(PunBlock { selector, decl });
+        }
+        Ok(PunDecl {
+            name,
+            blocks,
+            attrs,
+        })
+    }
+
+    /// `fn name(a: T, _: U) -> R;` or `fn name() -> (a: T, b: U) {}`
+    fn func_decl(&mut self, attrs: Vec<Attr>) -> Result<FuncDecl> {
+        trace!("fn");
+        let name = self.ident()?;
+        self.expect("(")?;
+        let inputs = self.params()?;
+        let outputs = if self.eat("->") {
+            if self.eat("(") {
+                self.params()?
+            } else {
+                vec![TypedVar {
+                    name: None,
+                    ty: self.tydent()?,
+                    attrs: vec![],
+                }]
+            }
+        } else {
+            vec![]
+        };
+        if self.eat("{") {
+            self.expect("}")?;
+        } else {
+            self.expect(";")?;
+        }
+        Ok(FuncDecl {
+            name,
+            inputs,
+            outputs,
+            attrs,
+            vals: vec![],
+            #[cfg(feature = "eval")]
+            body: vec![],
+        })
+    }
+
+    /// `const NAME = expr;`
+    fn const_decl(&mut self) -> Result<ConstDecl> {
+        trace!("const decl");
+        let name = self.ident()?;
+        self.expect("=")?;
+        let val = self.int_expr()?;
+        self.expect(";")?;
+        Ok(ConstDecl { name, val })
+    }
+
+    /// Any number of `#[...]` attributes.
+    fn attrs(&mut self) -> Result<Vec<Attr>> {
+        let mut attrs = vec![];
+        while self.eat("#[") {
+            let attr_name = self.word();
+            let attr = match attr_name.as_str() {
+                "packed" => Attr::Packed(AttrPacked {}),
+                "align" => {
+                    self.expect("(")?;
+                    let align = self.int_expr()?;
+                    self.expect(")")?;
+                    Attr::Align(AttrAligned { align })
+                }
+                "repr" => {
+                    self.expect("(")?;
+                    let mut reprs = vec![];
+                    while !self.eat(")") {
+                        let repr_name = self.word();
+                        let repr = match repr_name.as_str() {
+                            // Rust spells it `C`, kdl-script spells it `c`, take either
+                            "c" | "C" => Repr::Lang(LangRepr::C),
+                            "rust" | "Rust" => Repr::Lang(LangRepr::Rust),
+                            "transparent" => Repr::Transparent,
+                            name => {
+                                let prim = PRIMITIVES
+                                    .iter()
+                                    .find(|(prim_name, _)| *prim_name == name)
+                                    .map(|(_, prim)| *prim);
+                                let Some(prim) = prim else {
+                                    return Err(self.error_at(
+                                        Spanned::span(&repr_name),
+                                        "repr attr has unknown kind".to_owned(),
+                                        None,
+                                    ))?;
+                                };
+                                Repr::Primitive(prim)
+                            }
+                        };
+                        reprs.push(repr);
+                        if !self.eat(",") {
+                            self.expect(")")?;
+                            break;
+                        }
+                    }
+                    Attr::Repr(AttrRepr { reprs })
+                }
+                "passthrough" => {
+                    self.expect("(")?;
+                    // `passthrough(c, "...")` only applies to that language
+                    let selector = if self.at("\"") {
+                        PunSelector::Default
+                    } else {
+                        let lang = self.word();
+                        if lang.parse::<LangRepr>().is_err() {
+                            return Err(self.error_at(
+                                Spanned::span(&lang),
+                                format!("passthrough attribute for unknown language '{lang}'"),
+                                Some("the known languages are c and rust".to_owned()),
+                            ))?;
+                        }
+                        self.expect(",")?;
+                        PunSelector::Lang(lang)
+                    };
+                    let attr = self.string()?;
+                    self.expect(")")?;
+                    Attr::Passthrough(AttrPassthrough { selector, attr })
+                }
+                x => {
+                    return Err(self.error_at(
+                        Spanned::span(&attr_name),
+                        format!("I don't know what a '{x}' attribute is"),
+                        None,
+                    ))?;
+                }
+            };
+            self.expect("]")?;
+            attrs.push(attr);
+        }
+        Ok(attrs)
+    }
+
+    /// `{ a: T, _: U }`
+    fn named_fields(&mut self) -> Result<Vec<TypedVar>> {
+        self.expect("{")?;
+        let mut fields = vec![];
+        while !self.eat("}") {
+            fields.push(self.typed_var()?);
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// `T, U)` (the open paren was already eaten), all positional.
+    fn tuple_fields(&mut self) -> Result<Vec<TypedVar>> {
+        let mut fields = vec![];
+        while !self.eat(")") {
+            let attrs = self.attrs()?;
+            fields.push(TypedVar {
+                name: None,
+                ty: self.tydent()?,
+                attrs,
+            });
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// `a: T, _: U)` (the open paren was already eaten).
+    fn params(&mut self) -> Result<Vec<TypedVar>> {
+        let mut params = vec![];
+        while !self.eat(")") {
+            params.push(self.typed_var()?);
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    /// `#[attrs] name: T` (where `_` is a positional name)
+    fn typed_var(&mut self) -> Result<TypedVar> {
+        let attrs = self.attrs()?;
+        let name = self.ident()?;
+        let name = if &*name == "_" { None } else { Some(name) };
+        self.expect(":")?;
+        let ty = self.tydent()?;
+        Ok(TypedVar { name, ty, attrs })
+    }
+
+    /// Parse an identifier.
+    fn ident(&mut self) -> Result<Ident> {
+        self.skip_trivia();
+        let start = self.pos;
+        let Ok((rest, name)) = ident(self.rest()) else {
+            return Err(self.error("expected an identifier".to_owned(), None))?;
+        };
+        self.pos = self.input.len() - rest.len();
+        Ok(Ident {
+            val: Spanned::new(name.to_owned(), self.span_from(start)),
+            was_blank: false,
+        })
+    }
+
+    /// Parse a type like `u32`, `&MyType`, or `[u8; N]`.
+    fn tydent(&mut self) -> Result<Spanned<Tydent>> {
+        self.skip_trivia();
+        let start = self.pos;
+        let Ok((rest, mut ty)) = tydent(self.rest()).finish() else {
+            return Err(self.error("couldn't parse type".to_owned(), None))?;
+        };
+        self.pos = self.input.len() - rest.len();
+        let text = Spanned::new(
+            self.input[start..self.pos].to_owned(),
+            self.span_from(start),
+        );
+        inherit_spans(&mut ty, &text);
+        Ok(ty)
+    }
+
+    /// Parse a constant expression like `2 * N` or `sizeof(u32)`.
+    fn int_expr(&mut self) -> Result<IntExpr> {
+        self.skip_trivia();
+        let start = self.pos;
+        let Ok((rest, mut expr)) = const_expr(self.rest()).finish() else {
+            return Err(self.error(
+                "couldn't parse constant expression".to_owned(),
+                Some("try something like 2 * N or sizeof(u32)".to_owned()),
+            ))?;
+        };
+        self.pos = self.input.len() - rest.len();
+        let span = self.span_from(start);
+        let text = Spanned::new(self.input[start..self.pos].to_owned(), span);
+        inherit_const_spans(&mut expr, &text);
+        Ok(IntExpr { span, expr })
+    }
+
+    /// Parse a `"string literal"` (with `\"` and `\\` escapes).
+    fn string(&mut self) -> Result<Spanned<String>> {
+        self.skip_trivia();
+        let start = self.pos;
+        if !self.rest().starts_with('"') {
+            return Err(self.error("expected a string".to_owned(), None))?;
+        }
+        let mut val = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += idx + 1;
+                    return Ok(Spanned::new(val, self.span_from(start)));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => val.push(c),
+                    _ => break,
+                },
+                c => val.push(c),
+            }
+        }
+        Err(self.error("unterminated string".to_owned(), None))?
+    }
+
+    /// Get the next word (identifier-like thing), or "" if there isn't one.
+    fn word(&mut self) -> Spanned<String> {
+        self.skip_trivia();
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        let start = self.pos;
+        self.pos += len;
+        Spanned::new(
+            self.input[start..self.pos].to_owned(),
+            self.span_from(start),
+        )
+    }
+
+    /// Check if the next token is this punctuation.
+    fn at(&mut self, punct: &str) -> bool {
+        self.skip_trivia();
+        self.rest().starts_with(punct)
+    }
+
+    /// Consume this punctuation if it's next.
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.at(punct);
+        if found {
+            self.pos += punct.len();
+        }
+        found
+    }
+
+    /// Consume this punctuation or error.
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{punct}`"), None))?
+        }
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                self.pos += comment.find("*/").map_or(trimmed.len(), |end| end + 4);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skip to the end of the current item, to recover from an error in it.
+    ///
+    /// Items end with a `;` or a top-level `}`. Unclosed parens/brackets
+    /// (a likely reason we're here) are assumed to end at the next `}`.
+    fn skip_item(&mut self) {
+        let mut braces = 0usize;
+        let mut brackets = 0usize;
+        loop {
+            self.skip_trivia();
+            let Some(c) = self.rest().chars().next() else {
+                return;
+            };
+            if c == '"' {
+                if self.string().is_err() {
+                    self.pos = self.input.len();
+                }
+                continue;
+            }
+            self.pos += c.len_utf8();
+            match c {
+                '{' => braces += 1,
+                '(' | '[' => brackets += 1,
+                ')' | ']' => brackets = brackets.saturating_sub(1),
+                '}' => {
+                    brackets = 0;
+                    braces = braces.saturating_sub(1);
+                    if braces == 0 {
+                        return;
+                    }
+                }
+                ';' if braces == 0 && brackets == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn span_from(&self, start: usize) -> SourceSpan {
+        SourceSpan::from(start..self.pos)
+    }
+
+    /// An error at the current position.
+    fn error(&mut self, message: String, help: Option<String>) -> KdlScriptParseError {
+        self.skip_trivia();
+        let span = SourceSpan::from(self.pos..self.pos);
+        self.error_at(span, message, help)
+    }
+
+    fn error_at(
+        &self,
+        span: SourceSpan,
+        message: String,
+        help: Option<String>,
+    ) -> KdlScriptParseError {
+        KdlScriptParseError {
+            message,
+            src: self.src.clone(),
+            span,
+            help,
+        }
+    }
+}
+
+fn ty_decl_name(decl: &TyDecl) -> &Ident {
+    match decl {
+        TyDecl::Struct(decl) => &decl.name,
+        TyDecl::Union(decl) => &decl.name,
+        TyDecl::Enum(decl) => &decl.name,
+        TyDecl::Tagged(decl) => &decl.name,
+        TyDecl::Alias(decl) => &decl.name,
+        TyDecl::Pun(decl) => &decl.name,
+    }
+}
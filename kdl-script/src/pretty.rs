@@ -0,0 +1,857 @@
+//! Pretty-printing a [`ParsedProgram`][] back out as KDL.
+//!
+//! The output is normalized (consistent indentation, one item per node,
+//! blank lines between items) and should parse back to an equivalent program.
+//!
+//! If you have the source the program was parsed from, comments can be
+//! carried over with [`ParsedProgram::to_kdl_with_comments`][]. Each comment
+//! is attached to the next item (or if it's at the end of a line, the item
+//! on that line), so comments dangling at the end of a block will drift down
+//! to the next item.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+
+use miette::SourceSpan;
+
+use crate::emit::EmitError;
+use crate::parse::*;
+use crate::spanned::Spanned;
+use crate::types::PRIMITIVES;
+
+const INDENT: &str = "    ";
+const MAX_WIDTH: usize = 100;
+
+impl ParsedProgram {
+    /// Pretty-print this program as KDL.
+    ///
+    /// Programs built with a [`builder::ProgramBuilder`][crate::builder::ProgramBuilder]
+    /// can contain things KDL has no syntax for (like nested calls), which are
+    /// reported as [`EmitError::Unsupported`][].
+    pub fn to_kdl(&self) -> Result<String, EmitError> {
+        let mut out = String::new();
+        self.write_kdl(&mut out)?;
+        Ok(out)
+    }
+
+    /// Pretty-print this program as KDL.
+    pub fn write_kdl(&self, f: &mut dyn Write) -> Result<(), EmitError> {
+        Printer::new(f, None).finish(|p| p.program(self))
+    }
+
+    /// Pretty-print this program as KDL, preserving comments from the source it was parsed from.
+    ///
+    /// `/-` (slashdash) comments are not preserved, see [`find_slashdash`][].
+    pub fn to_kdl_with_comments(&self, src: &str) -> Result<String, EmitError> {
+        let mut out = String::new();
+        self.write_kdl_with_comments(src, &mut out)?;
+        Ok(out)
+    }
+
+    /// Pretty-print this program as KDL, preserving comments from the source it was parsed from.
+    pub fn write_kdl_with_comments(&self, src: &str, f: &mut dyn Write) -> Result<(), EmitError> {
+        Printer::new(f, Some(src)).finish(|p| p.program(self))
+    }
+}
+
+/// Find the first `/-` (slashdash) comment in this KDL source.
+///
+/// These comment out entire nodes or values, which the pretty-printer has no
+/// way of representing, so formatting would delete them.
+pub fn find_slashdash(src: &str) -> Option<SourceSpan> {
+    scan_source(src)
+        .slashdash
+        .map(|offset| SourceSpan::from(offset..offset + 2))
+}
+
+/// A comment from the source
+#[derive(Debug)]
+struct Comment {
+    /// Where the comment starts in the source
+    offset: usize,
+    /// The full text of the comment (incl. the `//` or `/*`)
+    text: String,
+    /// Whether this comment comes after some code on the same line
+    trailing: bool,
+    /// Whether this comment is followed by a blank line
+    blank_line_after: bool,
+}
+
+/// The comments and other trivia of some KDL source
+#[derive(Debug, Default)]
+struct Scanned {
+    comments: Vec<Comment>,
+    /// The offsets of all the `}` in the source
+    close_braces: Vec<usize>,
+    /// The first slashdash in the source
+    slashdash: Option<usize>,
+}
+
+/// The top-level items, so they can be printed in source order
+enum Item<'a> {
+    Const(&'a ConstDecl),
+    Ty(&'a TyDecl),
+    Func(&'a FuncDecl),
+}
+
+struct Printer<'a> {
+    f: &'a mut dyn Write,
+    indent: usize,
+    src: &'a str,
+    comments: VecDeque<Comment>,
+    close_braces: Vec<usize>,
+    /// The furthest point in the source we've printed
+    cursor: Option<usize>,
+    /// Why we gave up, if it's because KDL can't express something
+    unsupported: Option<String>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(f: &'a mut dyn Write, src: Option<&'a str>) -> Self {
+        let scanned = src.map(scan_source).unwrap_or_default();
+        Self {
+            f,
+            indent: 0,
+            src: src.unwrap_or_default(),
+            comments: scanned.comments.into(),
+            close_braces: scanned.close_braces,
+            cursor: None,
+            unsupported: None,
+        }
+    }
+
+    /// Run the printer, turning a [`Printer::unsupported`][] bailout into a proper error.
+    fn finish(mut self, print: impl FnOnce(&mut Self) -> fmt::Result) -> Result<(), EmitError> {
+        match print(&mut self) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(match self.unsupported.take() {
+                Some(msg) => EmitError::Unsupported(msg),
+                None => EmitError::Fmt(e),
+            }),
+        }
+    }
+
+    /// Give up because KDL has no syntax for something in the program.
+    fn unsupported(&mut self, msg: &str) -> fmt::Result {
+        self.unsupported = Some(msg.to_owned());
+        Err(fmt::Error)
+    }
+
+    fn program(&mut self, program: &ParsedProgram) -> fmt::Result {
+        let mut items = vec![];
+        items.extend(program.consts.values().map(Item::Const));
+        items.extend(program.tys.values().map(Item::Ty));
+        items.extend(
+            program
+                .funcs
+                .values()
+                .take(program.builtin_funcs_start)
+                .map(Item::Func),
+        );
+        // Things that came from source go in source order, generated things go at the end
+        items.sort_by_key(|item| {
+            let name = match item {
+                Item::Const(decl) => &decl.name,
+                Item::Ty(decl) => decl.name(),
+                Item::Func(decl) => &decl.name,
+            };
+            ident_anchor(name).unwrap_or(usize::MAX)
+        });
+
+        let mut prev_was_const = false;
+        for (idx, item) in items.into_iter().enumerate() {
+            let is_const = matches!(item, Item::Const(_));
+            // Consts are grouped together, everything else gets a blank line between
+            if idx != 0 && !(is_const && prev_was_const) {
+                writeln!(self.f)?;
+            }
+            prev_was_const = is_const;
+            match item {
+                Item::Const(decl) => self.const_decl(decl)?,
+                Item::Ty(decl) => self.ty_decl(decl)?,
+                Item::Func(decl) => self.func_decl(decl)?,
+            }
+        }
+
+        // Anything left over goes at the end
+        if !self.comments.is_empty() {
+            writeln!(self.f)?;
+            self.leading_comments(Some(usize::MAX))?;
+        }
+        Ok(())
+    }
+
+    fn line_start(&mut self) -> fmt::Result {
+        for _ in 0..self.indent {
+            self.f.write_str(INDENT)?;
+        }
+        Ok(())
+    }
+
+    /// Emit any comments that come before this point in the source.
+    fn leading_comments(&mut self, anchor: Option<usize>) -> fmt::Result {
+        let Some(anchor) = anchor else {
+            return Ok(());
+        };
+        self.cursor = Some(anchor);
+        while let Some(comment) = self.comments.front() {
+            if comment.offset >= anchor {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.line_start()?;
+            writeln!(self.f, "{}", comment.text)?;
+            if comment.blank_line_after {
+                writeln!(self.f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the source line containing this offset ends.
+    fn line_end(&self, offset: usize) -> usize {
+        self.src
+            .get(offset..)
+            .and_then(|rest| rest.find('\n'))
+            .map(|idx| offset + idx)
+            .unwrap_or(self.src.len())
+    }
+
+    /// The `}` that closes the block we're printing (if we know where we are in the source).
+    fn next_close_brace(&self) -> Option<usize> {
+        let cursor = self.cursor?;
+        let idx = self.close_braces.partition_point(|&brace| brace <= cursor);
+        self.close_braces.get(idx).copied()
+    }
+
+    /// End the line, appending any comment that was at the end of the line in the source.
+    fn end_line(&mut self, anchor: Option<usize>) -> fmt::Result {
+        if let (Some(anchor), false) = (anchor, self.comments.is_empty()) {
+            let line_end = self.line_end(anchor);
+            while let Some(comment) = self.comments.front() {
+                if !comment.trailing || comment.offset < anchor || comment.offset >= line_end {
+                    break;
+                }
+                let comment = self.comments.pop_front().unwrap();
+                write!(self.f, " {}", comment.text)?;
+            }
+        }
+        writeln!(self.f)
+    }
+
+    /// The anchor to use for trailing comments, which belong to the last item on a line.
+    ///
+    /// Returns None if the next item is on the same line as this one.
+    fn last_on_line(&self, anchor: Option<usize>, next_anchor: Option<usize>) -> Option<usize> {
+        match (anchor, next_anchor) {
+            (Some(anchor), Some(next)) if next < self.line_end(anchor) => None,
+            _ => anchor,
+        }
+    }
+
+    fn open_block(&mut self, anchor: Option<usize>) -> fmt::Result {
+        write!(self.f, " {{")?;
+        self.end_line(anchor)?;
+        self.indent += 1;
+        Ok(())
+    }
+
+    fn close_block(&mut self) -> fmt::Result {
+        // Keep any comments at the end of the block inside it
+        if let Some(brace) = self.next_close_brace() {
+            self.leading_comments(Some(brace))?;
+        }
+        self.indent -= 1;
+        self.line_start()?;
+        writeln!(self.f, "}}")
+    }
+
+    fn const_decl(&mut self, decl: &ConstDecl) -> fmt::Result {
+        let anchor = ident_anchor(&decl.name);
+        self.leading_comments(anchor)?;
+        self.line_start()?;
+        write!(
+            self.f,
+            "const {} {}",
+            kdl_string(&decl.name),
+            int_expr(&decl.val)
+        )?;
+        self.end_line(anchor)
+    }
+
+    fn attrs(&mut self, attrs: &[Attr]) -> fmt::Result {
+        for attr in attrs {
+            self.line_start()?;
+            match attr {
+                Attr::Packed(AttrPacked {}) => writeln!(self.f, "@packed")?,
+                Attr::Align(AttrAligned { align }) => {
+                    writeln!(self.f, "@align {}", int_expr(align))?
+                }
+                Attr::Repr(AttrRepr { reprs }) => {
+                    write!(self.f, "@repr")?;
+                    for repr in reprs {
+                        let repr = match repr {
+                            Repr::Primitive(prim) => PRIMITIVES
+                                .iter()
+                                .find(|(_, p)| p == prim)
+                                .map(|(name, _)| name.to_string())
+                                .expect("primitive repr had no name"),
+                            Repr::Lang(lang) => lang.to_string(),
+                            Repr::Transparent => "transparent".to_owned(),
+                        };
+                        write!(self.f, " {}", kdl_string(&repr))?;
+                    }
+                    writeln!(self.f)?;
+                }
+                Attr::Passthrough(AttrPassthrough { selector, attr }) => {
+                    // `@ "..."` is for every language, `@lang "..."` for just one
+                    let langs = selector_langs(selector);
+                    let lang = match (selector, langs.as_deref()) {
+                        (PunSelector::Default, _) => "",
+                        (_, Some([lang])) => lang,
+                        _ => {
+                            return self.unsupported(
+                                "KDL only has syntax for passthrough attributes with at most one language",
+                            )
+                        }
+                    };
+                    writeln!(self.f, "@{lang} {}", kdl_string(attr))?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn ty_decl(&mut self, decl: &TyDecl) -> fmt::Result {
+        let anchor = ident_anchor(decl.name());
+        self.leading_comments(anchor)?;
+        match decl {
+            TyDecl::Struct(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(self.f, "struct {}", kdl_string(&decl.name))?;
+                self.typed_var_block(anchor, &decl.fields)
+            }
+            TyDecl::Union(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(self.f, "union {}", kdl_string(&decl.name))?;
+                self.typed_var_block(anchor, &decl.fields)
+            }
+            TyDecl::Enum(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(self.f, "enum {}", kdl_string(&decl.name))?;
+                if decl.variants.is_empty() {
+                    return self.empty_block(anchor);
+                }
+                let first_anchor = ident_anchor(&decl.variants[0].name);
+                self.open_block(self.last_on_line(anchor, first_anchor))?;
+                for (idx, variant) in decl.variants.iter().enumerate() {
+                    let next_anchor = decl.variants.get(idx + 1).map(|v| &v.name);
+                    let anchor = self.last_on_line(
+                        ident_anchor(&variant.name),
+                        next_anchor.and_then(ident_anchor),
+                    );
+                    self.leading_comments(ident_anchor(&variant.name))?;
+                    self.line_start()?;
+                    write!(self.f, "{}", kdl_ident(&variant.name))?;
+                    if let Some(val) = &variant.val {
+                        write!(self.f, " {}", int_expr(val))?;
+                    }
+                    self.end_line(anchor)?;
+                }
+                self.close_block()
+            }
+            TyDecl::Tagged(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(self.f, "tagged {}", kdl_string(&decl.name))?;
+                if decl.variants.is_empty() {
+                    return self.empty_block(anchor);
+                }
+                let first_anchor = ident_anchor(&decl.variants[0].name);
+                self.open_block(self.last_on_line(anchor, first_anchor))?;
+                for (idx, variant) in decl.variants.iter().enumerate() {
+                    let next_anchor = decl.variants.get(idx + 1).map(|v| &v.name);
+                    let anchor = self.last_on_line(
+                        ident_anchor(&variant.name),
+                        next_anchor.and_then(ident_anchor),
+                    );
+                    self.leading_comments(ident_anchor(&variant.name))?;
+                    self.line_start()?;
+                    let variant_name = kdl_ident(&variant.name);
+                    write!(self.f, "{variant_name}")?;
+                    if let Some(fields) = &variant.fields {
+                        // An empty block still needs to be emitted to distinguish
+                        // `Foo {}` from `Foo`, so this can't use typed_var_block
+                        self.compact_typed_var_block(anchor, variant_name.len(), fields)?;
+                    } else {
+                        self.end_line(anchor)?;
+                    }
+                }
+                self.close_block()
+            }
+            TyDecl::Alias(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(
+                    self.f,
+                    "alias {} {}",
+                    kdl_string(&decl.name),
+                    kdl_string(&decl.alias.to_string())
+                )?;
+                self.end_line(anchor)
+            }
+            TyDecl::Pun(decl) => {
+                self.attrs(&decl.attrs)?;
+                self.line_start()?;
+                write!(self.f, "pun {}", kdl_string(&decl.name))?;
+                self.open_block(anchor)?;
+                for block in &decl.blocks {
+                    let anchor = match &block.selector {
+                        PunSelector::Any(langs) => match langs.first() {
+                            Some(PunSelector::Lang(lang)) => anchor_of(lang),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    self.leading_comments(anchor.or(ident_anchor(block.decl.name())))?;
+                    self.line_start()?;
+                    if block.selector == PunSelector::Default {
+                        write!(self.f, "default")?;
+                    } else if let Some(langs) = selector_langs(&block.selector) {
+                        write!(self.f, "lang")?;
+                        for lang in langs {
+                            write!(self.f, " {}", kdl_string(lang))?;
+                        }
+                    } else {
+                        return self.unsupported(
+                            "KDL only has syntax for pun selectors that match a list of languages",
+                        );
+                    }
+                    self.open_block(anchor)?;
+                    self.ty_decl(&block.decl)?;
+                    self.close_block()?;
+                }
+                self.close_block()
+            }
+        }
+    }
+
+    fn empty_block(&mut self, anchor: Option<usize>) -> fmt::Result {
+        self.open_block(anchor)?;
+        self.close_block()
+    }
+
+    fn typed_var_block(&mut self, anchor: Option<usize>, vars: &[TypedVar]) -> fmt::Result {
+        let first_anchor = vars.first().and_then(typed_var_anchor);
+        self.open_block(self.last_on_line(anchor, first_anchor))?;
+        self.typed_vars(vars)?;
+        self.close_block()
+    }
+
+    /// Like [`Printer::typed_var_block`][] but puts short lists on one line,
+    /// like `inputs { _ "u32"; y "f32"; }`.
+    ///
+    /// `prefix_len` is how much of the current line has been written already.
+    fn compact_typed_var_block(
+        &mut self,
+        anchor: Option<usize>,
+        prefix_len: usize,
+        vars: &[TypedVar],
+    ) -> fmt::Result {
+        let mut inline = String::from(" {");
+        for var in vars {
+            let name = var.name.as_ref().map(|name| name.as_str()).unwrap_or("_");
+            let ty = kdl_string(&var.ty.to_string());
+            write!(inline, " {} {ty};", kdl_ident(name))?;
+        }
+        inline.push_str(if vars.is_empty() { "}" } else { " }" });
+
+        // Don't inline if that would mangle a comment
+        if let Some(var) = vars.last() {
+            self.cursor = typed_var_anchor(var).or(self.cursor);
+        }
+        let block_end = self.next_close_brace();
+        let has_comments = self
+            .comments
+            .front()
+            .is_some_and(|comment| block_end.is_some_and(|block_end| comment.offset < block_end));
+        let has_attrs = vars.iter().any(|var| !var.attrs.is_empty());
+        let width = self.indent * INDENT.len() + prefix_len + inline.len();
+        if width <= MAX_WIDTH && !has_comments && !has_attrs {
+            write!(self.f, "{inline}")?;
+            if block_end.is_some() {
+                self.cursor = block_end;
+            }
+            self.end_line(anchor.or(vars.first().and_then(typed_var_anchor)))
+        } else {
+            self.typed_var_block(anchor, vars)
+        }
+    }
+
+    fn typed_vars(&mut self, vars: &[TypedVar]) -> fmt::Result {
+        for (idx, var) in vars.iter().enumerate() {
+            let next_anchor = vars.get(idx + 1).and_then(typed_var_anchor);
+            let anchor = self.last_on_line(typed_var_anchor(var), next_anchor);
+            self.leading_comments(typed_var_anchor(var))?;
+            self.attrs(&var.attrs)?;
+            self.line_start()?;
+            let name = var.name.as_ref().map(|name| name.as_str()).unwrap_or("_");
+            write!(
+                self.f,
+                "{} {}",
+                kdl_ident(name),
+                kdl_string(&var.ty.to_string())
+            )?;
+            self.end_line(anchor)?;
+        }
+        Ok(())
+    }
+
+    fn func_decl(&mut self, decl: &FuncDecl) -> fmt::Result {
+        let anchor = ident_anchor(&decl.name);
+        self.leading_comments(anchor)?;
+        self.attrs(&decl.attrs)?;
+        self.line_start()?;
+        write!(self.f, "fn {}", kdl_string(&decl.name))?;
+
+        #[cfg(feature = "eval")]
+        let has_body = !decl.body.is_empty();
+        #[cfg(not(feature = "eval"))]
+        let has_body = false;
+        if decl.inputs.is_empty() && decl.outputs.is_empty() && decl.vals.is_empty() && !has_body {
+            return self.end_line(anchor);
+        }
+
+        let first_anchor = decl.inputs.iter().chain(&decl.outputs).next();
+        self.open_block(self.last_on_line(anchor, first_anchor.and_then(typed_var_anchor)))?;
+        for (block_name, vars) in [("inputs", &decl.inputs), ("outputs", &decl.outputs)] {
+            if vars.is_empty() {
+                continue;
+            }
+            self.leading_comments(typed_var_anchor(&vars[0]))?;
+            self.line_start()?;
+            write!(self.f, "{block_name}")?;
+            self.compact_typed_var_block(None, block_name.len(), vars)?;
+        }
+        if let Some(first) = decl.vals.first() {
+            self.leading_comments(anchor_of(&first.path))?;
+            self.line_start()?;
+            write!(self.f, "vals")?;
+            self.open_block(None)?;
+            for val in &decl.vals {
+                let anchor = anchor_of(&val.path);
+                self.leading_comments(anchor)?;
+                self.line_start()?;
+                write!(self.f, "{} ", kdl_string(&val.path))?;
+                match &*val.val {
+                    ValLiteral::Int(val) => write!(self.f, "{val}")?,
+                    ValLiteral::Float(val) => write!(self.f, "{val:?}")?,
+                    ValLiteral::Bool(val) => write!(self.f, "{val}")?,
+                    ValLiteral::String(val) => write!(self.f, "{}", kdl_string(val))?,
+                }
+                self.end_line(anchor)?;
+            }
+            self.close_block()?;
+        }
+        #[cfg(feature = "eval")]
+        for stmt in &decl.body {
+            self.stmt(stmt)?;
+        }
+        self.close_block()
+    }
+}
+
+#[cfg(feature = "eval")]
+impl Printer<'_> {
+    fn stmt(&mut self, stmt: &Stmt) -> fmt::Result {
+        let expr = match stmt {
+            Stmt::Let(LetStmt { expr, .. }) => expr,
+            Stmt::Return(ReturnStmt { expr }) => expr,
+            Stmt::Print(PrintStmt { expr }) => expr,
+        };
+        let anchor = anchor_of(expr);
+        self.leading_comments(anchor)?;
+        self.line_start()?;
+        match stmt {
+            Stmt::Let(LetStmt { var, .. }) => {
+                let var = var.as_ref().map(|var| var.as_str()).unwrap_or("_");
+                write!(self.f, "let {} ", kdl_string(var))?;
+            }
+            Stmt::Return(_) => write!(self.f, "return ")?,
+            Stmt::Print(_) => write!(self.f, "print ")?,
+        }
+        self.expr(anchor, expr)
+    }
+
+    /// Print an expression and finish the line.
+    fn expr(&mut self, anchor: Option<usize>, expr: &Expr) -> fmt::Result {
+        match expr {
+            Expr::Call(CallExpr { func, args }) => {
+                write!(self.f, "{}", kdl_string(&format!("{func}:")))?;
+                for arg in args {
+                    write!(self.f, " ")?;
+                    self.smol_expr(arg)?;
+                }
+                self.end_line(anchor)
+            }
+            Expr::Ctor(CtorExpr { ty, vals }) => {
+                write!(self.f, "{}", kdl_string(ty))?;
+                self.open_block(anchor)?;
+                for val in vals {
+                    let anchor = anchor_of(val);
+                    self.leading_comments(anchor)?;
+                    self.line_start()?;
+                    let var = val.var.as_ref().map(|var| var.as_str()).unwrap_or("_");
+                    write!(self.f, "{} ", kdl_ident(var))?;
+                    self.expr(anchor, &val.expr)?;
+                }
+                self.close_block()
+            }
+            Expr::Path(_) | Expr::Literal(_) => {
+                self.smol_expr(expr)?;
+                self.end_line(anchor)
+            }
+        }
+    }
+
+    /// Print an expression that can be a function argument.
+    fn smol_expr(&mut self, expr: &Expr) -> fmt::Result {
+        match expr {
+            Expr::Path(PathExpr { var, path }) => {
+                let mut full_path = var.to_string();
+                for part in path {
+                    full_path.push('.');
+                    full_path.push_str(part);
+                }
+                write!(self.f, "{}", kdl_string(&full_path))
+            }
+            Expr::Literal(LiteralExpr { val, .. }) => match val {
+                Literal::Float(val) => write!(self.f, "{val:?}"),
+                Literal::Int(val) => write!(self.f, "{val}"),
+                Literal::Bool(val) => write!(self.f, "{val}"),
+            },
+            Expr::Call(_) | Expr::Ctor(_) => {
+                self.unsupported("KDL has no syntax for nested calls or ctors")
+            }
+        }
+    }
+}
+
+/// The languages a pun selector matches, if it just matches any of a (non-empty) list of them.
+///
+/// This is all KDL can express, but nested selectors can often be flattened into it.
+fn selector_langs(selector: &PunSelector) -> Option<Vec<&Spanned<String>>> {
+    let langs = match selector {
+        PunSelector::Lang(lang) => vec![lang],
+        PunSelector::Any(selectors) => {
+            let mut langs = vec![];
+            for selector in selectors {
+                langs.extend(selector_langs(selector)?);
+            }
+            langs
+        }
+        PunSelector::All(selectors) => match &selectors[..] {
+            [selector] => selector_langs(selector)?,
+            _ => return None,
+        },
+        PunSelector::Default => return None,
+    };
+    (!langs.is_empty()).then_some(langs)
+}
+
+/// Where in the source this span starts (if it came from source at all)
+fn anchor(span: SourceSpan) -> Option<usize> {
+    // Things we made up have empty spans at the start of the file,
+    // which no real item can have (there's always a keyword first)
+    if span.offset() == 0 && span.is_empty() {
+        None
+    } else {
+        Some(span.offset())
+    }
+}
+
+fn anchor_of<T>(val: &Spanned<T>) -> Option<usize> {
+    anchor(Spanned::span(val))
+}
+
+fn ident_anchor(ident: &Ident) -> Option<usize> {
+    anchor_of(&ident.val)
+}
+
+fn typed_var_anchor(var: &TypedVar) -> Option<usize> {
+    var.name
+        .as_ref()
+        .and_then(ident_anchor)
+        .or_else(|| anchor_of(&var.ty))
+}
+
+/// Find all the comments in some KDL source (and some other stuff).
+fn scan_source(src: &str) -> Scanned {
+    let mut comments = vec![];
+    let mut close_braces = vec![];
+    let mut slashdash = None;
+    let bytes = src.as_bytes();
+    let mut line_has_code = false;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\n' => {
+                line_has_code = false;
+                idx += 1;
+            }
+            b'"' => {
+                // Skip a string (minding escapes)
+                idx += 1;
+                while idx < bytes.len() && bytes[idx] != b'"' {
+                    if bytes[idx] == b'\\' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                idx += 1;
+                line_has_code = true;
+            }
+            b'r' if matches!(bytes.get(idx + 1), Some(b'"' | b'#'))
+                && (idx == 0 || !is_ident_byte(bytes[idx - 1])) =>
+            {
+                // Skip a raw string, which ends with a quote and the same number of #'s
+                let hashes = bytes[idx + 1..].iter().take_while(|&&b| b == b'#').count();
+                let start = idx + 1 + hashes;
+                line_has_code = true;
+                if bytes.get(start) != Some(&b'"') {
+                    // Just an identifier that starts with `r`
+                    idx += 1;
+                    continue;
+                }
+                let terminator = format!("\"{}", "#".repeat(hashes));
+                idx = src[start + 1..]
+                    .find(&terminator)
+                    .map(|end| start + 1 + end + terminator.len())
+                    .unwrap_or(bytes.len());
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'/') => {
+                let end = src[idx..]
+                    .find('\n')
+                    .map(|end| idx + end)
+                    .unwrap_or(bytes.len());
+                comments.push(Comment {
+                    offset: idx,
+                    text: src[idx..end].trim_end().to_owned(),
+                    trailing: line_has_code,
+                    blank_line_after: blank_line_after(src, end),
+                });
+                idx = end;
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'*') => {
+                // Block comments nest
+                let start = idx;
+                let mut depth = 0;
+                while idx < bytes.len() {
+                    if bytes[idx..].starts_with(b"/*") {
+                        depth += 1;
+                        idx += 2;
+                    } else if bytes[idx..].starts_with(b"*/") {
+                        depth -= 1;
+                        idx += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        idx += 1;
+                    }
+                }
+                comments.push(Comment {
+                    offset: start,
+                    text: src[start..idx].to_owned(),
+                    trailing: line_has_code,
+                    blank_line_after: blank_line_after(src, idx),
+                });
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'-') => {
+                slashdash.get_or_insert(idx);
+                idx += 2;
+            }
+            b' ' | b'\t' | b'\r' => idx += 1,
+            b'}' => {
+                close_braces.push(idx);
+                line_has_code = true;
+                idx += 1;
+            }
+            _ => {
+                line_has_code = true;
+                idx += 1;
+            }
+        }
+    }
+    Scanned {
+        comments,
+        close_braces,
+        slashdash,
+    }
+}
+
+/// Whether the rest of this line and the next are empty (and there's something after that).
+fn blank_line_after(src: &str, offset: usize) -> bool {
+    if src[offset..].trim().is_empty() {
+        return false;
+    }
+    let mut lines = src[offset..].split('\n');
+    let rest_of_line = lines.next().unwrap_or_default();
+    let next_line = lines.next();
+    rest_of_line.trim().is_empty() && next_line.is_some_and(|line| line.trim().is_empty())
+}
+
+/// Whether this byte could be part of a bare identifier (roughly).
+fn is_ident_byte(byte: u8) -> bool {
+    !matches!(
+        byte,
+        b' ' | b'\t' | b'\r' | b'\n' | b'{' | b'}' | b'(' | b')' | b';' | b'=' | b'"'
+    )
+}
+
+/// An [`IntExpr`][] as a KDL value (bare if it's a literal, a string otherwise).
+fn int_expr(expr: &IntExpr) -> String {
+    match &expr.expr {
+        ConstExpr::Literal(val) => val.to_string(),
+        _ => kdl_string(&expr.to_string()),
+    }
+}
+
+/// Quote and escape a KDL string.
+pub(crate) fn kdl_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A KDL node name, left bare if that's unambiguous.
+pub(crate) fn kdl_ident(s: &str) -> String {
+    let mut chars = s.chars();
+    let is_bare = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(s, "true" | "false" | "null");
+    if is_bare {
+        s.to_owned()
+    } else {
+        kdl_string(s)
+    }
+}
@@ -1127,3 +1127,227 @@ fn aliasless_alias() {
 }
 
  */
+
+#[test]
+#[should_panic = "couldn't parse constant expression"]
+fn const_bad_expr() {
+    let program = r##"
+        const "N" "1 +"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "need a value"]
+fn const_no_value() {
+    let program = r##"
+        const "N"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "duplicate definition of const"]
+fn const_duplicate() {
+    let program = r##"
+        const "N" 1
+        const "N" 2
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "duplicate definition of type Point"]
+fn ty_duplicate() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+        }
+        union "Point" {
+            y "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "couldn't parse type"]
+fn array_bad_len_expr() {
+    let program = r##"
+        struct "Bad" {
+            x "[u8; N +]"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "formatting would delete"]
+fn fmt_slashdash() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            /-y "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.format_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+fn gather_parse_errors() {
+    let program = r##"
+        widget "w"
+        struct "Point" {
+            x "f32"
+        }
+        struct "A" "B" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::with_error_handler(crate::ErrorHandler {
+        error_style: crate::ErrorStyle::Human,
+        error_mode: crate::ErrorMode::Gather(vec![]),
+    });
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    let Err(crate::KdlScriptError::Multiple(errs)) = res else {
+        panic!("expected several errors, got {res:?}");
+    };
+    assert_eq!(errs.errors.len(), 2);
+    assert!(matches!(errs.errors[0], crate::KdlScriptError::Parse(_)));
+}
+
+#[test]
+#[should_panic = "expected `:`"]
+fn compact_field_no_type() {
+    let program = r##"
+        struct Point { x f32 }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kds", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "variants of tagged unions can't have values"]
+fn compact_tagged_value() {
+    let program = r##"
+        enum Option { None = 1, Some(i32) }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kds", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+fn compact_gather_parse_errors() {
+    let program = r##"
+        widget w;
+        struct Point { x: f32 }
+        struct Bad { x: [f32 }
+        fn ok(p: Point) -> Point;
+        fn bad(p: Point) -> ;
+        union Also { a: u32 }
+    "##;
+    let mut compiler = crate::Compiler::with_error_handler(crate::ErrorHandler {
+        error_style: crate::ErrorStyle::Human,
+        error_mode: crate::ErrorMode::Gather(vec![]),
+    });
+    let res = compiler.compile_string("test.kds", program.to_owned());
+    let Err(crate::KdlScriptError::Multiple(errs)) = res else {
+        panic!("expected several errors, got {res:?}");
+    };
+    let messages = errs
+        .errors
+        .iter()
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "I don't know what a 'widget' is",
+            "couldn't parse type",
+            "couldn't parse type",
+        ]
+    );
+}
+
+#[test]
+#[should_panic = "attribute isn't followed by a field"]
+fn field_attr_dangling() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            @align 8
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "passthrough attribute for unknown language 'cpp'"]
+fn passthrough_unknown_lang() {
+    let program = r##"
+        @cpp "alignas(8)"
+        struct "Point" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "passthrough attribute for unknown language 'é'"]
+fn passthrough_unknown_lang_unicode() {
+    let program = r##"
+        @é "alignas(8)"
+        struct "Point" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "passthrough attribute for unknown language 'cpp'"]
+fn passthrough_unknown_lang_compact() {
+    let program = r##"
+        #[passthrough(cpp, "alignas(8)")]
+        struct Point { x: f32 }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kds", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "duplicate vals block"]
+fn duplicate_vals_block() {
+    let program = r##"
+        fn "blah" {
+            inputs { x "u32"; }
+            vals { x 1; }
+            vals { x 2; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
@@ -23,3 +23,494 @@ fn no_arg_type() {
     let res = compiler.compile_string("test.kdl", program.to_owned());
     res.map_err(miette::Report::new).unwrap();
 }
+
+#[test]
+#[should_panic = "undefined const"]
+fn const_undefined() {
+    let program = r##"
+        struct "Bad" {
+            x "[u8; N]"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "defined in terms of itself"]
+fn const_cycle() {
+    let program = r##"
+        const "A" "B + 1"
+        const "B" "A * 2"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "overflowed"]
+fn const_overflow() {
+    let program = r##"
+        const "BIG" "1 << 63"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "layout depends on itself"]
+fn const_sizeof_self() {
+    let program = r##"
+        struct "Bad" {
+            x "u32"
+            y "[u8; sizeof(Bad)]"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "can't be measured"]
+fn const_sizeof_tagged() {
+    let program = r##"
+        tagged "Opt" {
+            None
+            Some { _ "u32"; }
+        }
+        const "N" "sizeof(Opt)"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "power of two"]
+fn align_not_pow2() {
+    let program = r##"
+        @align "3 * 4"
+        struct "Bad" {
+            x "u32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "can't be negative"]
+fn array_len_negative() {
+    let program = r##"
+        struct "Bad" {
+            x "[u8; 2 - 3]"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "c doesn't have tagged unions impled yet"]
+fn emit_c_tagged() {
+    let program = r##"
+        tagged "Option" {
+            None
+            Some { _ "i32"; }
+        }
+        fn "f" {
+            inputs { _ "Option"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let res = crate::emit::emit_declarations(&typed, crate::emit::EmitLang::C, &[]);
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+fn gather_type_errors() {
+    let program = r##"
+        const "Bad" "1 / 0"
+        struct "A" {
+            x "nope"
+            y "[u8; Bad]"
+        }
+        struct "B" {
+            a "A"
+        }
+        fn "f" {
+            inputs { _ "also_nope"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::with_error_handler(crate::ErrorHandler {
+        error_style: crate::ErrorStyle::Json,
+        error_mode: crate::ErrorMode::Gather(vec![]),
+    });
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    let Err(err) = res else {
+        panic!("expected errors");
+    };
+    let json = compiler.error_handler.render(&err);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    // Each problem is reported once (no cascading errors from Bad or A)
+    let related = json["related"].as_array().unwrap();
+    let messages = related
+        .iter()
+        .map(|err| err["message"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "constant expression divides by zero: 1 / 0",
+            "use of undefined type name: nope",
+            "use of undefined type name: also_nope",
+        ]
+    );
+    assert_eq!(related[0]["code"], "kdl_script::type_error");
+    assert!(related[1]["labels"][0]["span"]["offset"].is_u64());
+}
+
+#[test]
+#[should_panic = "its layout is unspecified"]
+fn layout_of_repr_rust() {
+    let program = r##"
+        @repr "rust"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let env = crate::PunEnv {
+        lang: "rust".to_owned(),
+    };
+    let ty = typed.ty_by_name("Point").unwrap();
+    let res = typed.layout_of(ty, &env, &crate::layout::DataLayout::natural());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Inner has no @repr and defaults to \"rust\", so its layout is unspecified"]
+fn layout_of_default_repr_rust() {
+    let program = r##"
+        @repr "c"
+        struct "Outer" {
+            inner "Inner"
+        }
+        struct "Inner" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let env = crate::PunEnv {
+        lang: "rust".to_owned(),
+    };
+    let ty = typed.ty_by_name("Outer").unwrap();
+    let res = typed.layout_of_with_repr(
+        ty,
+        &env,
+        &crate::layout::DataLayout::natural(),
+        crate::parse::LangRepr::Rust,
+    );
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "300 doesn't fit in u8"]
+fn eval_literal_overflow() {
+    let program = r##"
+        fn "main" {
+            outputs { _ "u8"; }
+            return 300
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "m is MaybeInt::None, not Some"]
+fn eval_wrong_variant() {
+    let program = r##"
+        tagged "MaybeInt" {
+            None
+            Some { x "i32"; }
+        }
+        fn "main" {
+            outputs { _ "i32"; }
+            let "m" "MaybeInt.None" {}
+            return "m.Some.x"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let res = compiler.eval();
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "expected i16, found u8"]
+fn body_arith_mixed_types() {
+    let program = r##"
+        fn "shift" {
+            inputs { x "u8"; y "i16"; }
+            outputs { _ "i16"; }
+            return "+:" "y" "x"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "its signature can't use puns"]
+fn body_with_pun_signature() {
+    let program = r##"
+        pun "Blah" {
+            lang "rust" {
+                alias "Blah" "u8"
+            }
+            default {
+                alias "Blah" "u16"
+            }
+        }
+        fn "id" {
+            inputs { x "Blah"; }
+            outputs { _ "Blah"; }
+            return "x"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "nothing can come after `return`"]
+fn body_stmt_after_return() {
+    let program = r##"
+        fn "id" {
+            inputs { x "u8"; }
+            outputs { _ "u8"; }
+            return "x"
+            let "y" "x"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "fields can't have @repr"]
+fn field_attr_repr() {
+    let program = r##"
+        struct "Point" {
+            @repr "u8"
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "function arguments can't have attributes"]
+fn field_attr_on_arg() {
+    let program = r##"
+        fn "blah" {
+            inputs {
+                @align 8
+                x "f32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "blah has no argument named 'y'"]
+fn val_unknown_arg() {
+    let program = r##"
+        fn "blah" {
+            inputs { x "u32"; }
+            vals { "y" 1; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "this doesn't fit in a i8"]
+fn val_doesnt_fit() {
+    let program = r##"
+        fn "blah" {
+            inputs { x "i8"; }
+            vals { x 128; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "'u' was already given a different value"]
+fn val_conflicting_variants() {
+    let program = r##"
+        union "Bits" {
+            f "f32"
+            i "i32"
+        }
+        fn "blah" {
+            inputs { u "Bits"; }
+            vals {
+                "u.f" 1.0
+                "u.i" 1
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "'p' is a Point, which can't be given a value all at once"]
+fn val_not_a_leaf() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+        fn "blah" {
+            inputs { p "Point"; }
+            vals { p 1.0; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "'x' is a pun, which can't be given values"]
+fn val_in_pun() {
+    let program = r##"
+        pun "Blah" {
+            lang "rust" {
+                alias "Blah" "u32"
+            }
+            default {
+                alias "Blah" "i32"
+            }
+        }
+        fn "blah" {
+            inputs { x "Blah"; }
+            vals { x 1; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+fn compile_parsed_points_into_pretty_source() {
+    // Even after parsing something else, a built program's errors point into its own source
+    let mut program = crate::builder::ProgramBuilder::new();
+    program.add_func(
+        crate::builder::FuncBuilder::new("f").input("x", crate::parse::Tydent::named("Nope")),
+    );
+    let program = program.build();
+
+    let mut compiler = crate::Compiler::with_error_handler(crate::ErrorHandler {
+        error_style: crate::ErrorStyle::Json,
+        error_mode: crate::ErrorMode::Scream,
+    });
+    compiler
+        .parse_string("test.kdl", r#"struct "A" { x "u8"; }"#.to_owned())
+        .unwrap();
+    let Err(err) = compiler.compile_parsed(program) else {
+        panic!("expected errors");
+    };
+    let json = compiler.error_handler.render(&err);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["message"], "use of undefined type name: Nope");
+    assert_eq!(json["filename"], "<generated>.kdl");
+}
+
+#[test]
+#[should_panic = "duplicate definition of type Point"]
+fn builder_duplicate_ty() {
+    use crate::builder::*;
+    use crate::parse::Tydent;
+
+    let mut program = ProgramBuilder::new();
+    let res = program
+        .add_ty(StructBuilder::new("Point").field("x", Tydent::named("f32")))
+        .and_then(|program| {
+            program.add_ty(StructBuilder::new("Point").field("y", Tydent::named("f32")))
+        });
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "KDL only has syntax for pun selectors"]
+fn pretty_pun_selector_all() {
+    use crate::builder::*;
+    use crate::parse::{PunSelector, TyDecl, Tydent};
+
+    let lang = |lang: &str| PunSelector::Lang(lang.to_owned().into());
+    let TyDecl::Pun(mut pun) = PunBuilder::new("P")
+        .lang(&["rust"], AliasBuilder::new("P", Tydent::named("u32")))
+        .into()
+    else {
+        unreachable!()
+    };
+    pun.blocks[0].selector = PunSelector::All(vec![lang("rust"), lang("c")]);
+    let mut program = ProgramBuilder::new();
+    program.add_ty(TyDecl::Pun(pun)).unwrap();
+    let res = program.build().to_kdl();
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "u32 is 4 bytes, so aligning it to 8 needs padding after it"]
+fn field_align_needs_padding() {
+    let program = r##"
+        struct "Header" {
+            tag "u8"
+            @align 8
+            len "u32"
+            crc "u16"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
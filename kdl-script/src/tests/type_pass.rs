@@ -259,6 +259,99 @@ fn anon_vars() -> Result<(), miette::Report> {
     Ok(())
 }
 
+#[test]
+fn const_exprs() -> Result<(), miette::Report> {
+    let program = r##"
+        const "K" 4
+        const "N" "1 << K"
+
+        struct "Header" {
+            magic "u32"
+            bytes "[u8; N - sizeof(u32)]"
+        }
+
+        @align "2 * sizeof(Header)"
+        struct "Packet" {
+            header "Header"
+            payload "[u64; (K + 1) % 3]"
+        }
+
+        enum "Flags" {
+            A "1 << 0"
+            B "1 << 1"
+            C "-0x10"
+        }
+
+        fn "send" {
+            inputs { _ "&[Packet; alignof(Packet) / 8]"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let func = typed.realize_func(0);
+    assert_eq!(typed.format_ty(func.inputs[0].ty), "&[Packet; 4]");
+    Ok(())
+}
+
+#[test]
+fn const_sizeof_forward_ref() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Early" {
+            x "[u8; sizeof(Late)]"
+        }
+        @packed
+        struct "Late" {
+            a "u8"
+            b "u64"
+            c "&Early"
+        }
+        fn "f" {
+            inputs { _ "Early"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.target = crate::layout::DataLayout::x86_64();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let crate::types::Ty::Struct(early) = typed.realize_ty(typed.realize_func(0).inputs[0].ty)
+    else {
+        panic!("expected a struct");
+    };
+    assert_eq!(typed.format_ty(early.fields[0].ty), "[u8; 17]");
+    Ok(())
+}
+
+#[test]
+fn const_sizeof_target() -> Result<(), miette::Report> {
+    use crate::layout::DataLayout;
+    let program = r##"
+        struct "Pair" {
+            a "u32"
+            b "u64"
+            c "&u8"
+        }
+        fn "f" {
+            inputs { _ "[u8; sizeof(Pair)]"; _ "[u8; alignof(Pair)]"; }
+        }
+    "##;
+    for (target, size, align) in [(DataLayout::x86_64(), 24, 8), (DataLayout::i686(), 16, 4)] {
+        let mut compiler = crate::Compiler::new();
+        compiler.target = target;
+        let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+        let inputs = &typed.realize_func(0).inputs;
+        assert_eq!(typed.format_ty(inputs[0].ty), format!("[u8; {size}]"));
+        assert_eq!(typed.format_ty(inputs[1].ty), format!("[u8; {align}]"));
+
+        // sizeof agrees with the layout the program's types get on the same target
+        let env = crate::PunEnv {
+            lang: "c".to_owned(),
+        };
+        let pair = typed.ty_by_name("Pair").unwrap();
+        let layout = typed.layout_of(pair, &env, &compiler.target).unwrap();
+        assert_eq!((layout.size, layout.align), (size, align));
+    }
+    Ok(())
+}
+
 #[test]
 fn example_types() -> Result<(), miette::Report> {
     let mut compiler = crate::Compiler::new();
@@ -279,3 +372,1157 @@ fn example_puns() -> Result<(), miette::Report> {
     compiler.compile_path("examples/puns.kdl")?;
     Ok(())
 }
+
+#[test]
+fn builder_basics() -> Result<(), miette::Report> {
+    use crate::builder::*;
+    use crate::parse::{Attr, Tydent};
+
+    let mut program = ProgramBuilder::new();
+    program
+        .add_const("N", 4)?
+        .add_ty(
+            StructBuilder::new("Point")
+                .attr(Attr::align(
+                    "2 * N".parse::<crate::parse::IntExpr>().unwrap(),
+                ))
+                .field("x", Tydent::named("f32"))
+                .field("_", Tydent::named("u8")),
+        )?
+        .add_ty(
+            EnumBuilder::new("Kind")
+                .variant("A")
+                .variant_with_val("B", 7),
+        )?
+        .add_ty(
+            TaggedBuilder::new("Option")
+                .unit_variant("None")
+                .variant("Some", [("_", Tydent::named("Point"))]),
+        )?
+        .add_ty(AliasBuilder::new("Points", "[Point; N]".parse().unwrap()))?
+        .add_func(
+            FuncBuilder::new("func")
+                .input("a", Tydent::reference(Tydent::named("Points")))
+                .output("_", Tydent::named("Option")),
+        );
+    let program = program.build();
+
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_parsed(program.clone())?;
+    let func = typed.realize_func(0);
+    assert_eq!(typed.format_ty(func.inputs[0].ty), "&Points");
+    assert_eq!(typed.format_ty(func.outputs[0].ty), "Option");
+
+    // Should be able to round-trip through kdl too
+    let mut compiler = crate::Compiler::new();
+    let reparsed = compiler.parse_string("test.kdl", program.to_kdl()?)?;
+    assert_eq!(reparsed.to_kdl()?, program.to_kdl()?);
+    compiler.compile_parsed((*reparsed).clone())?;
+    Ok(())
+}
+
+#[test]
+fn pretty_roundtrip_examples() -> Result<(), miette::Report> {
+    for example in [
+        "examples/types.kdl",
+        "examples/simple.kdl",
+        "examples/puns.kdl",
+    ] {
+        let input = std::fs::read_to_string(example).unwrap();
+        let mut compiler = crate::Compiler::new();
+        let parsed = compiler.parse_string(example, input)?;
+        let output = parsed.to_kdl()?;
+
+        let mut compiler = crate::Compiler::new();
+        let reparsed = compiler.parse_string(example, output.clone())?;
+        assert_eq!(reparsed.to_kdl()?, output);
+        compiler.compile_parsed((*reparsed).clone())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn pretty_flattens_pun_selectors() -> Result<(), miette::Report> {
+    use crate::builder::*;
+    use crate::parse::{PunSelector, TyDecl, Tydent};
+
+    let lang = |lang: &str| PunSelector::Lang(lang.to_owned().into());
+    let TyDecl::Pun(mut pun) = PunBuilder::new("P")
+        .lang(&["rust"], AliasBuilder::new("P", Tydent::named("u32")))
+        .default(AliasBuilder::new("P", Tydent::named("i32")))
+        .into()
+    else {
+        unreachable!()
+    };
+    // KDL can only say "any of these languages", but that's all these amount to
+    pun.blocks[0].selector = PunSelector::Any(vec![
+        PunSelector::Any(vec![lang("rust"), lang("c")]),
+        PunSelector::All(vec![lang("cc")]),
+    ]);
+    let mut program = ProgramBuilder::new();
+    program.add_ty(TyDecl::Pun(pun))?;
+    let output = program.build().to_kdl()?;
+    assert!(output.contains(r#"lang "rust" "c" "cc" {"#), "{output}");
+    Ok(())
+}
+
+#[test]
+fn pretty_const_exprs() -> Result<(), miette::Report> {
+    let program = r##"
+        const "A" "(1 + 2) * 3"
+        const "B" "1 + 2 * 3"
+        const "C" "-(A - B) << 2"
+        const "D" "A - (B - C)"
+        const "E" "A - B - C"
+        const "F" "sizeof([u8; A | B])"
+        struct "S" {
+            x "[u8; F]"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let parsed = compiler.parse_string("test.kdl", program.to_owned())?;
+    let output = parsed.to_kdl()?;
+    assert!(output.contains(r#"const "A" "(1 + 2) * 3""#), "{output}");
+    assert!(output.contains(r#"const "B" "1 + 2 * 3""#), "{output}");
+    assert!(output.contains(r#"const "C" "-(A - B) << 2""#), "{output}");
+    assert!(output.contains(r#"const "D" "A - (B - C)""#), "{output}");
+    assert!(output.contains(r#"const "E" "A - B - C""#), "{output}");
+    assert!(
+        output.contains(r#"const "F" "sizeof([u8; A | B])""#),
+        "{output}"
+    );
+
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", output)?;
+    Ok(())
+}
+
+#[test]
+fn fmt_preserves_comments() -> Result<(), miette::Report> {
+    let program = r##"
+// A point
+@align 8
+
+
+struct "Point" {   // so pointy
+    x "f32" ; y "f32" // the y
+    /* z "f32" */
+}
+tagged "Option" { None ; Some { _ "Point";} }
+fn "f" { inputs { _ "Point"; opt "Option"; } // the inputs
+  // no outputs!
+}
+// the end
+"##;
+    let expected = r##"// A point
+@align 8
+struct "Point" { // so pointy
+    x "f32"
+    y "f32" // the y
+    /* z "f32" */
+}
+
+tagged "Option" {
+    None
+    Some { _ "Point"; }
+}
+
+fn "f" {
+    inputs { _ "Point"; opt "Option"; } // the inputs
+    // no outputs!
+}
+
+// the end
+"##;
+    let mut compiler = crate::Compiler::new();
+    let output = compiler.format_string("test.kdl", program.to_owned())?;
+    assert_eq!(output, expected);
+
+    // Formatting is idempotent
+    let mut compiler = crate::Compiler::new();
+    let output = compiler.format_string("test.kdl", output)?;
+    assert_eq!(output, expected);
+    compiler.compile_string("test.kdl", output)?;
+    Ok(())
+}
+
+#[test]
+fn emit_c_and_rust() -> Result<(), miette::Report> {
+    use crate::emit::{emit_declarations, EmitLang};
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+        struct "Line" {
+            start "&Point"
+            end "&Point"
+        }
+        fn "len" {
+            inputs { _ "Line"; }
+            outputs { _ "f32"; }
+        }
+        fn "mid" {
+            inputs { a "Point"; b "Point"; }
+            outputs { _ "Point"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+
+    let c = emit_declarations(&typed, EmitLang::C, &[])?;
+    assert_eq!(
+        c,
+        r##"#include <stdint.h>
+#include <stdbool.h>
+
+typedef struct Point {
+    float x;
+    float y;
+} Point;
+
+typedef struct Line {
+    Point *start;
+    Point *end;
+} Line;
+
+float len(Line arg0);
+Point mid(Point a, Point b);
+
+"##
+    );
+
+    // Only the types the requested functions need get emitted
+    let mid = typed
+        .all_funcs()
+        .find(|&func| typed.realize_func(func).name.as_str() == "mid")
+        .unwrap();
+    let rust = emit_declarations(&typed, EmitLang::Rust, &[mid])?;
+    assert_eq!(
+        rust,
+        r##"#[repr(C)]
+#[derive(Copy, Clone)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+extern "C" {
+    fn mid(a: Point, b: Point) -> Point;
+}
+
+"##
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn lsp_hover_and_definition() -> Result<(), miette::Report> {
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use serde_json::{json, Value};
+
+    let program = r##"
+struct "Point" {
+    x "f32"
+    y "f32"
+}
+pun "Scalar" {
+    lang "c" {
+        alias "Scalar" "f32"
+    }
+    lang "rust" {
+        alias "Scalar" "f64"
+    }
+}
+fn "mid" {
+    inputs { a "&Point"; b "Scalar"; }
+    outputs { _ "Point"; }
+}
+fn "bad" {
+    inputs { _ "Nope"; }
+}
+"##;
+    let uri = "file:///test.kdl";
+    // The byte offset of a needle, as a line/character position
+    let position = |needle: &str| {
+        let offset = program.find(needle).unwrap();
+        let line = program[..offset].matches('\n').count();
+        let character = offset - program[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+        json!({ "line": line, "character": character + 1 })
+    };
+
+    let (server, client) = Connection::memory();
+    let server = std::thread::spawn(move || crate::lsp::serve(&server).unwrap());
+
+    let send_request = |id: i32, method: &str, params: Value| -> Value {
+        let req = Request::new(RequestId::from(id), method.to_owned(), params);
+        client.sender.send(Message::Request(req)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(resp) => resp.result.unwrap(),
+            msg => panic!("expected a response, got {msg:?}"),
+        }
+    };
+
+    let notify = |method: &str, params: Value| -> Vec<Value> {
+        let not = Notification::new(method.to_owned(), params);
+        client.sender.send(Message::Notification(not)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Notification(not) => {
+                assert_eq!(not.method, "textDocument/publishDiagnostics");
+                not.params["diagnostics"].as_array().unwrap().clone()
+            }
+            msg => panic!("expected diagnostics, got {msg:?}"),
+        }
+    };
+    let at = |needle: &str| json!({ "textDocument": { "uri": uri }, "position": position(needle) });
+
+    let diags = notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": {
+            "uri": uri, "languageId": "kdl-script", "version": 1, "text": program,
+        }}),
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0]["code"], "kdl_script::type_error");
+    assert_eq!(
+        diags[0]["range"]["start"]["line"],
+        position("\"Nope\"")["line"]
+    );
+
+    // Definitions only need the program to parse
+    let def = send_request(1, "textDocument/definition", at("&Point"));
+    assert_eq!(def["uri"], uri);
+    assert_eq!(def["range"]["start"]["line"], 1);
+
+    // Inside a pun block, the block's own decl shadows the pun
+    let def = send_request(2, "textDocument/definition", at("\"Scalar\" \"f64\""));
+    assert_eq!(def["range"]["start"]["line"], 10);
+
+    // Fix the error, and now we can see types
+    let fixed = program.split("fn \"bad\"").next().unwrap();
+    let diags = notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": fixed }],
+        }),
+    );
+    // (but the pun without a default is still worth a warning)
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0]["code"], "kdl_script::type_warning");
+    assert_eq!(diags[0]["severity"], 2);
+
+    let hover = send_request(3, "textDocument/hover", at("&Point"));
+    let hover = hover["contents"]["value"].as_str().unwrap();
+    assert!(
+        hover.contains("struct Point {\n    x: f32,\n    y: f32,\n}"),
+        "{hover}"
+    );
+
+    let hover = send_request(4, "textDocument/hover", at("\"Scalar\"; }"));
+    let hover = hover["contents"]["value"].as_str().unwrap();
+    assert!(hover.contains("// c\nalias Scalar = f32"), "{hover}");
+    assert!(hover.contains("// rust\nalias Scalar = f64"), "{hover}");
+
+    let hover = send_request(5, "textDocument/hover", at("\"mid\""));
+    let hover = hover["contents"]["value"].as_str().unwrap();
+    assert!(
+        hover.contains("fn mid(a: &Point, b: Scalar) -> (out0: Point)"),
+        "{hover}"
+    );
+
+    send_request(6, "shutdown", Value::Null);
+    client
+        .sender
+        .send(Message::Notification(Notification::new(
+            "exit".to_owned(),
+            Value::Null,
+        )))
+        .unwrap();
+    server.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn layout_of_tys() -> Result<(), miette::Report> {
+    use crate::layout::DataLayout;
+
+    let program = r##"
+        struct "Mixed" {
+            a "u8"
+            b "f64"
+            c "&u8"
+            d "[u16; 3]"
+        }
+        @packed
+        struct "Packed" {
+            a "u8"
+            b "u32"
+        }
+        @align 16
+        union "Wide" {
+            a "u8"
+            b "u32"
+        }
+        @repr "u8"
+        tagged "Small" {
+            None
+            Some { _ "u16"; }
+        }
+        tagged "Big" {
+            None
+            Some { x "u8"; y "f64"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let env = crate::PunEnv {
+        lang: "c".to_owned(),
+    };
+    let layout = |name: &str, target: &DataLayout| {
+        typed
+            .layout_of(typed.ty_by_name(name).unwrap(), &env, target)
+            .unwrap()
+    };
+    let offsets = |layout: &crate::layout::TyLayout| {
+        layout
+            .fields
+            .iter()
+            .map(|field| field.offset)
+            .collect::<Vec<_>>()
+    };
+
+    let mixed = layout("Mixed", &DataLayout::x86_64());
+    assert_eq!((mixed.size, mixed.align), (32, 8));
+    assert_eq!(offsets(&mixed), [0, 8, 16, 24]);
+    let mixed = layout("Mixed", &DataLayout::i686());
+    assert_eq!((mixed.size, mixed.align), (24, 4));
+    assert_eq!(offsets(&mixed), [0, 4, 12, 16]);
+
+    let packed = layout("Packed", &DataLayout::x86_64());
+    assert_eq!((packed.size, packed.align), (5, 1));
+    assert_eq!(offsets(&packed), [0, 1]);
+
+    let wide = layout("Wide", &DataLayout::x86_64());
+    assert_eq!((wide.size, wide.align), (16, 16));
+    assert_eq!(offsets(&wide), [0, 0]);
+
+    // repr(u8): every variant is a struct starting with the tag
+    let small = layout("Small", &DataLayout::x86_64());
+    assert_eq!((small.size, small.align), (4, 2));
+    assert_eq!(small.tag.as_ref().unwrap().size, 1);
+    assert_eq!(small.variants[1].fields[0].offset, 2);
+
+    // repr(C): the tag, followed by a union of the variants
+    let big = layout("Big", &DataLayout::x86_64());
+    assert_eq!((big.size, big.align), (24, 8));
+    assert_eq!(big.tag.as_ref().unwrap().size, 4);
+    assert!(big.variants[0].fields.is_empty());
+    let some = &big.variants[1].fields;
+    assert_eq!((some[0].offset, some[1].offset), (8, 16));
+    Ok(())
+}
+
+#[test]
+fn abi_diff_changes() -> Result<(), miette::Report> {
+    use crate::diff::abi_diff;
+    use crate::layout::DataLayout;
+
+    let old = r##"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+        enum "Mode" {
+            Fast
+            Slow
+        }
+        struct "Config" {
+            mode "Mode"
+            origin "Point"
+            len "[u8; 4]"
+        }
+        fn "configure" {
+            inputs { cfg "&Config"; flags "u32"; count "u32"; }
+        }
+        fn "reset" {}
+    "##;
+    let additive = r##"
+        alias "Float" "f32"
+        struct "Point" {
+            x "Float"
+            y "f32"
+        }
+        enum "Mode" {
+            Fast
+            Slow
+            Turbo
+        }
+        struct "Config" {
+            mode "Mode"
+            origin "Point"
+            len "[u8; 4]"
+        }
+        fn "configure" {
+            inputs { config "&Config"; flags "u32"; count "u32"; }
+        }
+        fn "reset" {}
+        fn "launch" {
+            inputs { _ "Mode"; }
+        }
+    "##;
+    let breaking = r##"
+        struct "Point" {
+            y "f32"
+            x "f32"
+        }
+        enum "Mode" {
+            Slow
+            Fast
+        }
+        struct "Config" {
+            mode "Mode"
+            origin "Point"
+            len "[u8; 8]"
+        }
+        fn "configure" {
+            inputs { cfg "&Config"; flags "u64"; count "u64"; }
+        }
+    "##;
+    let compile = |src: &str| crate::Compiler::new().compile_string("test.kdl", src.to_owned());
+    let old = compile(old)?;
+    let env = crate::PunEnv {
+        lang: "c".to_owned(),
+    };
+    let target = DataLayout::x86_64();
+
+    let diff = abi_diff(&old, &*compile(additive)?, &env, &target)?;
+    assert!(!diff.is_breaking(), "{:#?}", diff.changes);
+    let changes = diff
+        .changes
+        .iter()
+        .map(|change| change.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            "added: Mode::Turbo: case was added",
+            "added: launch: function was added",
+        ]
+    );
+
+    let diff = abi_diff(&old, &*compile(breaking)?, &env, &target)?;
+    let changes = diff
+        .breaking()
+        .map(|change| change.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            "breaking: Mode::Fast: value changed from 0 to 1",
+            "breaking: Mode::Slow: value changed from 1 to 0",
+            "breaking: Point.x: field moved from position 0 to 1",
+            "breaking: Point.y: field moved from position 1 to 0",
+            "breaking: Config.len: array length changed from 4 to 8",
+            "breaking: Config: size changed from 16 to 20",
+            "breaking: configure(input flags): type changed from u32 to u64",
+            "breaking: configure(input count): type changed from u32 to u64",
+            "breaking: reset: function was removed",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn compact_syntax_matches_kdl() -> Result<(), miette::Report> {
+    let kdl = r##"
+        const "N" 4
+        @repr "c"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+        struct "Meters" {
+            _ "f32"
+        }
+        @packed
+        @align "2 * N"
+        union "Bits" {
+            f "f32"
+            u "u32"
+        }
+        enum "Mode" {
+            Fast
+            Slow "1 << N"
+        }
+        @repr "u8"
+        tagged "Shape" {
+            Empty
+            Circle {
+                _ "f32"
+            }
+            Line {
+                start "Point"
+                end "&Point"
+            }
+        }
+        tagged "Unit" {
+            A
+            B
+        }
+        alias "Coords" "[Point; N]"
+        pun "Size" {
+            lang "c" "cpp" {
+                alias "Size" "u32"
+            }
+            default {
+                @ "derive(Debug)"
+                struct "Size" {
+                    _ "u64"
+                }
+            }
+        }
+        fn "scale" {
+            inputs {
+                p "&Point"
+                _ "f32"
+            }
+            outputs {
+                _ "Point"
+            }
+        }
+        fn "split" {
+            inputs {
+                p "Point"
+            }
+            outputs {
+                x "f32"
+                y "f32"
+            }
+        }
+        fn "noop" {
+        }
+    "##;
+    let compact = r##"
+        // comments work too
+        const N = 4;
+        #[repr(C)]
+        struct Point { x: f32, y: f32 }
+        struct Meters(f32);
+        #[packed] #[align(2 * N)]
+        union Bits { f: f32, u: u32, }
+        enum Mode { Fast, Slow = 1 << N }
+        #[repr(u8)]
+        enum Shape {
+            Empty,
+            Circle(f32),
+            Line { start: Point, end: &Point },
+        }
+        tagged Unit { A, B }
+        type Coords = [Point; N];
+        /* puns pick a type per language */
+        pun Size {
+            lang(c, cpp) { type Size = u32; }
+            default {
+                #[passthrough("derive(Debug)")]
+                struct Size(u64);
+            }
+        }
+        fn scale(p: &Point, _: f32) -> Point;
+        fn split(p: Point) -> (x: f32, y: f32);
+        fn noop() {}
+    "##;
+    let from_kdl = crate::Compiler::new().parse_string("test.kdl", kdl.to_owned())?;
+    let from_compact = crate::Compiler::new().parse_string("test.kds", compact.to_owned())?;
+    assert_eq!(from_compact.to_kdl()?, from_kdl.to_kdl()?);
+
+    // And it typechecks like any other program
+    let typed = crate::Compiler::new().compile_string("test.kds", compact.to_owned())?;
+    let scale = typed.realize_func(0);
+    assert_eq!(typed.format_ty(scale.inputs[0].ty), "&Point");
+    Ok(())
+}
+
+#[test]
+fn definition_graph_dot() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Node" {
+            val "u32"
+            next "Link"
+        }
+        tagged "Link" {
+            None
+            Some { _ "&Node"; }
+        }
+        struct "Lonely" {
+            x "f64"
+        }
+        fn "push" {
+            inputs { _ "&Node"; }
+        }
+        fn "lonely" {
+            inputs { _ "Lonely"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let env = crate::PunEnv {
+        lang: "c".to_owned(),
+    };
+    let graph = typed.definition_graph(&env)?;
+    let push = typed
+        .all_funcs()
+        .find(|&f| *typed.realize_func(f).name == *"push")
+        .unwrap();
+
+    let dot = graph.to_dot(&typed, Some(&[push]));
+    assert!(dot.starts_with("digraph definitions {\n"));
+    assert!(dot.contains("label=\"fn push\", shape=box"));
+    assert!(!dot.contains("Lonely"));
+    // Node, Link, and &Node all need each other
+    let (_, cycle) = dot.split_once("subgraph cluster_0 {").unwrap();
+    let (cycle, rest) = cycle.split_once('}').unwrap();
+    assert!(cycle.contains("label=\"Node\", style=bold"));
+    assert!(cycle.contains("label=\"Link\", style=bold"));
+    assert!(cycle.contains("label=\"&Node\""));
+    assert!(!cycle.contains("u32"));
+    assert!(!rest.contains("cluster_1"));
+    // Only the pointee edge is by-reference
+    assert_eq!(dot.matches("[style=dashed]").count(), 1);
+
+    let dot = graph.to_dot(&typed, None);
+    assert!(dot.contains("label=\"Lonely\", style=bold"));
+    assert!(dot.contains("label=\"fn lonely\", shape=box"));
+    Ok(())
+}
+
+#[test]
+fn eval_func_bodies() -> Result<(), miette::Report> {
+    use crate::eval::Val;
+    use crate::types::PrimitiveTy;
+
+    let program = r##"
+        struct "Point" {
+            x "u8"
+            y "i16"
+        }
+        tagged "MaybePoint" {
+            None
+            Some { _ "Point"; }
+        }
+        fn "shift" {
+            inputs { p "&Point"; by "[u8; 2]"; }
+            outputs { _ "MaybePoint"; }
+            let "x" "+:" "p.x" "by.0"
+            let "y" "-:" "p.y" 1
+            let "moved" "Point" {
+                x "x"
+                y "y"
+            }
+            return "MaybePoint.Some" {
+                _ "moved"
+            }
+        }
+        fn "unwrap_y" {
+            inputs { m "MaybePoint"; }
+            outputs { _ "i16"; }
+            return "*:" "m.Some.field0.y" 2
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let func = |name: &str| {
+        typed
+            .all_funcs()
+            .find(|&f| *typed.realize_func(f).name == *name)
+            .unwrap()
+    };
+    let point = Val::Struct(
+        typed.ty_by_name("Point").unwrap(),
+        vec![
+            Val::Int(PrimitiveTy::U8, 250),
+            Val::Int(PrimitiveTy::I16, 0x8000),
+        ],
+    );
+    let by = Val::Array(vec![
+        Val::Int(PrimitiveTy::U8, 10),
+        Val::Int(PrimitiveTy::U8, 0),
+    ]);
+
+    // u8 and i16 both wrap
+    let shifted = typed.eval_func(func("shift"), vec![Val::Ref(Box::new(point)), by])?;
+    assert_eq!(
+        typed.format_val(&shifted[0]),
+        "MaybePoint::Some { field0: Point { x: 4, y: 32767 } }"
+    );
+    let y = typed.eval_func(func("unwrap_y"), shifted)?;
+    assert_eq!(y, vec![Val::Int(PrimitiveTy::I16, 0xfffe)]);
+    assert_eq!(typed.format_val(&y[0]), "-2");
+    Ok(())
+}
+
+#[test]
+fn lint_warnings() -> Result<(), miette::Report> {
+    use crate::lint::Lint;
+
+    let program = r##"
+        struct "Empty" {}
+        struct "Unused" {
+            x "u8"
+        }
+        struct "Used" {
+            x "Inner"
+        }
+        struct "Inner" {
+            y "u32"
+        }
+        pun "Blah" {
+            lang "rust" {
+                alias "Blah" "u8"
+            }
+        }
+        fn "nothing" {}
+        fn "outparam" {
+            inputs { _ "Used"; _ "Blah"; _ "Empty"; }
+            outputs { out "&u32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let lints = typed
+        .warnings()
+        .iter()
+        .map(|warning| (warning.lint, warning.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lints,
+        vec![
+            (Lint::EmptyStruct, "Empty has no fields"),
+            (Lint::UnusedType, "Unused is never used"),
+            (Lint::PunWithoutDefault, "Blah has no default block"),
+            (Lint::EmptyFunc, "nothing has no inputs or outputs"),
+            (
+                Lint::RefInOutput,
+                "output out of outparam contains a reference"
+            ),
+        ]
+    );
+
+    // Types with no functions to use them aren't unused
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    assert!(typed.warnings().is_empty());
+    Ok(())
+}
+
+#[test]
+fn emit_keywords() -> Result<(), miette::Report> {
+    use crate::emit::{emit_declarations, EmitLang};
+    let program = r##"
+        struct "type" {
+            int "u8"
+            match "i16"
+            self "u32"
+        }
+        enum "do" {
+            while
+            Self
+        }
+        fn "int" {
+            inputs { match "type"; dir "do"; }
+            outputs { _ "type"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+
+    let c = emit_declarations(&typed, EmitLang::C, &[])?;
+    assert_eq!(
+        c,
+        r##"#include <stdint.h>
+#include <stdbool.h>
+
+typedef enum do_ {
+    do__while,
+    do__Self,
+} do_;
+
+typedef struct type {
+    uint8_t int_;
+    int16_t match;
+    uint32_t self;
+} type;
+
+type int_(type match, do_ dir);
+
+"##
+    );
+    let rust = emit_declarations(&typed, EmitLang::Rust, &[])?;
+    // Function names are symbols, so they're spelled the same as in C
+    assert_eq!(
+        rust,
+        r##"#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum r#do {
+    r#while,
+    Self_,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct r#type {
+    int: u8,
+    r#match: i16,
+    self_: u32,
+}
+
+extern "C" {
+    fn int_(r#match: r#type, dir: r#do) -> r#type;
+}
+
+"##
+    );
+    Ok(())
+}
+
+#[test]
+fn emit_keywords_stay_unique() -> Result<(), miette::Report> {
+    use crate::emit::{emit_declarations, EmitLang};
+    let program = r##"
+        struct "int" {
+            int "u8"
+            int_ "u16"
+        }
+        struct "int_" {
+            x "u32"
+        }
+        fn "float" {
+            inputs { a "int"; b "int_"; }
+        }
+        fn "float_" {
+            inputs { a "int"; long "u8"; long_ "u16"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+
+    let c = emit_declarations(&typed, EmitLang::C, &[])?;
+    assert_eq!(
+        c,
+        r##"#include <stdint.h>
+#include <stdbool.h>
+
+typedef struct int_ {
+    uint32_t x;
+} int_;
+
+typedef struct int__ {
+    uint8_t int__;
+    uint16_t int_;
+} int__;
+
+void float__(int__ a, int_ b);
+void float_(int__ a, uint8_t long__, uint16_t long_);
+
+"##
+    );
+    let rust = emit_declarations(&typed, EmitLang::Rust, &[])?;
+    assert!(rust.contains("    fn float__(a: int, b: int_);\n"));
+    assert!(rust.contains("    fn float_(a: int, long: u8, long_: u16);\n"));
+    Ok(())
+}
+
+#[test]
+fn field_attrs() -> Result<(), miette::Report> {
+    use crate::emit::{emit_declarations, EmitLang};
+    use crate::layout::DataLayout;
+
+    let program = r##"
+        struct "Header" {
+            tag "u8"
+            @align 8
+            len "[u32; 2]"
+            @packed
+            crc "u64"
+            @align 8
+            flags "u16"
+        }
+        union "Slot" {
+            a "u8"
+            @align 4
+            b "u16"
+        }
+        fn "send" {
+            inputs { header "Header"; slot "Slot"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", program.to_owned())?;
+    let env = crate::PunEnv {
+        lang: "c".to_owned(),
+    };
+
+    let header = typed
+        .layout_of(
+            typed.ty_by_name("Header").unwrap(),
+            &env,
+            &DataLayout::x86_64(),
+        )
+        .unwrap();
+    assert_eq!((header.size, header.align), (32, 8));
+    let offsets = header.fields.iter().map(|f| f.offset).collect::<Vec<_>>();
+    assert_eq!(offsets, [0, 8, 16, 24]);
+
+    let slot = typed
+        .layout_of(
+            typed.ty_by_name("Slot").unwrap(),
+            &env,
+            &DataLayout::x86_64(),
+        )
+        .unwrap();
+    assert_eq!((slot.size, slot.align), (4, 4));
+
+    let c = emit_declarations(&typed, EmitLang::C, &[])?;
+    assert!(c.contains("    _Alignas(8) uint32_t len[2];\n"));
+    assert!(c.contains("    uint64_t crc __attribute__((packed));\n"));
+    assert!(c.contains("    _Alignas(4) uint16_t b;\n"));
+
+    // Rust can't align or pack a single field, so they get wrapped instead
+    let rust = emit_declarations(&typed, EmitLang::Rust, &[])?;
+    assert_eq!(rust.matches("struct FieldAlign8<T>(T);").count(), 1);
+    assert!(rust.contains("#[repr(C, packed)]\n#[derive(Copy, Clone)]\nstruct FieldPacked<T>(T);"));
+    assert!(rust.contains("    len: FieldAlign8<[u32; 2]>,\n"));
+    assert!(rust.contains("    crc: FieldPacked<u64>,\n"));
+    assert!(rust.contains("    b: FieldAlign4<u16>,\n"));
+    Ok(())
+}
+
+#[test]
+fn lang_passthrough_attrs() -> Result<(), miette::Report> {
+    use crate::emit::{emit_declarations, EmitLang};
+
+    let kdl = r##"
+        @c "__attribute__((ms_struct))"
+        @rust "#[derive(Debug)]"
+        struct "Point" {
+            @rust "#[allow(dead_code)]"
+            x "f32"
+            @c "volatile"
+            y "f32"
+        }
+        fn "draw" {
+            inputs { p "Point"; }
+        }
+    "##;
+    let compact = r##"
+        #[passthrough(c, "__attribute__((ms_struct))")]
+        #[passthrough(rust, "#[derive(Debug)]")]
+        struct Point {
+            #[passthrough(rust, "#[allow(dead_code)]")]
+            x: f32,
+            #[passthrough(c, "volatile")]
+            y: f32,
+        }
+        fn draw(p: Point);
+    "##;
+    let from_kdl = crate::Compiler::new().parse_string("test.kdl", kdl.to_owned())?;
+    let from_compact = crate::Compiler::new().parse_string("test.kds", compact.to_owned())?;
+    assert_eq!(from_compact.to_kdl()?, from_kdl.to_kdl()?);
+    let reparsed = crate::Compiler::new().parse_string("test.kdl", from_kdl.to_kdl()?)?;
+    assert_eq!(reparsed.to_kdl()?, from_kdl.to_kdl()?);
+
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", kdl.to_owned())?;
+
+    let c = emit_declarations(&typed, EmitLang::C, &[])?;
+    assert!(c.contains("typedef struct __attribute__((ms_struct)) Point {\n"));
+    assert!(c.contains("    float x;\n    volatile float y;\n"));
+    assert!(!c.contains("derive"));
+
+    let rust = emit_declarations(&typed, EmitLang::Rust, &[])?;
+    assert!(rust.contains("#[repr(C)]\n#[derive(Debug)]\n"));
+    assert!(rust.contains("    #[allow(dead_code)]\n    x: f32,\n    y: f32,\n"));
+    assert!(!rust.contains("ms_struct") && !rust.contains("volatile"));
+    Ok(())
+}
+
+#[test]
+fn explicit_vals() -> Result<(), miette::Report> {
+    use crate::vals::{ExplicitVal, ExplicitValKind};
+
+    let kdl = r##"
+        struct "Point" {
+            x "f32"
+            y "i16"
+        }
+        tagged "MaybePoint" {
+            None
+            Some { _ "Point"; }
+        }
+        union "Bits" {
+            f "f64"
+            u "u64"
+        }
+        alias "Flag" "bool"
+        fn "draw" {
+            inputs {
+                pts "[Point; 2]"
+                maybe "&MaybePoint"
+                bits "Bits"
+                big "u128"
+                flag "Flag"
+            }
+            outputs { other "MaybePoint"; }
+            vals {
+                "pts[1].x" 1.5
+                "pts[0].y" "0xffff"
+                "maybe.*.Some.field0.y" -2
+                bits "u"
+                big "0xffff_ffff_ffff_ffff_ffff_ffff_ffff_fffe"
+                flag true
+                other "None"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let typed = compiler.compile_string("test.kdl", kdl.to_owned())?;
+    let func = typed.all_funcs().next().unwrap();
+    let vals = &typed.realize_func(func).vals;
+    let val = |path: &str, kind| ExplicitVal {
+        path: path.to_owned(),
+        kind,
+    };
+    let mut big = vec![0xff; 16];
+    big[0] = 0xfe;
+    assert_eq!(
+        vals,
+        &[
+            val(
+                "pts[1].x",
+                ExplicitValKind::Bytes(1.5f32.to_le_bytes().to_vec())
+            ),
+            val("pts[0].y", ExplicitValKind::Bytes(vec![0xff, 0xff])),
+            val("maybe.*", ExplicitValKind::Variant(1)),
+            val(
+                "maybe.*.Some.field0.y",
+                ExplicitValKind::Bytes(vec![0xfe, 0xff])
+            ),
+            val("bits", ExplicitValKind::Variant(1)),
+            val("big", ExplicitValKind::Bytes(big)),
+            val("flag", ExplicitValKind::Bytes(vec![1])),
+            val("other", ExplicitValKind::Variant(0)),
+        ]
+    );
+
+    let parsed = crate::Compiler::new().parse_string("test.kdl", kdl.to_owned())?;
+    let reparsed = crate::Compiler::new().parse_string("test.kdl", parsed.to_kdl()?)?;
+    assert_eq!(reparsed.to_kdl()?, parsed.to_kdl()?);
+    Ok(())
+}
@@ -50,6 +50,7 @@ use petgraph::graph::DiGraph;
 use petgraph::graph::NodeIndex;
 use thiserror::Error;
 
+use crate::layout::{DataLayout, TyLayout};
 use crate::parse::*;
 use crate::spanned::*;
 use crate::Compiler;
@@ -58,6 +59,7 @@ use crate::Result;
 /// An error that occured while processing the types of a program.
 #[derive(Debug, Error, Diagnostic)]
 #[error("{message}")]
+#[diagnostic(code(kdl_script::type_error))]
 pub struct KdlScriptTypeError {
     pub message: String,
     #[source_code]
@@ -85,6 +87,7 @@ pub struct TypedProgram {
     tcx: TyCtx,
     funcs: Vec<Func>,
     builtin_funcs_start: usize,
+    warnings: Vec<crate::lint::KdlScriptTypeWarning>,
 }
 
 /// A type id
@@ -152,9 +155,11 @@ pub struct Func {
     pub outputs: Vec<Arg>,
     /// Any attributes hanging off the function
     pub attrs: Vec<Attr>,
+    /// Values the function's arguments were explicitly given (the `vals` block)
+    pub vals: Vec<crate::vals::ExplicitVal>,
     #[cfg(feature = "eval")]
-    /// The body of the function (TBD, not needed for abi-cafe)
-    pub body: (),
+    /// The body of the function (empty if it only has a signature)
+    pub body: crate::body::TypedBody,
 }
 
 /// A function argument (input or output).
@@ -260,7 +265,9 @@ pub struct EnumTy {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct EnumVariantTy {
     pub name: Ident,
-    // pub val: LiteralExpr,
+    /// The value this case is required to have in its underlying
+    /// integer representation (if one was specified).
+    pub val: Option<i64>,
 }
 
 /// The Ty of a tagged union (rust-style enum).
@@ -302,6 +309,8 @@ pub struct FieldTy {
     pub idx: usize,
     pub ident: Ident,
     pub ty: TyIdx,
+    /// Attributes like `@align` (always empty for the fields of a [`TaggedTy`][]).
+    pub attrs: Vec<Attr>,
 }
 
 /// The Ty of a fixed length array.
@@ -410,6 +419,29 @@ pub(crate) struct TyCtx {
 
     ty_facts: HashMap<TyIdx, TypeFact>,
 
+    /// Nominal types that have been declared but not yet completed.
+    ///
+    /// These are usually completed in declaration order, but constant
+    /// expressions like `sizeof(MyType)` can force one to be completed early.
+    pending_tys: HashMap<TyIdx, TyDecl>,
+
+    /// Nominal types that are in the middle of being completed (to catch cycles).
+    completing_tys: HashSet<TyIdx>,
+
+    /// The values of the named constants we've evaluated so far.
+    consts: HashMap<Ident, i64>,
+
+    /// Named constants we haven't evaluated yet.
+    ///
+    /// Like `pending_tys`, these are evaluated lazily so they can be declared in any order.
+    pending_consts: HashMap<Ident, ConstDecl>,
+
+    /// Named constants that are in the middle of being evaluated (to catch cycles).
+    evaluating_consts: HashSet<Ident>,
+
+    /// The target `sizeof` and `alignof` measure types for.
+    target: DataLayout,
+
     /// Scoped type info, reflecting the fact that struct definitions
     /// and variables come in and out of scope.
     ///
@@ -440,6 +472,12 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
         tys: vec![],
         ty_map: HashMap::new(),
         ty_facts: HashMap::new(),
+        pending_tys: HashMap::new(),
+        completing_tys: HashSet::new(),
+        consts: HashMap::new(),
+        pending_consts: HashMap::new(),
+        evaluating_consts: HashSet::new(),
+        target: comp.target.clone(),
         envs: vec![],
     };
 
@@ -455,62 +493,126 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
     });
 
     // Add all the user defined types
-    for (ty_name, _ty_decl) in &parsed.tys {
-        let _ty_idx = tcx.push_nominal_decl_incomplete(ty_name.clone());
-    }
+    let mut user_tys = vec![];
     for (ty_name, ty_decl) in &parsed.tys {
-        tcx.complete_nominal_decl(ty_name, ty_decl)?;
+        let ty_idx = tcx.push_nominal_decl_incomplete(ty_name.clone());
+        tcx.pending_tys.insert(ty_idx, ty_decl.clone());
+        user_tys.push(ty_idx);
     }
 
-    let funcs = parsed
-        .funcs
-        .iter()
-        .map(|(_func_name, func_decl)| -> Result<Func> {
-            let inputs = func_decl
-                .inputs
-                .iter()
-                .enumerate()
-                .map(|(idx, var)| -> Result<Arg> {
-                    let name = ident_var(var.name.clone(), "arg", idx, &var.ty);
-                    let ty = tcx.memoize_ty(&var.ty)?;
-                    Ok(Arg { name, ty })
-                })
-                .collect::<Result<Vec<_>>>()?;
-            let outputs = func_decl
-                .outputs
-                .iter()
-                .enumerate()
-                .map(|(idx, var)| {
-                    let name = ident_var(var.name.clone(), "out", idx, &var.ty);
-                    let ty = tcx.memoize_ty(&var.ty)?;
-                    Ok(Arg { name, ty })
-                })
-                .collect::<Result<Vec<_>>>()?;
-
-            let name = func_decl.name.clone();
-            let attrs = func_decl.attrs.clone();
-            Ok(Func {
-                name,
-                inputs,
-                outputs,
-                attrs,
-                body: (),
-            })
-        })
-        .collect::<Result<Vec<_>>>()?;
+    // Evaluate all the constants (which may complete some types if they use sizeof)
+    for (const_name, const_decl) in &parsed.consts {
+        tcx.pending_consts
+            .insert(const_name.clone(), const_decl.clone());
+    }
+    for const_name in parsed.consts.keys() {
+        if let Err(e) = tcx.eval_const(const_name) {
+            comp.error_handler.report(e)?;
+        }
+    }
+
+    // Complete whatever types are left
+    for &ty_idx in &user_tys {
+        if let Err(e) = tcx.complete_pending_decl(ty_idx) {
+            comp.error_handler.report(e)?;
+        }
+    }
+
+    // Aligned fields are checked once every type can be measured
+    for ty_idx in 0..tcx.tys.len() {
+        if let Err(e) = tcx.check_aligned_fields(ty_idx) {
+            comp.error_handler.report(e)?;
+        }
+    }
+
+    let mut funcs = vec![];
+    for func_decl in parsed.funcs.values() {
+        match tcx.check_func(func_decl) {
+            Ok(func) => funcs.push(func),
+            Err(e) => comp.error_handler.report(e)?,
+        }
+    }
 
     // Now that everything's added, compute some facts
-    tcx.compute_ty_facts()?;
+    if let Err(e) = tcx.compute_ty_facts() {
+        comp.error_handler.report(e)?;
+    }
 
     let builtin_funcs_start = parsed.builtin_funcs_start;
-    Ok(TypedProgram {
+    let mut program = TypedProgram {
         tcx,
         funcs,
         builtin_funcs_start,
-    })
+        warnings: vec![],
+    };
+
+    // Explicit values can point anywhere inside the arguments, so every type has to be done
+    for func in program.all_funcs() {
+        let Some(func_decl) = parsed.funcs.get(&program.funcs[func].name) else {
+            continue;
+        };
+        match crate::vals::typeck_vals(&program, func, &func_decl.vals) {
+            Ok(vals) => program.funcs[func].vals = vals,
+            Err(e) => comp.error_handler.report(e)?,
+        }
+    }
+
+    // Bodies can call any function, so they're checked once every signature is known
+    #[cfg(feature = "eval")]
+    for func in program.all_funcs() {
+        let Some(func_decl) = parsed.funcs.get(&program.funcs[func].name) else {
+            continue;
+        };
+        match crate::body::typeck_body(&program, func, &func_decl.body) {
+            Ok(body) => program.funcs[func].body = body,
+            Err(e) => comp.error_handler.report(e)?,
+        }
+    }
+
+    program.warnings = crate::lint::lint_program(&program, &user_tys);
+
+    Ok(program)
 }
 
 impl TyCtx {
+    /// Resolve the types of a function's signature.
+    fn check_func(&mut self, func_decl: &FuncDecl) -> Result<Func> {
+        let inputs = func_decl
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(idx, var)| -> Result<Arg> {
+                self.no_var_attrs(var, "function arguments")?;
+                let name = ident_var(var.name.clone(), "arg", idx, &var.ty);
+                let ty = self.memoize_ty(&var.ty)?;
+                Ok(Arg { name, ty })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let outputs = func_decl
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(idx, var)| {
+                self.no_var_attrs(var, "function outputs")?;
+                let name = ident_var(var.name.clone(), "out", idx, &var.ty);
+                let ty = self.memoize_ty(&var.ty)?;
+                Ok(Arg { name, ty })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let name = func_decl.name.clone();
+        let attrs = self.eval_attrs(&func_decl.attrs)?;
+        Ok(Func {
+            name,
+            inputs,
+            outputs,
+            attrs,
+            vals: vec![],
+            #[cfg(feature = "eval")]
+            body: Default::default(),
+        })
+    }
+
     /// Add the builtin types to the TyCtx
     fn add_builtins(&mut self) {
         let builtins = PRIMITIVES
@@ -546,16 +648,27 @@ impl TyCtx {
     }
 
     /// Complete a nominal decl created with [`TyCtx::push_nominal_decl_incomplete`][].
-    fn complete_nominal_decl(&mut self, ty_name: &Ident, ty_decl: &TyDecl) -> Result<()> {
-        // This failing is an ICE and not a user issue!
-        let ty_idx = self
-            .resolve_nominal_ty(ty_name)
-            .expect("completing a nominal ty that hasn't been decl'd");
-        let ty = self.memoize_nominal_parts(ty_decl)?;
-        self.tys[ty_idx] = ty;
+    fn complete_nominal_decl(&mut self, ty_idx: TyIdx, ty_decl: &TyDecl) -> Result<()> {
+        self.completing_tys.insert(ty_idx);
+        let ty = self.memoize_nominal_parts(ty_decl);
+        self.completing_tys.remove(&ty_idx);
+        self.tys[ty_idx] = ty?;
         Ok(())
     }
 
+    /// Complete a top-level nominal decl in `pending_tys`, if it hasn't been already.
+    fn complete_pending_decl(&mut self, ty_idx: TyIdx) -> Result<()> {
+        let Some(ty_decl) = self.pending_tys.remove(&ty_idx) else {
+            return Ok(());
+        };
+        // We might have been forced from inside a pun block, so make sure
+        // only the top-level scopes are visible while we complete this.
+        let inner_envs = self.envs.split_off(2);
+        let res = self.complete_nominal_decl(ty_idx, &ty_decl);
+        self.envs.extend(inner_envs);
+        res
+    }
+
     /// Memoize the parts of a nominal ty.
     fn memoize_nominal_parts(&mut self, ty_decl: &TyDecl) -> Result<Ty> {
         let ty = match ty_decl {
@@ -569,6 +682,7 @@ impl TyCtx {
                             idx,
                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
                             ty: self.memoize_ty(&f.ty)?,
+                            attrs: self.eval_field_attrs(f)?,
                         })
                     })
                     .collect::<Result<Vec<_>>>()?;
@@ -576,7 +690,7 @@ impl TyCtx {
                 Ty::Struct(StructTy {
                     name: decl.name.clone(),
                     fields,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                     all_fields_were_blank,
                 })
             }
@@ -590,27 +704,36 @@ impl TyCtx {
                             idx,
                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
                             ty: self.memoize_ty(&f.ty)?,
+                            attrs: self.eval_field_attrs(f)?,
                         })
                     })
                     .collect::<Result<Vec<_>>>()?;
                 Ty::Union(UnionTy {
                     name: decl.name.clone(),
                     fields,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                 })
             }
             TyDecl::Enum(decl) => {
                 let variants = decl
                     .variants
                     .iter()
-                    .map(|v| EnumVariantTy {
-                        name: v.name.clone(),
+                    .map(|v| {
+                        let val = v
+                            .val
+                            .as_ref()
+                            .map(|val| self.eval_int_expr(val))
+                            .transpose()?;
+                        Ok(EnumVariantTy {
+                            name: v.name.clone(),
+                            val,
+                        })
                     })
-                    .collect::<Vec<_>>();
+                    .collect::<Result<Vec<_>>>()?;
                 Ty::Enum(EnumTy {
                     name: decl.name.clone(),
                     variants,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                 })
             }
             TyDecl::Tagged(decl) => {
@@ -624,10 +747,12 @@ impl TyCtx {
                                     .iter()
                                     .enumerate()
                                     .map(|(idx, f)| {
+                                        self.no_var_attrs(f, "fields of tagged unions")?;
                                         Ok(FieldTy {
                                             idx,
                                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
                                             ty: self.memoize_ty(&f.ty)?,
+                                            attrs: vec![],
                                         })
                                     })
                                     .collect::<Result<Vec<_>>>()?,
@@ -650,7 +775,7 @@ impl TyCtx {
                 Ty::Tagged(TaggedTy {
                     name: decl.name.clone(),
                     variants,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                 })
             }
             TyDecl::Alias(decl) => {
@@ -658,7 +783,7 @@ impl TyCtx {
                 Ty::Alias(AliasTy {
                     name: decl.name.clone(),
                     real: real_ty,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                 })
             }
             TyDecl::Pun(decl) => {
@@ -666,14 +791,16 @@ impl TyCtx {
                     .blocks
                     .iter()
                     .map(|block| {
-                        // !!! If this ever becomes fallible we'll want a proper stack guard to pop!
+                        // Pop the block's env even if it failed, so the rest of the
+                        // program still typechecks in the right env
                         self.envs.push(CheckEnv {
                             tys: HashMap::new(),
                         });
                         let real_decl = &block.decl;
                         let real = self.push_nominal_decl_incomplete(decl.name.clone());
-                        self.complete_nominal_decl(&decl.name, real_decl)?;
+                        let result = self.complete_nominal_decl(real, real_decl);
                         self.envs.pop();
+                        result?;
 
                         Ok(PunBlockTy {
                             selector: block.selector.clone(),
@@ -685,7 +812,7 @@ impl TyCtx {
                 Ty::Pun(PunTy {
                     name: decl.name.clone(),
                     blocks,
-                    attrs: decl.attrs.clone(),
+                    attrs: self.eval_attrs(&decl.attrs)?,
                 })
             }
         };
@@ -714,9 +841,18 @@ impl TyCtx {
                 let pointee_ty = self.memoize_ty(pointee_ty_ref)?;
                 self.memoize_inner(Ty::Ref(RefTy { pointee_ty }))
             }
-            Tydent::Array(elem_ty_ref, len) => {
+            Tydent::Array(elem_ty_ref, len_expr) => {
                 let elem_ty = self.memoize_ty(elem_ty_ref)?;
-                self.memoize_inner(Ty::Array(ArrayTy { elem_ty, len: *len }))
+                let len = self.eval_int_expr(len_expr)?;
+                let Ok(len) = u64::try_from(len) else {
+                    return Err(KdlScriptTypeError {
+                        message: format!("array length can't be negative (it's {len})"),
+                        src: self.src.clone(),
+                        span: len_expr.span,
+                        help: None,
+                    })?;
+                };
+                self.memoize_inner(Ty::Array(ArrayTy { elem_ty, len }))
             }
             Tydent::Name(name) => {
                 // Nominal types take a separate path because they're scoped
@@ -751,6 +887,270 @@ impl TyCtx {
         }
     }
 
+    /// Evaluate any constant expressions in these attributes, folding them to literals.
+    fn eval_attrs(&mut self, attrs: &[Attr]) -> Result<Vec<Attr>> {
+        attrs
+            .iter()
+            .map(|attr| -> Result<Attr> {
+                let attr = match attr {
+                    Attr::Align(AttrAligned { align }) => {
+                        let val = self.eval_int_expr(align)?;
+                        if val <= 0 || val.count_ones() != 1 {
+                            return Err(KdlScriptTypeError {
+                                message: format!("alignment must be a power of two (it's {val})"),
+                                src: self.src.clone(),
+                                span: align.span,
+                                help: None,
+                            })?;
+                        }
+                        Attr::Align(AttrAligned {
+                            align: IntExpr {
+                                span: align.span,
+                                expr: ConstExpr::Literal(val),
+                            },
+                        })
+                    }
+                    Attr::Packed(_) | Attr::Repr(_) | Attr::Passthrough(_) => attr.clone(),
+                };
+                Ok(attr)
+            })
+            .collect()
+    }
+
+    /// Evaluate the attributes of a struct or union field.
+    fn eval_field_attrs(&mut self, field: &TypedVar) -> Result<Vec<Attr>> {
+        if field.attrs.iter().any(|attr| matches!(attr, Attr::Repr(_))) {
+            return Err(KdlScriptTypeError {
+                message: "fields can't have @repr".to_owned(),
+                src: self.src.clone(),
+                span: Spanned::span(&field.ty),
+                help: Some("put the @repr on the field's type instead".to_owned()),
+            })?;
+        }
+        self.eval_attrs(&field.attrs)
+    }
+
+    /// Complain if a var that can't have attributes has some.
+    fn no_var_attrs(&self, var: &TypedVar, what: &str) -> Result<()> {
+        if !var.attrs.is_empty() {
+            return Err(KdlScriptTypeError {
+                message: format!("{what} can't have attributes"),
+                src: self.src.clone(),
+                span: Spanned::span(&var.ty),
+                help: Some("only fields of structs and unions can have attributes".to_owned()),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate an [`IntExpr`][].
+    fn eval_int_expr(&mut self, expr: &IntExpr) -> Result<i64> {
+        self.eval_const_expr(&expr.expr, expr.span)
+    }
+
+    /// Evaluate a [`ConstExpr`][], reporting errors at the given span.
+    fn eval_const_expr(&mut self, expr: &ConstExpr, span: SourceSpan) -> Result<i64> {
+        let val = match expr {
+            ConstExpr::Literal(val) => *val,
+            ConstExpr::Const(name) => self.eval_const(name)?,
+            ConstExpr::Neg(inner) => {
+                let inner = self.eval_const_expr(inner, span)?;
+                let Some(val) = inner.checked_neg() else {
+                    return Err(KdlScriptTypeError {
+                        message: format!("constant expression overflowed: -({inner})"),
+                        src: self.src.clone(),
+                        span,
+                        help: None,
+                    })?;
+                };
+                val
+            }
+            ConstExpr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval_const_expr(lhs, span)?;
+                let rhs = self.eval_const_expr(rhs, span)?;
+                if matches!(op, BinOp::Div | BinOp::Rem) && rhs == 0 {
+                    return Err(KdlScriptTypeError {
+                        message: format!("constant expression divides by zero: {lhs} {op} {rhs}"),
+                        src: self.src.clone(),
+                        span,
+                        help: None,
+                    })?;
+                }
+                let shift = u32::try_from(rhs).ok();
+                let val = match op {
+                    BinOp::Add => lhs.checked_add(rhs),
+                    BinOp::Sub => lhs.checked_sub(rhs),
+                    BinOp::Mul => lhs.checked_mul(rhs),
+                    BinOp::Div => lhs.checked_div(rhs),
+                    BinOp::Rem => lhs.checked_rem(rhs),
+                    // checked_shl only checks the shift amount, so also make sure no bits fell off
+                    BinOp::Shl => shift
+                        .and_then(|shift| lhs.checked_shl(shift))
+                        .filter(|val| val >> rhs == lhs),
+                    BinOp::Shr => shift.and_then(|shift| lhs.checked_shr(shift)),
+                    BinOp::BitAnd => Some(lhs & rhs),
+                    BinOp::BitXor => Some(lhs ^ rhs),
+                    BinOp::BitOr => Some(lhs | rhs),
+                };
+                let Some(val) = val else {
+                    return Err(KdlScriptTypeError {
+                        message: format!("constant expression overflowed: {lhs} {op} {rhs}"),
+                        src: self.src.clone(),
+                        span,
+                        help: None,
+                    })?;
+                };
+                val
+            }
+            ConstExpr::SizeOf(tydent) | ConstExpr::AlignOf(tydent) => {
+                let ty = self.memoize_ty(tydent)?;
+                let layout = self.measure_ty(ty, span)?;
+                let val = if let ConstExpr::SizeOf(_) = expr {
+                    layout.size
+                } else {
+                    layout.align
+                };
+                let Ok(val) = i64::try_from(val) else {
+                    return Err(KdlScriptTypeError {
+                        message: format!("{} is too big to measure", self.format_ty(ty)),
+                        src: self.src.clone(),
+                        span,
+                        help: None,
+                    })?;
+                };
+                val
+            }
+        };
+        Ok(val)
+    }
+
+    /// Get the value of a named constant, evaluating it if necessary.
+    fn eval_const(&mut self, name: &Ident) -> Result<i64> {
+        if let Some(val) = self.consts.get(name) {
+            return Ok(*val);
+        }
+        if self.evaluating_consts.contains(name) {
+            return Err(KdlScriptTypeError {
+                message: format!("const {name} is defined in terms of itself"),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: None,
+            })?;
+        }
+        let Some(decl) = self.pending_consts.remove(name) else {
+            return Err(KdlScriptTypeError {
+                message: format!("use of undefined const: {name}"),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: None,
+            })?;
+        };
+
+        self.evaluating_consts.insert(decl.name.clone());
+        let val = self.eval_int_expr(&decl.val);
+        self.evaluating_consts.remove(&decl.name);
+        // Record a value even if evaluation failed, so that when we're gathering
+        // errors every use of this const doesn't report the same problem again.
+        self.consts.insert(decl.name, *val.as_ref().unwrap_or(&0));
+        val
+    }
+
+    /// Compute the layout of a type for `sizeof` and `alignof`.
+    ///
+    /// This is the layout C would give the type on the compiler's target
+    /// (see [`TypedProgram::layout_of`][]). Types whose layout is inherently
+    /// language-specific (tagged unions, puns, `@repr "rust"`) are rejected.
+    fn measure_ty(&mut self, ty: TyIdx, span: SourceSpan) -> Result<TyLayout> {
+        let mut visiting = HashSet::new();
+        self.complete_measured_ty(ty, span, &mut visiting)?;
+        self.layout_of_with_repr(ty, None, &self.target, LangRepr::C)
+    }
+
+    /// Make sure every type a measured type's layout depends on is defined
+    /// (and doesn't depend on itself).
+    fn complete_measured_ty(
+        &mut self,
+        ty: TyIdx,
+        span: SourceSpan,
+        visiting: &mut HashSet<TyIdx>,
+    ) -> Result<()> {
+        self.complete_pending_decl(ty)?;
+        if self.completing_tys.contains(&ty) || !visiting.insert(ty) {
+            return Err(KdlScriptTypeError {
+                message: "this type's layout depends on itself".to_owned(),
+                src: self.src.clone(),
+                span,
+                help: Some("sizeof/alignof can't be used on a type while defining it".to_owned()),
+            })?;
+        }
+
+        match self.realize_ty(ty).clone() {
+            Ty::Primitive(_) | Ty::Empty | Ty::Ref(_) | Ty::Enum(_) => {}
+            Ty::Alias(alias_ty) => self.complete_measured_ty(alias_ty.real, span, visiting)?,
+            Ty::Array(array_ty) => self.complete_measured_ty(array_ty.elem_ty, span, visiting)?,
+            Ty::Struct(StructTy { fields, .. }) | Ty::Union(UnionTy { fields, .. }) => {
+                for field in &fields {
+                    self.complete_measured_ty(field.ty, span, visiting)?;
+                }
+            }
+            Ty::Tagged(_) | Ty::Pun(_) => {
+                return Err(KdlScriptTypeError {
+                    message: format!(
+                        "{} has a language-specific layout, so it can't be measured",
+                        self.format_ty(ty)
+                    ),
+                    src: self.src.clone(),
+                    span,
+                    help: Some("sizeof/alignof only work on types with a C-like layout".to_owned()),
+                })?;
+            }
+        }
+
+        visiting.remove(&ty);
+        Ok(())
+    }
+
+    /// Reject `@align` on a struct field that would need padding after it.
+    ///
+    /// Rust can only align a field by wrapping it in an aligned newtype, which
+    /// pads it out to its alignment. That only matches C's layout if the field's
+    /// type is already a multiple of that alignment, or if nothing comes after it.
+    fn check_aligned_fields(&mut self, ty: TyIdx) -> Result<()> {
+        let Ty::Struct(struct_ty) = self.realize_ty(ty).clone() else {
+            return Ok(());
+        };
+        let packed = crate::layout::is_packed(&struct_ty.attrs);
+        let Some((_last, fields)) = struct_ty.fields.split_last() else {
+            return Ok(());
+        };
+        for field in fields {
+            let Some(span) = field.attrs.iter().find_map(|attr| match attr {
+                Attr::Align(AttrAligned { align }) => Some(align.span),
+                _ => None,
+            }) else {
+                continue;
+            };
+            // Types with a language-specific layout have no C layout to match
+            let Ok(layout) = self.measure_ty(field.ty, span) else {
+                continue;
+            };
+            let align = crate::layout::field_align(layout.align, packed, &field.attrs);
+            if layout.size % align != 0 {
+                return Err(KdlScriptTypeError {
+                    message: format!(
+                        "{} is {} bytes, so aligning it to {align} needs padding after it",
+                        self.format_ty(field.ty),
+                        layout.size
+                    ),
+                    src: self.src.clone(),
+                    span,
+                    help: Some("rust can only align a field by padding it out to its alignment, so move this field to the end of the struct".to_owned()),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     /// Get the type-structure (Ty) associated with this type id (TyIdx).
     pub fn realize_ty(&self, ty: TyIdx) -> &Ty {
         self.tys
@@ -758,6 +1158,30 @@ impl TyCtx {
             .expect("Internal Compiler Error: invalid TyIdx")
     }
 
+    /// Get the TyIdx of a primitive.
+    pub(crate) fn prim_ty(&self, prim: PrimitiveTy) -> TyIdx {
+        let (name, _) = PRIMITIVES
+            .iter()
+            .find(|(_, builtin)| *builtin == prim)
+            .expect("Internal Compiler Error: unknown primitive");
+        self.envs[0].tys[*name]
+    }
+
+    /// Make an error about a type, pointing at its declaration.
+    pub(crate) fn ty_error(
+        &self,
+        ty: TyIdx,
+        message: String,
+        help: Option<String>,
+    ) -> KdlScriptTypeError {
+        KdlScriptTypeError {
+            message,
+            src: self.src.clone(),
+            span: self.span_for_ty_decl(ty),
+            help,
+        }
+    }
+
     /// Resolve a [`PunTy`][] based on the current [`PunEnv`][].
     pub fn resolve_pun(&self, pun: &PunTy, env: &PunEnv) -> Result<TyIdx> {
         for block in &pun.blocks {
@@ -992,6 +1416,11 @@ impl TypedProgram {
         0..self.builtin_funcs_start
     }
 
+    /// Every type in the program (including builtins and the blocks of puns).
+    pub fn all_tys(&self) -> impl Iterator<Item = TyIdx> {
+        0..self.tcx.tys.len()
+    }
+
     /// Resolve a [`PunTy`][] based on the current [`PunEnv`][].
     pub fn resolve_pun(&self, pun: &PunTy, env: &PunEnv) -> Result<TyIdx> {
         self.tcx.resolve_pun(pun, env)
@@ -1002,6 +1431,69 @@ impl TypedProgram {
         self.tcx.format_ty(ty)
     }
 
+    /// Look up a type by name in the program's top-level scope (including builtins).
+    pub fn ty_by_name(&self, name: &str) -> Option<TyIdx> {
+        self.tcx
+            .envs
+            .iter()
+            .rev()
+            .find_map(|env| env.tys.get(name).copied())
+    }
+
+    /// Look up the type a type expression refers to, if the program ever uses it.
+    ///
+    /// Only literal array lengths are supported.
+    pub fn ty_by_tydent(&self, tydent: &Tydent) -> Option<TyIdx> {
+        let ty = match tydent {
+            Tydent::Name(name) => return self.ty_by_name(name),
+            Tydent::Empty => return self.ty_by_name("()"),
+            Tydent::Ref(pointee) => Ty::Ref(RefTy {
+                pointee_ty: self.ty_by_tydent(pointee)?,
+            }),
+            Tydent::Array(elem, len) => {
+                let ConstExpr::Literal(len) = len.expr else {
+                    return None;
+                };
+                Ty::Array(ArrayTy {
+                    elem_ty: self.ty_by_tydent(elem)?,
+                    len: u64::try_from(len).ok()?,
+                })
+            }
+        };
+        self.tcx.ty_map.get(&ty).copied()
+    }
+
+    /// Get the TyIdx of a reference to this type, if the program ever uses it.
+    pub(crate) fn ref_ty(&self, pointee_ty: TyIdx) -> Option<TyIdx> {
+        self.tcx.ty_map.get(&Ty::Ref(RefTy { pointee_ty })).copied()
+    }
+
+    /// Get the TyIdx of a primitive.
+    pub fn prim_ty(&self, prim: PrimitiveTy) -> TyIdx {
+        self.tcx.prim_ty(prim)
+    }
+
+    /// Things about the program that typechecked but are probably mistakes
+    /// (see [`crate::lint`][]).
+    pub fn warnings(&self) -> &[crate::lint::KdlScriptTypeWarning] {
+        &self.warnings
+    }
+
+    /// Get the span of the declaration of a type.
+    pub(crate) fn span_for_ty_decl(&self, ty: TyIdx) -> SourceSpan {
+        self.tcx.span_for_ty_decl(ty)
+    }
+
+    /// The source code this program was compiled from.
+    pub(crate) fn src(&self) -> &Arc<NamedSource> {
+        &self.tcx.src
+    }
+
+    /// The type context this program was checked with.
+    pub(crate) fn tcx(&self) -> &TyCtx {
+        &self.tcx
+    }
+
     /// Compute the dependency graph between types ([`DefinitionGraph`][]).
     ///
     /// This serves two purposes:
@@ -1176,16 +1668,7 @@ impl DefinitionGraph {
     /// A C backend would only need to emit 2 and 5 (and maybe 4 if it's not Real Option).
     pub fn definitions(&self, funcs: impl IntoIterator<Item = FuncIdx>) -> Vec<Definition> {
         // Take the requested functions and compute all their dependencies!
-        let mut reachable = std::collections::HashSet::new();
-        petgraph::visit::depth_first_search(
-            &self.graph,
-            funcs.into_iter().map(|f| self.func_nodes[f]),
-            |event| {
-                if let petgraph::visit::DfsEvent::Discover(node, _) = event {
-                    reachable.insert(node);
-                }
-            },
-        );
+        let reachable = self.reachable(funcs.into_iter().map(|f| self.func_nodes[f]));
 
         // Languages like C and C++ require types to be defined before they're used,
         // so we need to build a dependency graph between types and functions and
@@ -1252,6 +1735,105 @@ impl DefinitionGraph {
 
         output
     }
+
+    /// Render the graph in Graphviz's DOT format.
+    ///
+    /// If `funcs` is given, only those functions and what they need are included.
+    /// Otherwise every function and nominal type the program declares is (this is
+    /// the useful view for programs without functions, like procgen tests).
+    ///
+    /// Functions are boxes and nominal types are bold. Edges out of a reference type
+    /// (to its pointee) are dashed, every other edge means "contains by-value".
+    /// Cycles (the SCCs that [`DefinitionGraph::definitions`][] has to break
+    /// with forward-declares) are drawn as highlighted clusters.
+    ///
+    /// The result can be rendered with something like `dot -Tsvg`.
+    pub fn to_dot(&self, program: &TypedProgram, funcs: Option<&[FuncIdx]>) -> String {
+        use petgraph::visit::EdgeRef;
+        use std::fmt::Write;
+
+        let reachable = if let Some(funcs) = funcs {
+            self.reachable(funcs.iter().map(|&f| self.func_nodes[f]))
+        } else {
+            let roots = self.graph.node_indices().filter(|&n| match self.graph[n] {
+                DefinitionGraphNode::Func(func_idx) => program.all_funcs().any(|f| f == func_idx),
+                DefinitionGraphNode::Ty(ty_idx) => program.realize_ty(ty_idx).is_nominal(),
+            });
+            self.reachable(roots)
+        };
+        let node_attrs = |node_idx: NodeIndex| match self.graph[node_idx] {
+            DefinitionGraphNode::Func(func_idx) => {
+                let name = &program.realize_func(func_idx).name;
+                format!("label=\"fn {}\", shape=box", dot_escape(name))
+            }
+            DefinitionGraphNode::Ty(ty_idx) => {
+                let label = dot_escape(&program.format_ty(ty_idx));
+                if program.realize_ty(ty_idx).is_nominal() {
+                    format!("label=\"{label}\", style=bold")
+                } else {
+                    format!("label=\"{label}\"")
+                }
+            }
+        };
+
+        let mut out = String::new();
+        writeln!(out, "digraph definitions {{").unwrap();
+        let mut num_cycles = 0;
+        for component in &self.def_order {
+            let nodes = component
+                .iter()
+                .rev()
+                .filter(|n| reachable.contains(*n))
+                .collect::<Vec<_>>();
+            if nodes.len() > 1 {
+                writeln!(out, "    subgraph cluster_{num_cycles} {{").unwrap();
+                writeln!(out, "        label=\"cycle (needs forward-declares)\";").unwrap();
+                writeln!(out, "        style=filled;").unwrap();
+                writeln!(out, "        color=lightpink;").unwrap();
+                for &node_idx in &nodes {
+                    let attrs = node_attrs(*node_idx);
+                    writeln!(out, "        n{} [{attrs}];", node_idx.index()).unwrap();
+                }
+                writeln!(out, "    }}").unwrap();
+                num_cycles += 1;
+            } else {
+                for &node_idx in &nodes {
+                    let attrs = node_attrs(*node_idx);
+                    writeln!(out, "    n{} [{attrs}];", node_idx.index()).unwrap();
+                }
+            }
+        }
+        for edge in self.graph.edge_references() {
+            let (from, to) = (edge.source(), edge.target());
+            if !reachable.contains(&from) {
+                continue;
+            }
+            let by_ref = matches!(
+                self.graph[from],
+                DefinitionGraphNode::Ty(ty_idx) if matches!(program.realize_ty(ty_idx), Ty::Ref(_))
+            );
+            let style = if by_ref { " [style=dashed]" } else { "" };
+            writeln!(out, "    n{} -> n{}{style};", from.index(), to.index()).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    /// Get every node the given nodes (transitively) depend on, including themselves.
+    fn reachable(&self, roots: impl IntoIterator<Item = NodeIndex>) -> HashSet<NodeIndex> {
+        let mut reachable = HashSet::new();
+        petgraph::visit::depth_first_search(&self.graph, roots, |event| {
+            if let petgraph::visit::DfsEvent::Discover(node, _) = event {
+                reachable.insert(node);
+            }
+        });
+        reachable
+    }
+}
+
+/// Escape a string for use in a quoted DOT label.
+fn dot_escape(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
 fn ident_var<T>(val: Option<Ident>, basename: &str, idx: usize, backup_span: &Spanned<T>) -> Ident {
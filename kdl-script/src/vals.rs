@@ -0,0 +1,482 @@
+//! Typechecking explicit values (the `vals` block of a function).
+//!
+//! A `vals` block pins down parts of a function's arguments:
+//!
+//! ```kdl
+//! fn "blend" {
+//!     inputs { a "Color"; opt "MaybeF32"; }
+//!     vals {
+//!         "a.r" 255
+//!         "opt.Some.field0" "0x7fc00001"
+//!     }
+//! }
+//! ```
+//!
+//! Each entry is a path to a value, named the same way the harness names values
+//! (`arg.field`, `arr[2]`, `ref.*`, `tagged.Variant.field`), and a literal. Every
+//! entry is resolved to an [`ExplicitVal`][] here, so anything that reads them
+//! (like abi-cafe's value generators) only has to look paths up.
+//!
+//! Setting a value inside of a union or tagged union also selects that field/variant,
+//! and the union can instead be given the name of a field/variant directly.
+//! Puns can't be given values, because they're different types in each language.
+
+use miette::SourceSpan;
+
+use crate::parse::{ValDecl, ValLiteral};
+use crate::spanned::Spanned;
+use crate::types::{FuncIdx, KdlScriptTypeError, PrimitiveTy, Ty, TyIdx, TypedProgram};
+use crate::Result;
+
+/// A value that was explicitly given for part of a function's arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplicitVal {
+    /// The path to the value (`arg.field[1].*`)
+    pub path: String,
+    /// What the value should be
+    pub kind: ExplicitValKind,
+}
+
+/// The contents of an [`ExplicitVal`][].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExplicitValKind {
+    /// The little-endian bytes of a primitive
+    Bytes(Vec<u8>),
+    /// The case of an enum, or the active field/variant of a union/tagged union
+    Variant(usize),
+}
+
+/// Resolve the entries of a function's `vals` block.
+pub fn typeck_vals(
+    program: &TypedProgram,
+    func_idx: FuncIdx,
+    decls: &[ValDecl],
+) -> Result<Vec<ExplicitVal>> {
+    let mut checker = ValChecker {
+        program,
+        vals: vec![],
+    };
+    for decl in decls {
+        checker.check_val(func_idx, decl)?;
+    }
+    Ok(checker.vals)
+}
+
+struct ValChecker<'a> {
+    program: &'a TypedProgram,
+    vals: Vec<ExplicitVal>,
+}
+
+/// One step of a value path.
+enum PathPart<'a> {
+    /// `.name`
+    Field(&'a str),
+    /// `[idx]`
+    Index(&'a str),
+}
+
+impl ValChecker<'_> {
+    fn check_val(&mut self, func_idx: FuncIdx, decl: &ValDecl) -> Result<()> {
+        let path_span = Spanned::span(&decl.path);
+        let func = self.program.realize_func(func_idx);
+        let arg_len = decl.path.find(['.', '[']).unwrap_or(decl.path.len());
+        let (arg_name, mut rest) = decl.path.split_at(arg_len);
+        let Some(arg) = func
+            .inputs
+            .iter()
+            .chain(&func.outputs)
+            .find(|arg| arg.name == *arg_name)
+        else {
+            let args = func
+                .inputs
+                .iter()
+                .chain(&func.outputs)
+                .map(|arg| arg.name.to_string())
+                .collect::<Vec<_>>();
+            return Err(self.error(
+                format!("{} has no argument named '{arg_name}'", func.name),
+                path_span,
+                Some(format!("the arguments are: {}", args.join(", "))),
+            ))?;
+        };
+
+        let mut path = arg_name.to_owned();
+        let mut ty_idx = arg.ty;
+        loop {
+            ty_idx = self.resolve_alias(ty_idx);
+            let ty = self.program.realize_ty(ty_idx);
+            if let Ty::Pun(_) = ty {
+                return Err(self.error(
+                    format!("'{path}' is a pun, which can't be given values"),
+                    path_span,
+                    Some("puns are different types in each language".to_owned()),
+                ))?;
+            }
+            let Some((part, new_rest)) = self.path_part(rest, path_span)? else {
+                break;
+            };
+            rest = new_rest;
+            match (ty, part) {
+                (Ty::Ref(ty), PathPart::Field("*")) => {
+                    path.push_str(".*");
+                    ty_idx = ty.pointee_ty;
+                }
+                (Ty::Struct(ty), PathPart::Field(name)) => {
+                    let Some(field) = ty.fields.iter().find(|f| f.ident == *name) else {
+                        return Err(self.no_such(&path, ty_idx, name, path_span))?;
+                    };
+                    path = format!("{path}.{name}");
+                    ty_idx = field.ty;
+                }
+                (Ty::Array(ty), PathPart::Index(idx)) => {
+                    let Some(idx) = idx.parse::<u64>().ok().filter(|idx| *idx < ty.len) else {
+                        return Err(self.error(
+                            format!("'{idx}' isn't an index into '{path}'"),
+                            path_span,
+                            Some(format!("it has {} elements", ty.len)),
+                        ))?;
+                    };
+                    path = format!("{path}[{idx}]");
+                    ty_idx = ty.elem_ty;
+                }
+                (Ty::Union(ty), PathPart::Field(name)) => {
+                    let Some(idx) = ty.fields.iter().position(|f| f.ident == *name) else {
+                        return Err(self.no_such(&path, ty_idx, name, path_span))?;
+                    };
+                    self.insert(path.clone(), ExplicitValKind::Variant(idx), path_span)?;
+                    path = format!("{path}.{name}");
+                    ty_idx = ty.fields[idx].ty;
+                }
+                (Ty::Tagged(ty), PathPart::Field(name)) => {
+                    let Some(idx) = ty.variants.iter().position(|v| v.name == *name) else {
+                        return Err(self.no_such(&path, ty_idx, name, path_span))?;
+                    };
+                    let tag_path = path.clone();
+                    self.insert(tag_path.clone(), ExplicitValKind::Variant(idx), path_span)?;
+                    path = format!("{path}.{name}");
+                    let Some((part, new_rest)) = self.path_part(rest, path_span)? else {
+                        return Err(self.error(
+                            format!("'{path}' is a variant, not a value"),
+                            path_span,
+                            Some(format!("to pick the variant, use '{tag_path}' \"{name}\"")),
+                        ))?;
+                    };
+                    rest = new_rest;
+                    let fields = ty.variants[idx].fields.as_deref().unwrap_or_default();
+                    let field = match part {
+                        PathPart::Field(name) => fields.iter().find(|f| f.ident == *name),
+                        PathPart::Index(_) => None,
+                    };
+                    let Some(field) = field else {
+                        return Err(self.error(
+                            format!("'{path}' doesn't have that field"),
+                            path_span,
+                            None,
+                        ))?;
+                    };
+                    path = format!("{path}.{}", field.ident);
+                    ty_idx = field.ty;
+                }
+                (ty, part) => {
+                    let part = match part {
+                        PathPart::Field(name) => format!(".{name}"),
+                        PathPart::Index(idx) => format!("[{idx}]"),
+                    };
+                    let help = match ty {
+                        Ty::Ref(_) => Some(format!("references are followed with '{path}.*'")),
+                        Ty::Array(_) => Some(format!("arrays are indexed like '{path}[0]'")),
+                        _ => None,
+                    };
+                    return Err(self.error(
+                        format!("'{path}{part}' isn't a value in this function"),
+                        path_span,
+                        help,
+                    ))?;
+                }
+            }
+        }
+
+        let val_span = Spanned::span(&decl.val);
+        let kind = match self.program.realize_ty(ty_idx) {
+            Ty::Primitive(prim) => {
+                ExplicitValKind::Bytes(self.prim_bytes(*prim, ty_idx, &decl.val)?)
+            }
+            Ty::Enum(ty) => {
+                let names = ty.variants.iter().map(|v| v.name.as_str());
+                ExplicitValKind::Variant(self.variant(&decl.val, names)?)
+            }
+            Ty::Union(ty) => {
+                let names = ty.fields.iter().map(|f| f.ident.as_str());
+                ExplicitValKind::Variant(self.variant(&decl.val, names)?)
+            }
+            Ty::Tagged(ty) => {
+                let names = ty.variants.iter().map(|v| v.name.as_str());
+                ExplicitValKind::Variant(self.variant(&decl.val, names)?)
+            }
+            Ty::Empty => {
+                return Err(self.error(
+                    format!("'{path}' is (), which has no values"),
+                    path_span,
+                    None,
+                ))?;
+            }
+            Ty::Struct(_) | Ty::Array(_) | Ty::Ref(_) | Ty::Alias(_) | Ty::Pun(_) => {
+                return Err(self.error(
+                    format!(
+                        "'{path}' is a {}, which can't be given a value all at once",
+                        self.program.format_ty(ty_idx)
+                    ),
+                    val_span,
+                    Some("give values to its parts instead".to_owned()),
+                ))?;
+            }
+        };
+        self.insert(path, kind, path_span)
+    }
+
+    /// Record a value, unless something else already said the opposite.
+    fn insert(&mut self, path: String, kind: ExplicitValKind, span: SourceSpan) -> Result<()> {
+        if let Some(old) = self.vals.iter().find(|val| val.path == path) {
+            if old.kind != kind {
+                return Err(self.error(
+                    format!("'{path}' was already given a different value"),
+                    span,
+                    Some("(values inside a union also pick its field/variant)".to_owned()),
+                ))?;
+            }
+            return Ok(());
+        }
+        self.vals.push(ExplicitVal { path, kind });
+        Ok(())
+    }
+
+    /// Split the next part off of a path.
+    fn path_part<'p>(
+        &self,
+        rest: &'p str,
+        span: SourceSpan,
+    ) -> Result<Option<(PathPart<'p>, &'p str)>> {
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if let Some(rest) = rest.strip_prefix('.') {
+            let len = rest.find(['.', '[']).unwrap_or(rest.len());
+            if len != 0 {
+                return Ok(Some((PathPart::Field(&rest[..len]), &rest[len..])));
+            }
+        } else if let Some((idx, rest)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+            return Ok(Some((PathPart::Index(idx), rest)));
+        }
+        Err(self.error(
+            format!("couldn't understand the '{rest}' part of this path"),
+            span,
+            Some("paths look like \"arg.field[1].*\"".to_owned()),
+        ))?
+    }
+
+    fn resolve_alias(&self, mut ty_idx: TyIdx) -> TyIdx {
+        while let Ty::Alias(alias) = self.program.realize_ty(ty_idx) {
+            ty_idx = alias.real;
+        }
+        ty_idx
+    }
+
+    /// Get the index of the variant (or field) a string literal names.
+    fn variant<'n>(
+        &self,
+        val: &Spanned<ValLiteral>,
+        names: impl Iterator<Item = &'n str> + Clone,
+    ) -> Result<usize> {
+        if let ValLiteral::String(name) = &**val {
+            if let Some(idx) = names.clone().position(|n| n == name) {
+                return Ok(idx);
+            }
+        }
+        Err(self.error(
+            "this should be the name of a variant".to_owned(),
+            Spanned::span(val),
+            Some(format!(
+                "one of: {}",
+                names
+                    .map(|n| format!("\"{n}\""))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        ))?
+    }
+
+    /// Get the bytes of a primitive's value.
+    fn prim_bytes(
+        &self,
+        prim: PrimitiveTy,
+        ty_idx: TyIdx,
+        val: &Spanned<ValLiteral>,
+    ) -> Result<Vec<u8>> {
+        use PrimitiveTy::*;
+        let span = Spanned::span(val);
+        let ty_name = self.program.format_ty(ty_idx);
+        let (width, signed) = match prim {
+            I8 => (8, true),
+            I16 => (16, true),
+            I32 => (32, true),
+            I64 => (64, true),
+            I128 => (128, true),
+            U8 | Bool => (8, false),
+            U16 | F16 => (16, false),
+            U32 | F32 => (32, false),
+            U64 | F64 | Ptr => (64, false),
+            U128 | F128 => (128, false),
+            I256 | U256 => {
+                return Err(self.error(
+                    format!("{ty_name} values can't be given explicitly (yet)"),
+                    span,
+                    None,
+                ))?;
+            }
+        };
+        let bits: u128 = match (prim, &**val) {
+            (Bool, ValLiteral::Bool(val)) => *val as u128,
+            (Bool, _) => {
+                return Err(self.error("this should be true or false".to_owned(), span, None))?;
+            }
+            (F32, ValLiteral::Float(val)) => (*val as f32).to_bits() as u128,
+            (F32, ValLiteral::Int(val)) => (*val as f32).to_bits() as u128,
+            (F64, ValLiteral::Float(val)) => val.to_bits() as u128,
+            (F64, ValLiteral::Int(val)) => (*val as f64).to_bits() as u128,
+            (F32, ValLiteral::String(s)) if float_special(s).is_some() => {
+                (float_special(s).unwrap() as f32).to_bits() as u128
+            }
+            (F64, ValLiteral::String(s)) if float_special(s).is_some() => {
+                float_special(s).unwrap().to_bits() as u128
+            }
+            // Any other float is given as its bits
+            (F16 | F32 | F64 | F128, ValLiteral::String(s)) => {
+                match parse_int(s).filter(|(neg, _, _)| !neg) {
+                    Some((_, bits, _)) if fits(bits, width) => bits,
+                    _ => {
+                        return Err(self.error(
+                            format!("this isn't a float, or the {width} bits of one"),
+                            span,
+                            Some("bits look like \"0x7fc00001\"".to_owned()),
+                        ))?;
+                    }
+                }
+            }
+            (F16 | F128, _) => {
+                return Err(self.error(
+                    format!("{ty_name} values have to be given as their bits"),
+                    span,
+                    Some("like \"0x3c00\"".to_owned()),
+                ))?;
+            }
+            (_, ValLiteral::Int(val)) => {
+                let (neg, mag) = (*val < 0, val.unsigned_abs() as u128);
+                self.int_bits(&ty_name, width, signed, neg, mag, span)?
+            }
+            (_, ValLiteral::String(s)) => {
+                let Some((neg, mag, is_bits)) = parse_int(s) else {
+                    return Err(self.error(
+                        format!("this isn't a {ty_name}"),
+                        span,
+                        Some(
+                            "big integers can be written as strings like \"0xffff_ffff\""
+                                .to_owned(),
+                        ),
+                    ))?;
+                };
+                // Hex/octal/binary literals are bit patterns, so they can fill a signed int
+                if is_bits && !neg && fits(mag, width) {
+                    mag
+                } else {
+                    self.int_bits(&ty_name, width, signed, neg, mag, span)?
+                }
+            }
+            (_, ValLiteral::Float(_) | ValLiteral::Bool(_)) => {
+                return Err(self.error(format!("this isn't a {ty_name}"), span, None))?;
+            }
+        };
+        Ok(bits.to_le_bytes()[..width as usize / 8].to_vec())
+    }
+
+    /// Get the bits of an integer, if it fits in its type.
+    fn int_bits(
+        &self,
+        ty_name: &str,
+        width: u32,
+        signed: bool,
+        neg: bool,
+        mag: u128,
+        span: SourceSpan,
+    ) -> Result<u128> {
+        let max = if signed { 1u128 << (width - 1) } else { 0 };
+        let fits = match (neg, signed) {
+            (true, true) => mag <= max,
+            (true, false) => mag == 0,
+            (false, true) => mag < max,
+            (false, false) => fits(mag, width),
+        };
+        if !fits {
+            return Err(self.error(format!("this doesn't fit in a {ty_name}"), span, None))?;
+        }
+        Ok(if neg { mag.wrapping_neg() } else { mag })
+    }
+
+    fn error(&self, message: String, span: SourceSpan, help: Option<String>) -> KdlScriptTypeError {
+        KdlScriptTypeError {
+            message,
+            src: self.program.src().clone(),
+            span,
+            help,
+        }
+    }
+
+    fn no_such(&self, path: &str, ty: TyIdx, name: &str, span: SourceSpan) -> KdlScriptTypeError {
+        self.error(
+            format!(
+                "'{path}' is a {}, which doesn't have '{name}'",
+                self.program.format_ty(ty)
+            ),
+            span,
+            None,
+        )
+    }
+}
+
+/// Whether these bits fit in an integer this wide.
+fn fits(bits: u128, width: u32) -> bool {
+    width >= 128 || bits >> width == 0
+}
+
+/// Parse the floats KDL can't write.
+fn float_special(s: &str) -> Option<f64> {
+    match s {
+        "inf" => Some(f64::INFINITY),
+        "-inf" => Some(f64::NEG_INFINITY),
+        "nan" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+/// Parse an integer like `-12`, `0xffff_ffff`, or `0b1010`, returning
+/// whether it's negative, its magnitude, and whether it had a radix prefix.
+fn parse_int(s: &str) -> Option<(bool, u128, bool)> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (radix, digits) = if let Some(digits) = s.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = s.strip_prefix("0o") {
+        (8, digits)
+    } else if let Some(digits) = s.strip_prefix("0b") {
+        (2, digits)
+    } else {
+        (10, s)
+    };
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || digits.starts_with('+') {
+        return None;
+    }
+    let mag = u128::from_str_radix(&digits, radix).ok()?;
+    Some((neg, mag, radix != 10))
+}
@@ -65,12 +65,24 @@ struct Cli {
     #[clap(long, short, value_delimiter(','))]
     pairs: Vec<String>,
 
-    /// which values to try for each test (graffiti, random1, random17, ...)
+    /// which values to try for each test (graffiti, random1, random17, edges, ...)
     ///
     /// "graffiti" prefers patterning the bytes of values in a way that helps you
     /// identify which byte of which field each recorded value was.
     ///
     /// "randomN" seeds an RNG with N to make random (repeatable) values with.
+    /// "randomN..=M" (or "randomN..M") runs every seed in the range, and "random*N" is
+    /// short for "random1..=N". The runs of a range are reported together, and a range
+    /// can have at most 100000 seeds.
+    ///
+    /// "edges" cycles through boundary values (0, -1, min/max, NaNs, subnormals, ...)
+    /// of each primitive, and "edgesN" starts N steps into those cycles.
+    ///
+    /// "exhaustive" runs every combination of variants (of enums, tagged unions, bools...)
+    /// as "variants0", "variants1", ...
+    ///
+    /// "explicit" uses the values each test's functions give in their `vals` blocks
+    /// (and graffiti for everything else).
     #[clap(long, short, value_delimiter(','))]
     #[clap(default_values_t = vec![ValueGeneratorKind::Graffiti])]
     gen_vals: Vec<ValueGeneratorKind>,
@@ -88,6 +100,17 @@ struct Cli {
     #[clap(default_values_t = vec![WriteImpl::HarnessCallback])]
     write_vals: Vec<WriteImpl>,
 
+    /// how to compare float values when checking tests (bitwise, nan, value)
+    ///
+    /// "bitwise" requires every bit to match (the default).
+    /// "nan" lets any NaN match any other NaN (quieted signalling NaNs, changed payloads...).
+    /// "value" only requires floats to be equal as numbers (NaNs still match each other,
+    /// and -0.0 matches 0.0).
+    ///
+    /// "TEST=MODE" (like "f16=nan") only applies to that test.
+    #[clap(long, value_delimiter(','))]
+    float_compare: Vec<FloatCompareRule>,
+
     /// UNIMPLEMENTED: which of the values in a test to write (see --write-vals)
     ///
     /// This is an internal feature of abi-cafe, and used in minimization (see --minimize-vals),
@@ -136,6 +159,31 @@ struct Cli {
     #[clap(long)]
     disable_builtin_tests: bool,
 
+    /// treat warnings from loading tests (unused types, puns without defaults, ...) as errors
+    #[clap(long)]
+    deny_warnings: bool,
+
+    /// write the tests generated from .procgen.kdl files to target/generated_impls/procgen/
+    ///
+    /// These are pretty-printed as regular kdl-script, so they can be inspected
+    /// (or copied into a test of their own).
+    #[clap(long)]
+    dump_procgen: bool,
+
+    /// don't replay the failing-seed corpus, or record new failures in it
+    ///
+    /// By default every "randomN" run that fails is recorded in target/failing-seeds.json,
+    /// and later runs replay those seeds alongside the requested --gen-vals.
+    #[clap(long)]
+    no_corpus: bool,
+
+    /// only run the seeds in the failing-seed corpus (ignoring --gen-vals),
+    /// and remove the ones that pass now
+    ///
+    /// Seeds for tests/pairings that weren't selected are left alone.
+    #[clap(long, conflicts_with = "no_corpus")]
+    prune_corpus: bool,
+
     /// deprecated, does nothing (we always procgen now)
     #[clap(long, hide = true)]
     procgen_tests: bool,
@@ -150,11 +198,16 @@ pub fn make_app() -> Config {
         pairs,
         gen_vals,
         write_vals,
+        float_compare,
         minimize_vals,
         output_format,
         add_rustc_codegen_backend,
         add_tests,
         disable_builtin_tests,
+        deny_warnings,
+        dump_procgen,
+        no_corpus,
+        prune_corpus,
         // unimplemented
         select_vals: _,
         key: _,
@@ -246,8 +299,13 @@ Hint: Try using `--pairs {name}_calls_rustc` or `--pairs rustc_calls_{name}`.
         run_values,
         run_writers,
         run_selections,
+        float_compare,
         minimizing_write_impl,
         disable_builtin_tests,
+        deny_warnings,
+        dump_procgen,
+        use_corpus: !no_corpus,
+        prune_corpus,
         paths,
     }
 }
@@ -0,0 +1,152 @@
+//! The failing-seed corpus.
+//!
+//! Whenever a `randomN` run fails, we record which test/pairing/seed it was in
+//! `target/failing-seeds.json`, and later runs replay those seeds alongside whatever
+//! `--gen-vals` asked for. `--prune-corpus` runs only the corpus and forgets the
+//! seeds that pass now.
+
+use std::collections::BTreeSet;
+
+use camino::Utf8PathBuf;
+use kdl_script::parse::LangRepr;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::CorpusError;
+use crate::files::Paths;
+use crate::harness::test::{CallingConvention, TestId, ToolchainId};
+use crate::harness::vals::ValueGeneratorKind;
+use crate::report::{TestConclusion, TestKey, TestReport};
+
+/// A seed that made a test fail.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    pub test: TestId,
+    pub caller: ToolchainId,
+    pub callee: ToolchainId,
+    pub convention: String,
+    pub repr: String,
+    pub seed: u64,
+}
+
+impl CorpusEntry {
+    /// The entry for this run, if it was a `randomN` run.
+    fn from_key(key: &TestKey) -> Option<Self> {
+        let ValueGeneratorKind::Random { seed } = key.options.val_generator else {
+            return None;
+        };
+        Some(CorpusEntry {
+            test: key.test.clone(),
+            caller: key.caller.clone(),
+            callee: key.callee.clone(),
+            convention: key.options.convention.to_string(),
+            repr: key.options.repr.to_string(),
+            seed,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Corpus {
+    path: Utf8PathBuf,
+    entries: BTreeSet<CorpusEntry>,
+}
+
+impl Corpus {
+    /// Load the corpus (an empty one if we've never recorded anything).
+    pub fn load(paths: &Paths) -> Result<Self, CorpusError> {
+        let path = paths.corpus_file();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                serde_json::from_str(&contents).map_err(|details| CorpusError::Parse {
+                    path: path.clone(),
+                    details,
+                })?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => Err(e)?,
+        };
+        Ok(Corpus { path, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn path(&self) -> &Utf8PathBuf {
+        &self.path
+    }
+
+    /// The seeds that failed for this combination of test settings.
+    pub fn seeds(
+        &self,
+        test: &str,
+        caller: &str,
+        callee: &str,
+        convention: CallingConvention,
+        repr: LangRepr,
+    ) -> Vec<u64> {
+        let convention = convention.to_string();
+        let repr = repr.to_string();
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.test == test
+                    && entry.caller == caller
+                    && entry.callee == callee
+                    && entry.convention == convention
+                    && entry.repr == repr
+            })
+            .map(|entry| entry.seed)
+            .collect()
+    }
+
+    /// Record the seeds that failed in these reports, and (if pruning) forget the ones
+    /// that passed. Returns whether the corpus changed.
+    pub fn update(&mut self, reports: &[TestReport], prune: bool) -> bool {
+        let mut failed = BTreeSet::new();
+        let mut passed = BTreeSet::new();
+        for report in reports {
+            let Some(entry) = CorpusEntry::from_key(&report.key) else {
+                continue;
+            };
+            match report.conclusion {
+                TestConclusion::Failed => {
+                    failed.insert(entry);
+                }
+                TestConclusion::Passed => {
+                    passed.insert(entry);
+                }
+                // A busted run failed the way we expected, which doesn't mean the seed passes
+                TestConclusion::Busted | TestConclusion::Skipped => {}
+            }
+        }
+
+        let mut pruned = 0;
+        if prune {
+            let before = self.entries.len();
+            self.entries
+                .retain(|entry| failed.contains(entry) || !passed.contains(entry));
+            pruned = before - self.entries.len();
+            info!(
+                "pruned {pruned} seeds that pass now from {} ({} left)",
+                self.path,
+                self.entries.len()
+            );
+        }
+
+        let before = self.entries.len();
+        self.entries.extend(failed);
+        let new = self.entries.len() - before;
+        if new > 0 {
+            info!("recorded {new} new failing seeds in {}", self.path);
+        }
+        pruned > 0 || new > 0
+    }
+
+    pub fn save(&self) -> Result<(), CorpusError> {
+        let contents = serde_json::to_string_pretty(&self.entries).expect("failed to serialize");
+        std::fs::write(&self.path, contents)?;
+        Ok(())
+    }
+}
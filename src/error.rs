@@ -56,6 +56,15 @@ impl std::borrow::Borrow<dyn Diagnostic> for Box<GenerateError> {
     }
 }
 
+impl From<kdl_script::emit::EmitError> for GenerateError {
+    fn from(e: kdl_script::emit::EmitError) -> Self {
+        match e {
+            kdl_script::emit::EmitError::Unsupported(msg) => UnsupportedError::Other(msg).into(),
+            kdl_script::emit::EmitError::Fmt(e) => e.into(),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum BuildError {
     #[error("io error\n{0}")]
@@ -78,10 +87,11 @@ pub enum CheckFailure {
       caller: {}
       callee: {}
     the value was {val_path}: {val_ty_name}
-    whose arg was {arg_name}: {arg_ty_name}",
+    whose arg was {arg_name}: {arg_ty_name}{}",
         fmt_bytes(expected),
         fmt_bytes(caller),
-        fmt_bytes(callee)
+        fmt_bytes(callee),
+        fmt_misplaced(misplaced)
     )]
     ValMismatch {
         func_idx: usize,
@@ -95,6 +105,8 @@ pub enum CheckFailure {
         expected: Vec<u8>,
         caller: Vec<u8>,
         callee: Vec<u8>,
+        /// Where each side's wrong bytes came from (if they're graffiti)
+        misplaced: Vec<String>,
     },
     #[error(
         "  func {func_name}'s value had unexpected variant
@@ -118,6 +130,126 @@ pub enum CheckFailure {
         caller: String,
         callee: String,
     },
+    #[error(
+        "  func {func_name}'s float value differed ({issue})
+    values:
+      expect: {expected}
+      caller: {caller}
+      callee: {callee}
+    the value was {val_path}: {val_ty_name}
+    whose arg was {arg_name}: {arg_ty_name}{}",
+        fmt_misplaced(misplaced)
+    )]
+    FloatMismatch {
+        func_idx: usize,
+        arg_idx: usize,
+        val_idx: usize,
+        func_name: String,
+        arg_name: String,
+        arg_ty_name: String,
+        val_path: String,
+        val_ty_name: String,
+        /// What went wrong, like "signalling NaN was quieted"
+        issue: String,
+        expected: String,
+        caller: String,
+        callee: String,
+        /// Where each side's wrong bytes came from (if they're graffiti)
+        misplaced: Vec<String>,
+    },
+}
+
+impl CheckFailure {
+    /// The (func_idx, arg_idx, val_idx) of the value that differed.
+    pub fn val_idx(&self) -> (usize, usize, usize) {
+        match *self {
+            CheckFailure::ValMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                ..
+            }
+            | CheckFailure::TagMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                ..
+            }
+            | CheckFailure::FloatMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                ..
+            } => (func_idx, arg_idx, val_idx),
+        }
+    }
+
+    /// The path and type of the value that differed, like ("a.x", "i32").
+    pub fn val_path(&self) -> (&str, &str) {
+        match self {
+            CheckFailure::ValMismatch {
+                val_path,
+                val_ty_name,
+                ..
+            }
+            | CheckFailure::TagMismatch {
+                val_path,
+                val_ty_name,
+                ..
+            }
+            | CheckFailure::FloatMismatch {
+                val_path,
+                val_ty_name,
+                ..
+            } => (val_path, val_ty_name),
+        }
+    }
+
+    /// Where each side's wrong bytes came from (if they're graffiti).
+    pub fn misplaced(&self) -> &[String] {
+        match self {
+            CheckFailure::ValMismatch { misplaced, .. }
+            | CheckFailure::FloatMismatch { misplaced, .. } => misplaced,
+            CheckFailure::TagMismatch { .. } => &[],
+        }
+    }
+
+    /// The expected/caller/callee values, each formatted to fit on one line.
+    pub fn short_vals(&self) -> [String; 3] {
+        match self {
+            CheckFailure::ValMismatch {
+                expected,
+                caller,
+                callee,
+                ..
+            } => [fmt_bytes(expected), fmt_bytes(caller), fmt_bytes(callee)],
+            CheckFailure::TagMismatch {
+                expected,
+                caller,
+                callee,
+                ..
+            }
+            | CheckFailure::FloatMismatch {
+                expected,
+                caller,
+                callee,
+                ..
+            } => [expected.clone(), caller.clone(), callee.clone()],
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error, Diagnostic)]
+pub enum CorpusError {
+    #[error("io error\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "couldn't parse the failing-seed corpus at {path} (delete it to start over)\n{details}"
+    )]
+    Parse {
+        path: camino::Utf8PathBuf,
+        details: serde_json::Error,
+    },
 }
 
 #[derive(Debug, thiserror::Error, Diagnostic)]
@@ -147,3 +279,10 @@ fn fmt_bytes(bytes: &[u8]) -> String {
         .collect::<Vec<_>>()
         .join(" ")
 }
+
+fn fmt_misplaced(misplaced: &[String]) -> String {
+    misplaced
+        .iter()
+        .map(|explanation| format!("\n    {explanation}"))
+        .collect()
+}
@@ -19,6 +19,11 @@ impl Paths {
         self.out_dir.join("harness.rs")
     }
 
+    /// Where failing random seeds are recorded (see [`crate::corpus`][])
+    pub fn corpus_file(&self) -> Utf8PathBuf {
+        self.target_dir.join("failing-seeds.json")
+    }
+
     /// Delete and recreate the build dir
     pub fn init_dirs(&self) -> Result<(), GenerateError> {
         // Make sure these dirs exist and are empty
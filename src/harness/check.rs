@@ -1,6 +1,8 @@
 use console::Style;
+use harness::graffiti::explain_graffiti;
 use harness::run::{FuncBuffer, ValBuffer};
-use kdl_script::types::Ty;
+use kdl_script::layout::DataLayout;
+use kdl_script::types::{PrimitiveTy, Ty, TyIdx};
 use tracing::{error, info};
 
 use crate::error::*;
@@ -11,6 +13,7 @@ impl TestHarness {
     pub async fn check_test(
         &self,
         key: &TestKey,
+        rules: &TestRules,
         RunOutput {
             caller_funcs,
             callee_funcs,
@@ -28,7 +31,10 @@ impl TestHarness {
         // Start peeling back the layers of the buffers.
         // funcs (subtests) -> vals (args/returns) -> fields -> bytes
 
-        let mut results: Vec<Result<(), CheckFailure>> = Vec::new();
+        let mut results: Vec<Result<(), Vec<CheckFailure>>> = Vec::new();
+        let mut diffs: Vec<Vec<ArgDiff>> = Vec::new();
+        let env = self.toolchain_by_test_key(key, CallSide::Caller).pun_env();
+        let target = DataLayout::host();
 
         // `Run` already checks that this length is congruent with all the inputs/outputs Vecs
         let expected_funcs = key.options.functions.active_funcs(&test.types);
@@ -36,7 +42,7 @@ impl TestHarness {
         // Layer 1 is the funcs/subtests. Because we have already checked
         // that they agree on their lengths, we can zip them together
         // to walk through their views of each subtest's execution.
-        'funcs: for func_idx in expected_funcs {
+        for func_idx in expected_funcs {
             let caller_func = caller_funcs.funcs.get(func_idx).unwrap_or(&empty_func);
             let callee_func = callee_funcs.funcs.get(func_idx).unwrap_or(&empty_func);
             let mut expected_vals = vec![];
@@ -48,22 +54,55 @@ impl TestHarness {
                 }
             }
 
+            // Each value is absolutely indexed, so we can check them all independently
+            // and report every mismatch (the first is usually the most interesting).
+            // Once a tag differs, the values under it belong to a variant at least one
+            // side didn't have, so reporting them too would just be noise.
+            let mut failures = vec![];
+            let mut mismatched_tags: Vec<String> = vec![];
             for expected_val in expected_vals {
+                if mismatched_tags
+                    .iter()
+                    .any(|tag_path| is_under(&expected_val.path, tag_path))
+                {
+                    continue;
+                }
                 let val_idx = expected_val.absolute_val_idx;
                 let caller_val = caller_func.vals.get(val_idx).unwrap_or(&empty_val);
                 let callee_val = callee_func.vals.get(val_idx).unwrap_or(&empty_val);
-                if let Err(e) = self.check_val(&test, expected_val, caller_val, callee_val) {
-                    results.push(Err(e));
-                    // FIXME: now that each value is absolutely indexed,
-                    // we should be able to check all the values independently
-                    // and return all errors. However the first one is the most
-                    // important one, so the UX needs to be worked on...
-                    continue 'funcs;
+                if let Err(e) = self.check_val(&test, rules, expected_val, caller_val, callee_val) {
+                    if let CheckFailure::TagMismatch { val_path, .. } = &e {
+                        mismatched_tags.push(val_path.clone());
+                    }
+                    failures.push(e);
+                }
+            }
+
+            // Lay out any aggregate that had a mismatch, in case it was the layout that differed
+            let mut arg_diffs = vec![];
+            for arg in test.vals.at_func(func_idx) {
+                let failed = failures
+                    .iter()
+                    .any(|failure| failure.val_idx().1 == arg.arg_idx);
+                if failed && is_aggregate(&test.types, arg.arg().ty) {
+                    arg_diffs.push(ArgDiff::new(
+                        &test.types,
+                        &env,
+                        &target,
+                        options.repr,
+                        arg,
+                        caller_func,
+                        callee_func,
+                    ));
                 }
             }
+            diffs.push(arg_diffs);
 
-            // If we got this far then the test passes
-            results.push(Ok(()));
+            if failures.is_empty() {
+                results.push(Ok(()));
+            } else {
+                results.push(Err(failures));
+            }
         }
 
         // Report the results of each subtest
@@ -86,12 +125,17 @@ impl TestHarness {
                     Ok(()) => {
                         info!("Test {subtest_name:width$} passed", width = max_name_len);
                     }
-                    Err(e) => {
+                    Err(failures) => {
                         let red = console::Style::new().red();
-                        let message = format!(
-                            "Test {subtest_name:width$} failed!\n{e}",
+                        let mut message = format!(
+                            "Test {subtest_name:width$} failed! ({})",
+                            values_differed(failures.len()),
                             width = max_name_len
                         );
+                        for e in failures {
+                            message.push('\n');
+                            message.push_str(&e.to_string());
+                        }
                         info!("{}", red.apply_to(message));
                     }
                 }
@@ -108,6 +152,7 @@ impl TestHarness {
             all_passed,
             subtest_names: names,
             subtest_checks: results,
+            subtest_diffs: diffs,
         }
     }
 
@@ -115,6 +160,7 @@ impl TestHarness {
     fn check_val(
         &self,
         test: &TestWithVals,
+        rules: &TestRules,
         expected_val: ValueRef,
         caller_val: &ValBuffer,
         callee_val: &ValBuffer,
@@ -150,12 +196,68 @@ impl TestHarness {
                 let callee = enum_variant_name(enum_ty, callee_tag);
                 return Err(tag_error(types, &expected_val, expected, caller, callee));
             }
-        } else if caller_val.bytes != callee_val.bytes {
+        } else if let Some(format) =
+            float_format(types.realize_ty(expected_val.ty)).filter(|format| {
+                caller_val.bytes.len() == format.size() && callee_val.bytes.len() == format.size()
+            })
+        {
+            // Floats get compared according to the test's rules, and explained
+            let mut expected_raw = vec![0; format.size()];
+            expected_val.fill_bytes(&mut expected_raw);
+            let expected = format.load(&expected_raw);
+            let caller = format.load(&caller_val.bytes);
+            let callee = format.load(&callee_val.bytes);
+            // Inputs go from the caller to the callee, and outputs go the other way
+            let func = types.realize_func(expected_val.func_idx);
+            let (from, to) = if expected_val.arg_idx < func.inputs.len() {
+                (&caller, &callee)
+            } else {
+                (&callee, &caller)
+            };
+            let mode = rules.float_compare;
+            let issue = if !from.matches(to, mode) {
+                Some(from.issue(to))
+            } else if expected_val.fixed_bytes().is_some() && !expected.matches(from, mode) {
+                Some(expected.issue(from))
+            } else {
+                None
+            };
+            if let Some(issue) = issue {
+                let func = expected_val.func();
+                let arg = expected_val.arg();
+                return Err(CheckFailure::FloatMismatch {
+                    func_idx: expected_val.func_idx,
+                    arg_idx: expected_val.arg_idx,
+                    val_idx: expected_val.val_idx,
+                    func_name: func.func_name.to_string(),
+                    arg_name: arg.arg_name.to_string(),
+                    arg_ty_name: types.format_ty(arg.ty),
+                    val_path: expected_val.path.to_string(),
+                    val_ty_name: types.format_ty(expected_val.ty),
+                    issue: issue.to_owned(),
+                    expected: expected.to_string(),
+                    caller: caller.to_string(),
+                    callee: callee.to_string(),
+                    misplaced: misplaced_bytes(
+                        types,
+                        &expected_val,
+                        caller_val,
+                        callee_val,
+                        &expected_raw,
+                    ),
+                });
+            }
+        } else if caller_val.bytes != callee_val.bytes
+            || expected_val
+                .fixed_bytes()
+                .is_some_and(|expected| caller_val.bytes != expected)
+        {
             // General case, just get a pile of bytes to span both values
+            // (if a function body computed the value, both sides also need to match it)
             let func = expected_val.func();
             let arg = expected_val.arg();
-            let mut expected = vec![0; caller_val.bytes.len().max(callee_val.bytes.len())];
-            expected_val.fill_bytes(&mut expected);
+            let len = caller_val.bytes.len().max(callee_val.bytes.len());
+            let expected = expected_bytes(types, &expected_val, len);
             // FIXME: this doesn't do the right thing for enums
             // <https://github.com/Gankra/abi-cafe/issues/34>
             return Err(CheckFailure::ValMismatch {
@@ -167,6 +269,7 @@ impl TestHarness {
                 arg_ty_name: types.format_ty(arg.ty),
                 val_path: expected_val.path.to_string(),
                 val_ty_name: types.format_ty(expected_val.ty),
+                misplaced: misplaced_bytes(types, &expected_val, caller_val, callee_val, &expected),
                 expected,
                 caller: caller_val.bytes.clone(),
                 callee: callee_val.bytes.clone(),
@@ -177,11 +280,210 @@ impl TestHarness {
     }
 }
 
-fn load_tag(val: &ValBuffer) -> usize {
+/// Explain where each side's wrong bytes came from, if the value was graffiti.
+fn misplaced_bytes(
+    types: &kdl_script::TypedProgram,
+    val: &ValueRef,
+    caller: &ValBuffer,
+    callee: &ValBuffer,
+    expected: &[u8],
+) -> Vec<String> {
+    [(CallSide::Caller, caller), (CallSide::Callee, callee)]
+        .into_iter()
+        .filter_map(|(side, buf)| explain_graffiti(types, val, side, &buf.bytes, expected))
+        .collect()
+}
+
+/// Whether a type has more than one value in it (so laying it out is interesting).
+fn is_aggregate(types: &kdl_script::TypedProgram, ty: TyIdx) -> bool {
+    match types.realize_ty(ty) {
+        Ty::Primitive(_) | Ty::Enum(_) | Ty::Empty => false,
+        Ty::Alias(alias_ty) => is_aggregate(types, alias_ty.real),
+        _ => true,
+    }
+}
+
+/// Whether the value at `path` is inside the value at `parent` (like `arg0.Some.0` in `arg0`).
+fn is_under(path: &str, parent: &str) -> bool {
+    path.strip_prefix(parent)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// The bytes we expect a (non-tag) value to have.
+pub(super) fn expected_bytes(types: &kdl_script::TypedProgram, val: &Value, len: usize) -> Vec<u8> {
+    let mut expected = vec![0; len];
+    val.fill_bytes(&mut expected);
+    if let Ty::Primitive(PrimitiveTy::Bool) = types.realize_ty(val.ty) {
+        // bools are picked by the value rather than made of its bytes
+        if let Some(byte) = expected.first_mut() {
+            *byte = u8::from(val.generate_bool());
+        }
+    }
+    expected
+}
+
+/// The layout of an IEEE float type
+#[derive(Debug, Copy, Clone)]
+struct FloatFormat {
+    exp_bits: u32,
+    mant_bits: u32,
+}
+
+fn float_format(ty: &Ty) -> Option<FloatFormat> {
+    let (exp_bits, mant_bits) = match ty {
+        Ty::Primitive(PrimitiveTy::F16) => (5, 10),
+        Ty::Primitive(PrimitiveTy::F32) => (8, 23),
+        Ty::Primitive(PrimitiveTy::F64) => (11, 52),
+        Ty::Primitive(PrimitiveTy::F128) => (15, 112),
+        _ => return None,
+    };
+    Some(FloatFormat {
+        exp_bits,
+        mant_bits,
+    })
+}
+
+impl FloatFormat {
+    fn size(&self) -> usize {
+        (1 + self.exp_bits + self.mant_bits) as usize / 8
+    }
+
+    /// Read a float out of its native-endian bytes
+    fn load(self, bytes: &[u8]) -> Float {
+        let bits = if cfg!(target_endian = "little") {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |bits, &b| (bits << 8) | b as u128)
+        } else {
+            bytes.iter().fold(0, |bits, &b| (bits << 8) | b as u128)
+        };
+        Float { format: self, bits }
+    }
+}
+
+/// A float of some [`FloatFormat`][], as its bits
+#[derive(Debug, Copy, Clone)]
+struct Float {
+    format: FloatFormat,
+    bits: u128,
+}
+
+impl Float {
+    fn sign(&self) -> bool {
+        (self.bits >> (self.format.exp_bits + self.format.mant_bits)) & 1 == 1
+    }
+    fn exp(&self) -> u128 {
+        (self.bits >> self.format.mant_bits) & ((1 << self.format.exp_bits) - 1)
+    }
+    fn mant(&self) -> u128 {
+        self.bits & ((1 << self.format.mant_bits) - 1)
+    }
+    fn is_nan(&self) -> bool {
+        self.exp() == (1 << self.format.exp_bits) - 1 && self.mant() != 0
+    }
+    fn is_quiet_nan(&self) -> bool {
+        self.is_nan() && (self.mant() >> (self.format.mant_bits - 1)) & 1 == 1
+    }
+    fn is_zero(&self) -> bool {
+        self.exp() == 0 && self.mant() == 0
+    }
+    fn is_finite(&self) -> bool {
+        self.exp() != (1 << self.format.exp_bits) - 1
+    }
+
+    /// Whether these floats are close enough for the given comparison
+    fn matches(&self, other: &Float, mode: FloatCompare) -> bool {
+        let both_nan = self.is_nan() && other.is_nan();
+        let both_zero = self.is_zero() && other.is_zero();
+        match mode {
+            FloatCompare::Bitwise => self.bits == other.bits,
+            FloatCompare::NanInsensitive => self.bits == other.bits || both_nan,
+            FloatCompare::Value => self.bits == other.bits || both_nan || both_zero,
+        }
+    }
+
+    /// Describe what happened to this float to make it into `other`
+    fn issue(&self, other: &Float) -> &'static str {
+        let quiet_bit = 1 << (self.format.mant_bits - 1);
+        let sign_bit = 1 << (self.format.exp_bits + self.format.mant_bits);
+        if self.is_nan() && other.is_nan() {
+            if !self.is_quiet_nan() && self.bits | quiet_bit == other.bits {
+                "signalling NaN was quieted"
+            } else if self.bits ^ sign_bit == other.bits {
+                "sign of NaN changed"
+            } else {
+                "NaN payload changed"
+            }
+        } else if self.is_nan() {
+            "NaN became a number"
+        } else if other.is_nan() {
+            "became NaN"
+        } else if self.is_zero() && other.is_zero() {
+            "sign of zero changed"
+        } else if self.bits ^ sign_bit == other.bits {
+            "sign changed"
+        } else if self.is_finite()
+            && other.is_finite()
+            && self.sign() == other.sign()
+            && self.exp().abs_diff(other.exp()) <= 1
+            && other.mant().trailing_zeros() > self.mant().trailing_zeros()
+        {
+            // Rounding to fewer bits (like going through an f32, or x87 shenanigans)
+            // zeroes the low bits, and might carry into the exponent
+            "precision lost"
+        } else {
+            "value changed"
+        }
+    }
+
+    /// The value as an f64 (for display, so f128s can be approximate)
+    fn to_f64(self) -> f64 {
+        let FloatFormat {
+            exp_bits,
+            mant_bits,
+        } = self.format;
+        let bias = (1 << (exp_bits - 1)) - 1;
+        let mant = self.mant() as f64 / 2f64.powi(mant_bits as i32);
+        let val = if self.exp() == 0 {
+            mant * 2f64.powi(1 - bias)
+        } else if self.is_finite() {
+            (1.0 + mant) * 2f64.powi(self.exp() as i32 - bias)
+        } else {
+            f64::INFINITY
+        };
+        if self.sign() {
+            -val
+        } else {
+            val
+        }
+    }
+}
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.sign() { "-" } else { "" };
+        if self.is_quiet_nan() {
+            write!(f, "{sign}qNaN")?;
+        } else if self.is_nan() {
+            write!(f, "{sign}sNaN")?;
+        } else if self.format.mant_bits == 23 {
+            write!(f, "{:?}", self.to_f64() as f32)?;
+        } else if self.format.mant_bits > 52 {
+            write!(f, "~{:?}", self.to_f64())?;
+        } else {
+            write!(f, "{:?}", self.to_f64())?;
+        }
+        let digits = self.format.size() * 2;
+        write!(f, " (0x{:0digits$X})", self.bits)
+    }
+}
+
+pub(super) fn load_tag(val: &ValBuffer) -> usize {
     u32::from_ne_bytes(<[u8; 4]>::try_from(&val.bytes[..4]).unwrap()) as usize
 }
 
-fn tagged_variant_name(tagged_ty: &kdl_script::types::TaggedTy, tag: usize) -> String {
+pub(super) fn tagged_variant_name(tagged_ty: &kdl_script::types::TaggedTy, tag: usize) -> String {
     let tagged_name = &tagged_ty.name;
     let variant_name = tagged_ty
         .variants
@@ -191,7 +493,7 @@ fn tagged_variant_name(tagged_ty: &kdl_script::types::TaggedTy, tag: usize) -> S
     format!("{tagged_name}::{variant_name}")
 }
 
-fn enum_variant_name(enum_ty: &kdl_script::types::EnumTy, tag: usize) -> String {
+pub(super) fn enum_variant_name(enum_ty: &kdl_script::types::EnumTy, tag: usize) -> String {
     let enum_name = &enum_ty.name;
     let variant_name = enum_ty
         .variants
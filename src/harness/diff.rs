@@ -0,0 +1,337 @@
+//! Layout-annotated views of the bytes of failing arguments.
+//!
+//! Tests only write back the bytes of each leaf value (never padding), so a
+//! [`CheckFailure`][crate::error::CheckFailure] can only show one value at a time.
+//! When the real problem is how an aggregate was laid out, it's much easier to see
+//! with every value put back at the offset the type says it lives at, so
+//! [`ArgDiff`][] lays out the whole argument for the host (with kdl-script's
+//! layout engine) and records what each side saw in every field.
+
+use kdl_script::layout::{DataLayout, TyLayout};
+use kdl_script::parse::LangRepr;
+use kdl_script::types::{Ty, TyIdx};
+use kdl_script::{PunEnv, TypedProgram};
+use serde::Serialize;
+
+use super::check::{enum_variant_name, expected_bytes, load_tag, tagged_variant_name};
+use super::run::{FuncBuffer, ValBuffer};
+use super::vals::{ArgValuesIter, ValueRef};
+
+/// One argument of a failing subtest, laid out in memory.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgDiff {
+    pub arg_idx: usize,
+    pub arg_name: String,
+    pub arg_ty_name: String,
+    /// The argument itself, followed by whatever its references point to
+    pub blocks: Vec<MemoryBlock>,
+    /// Why the argument couldn't be laid out (like it being `repr(Rust)`)
+    pub layout_error: Option<String>,
+}
+
+/// A contiguous piece of memory: an argument, or the pointee of a reference in one.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryBlock {
+    pub path: String,
+    pub ty_name: String,
+    pub size: u64,
+    pub align: u64,
+    /// Everything in the block in order of offset, with the gaps filled in by padding
+    pub regions: Vec<MemoryRegion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryRegion {
+    pub offset: u64,
+    pub size: u64,
+    pub contents: RegionContents,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum RegionContents {
+    /// Bytes that aren't part of any (active) field
+    Padding,
+    /// A reference, whose pointee is laid out in the block with this path
+    Pointer { pointee: String },
+    /// A value, and the bytes each side saw for it (native-endian)
+    Bytes {
+        path: String,
+        ty_name: String,
+        expected: Vec<u8>,
+        caller: Vec<u8>,
+        callee: Vec<u8>,
+    },
+    /// The variant of an enum or tagged union (which tests report by name, not bytes)
+    Variant {
+        path: String,
+        ty_name: String,
+        expected: String,
+        caller: String,
+        callee: String,
+    },
+}
+
+impl RegionContents {
+    /// Whether either side saw something other than what we expected here.
+    pub fn differs(&self) -> bool {
+        match self {
+            RegionContents::Padding | RegionContents::Pointer { .. } => false,
+            RegionContents::Bytes {
+                expected,
+                caller,
+                callee,
+                ..
+            } => caller != expected || callee != expected,
+            RegionContents::Variant {
+                expected,
+                caller,
+                callee,
+                ..
+            } => caller != expected || callee != expected,
+        }
+    }
+}
+
+impl ArgDiff {
+    /// Lay out an argument and the values each side wrote for it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        types: &TypedProgram,
+        env: &PunEnv,
+        target: &DataLayout,
+        repr: LangRepr,
+        vals: ArgValuesIter,
+        caller: &FuncBuffer,
+        callee: &FuncBuffer,
+    ) -> Self {
+        let arg = vals.arg();
+        let mut layouter = ArgLayouter {
+            types,
+            env,
+            target,
+            repr,
+            vals,
+            caller,
+            callee,
+            blocks: vec![],
+        };
+        let result = layouter
+            .new_block(arg.ty, &arg.arg_name)
+            .and_then(|block| layouter.place(block, arg.ty, 0, &arg.arg_name));
+        let (blocks, layout_error) = match result {
+            Ok(()) => (layouter.finish(), None),
+            Err(e) => (vec![], Some(e.to_string())),
+        };
+        ArgDiff {
+            arg_idx: layouter.vals.arg_idx,
+            arg_name: arg.arg_name.clone(),
+            arg_ty_name: types.format_ty(arg.ty),
+            blocks,
+            layout_error,
+        }
+    }
+}
+
+/// Walks an argument's type in the same order the values were generated in,
+/// putting each value where the layout says it goes.
+struct ArgLayouter<'a> {
+    types: &'a TypedProgram,
+    env: &'a PunEnv,
+    target: &'a DataLayout,
+    repr: LangRepr,
+    vals: ArgValuesIter<'a>,
+    caller: &'a FuncBuffer,
+    callee: &'a FuncBuffer,
+    blocks: Vec<MemoryBlock>,
+}
+
+impl<'a> ArgLayouter<'a> {
+    fn layout_of(&self, ty: TyIdx) -> kdl_script::Result<TyLayout> {
+        self.types
+            .layout_of_with_repr(ty, self.env, self.target, self.repr)
+    }
+
+    fn new_block(&mut self, ty: TyIdx, path: &str) -> kdl_script::Result<usize> {
+        let layout = self.layout_of(ty)?;
+        self.blocks.push(MemoryBlock {
+            path: path.to_owned(),
+            ty_name: self.types.format_ty(ty),
+            size: layout.size,
+            align: layout.align,
+            regions: vec![],
+        });
+        Ok(self.blocks.len() - 1)
+    }
+
+    fn push(&mut self, block: usize, offset: u64, size: u64, contents: RegionContents) {
+        self.blocks[block].regions.push(MemoryRegion {
+            offset,
+            size,
+            contents,
+        });
+    }
+
+    /// The next value, and what the caller and callee wrote for it.
+    fn next_val(&mut self) -> (ValueRef<'a>, &'a ValBuffer, &'a ValBuffer) {
+        let val = self.vals.next_val();
+        let caller = self.caller.vals.get(val.absolute_val_idx);
+        let callee = self.callee.vals.get(val.absolute_val_idx);
+        (
+            val,
+            caller.unwrap_or(&EMPTY_VAL),
+            callee.unwrap_or(&EMPTY_VAL),
+        )
+    }
+
+    fn place(
+        &mut self,
+        block: usize,
+        ty: TyIdx,
+        offset: u64,
+        path: &str,
+    ) -> kdl_script::Result<()> {
+        let types = self.types;
+        match types.realize_ty(ty) {
+            Ty::Primitive(_) => {
+                let size = self.layout_of(ty)?.size;
+                let (val, caller, callee) = self.next_val();
+                let contents = RegionContents::Bytes {
+                    path: val.path.clone(),
+                    ty_name: types.format_ty(ty),
+                    expected: expected_bytes(types, &val, size as usize),
+                    caller: caller.bytes.clone(),
+                    callee: callee.bytes.clone(),
+                };
+                self.push(block, offset, size, contents);
+            }
+            Ty::Enum(enum_ty) => {
+                let size = self.layout_of(ty)?.size;
+                let (val, caller, callee) = self.next_val();
+                let expected = val.generate_idx(enum_ty.variants.len());
+                let contents = RegionContents::Variant {
+                    path: val.path.clone(),
+                    ty_name: types.format_ty(ty),
+                    expected: enum_variant_name(enum_ty, expected),
+                    caller: tag_name(caller, |tag| enum_variant_name(enum_ty, tag)),
+                    callee: tag_name(callee, |tag| enum_variant_name(enum_ty, tag)),
+                };
+                self.push(block, offset, size, contents);
+            }
+            Ty::Empty => {}
+            Ty::Alias(alias_ty) => self.place(block, alias_ty.real, offset, path)?,
+            Ty::Pun(pun_ty) => {
+                let real = types.resolve_pun(pun_ty, self.env)?;
+                self.place(block, real, offset, path)?;
+            }
+            Ty::Ref(ref_ty) => {
+                // The pointer itself isn't a value, but what it points to is
+                let pointee = format!("{path}.*");
+                self.push(
+                    block,
+                    offset,
+                    self.target.pointer_size,
+                    RegionContents::Pointer {
+                        pointee: pointee.clone(),
+                    },
+                );
+                let pointee_block = self.new_block(ref_ty.pointee_ty, &pointee)?;
+                self.place(pointee_block, ref_ty.pointee_ty, 0, &pointee)?;
+            }
+            Ty::Array(array_ty) => {
+                let elem_size = self.layout_of(array_ty.elem_ty)?.size;
+                for idx in 0..array_ty.len {
+                    let elem_path = format!("{path}[{idx}]");
+                    let elem_offset = offset + elem_size * idx;
+                    self.place(block, array_ty.elem_ty, elem_offset, &elem_path)?;
+                }
+            }
+            Ty::Struct(struct_ty) => {
+                let layout = self.layout_of(ty)?;
+                for (idx, field) in struct_ty.fields.iter().enumerate() {
+                    // (a transparent struct only lays out its first field, the rest are empty)
+                    let field_offset = layout.fields.get(idx).map_or(0, |field| field.offset);
+                    let field_path = format!("{path}.{}", field.ident);
+                    self.place(block, field.ty, offset + field_offset, &field_path)?;
+                }
+            }
+            Ty::Union(union_ty) => {
+                // The harness picks which field is active, it's not in memory
+                let (val, _, _) = self.next_val();
+                let active = val.generate_idx(union_ty.fields.len());
+                if let Some(field) = union_ty.fields.get(active) {
+                    let field_path = format!("{path}.{}", field.ident);
+                    self.place(block, field.ty, offset, &field_path)?;
+                }
+            }
+            Ty::Tagged(tagged_ty) => {
+                let layout = self.layout_of(ty)?;
+                let (val, caller, callee) = self.next_val();
+                let active = val.generate_idx(tagged_ty.variants.len());
+                if let Some(tag) = &layout.tag {
+                    let contents = RegionContents::Variant {
+                        path: val.path.clone(),
+                        ty_name: types.format_ty(ty),
+                        expected: tagged_variant_name(tagged_ty, active),
+                        caller: tag_name(caller, |tag| tagged_variant_name(tagged_ty, tag)),
+                        callee: tag_name(callee, |tag| tagged_variant_name(tagged_ty, tag)),
+                    };
+                    self.push(block, offset + tag.offset, tag.size, contents);
+                }
+                let variant = tagged_ty.variants.get(active);
+                let fields = variant.and_then(|variant| variant.fields.as_deref());
+                let field_layouts = layout.variants.get(active).map(|variant| &variant.fields);
+                for (idx, field) in fields.unwrap_or_default().iter().enumerate() {
+                    let field_offset = field_layouts
+                        .and_then(|fields| fields.get(idx))
+                        .map_or(0, |field| field.offset);
+                    let field_path = format!(
+                        "{path}.{}.{}",
+                        variant.expect("fields without a variant!?").name,
+                        field.ident
+                    );
+                    self.place(block, field.ty, offset + field_offset, &field_path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sort each block's regions and fill in the padding between them.
+    fn finish(&mut self) -> Vec<MemoryBlock> {
+        let mut blocks = std::mem::take(&mut self.blocks);
+        for block in &mut blocks {
+            let mut regions = std::mem::take(&mut block.regions);
+            regions.sort_by_key(|region| region.offset);
+            let mut end = 0;
+            for region in regions {
+                if region.offset > end {
+                    block.regions.push(padding(end, region.offset));
+                }
+                end = end.max(region.offset + region.size);
+                block.regions.push(region);
+            }
+            if block.size > end {
+                block.regions.push(padding(end, block.size));
+            }
+        }
+        blocks
+    }
+}
+
+static EMPTY_VAL: ValBuffer = ValBuffer { bytes: vec![] };
+
+fn padding(start: u64, end: u64) -> MemoryRegion {
+    MemoryRegion {
+        offset: start,
+        size: end - start,
+        contents: RegionContents::Padding,
+    }
+}
+
+/// The name of the variant a side wrote (if it wrote one at all).
+fn tag_name(val: &ValBuffer, variant_name: impl Fn(usize) -> String) -> String {
+    if val.bytes.len() < 4 {
+        return String::new();
+    }
+    variant_name(load_tag(val))
+}
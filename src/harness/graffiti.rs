@@ -0,0 +1,210 @@
+//! Decoding graffiti values.
+//!
+//! Graffiti values write the (wrapping) index of the value into the high nibble of
+//! each byte and the (wrapping) index of the byte into the low nibble. So when a side
+//! sees the wrong bytes for a value, we can usually work out which bytes of which
+//! value it saw instead, and say "callee's arg0.y contains bytes 0-3 of arg0.x"
+//! rather than making someone squint at the hex.
+
+use kdl_script::layout::DataLayout;
+use kdl_script::types::{PrimitiveTy, Ty};
+use kdl_script::TypedProgram;
+
+use super::test::CallSide;
+use super::vals::{ValueGenerator, ValueRef, ValueTree};
+
+/// The graffiti for byte `byte_idx` of the value with graffiti index `idx`.
+pub fn graffiti_byte(idx: u64, byte_idx: usize) -> u8 {
+    ((idx as u8) << 4) | ((byte_idx as u8) & 0b1111)
+}
+
+/// A value whose graffiti a byte might have come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Source {
+    func_idx: usize,
+    arg_idx: usize,
+    val_idx: usize,
+    absolute_val_idx: usize,
+    idx: u64,
+    size: usize,
+}
+
+/// A run of bytes of the value we're explaining.
+#[derive(Debug, PartialEq, Eq)]
+enum Run {
+    /// Bytes `start..` of this source (which may be the value itself)
+    From { source: Source, start: usize },
+    /// Bytes that aren't anyone's graffiti
+    Unknown,
+}
+
+/// Explain where the bytes a side saw for a graffiti value came from,
+/// or None if they're right (or the value isn't graffiti).
+pub fn explain_graffiti(
+    types: &TypedProgram,
+    val: &ValueRef,
+    side: CallSide,
+    bytes: &[u8],
+    expected: &[u8],
+) -> Option<String> {
+    if !matches!(val.val, ValueGenerator::Graffiti { .. }) || bytes.is_empty() {
+        return None;
+    }
+    if bytes == expected {
+        return None;
+    }
+    let sources = graffiti_sources(types, val.tree);
+    let this = sources
+        .iter()
+        .find(|source| {
+            source.absolute_val_idx == val.absolute_val_idx && source.func_idx == val.func_idx
+        })
+        .copied()?;
+
+    // Decode each byte, preferring to keep reading from the same source as the last one
+    let mut runs: Vec<(usize, usize, Run)> = vec![];
+    for (byte_idx, &byte) in bytes.iter().enumerate() {
+        if let Some((run_start, end, Run::From { source, start })) = runs.last_mut() {
+            let src_byte = *start + (byte_idx - *run_start);
+            if src_byte < source.size && graffiti_byte(source.idx, src_byte) == byte {
+                *end = byte_idx + 1;
+                continue;
+            }
+        }
+        let run = decode_byte(&sources, &this, byte_idx, byte);
+        runs.push((byte_idx, byte_idx + 1, run));
+    }
+    // A lone byte of a wider value is more likely a coincidence (like a zero) than graffiti
+    for (start, end, run) in &mut runs {
+        if let Run::From { source, .. } = run {
+            if *end - *start == 1 && source.size > 1 {
+                *run = Run::Unknown;
+            }
+        }
+    }
+    runs.dedup_by(|(_, end, run), (_, prev_end, prev_run)| {
+        let merge = *run == Run::Unknown && *prev_run == Run::Unknown;
+        if merge {
+            *prev_end = *end;
+        }
+        merge
+    });
+
+    let parts = runs
+        .iter()
+        .map(|(start, end, run)| match run {
+            Run::From {
+                source,
+                start: src_start,
+            } if *source == this && src_start == start => {
+                format!("the right {}", byte_range(*start, *end))
+            }
+            Run::From {
+                source,
+                start: src_start,
+            } => {
+                let src_end = src_start + (end - start);
+                format!(
+                    "{} of {}",
+                    byte_range(*src_start, src_end),
+                    source_name(val, source)
+                )
+            }
+            Run::Unknown => {
+                let hex = bytes[*start..*end]
+                    .iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("{} that aren't graffiti ({hex})", byte_count(end - start))
+            }
+        })
+        .collect::<Vec<_>>();
+    Some(format!(
+        "{side}'s {} contains {}",
+        val.path,
+        parts.join(", then ")
+    ))
+}
+
+/// Find the value this byte is most likely graffiti from: the nearest one in the
+/// same function, or failing that the nearest function.
+fn decode_byte(sources: &[Source], this: &Source, byte_idx: usize, byte: u8) -> Run {
+    let val_nibble = u64::from(byte >> 4);
+    let byte_nibble = usize::from(byte & 0b1111);
+    sources
+        .iter()
+        .filter(|source| source.idx % 16 == val_nibble && byte_nibble < source.size)
+        .min_by_key(|source| {
+            (
+                source.func_idx.abs_diff(this.func_idx),
+                source.absolute_val_idx.abs_diff(this.absolute_val_idx),
+            )
+        })
+        .map(|&source| {
+            // Values bigger than 16 bytes repeat byte indices, so pick the closest one
+            let start = (byte_nibble..source.size)
+                .step_by(16)
+                .min_by_key(|src_byte| src_byte.abs_diff(byte_idx))
+                .unwrap_or(byte_nibble);
+            Run::From { source, start }
+        })
+        .unwrap_or(Run::Unknown)
+}
+
+/// Every value in the test that was written as graffiti bytes.
+fn graffiti_sources(types: &TypedProgram, tree: &ValueTree) -> Vec<Source> {
+    let target = DataLayout::host();
+    let mut sources = vec![];
+    for (func_idx, func) in tree.funcs.iter().enumerate() {
+        for (arg_idx, arg) in func.args.iter().enumerate() {
+            for (val_idx, val) in arg.vals.iter().enumerate() {
+                let ValueGenerator::Graffiti { idx } = val.val else {
+                    continue;
+                };
+                // Tags and bools are picked by the value, not made of its bytes
+                let size = match types.realize_ty(val.ty) {
+                    Ty::Primitive(PrimitiveTy::Bool) => continue,
+                    Ty::Primitive(prim) => target.primitive(*prim).0 as usize,
+                    _ => continue,
+                };
+                sources.push(Source {
+                    func_idx,
+                    arg_idx,
+                    val_idx,
+                    absolute_val_idx: arg.absolute_val_idx_start + val_idx,
+                    idx,
+                    size,
+                });
+            }
+        }
+    }
+    sources
+}
+
+/// The path of a source, with its function if it's not the one we're explaining.
+fn source_name(val: &ValueRef, source: &Source) -> String {
+    let func = &val.tree.funcs[source.func_idx];
+    let path = &func.args[source.arg_idx].vals[source.val_idx].path;
+    if source.func_idx == val.func_idx {
+        path.clone()
+    } else {
+        format!("{}'s {path}", func.func_name)
+    }
+}
+
+fn byte_range(start: usize, end: usize) -> String {
+    if end - start == 1 {
+        format!("byte {start}")
+    } else {
+        format!("bytes {start}-{}", end - 1)
+    }
+}
+
+fn byte_count(count: usize) -> String {
+    if count == 1 {
+        "1 byte".to_owned()
+    } else {
+        format!("{count} bytes")
+    }
+}
@@ -15,12 +15,15 @@ use tracing::warn;
 
 mod build;
 mod check;
+mod diff;
 mod generate;
+mod graffiti;
 mod read;
 mod run;
 pub mod test;
 pub mod vals;
 
+pub use diff::{ArgDiff, RegionContents};
 pub use read::{find_tests, spawn_read_test};
 pub use run::TestBuffer;
 
@@ -36,6 +39,7 @@ pub struct TestHarness {
     generated_sources: Memoized<Utf8PathBuf, ()>,
     built_static_libs: Memoized<String, String>,
     concurrency_limiter: tokio::sync::Semaphore,
+    float_compare: Vec<FloatCompareRule>,
 }
 
 impl TestHarness {
@@ -50,6 +54,7 @@ impl TestHarness {
             generated_sources: Default::default(),
             built_static_libs: Default::default(),
             concurrency_limiter: Semaphore::new(128),
+            float_compare: cfg.float_compare.clone(),
         }
     }
 
@@ -113,7 +118,18 @@ impl TestHarness {
         let caller = self.toolchains[&test_key.caller].clone();
         let callee = self.toolchains[&test_key.callee].clone();
 
-        get_test_rules(test_key, &*caller, &*callee)
+        let mut rules = get_test_rules(test_key, &*caller, &*callee);
+        // --float-compare overrides the builtin rules, and a rule for this specific test
+        // overrides a rule for every test
+        let general = self.float_compare.iter().filter(|rule| rule.test.is_none());
+        let specific = self
+            .float_compare
+            .iter()
+            .filter(|rule| rule.test.as_ref() == Some(&test_key.test));
+        if let Some(rule) = general.chain(specific).last() {
+            rules.float_compare = rule.mode;
+        }
+        rules
     }
 
     pub fn spawn_test(
@@ -198,7 +214,7 @@ impl TestHarness {
         }
 
         res.ran_to = Check;
-        res.check = Some(self.check_test(&res.key, run).await);
+        res.check = Some(self.check_test(&res.key, &res.rules, run).await);
 
         res
     }
@@ -280,9 +296,14 @@ impl TestHarness {
             ValueGeneratorKind::Graffiti => {
                 // Do nothing, implicit default
             }
-            ValueGeneratorKind::Random { seed } => {
+            ValueGeneratorKind::Random { .. }
+            | ValueGeneratorKind::RandomRange { .. }
+            | ValueGeneratorKind::Edges { .. }
+            | ValueGeneratorKind::Exhaustive
+            | ValueGeneratorKind::Explicit
+            | ValueGeneratorKind::Variants { .. } => {
                 output.push_str(separator);
-                output.push_str(&format!("random{seed}"));
+                output.push_str(&val_generator.to_string());
             }
         }
         output
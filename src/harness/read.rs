@@ -105,13 +105,20 @@ pub fn spawn_read_test(
     rt: &tokio::runtime::Runtime,
     test: TestId,
     test_file: TestFile,
+    procgen_dump_dir: Option<Utf8PathBuf>,
 ) -> tokio::task::JoinHandle<Result<Arc<Test>, GenerateError>> {
-    rt.spawn(async move { read_test(test, test_file).await })
+    rt.spawn(async move { read_test(test, test_file, procgen_dump_dir).await })
 }
 
 /// Read a test .kdl file
-async fn read_test(test: TestId, test_file: TestFile) -> Result<Arc<Test>, GenerateError> {
-    read_test_inner(&test, test_file)
+///
+/// If `procgen_dump_dir` is set, tests generated from .procgen.kdl files are written there.
+async fn read_test(
+    test: TestId,
+    test_file: TestFile,
+    procgen_dump_dir: Option<Utf8PathBuf>,
+) -> Result<Arc<Test>, GenerateError> {
+    read_test_inner(&test, test_file, procgen_dump_dir.as_deref())
         .await
         .map_err(|e| GenerateError::ReadTest {
             test,
@@ -119,20 +126,42 @@ async fn read_test(test: TestId, test_file: TestFile) -> Result<Arc<Test>, Gener
         })
 }
 
-async fn read_test_inner(test: &TestId, test_file: TestFile) -> Result<Arc<Test>, GenerateError> {
-    let (test_file, input) = match test_file {
+async fn read_test_inner(
+    test: &TestId,
+    test_file: TestFile,
+    procgen_dump_dir: Option<&Utf8Path>,
+) -> Result<Arc<Test>, GenerateError> {
+    // Gather up every error in the file so they can all be fixed in one go
+    let mut compiler = kdl_script::Compiler::with_error_handler(kdl_script::ErrorHandler {
+        error_style: kdl_script::ErrorStyle::Human,
+        error_mode: kdl_script::ErrorMode::Gather(vec![]),
+    });
+    let types = match test_file {
         TestFile::KdlProcgen(test_file) => {
             let ty_def = read_file_to_string(&test_file)?;
-            let input = procgen::procgen_test_for_ty_string(test, Some(&ty_def));
-            (test_file, input)
+            let ty_defs = compiler.parse_string(test_file.as_str(), ty_def.clone())?;
+            let program = procgen::procgen_test_for_ty(test, &ty_defs)
+                .map_err(kdl_script::KdlScriptError::from)?;
+
+            if let Some(dump_dir) = procgen_dump_dir {
+                // (comments can only be carried over from the kdl syntax)
+                let dump = if kdl_script::parse::compact::is_compact_path(test_file.as_str()) {
+                    program.to_kdl()
+                } else {
+                    program.to_kdl_with_comments(&ty_def)
+                };
+                let dump = dump.map_err(kdl_script::KdlScriptError::from)?;
+                std::fs::create_dir_all(dump_dir)?;
+                std::fs::write(dump_dir.join(format!("{test}.kdl")), dump)?;
+            }
+
+            compiler.compile_derived(program)?
         }
         TestFile::Kdl(test_file) => {
             let input = read_file_to_string(&test_file)?;
-            (test_file, input)
+            compiler.compile_string(test_file.as_str(), input)?
         }
     };
-    let mut compiler = kdl_script::Compiler::new();
-    let types = compiler.compile_string(test_file.as_str(), input)?;
     Ok(Arc::new(Test {
         name: test.to_owned(),
         types,
@@ -146,7 +175,6 @@ fn read_file_to_string(pathish: &Pathish) -> std::io::Result<String> {
     }
 }
 
-#[allow(clippy::manual_map)]
 fn classify_test(test_file: &Utf8Path, is_runtime: bool) -> Option<(String, TestFile)> {
     let file_name = test_file.file_name().expect("test file had no name!?");
     let pathish = if is_runtime {
@@ -154,13 +182,15 @@ fn classify_test(test_file: &Utf8Path, is_runtime: bool) -> Option<(String, Test
     } else {
         Pathish::Static(test_file.to_owned())
     };
-    if let Some(test_name) = file_name.strip_suffix(".procgen.kdl") {
-        Some((test_name.to_owned(), TestFile::KdlProcgen(pathish)))
-    } else if let Some(test_name) = file_name.strip_suffix(".kdl") {
-        Some((test_name.to_owned(), TestFile::Kdl(pathish)))
-    } else {
-        None
+    // Programs can be written in kdl or the compact syntax
+    for ext in ["kdl", kdl_script::parse::compact::EXTENSION] {
+        if let Some(test_name) = file_name.strip_suffix(&format!(".procgen.{ext}")) {
+            return Some((test_name.to_owned(), TestFile::KdlProcgen(pathish)));
+        } else if let Some(test_name) = file_name.strip_suffix(&format!(".{ext}")) {
+            return Some((test_name.to_owned(), TestFile::Kdl(pathish)));
+        }
     }
+    None
 }
 
 fn read_runtime_file_to_string(file: &Utf8Path) -> std::io::Result<String> {
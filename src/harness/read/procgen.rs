@@ -1,47 +1,48 @@
-pub fn procgen_test_for_ty_string(ty_name: &str, ty_def: Option<&str>) -> String {
-    let mut test_body = String::new();
-    procgen_test_for_ty_impl(&mut test_body, ty_name, ty_def).unwrap();
-    test_body
+use kdl_script::builder::{BuilderError, FuncBuilder, ProgramBuilder, StructBuilder};
+use kdl_script::parse::{TyDecl, Tydent, TypedVar};
+use kdl_script::ParsedProgram;
+
+pub fn procgen_test_for_ty(
+    ty_name: &str,
+    ty_defs: &ParsedProgram,
+) -> Result<ParsedProgram, BuilderError> {
+    // Apply the type's definitions first
+    let mut out = ProgramBuilder::from_parsed(ty_defs);
+    procgen_test_for_ty_impl(&mut out, ty_name, ty_defs)?;
+    Ok(out.build())
 }
 
 fn procgen_test_for_ty_impl(
-    out: &mut dyn std::fmt::Write,
+    out: &mut ProgramBuilder,
     ty_name: &str,
-    ty_def: Option<&str>,
-) -> std::fmt::Result {
-    let ty = ty_name;
-    let ty_ref = format!("&{ty_name}");
+    ty_defs: &ParsedProgram,
+) -> Result<(), BuilderError> {
+    let ty = Tydent::named(ty_name);
+    let ty_ref = Tydent::reference(ty.clone());
 
-    // Apply the type's definitions first
-    let has_refs = if let Some(ty_def) = ty_def {
-        writeln!(out, "{}", ty_def)?;
-        // To avoid outparam nonsense, avoid testing outputs of the type
-        // if any part of its definition involves a reference.
-        // (Yes this is a blunt check but it's fine enough.)
-        ty_def.contains('&')
-    } else {
-        false
-    };
+    // To avoid outparam nonsense, avoid testing outputs of the type
+    // if any part of its definition involves a reference.
+    let has_refs = ty_defs.tys.values().any(decl_has_refs);
 
     // Start gentle with basic one value in/out tests
-    add_func(out, "val_in", &[ty], &[])?;
-    add_func(out, "ref_in", &[&ty_ref], &[])?;
+    add_func(out, "val_in", vec![ty.clone()], vec![]);
+    add_func(out, "ref_in", vec![ty_ref], vec![]);
     if !has_refs {
-        add_func(out, "val_out", &[], &[ty])?;
-        add_func(out, "val_in_out", &[ty], &[ty])?;
+        add_func(out, "val_out", vec![], vec![ty.clone()]);
+        add_func(out, "val_in_out", vec![ty.clone()], vec![ty.clone()]);
     }
 
     // Stress out the calling convention and try lots of different
     // input counts. For many types this will result in register
     // exhaustion and get some things passed on the stack.
     for len in 2..=16 {
-        add_func(out, &format!("val_in_{len}"), &vec![ty; len], &[])?;
+        add_func(out, &format!("val_in_{len}"), vec![ty.clone(); len], vec![]);
     }
 
     // Stress out the calling convention with a struct full of values.
     // Some conventions will just shove this in a pointer/stack,
     // others will try to scalarize this into registers anyway.
-    add_structs(out, ty)?;
+    add_structs(out, &ty)?;
 
     // Now perturb the arguments by including a byte and a float in
     // the argument list. This will mess with alignment and also mix
@@ -54,58 +55,57 @@ fn procgen_test_for_ty_impl(
     let small_count = 4;
     let big_count = 16;
 
-    add_perturbs(out, ty, small_count, "small")?;
-    add_perturbs(out, ty, big_count, "big")?;
-    add_perturbs_struct(out, ty, small_count, "small")?;
-    add_perturbs_struct(out, ty, big_count, "big")?;
+    add_perturbs(out, &ty, small_count, "small");
+    add_perturbs(out, &ty, big_count, "big");
+    add_perturbs_struct(out, &ty, small_count, "small")?;
+    add_perturbs_struct(out, &ty, big_count, "big")?;
     Ok(())
 }
 
-fn add_structs(out: &mut dyn std::fmt::Write, ty: &str) -> std::fmt::Result {
+fn add_structs(out: &mut ProgramBuilder, ty: &Tydent) -> Result<(), BuilderError> {
     for len in 1..=16 {
         // Establish type names
-        let struct_ty = format!("Many{len}");
-        let struct_ty_ref = format!("&{struct_ty}");
+        let struct_ty = Tydent::named(&format!("Many{len}"));
+        let struct_ty_ref = Tydent::reference(struct_ty.clone());
 
         // Emit struct defs
-        writeln!(out, r#"struct "{struct_ty}" {{"#)?;
+        let mut struct_decl = StructBuilder::new(&format!("Many{len}"));
         for field_idx in 0..len {
-            writeln!(out, r#"    f{field_idx} "{ty}""#)?;
+            struct_decl = struct_decl.field(&format!("f{field_idx}"), ty.clone());
         }
-        writeln!(out, r#"}}"#)?;
+        out.add_ty(struct_decl)?;
 
         // Check that by-val works
-        add_func(out, &format!("struct_in_{len}"), &[&struct_ty], &[])?;
+        add_func(out, &format!("struct_in_{len}"), vec![struct_ty], vec![]);
         // Check that by-ref works, for good measure
-        add_func(out, &format!("ref_struct_in_{len}"), &[&struct_ty_ref], &[])?;
+        add_func(
+            out,
+            &format!("ref_struct_in_{len}"),
+            vec![struct_ty_ref],
+            vec![],
+        );
     }
     Ok(())
 }
 
-fn add_perturbs(
-    out: &mut dyn std::fmt::Write,
-    ty: &str,
-    count: usize,
-    label: &str,
-) -> std::fmt::Result {
+fn add_perturbs(out: &mut ProgramBuilder, ty: &Tydent, count: usize, label: &str) {
     for idx in 0..count {
         let inputs = perturb_list(ty, count, idx);
         add_func(
             out,
             &format!("val_in_{idx}_perturbed_{label}"),
-            &inputs,
-            &[],
-        )?;
+            inputs,
+            vec![],
+        );
     }
-    Ok(())
 }
 
 fn add_perturbs_struct(
-    out: &mut dyn std::fmt::Write,
-    ty: &str,
+    out: &mut ProgramBuilder,
+    ty: &Tydent,
     count: usize,
     label: &str,
-) -> std::fmt::Result {
+) -> Result<(), BuilderError> {
     for idx in 0..count {
         let inputs = perturb_list(ty, count, idx);
 
@@ -113,50 +113,63 @@ fn add_perturbs_struct(
         let struct_ty = format!("Perturbed{label}{idx}");
 
         // Emit struct defs
-        writeln!(out, r#"struct "{struct_ty}" {{"#)?;
-        for (field_idx, field_ty) in inputs.iter().enumerate() {
-            writeln!(out, r#"    f{field_idx} "{field_ty}""#)?;
+        let mut struct_decl = StructBuilder::new(&struct_ty);
+        for (field_idx, field_ty) in inputs.into_iter().enumerate() {
+            struct_decl = struct_decl.field(&format!("f{field_idx}"), field_ty);
         }
-        writeln!(out, r#"}}"#)?;
+        out.add_ty(struct_decl)?;
 
         // Add the function
         add_func(
             out,
             &format!("val_in_{idx}_perturbed_{label}"),
-            &[&struct_ty],
-            &[],
-        )?;
+            vec![Tydent::named(&struct_ty)],
+            vec![],
+        );
     }
     Ok(())
 }
 
-fn perturb_list(ty: &str, count: usize, idx: usize) -> Vec<&str> {
-    let mut inputs = vec![ty; count];
+fn perturb_list(ty: &Tydent, count: usize, idx: usize) -> Vec<Tydent> {
+    let mut inputs = vec![ty.clone(); count];
 
     let byte_idx = idx;
     let float_idx = count - 1 - idx;
-    inputs[byte_idx] = "u8";
-    inputs[float_idx] = "f32";
+    inputs[byte_idx] = Tydent::named("u8");
+    inputs[float_idx] = Tydent::named("f32");
     inputs
 }
 
-fn add_func(
-    out: &mut dyn std::fmt::Write,
-    func_name: &str,
-    inputs: &[&str],
-    outputs: &[&str],
-) -> std::fmt::Result {
-    writeln!(out, r#"fn "{func_name}" {{"#)?;
-    writeln!(out, r#"    inputs {{"#)?;
+fn add_func(out: &mut ProgramBuilder, func_name: &str, inputs: Vec<Tydent>, outputs: Vec<Tydent>) {
+    let mut func = FuncBuilder::new(func_name);
     for arg_ty in inputs {
-        writeln!(out, r#"        _ "{arg_ty}""#)?;
+        func = func.input("_", arg_ty);
     }
-    writeln!(out, r#"    }}"#)?;
-    writeln!(out, r#"    outputs {{"#)?;
     for arg_ty in outputs {
-        writeln!(out, r#"        _ "{arg_ty}""#)?;
+        func = func.output("_", arg_ty);
+    }
+    out.add_func(func);
+}
+
+fn decl_has_refs(decl: &TyDecl) -> bool {
+    let vars_have_refs = |vars: &[TypedVar]| vars.iter().any(|var| tydent_has_refs(&var.ty));
+    match decl {
+        TyDecl::Struct(decl) => vars_have_refs(&decl.fields),
+        TyDecl::Union(decl) => vars_have_refs(&decl.fields),
+        TyDecl::Enum(_) => false,
+        TyDecl::Tagged(decl) => decl
+            .variants
+            .iter()
+            .any(|variant| variant.fields.as_deref().is_some_and(vars_have_refs)),
+        TyDecl::Alias(decl) => tydent_has_refs(&decl.alias),
+        TyDecl::Pun(decl) => decl.blocks.iter().any(|block| decl_has_refs(&block.decl)),
+    }
+}
+
+fn tydent_has_refs(ty: &Tydent) -> bool {
+    match ty {
+        Tydent::Ref(_) => true,
+        Tydent::Array(elem_ty, _) => tydent_has_refs(elem_ty),
+        Tydent::Name(_) | Tydent::Empty => false,
     }
-    writeln!(out, r#"    }}"#)?;
-    writeln!(out, r#"}}"#)?;
-    Ok(())
 }
@@ -234,6 +234,64 @@ impl std::fmt::Display for WriteImpl {
     }
 }
 
+/// How float values are compared when checking a test
+#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, Default)]
+pub enum FloatCompare {
+    /// Every bit has to match
+    #[default]
+    Bitwise,
+    /// Any NaN matches any other NaN (the payload, quietness, and sign can change)
+    NanInsensitive,
+    /// Floats only have to be equal as numbers, except that NaNs match
+    /// (so this also allows `-0.0` to become `0.0`)
+    Value,
+}
+impl std::str::FromStr for FloatCompare {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitwise" => Ok(Self::Bitwise),
+            "nan" => Ok(Self::NanInsensitive),
+            "value" => Ok(Self::Value),
+            _ => Err(CliParseError::Other(format!(
+                "{s} is not a float comparison (bitwise, nan, value)"
+            ))),
+        }
+    }
+}
+impl std::fmt::Display for FloatCompare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Bitwise => "bitwise",
+            Self::NanInsensitive => "nan",
+            Self::Value => "value",
+        };
+        s.fmt(f)
+    }
+}
+
+/// A `--float-compare` setting, either for every test or just one (`test=mode`)
+#[derive(Debug, Clone)]
+pub struct FloatCompareRule {
+    pub test: Option<TestId>,
+    pub mode: FloatCompare,
+}
+impl std::str::FromStr for FloatCompareRule {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (test, mode) = match s.split_once('=') {
+            Some((test, mode)) => (Some(test.to_owned()), mode),
+            None => (None, s),
+        };
+        Ok(Self {
+            test,
+            mode: mode.parse()?,
+        })
+    }
+}
+
 impl Test {
     pub fn has_convention(&self, _convention: CallingConvention) -> bool {
         true
@@ -1,9 +1,15 @@
+use std::collections::HashMap;
+
+use kdl_script::eval::Val;
 use kdl_script::types::*;
+use kdl_script::vals::ExplicitValKind;
 use rand::Rng;
 use rand_core::{RngCore, SeedableRng};
 use serde::Serialize;
+use tracing::warn;
 
 use crate::error::*;
+use crate::harness::graffiti::graffiti_byte;
 use crate::harness::test::*;
 
 type RngImpl = rand_pcg::Pcg64;
@@ -61,32 +67,132 @@ pub struct Value {
 
 #[derive(Debug, Clone)]
 pub enum ValueGenerator {
-    Graffiti { idx: u64 },
-    Random { seed: u64 },
+    Graffiti {
+        idx: u64,
+    },
+    Random {
+        seed: u64,
+    },
+    /// A value with exact expected bytes, either computed by running the function's
+    /// body in the kdl-script interpreter or picked from the edge cases of its type
+    /// (little-endian bytes, or a little-endian u32 index for tags)
+    Fixed {
+        bytes: Vec<u8>,
+    },
 }
 
 #[derive(Debug, Clone)]
 enum ValueGeneratorBuilder {
-    Graffiti { idx: u64 },
-    Random { rng: RngImpl },
+    Graffiti {
+        idx: u64,
+    },
+    Random {
+        rng: RngImpl,
+    },
+    /// How far each primitive (and tags) have gotten through their edge cases
+    Edges {
+        start: usize,
+        prims: HashMap<PrimitiveTy, usize>,
+        tags: usize,
+    },
+    /// Graffiti values, but with the variants (and bools) of the current function
+    /// picked by the given combination (see [`func_variant_choices`][])
+    Variants {
+        idx: u64,
+        combo: u64,
+        choices: HashMap<String, usize>,
+    },
+    /// Graffiti values, except for the ones the current function's `vals` block gives
+    Explicit {
+        idx: u64,
+        vals: HashMap<String, ExplicitValKind>,
+    },
 }
 
+/// The most variant combinations `exhaustive` will run for a single test.
+pub const MAX_VARIANT_COMBOS: u64 = 64;
+
+/// The most seeds a single `randomN..=M` range can have.
+pub const MAX_SEED_RANGE: u64 = 100_000;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub enum ValueGeneratorKind {
     Graffiti,
-    Random { seed: u64 },
+    Random {
+        seed: u64,
+    },
+    /// Every seed in `start..=end` (expanded into `Random` for each)
+    RandomRange {
+        start: u64,
+        end: u64,
+    },
+    Edges {
+        start: u64,
+    },
+    /// Run every combination of variants (expanded into `Variants` for each test)
+    Exhaustive,
+    Variants {
+        combo: u64,
+    },
+    /// The values given by each function's `vals` block (graffiti for the rest)
+    Explicit,
 }
 impl std::str::FromStr for ValueGeneratorKind {
     type Err = CliParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(seed) = s.strip_prefix("random") {
-            let seed: u64 = seed.parse().map_err(|_| {
+        if let Some(seeds) = s.strip_prefix("random") {
+            let parse_seed = |seed: &str| -> Result<u64, CliParseError> {
+                seed.parse().map_err(|_| {
+                    CliParseError::Other(format!(
+                        "{seed} isn't a u64 (parsing random value generator)"
+                    ))
+                })
+            };
+            // random*N is shorthand for random1..=N
+            let (start, end) = if let Some(count) = seeds.strip_prefix('*') {
+                (1, parse_seed(count)?)
+            } else if let Some((start, end)) = seeds.split_once("..=") {
+                (parse_seed(start)?, parse_seed(end)?)
+            } else if let Some((start, end)) = seeds.split_once("..") {
+                (parse_seed(start)?, parse_seed(end)?.saturating_sub(1))
+            } else {
+                let seed = parse_seed(seeds)?;
+                return Ok(ValueGeneratorKind::Random { seed });
+            };
+            if start > end {
+                return Err(CliParseError::Other(format!(
+                    "{s} doesn't have any seeds in it"
+                )));
+            }
+            if end - start >= MAX_SEED_RANGE {
+                return Err(CliParseError::Other(format!(
+                    "{s} has too many seeds in it (a range can have at most {MAX_SEED_RANGE})"
+                )));
+            }
+            Ok(ValueGeneratorKind::RandomRange { start, end })
+        } else if let Some(start) = s.strip_prefix("edges") {
+            let start: u64 = if start.is_empty() {
+                0
+            } else {
+                start.parse().map_err(|_| {
+                    CliParseError::Other(format!(
+                        "{start} isn't a u64 (parsing edges value generator)"
+                    ))
+                })?
+            };
+            Ok(ValueGeneratorKind::Edges { start })
+        } else if s == "exhaustive" {
+            Ok(ValueGeneratorKind::Exhaustive)
+        } else if let Some(combo) = s.strip_prefix("variants") {
+            let combo: u64 = combo.parse().map_err(|_| {
                 CliParseError::Other(format!(
-                    "{seed} isn't a u64 (parsing random value generator)"
+                    "{combo} isn't a u64 (parsing variants value generator)"
                 ))
             })?;
-            Ok(ValueGeneratorKind::Random { seed })
+            Ok(ValueGeneratorKind::Variants { combo })
+        } else if s == "explicit" {
+            Ok(ValueGeneratorKind::Explicit)
         } else if s == "graffiti" {
             Ok(ValueGeneratorKind::Graffiti)
         } else {
@@ -100,6 +206,12 @@ impl std::fmt::Display for ValueGeneratorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Random { seed } => write!(f, "random{seed}"),
+            Self::RandomRange { start, end } => write!(f, "random{start}..={end}"),
+            Self::Edges { start: 0 } => write!(f, "edges"),
+            Self::Edges { start } => write!(f, "edges{start}"),
+            Self::Exhaustive => write!(f, "exhaustive"),
+            Self::Variants { combo } => write!(f, "variants{combo}"),
+            Self::Explicit => write!(f, "explicit"),
             Self::Graffiti => write!(f, "graffiti"),
         }
     }
@@ -116,6 +228,7 @@ impl ValueTree {
         let funcs = types
             .all_funcs()
             .map(|func_idx| {
+                generators.start_func(types, func_idx);
                 let func = types.realize_func(func_idx);
                 let func_name = func.name.to_string();
                 let mut absolute_val_idx_start = 0;
@@ -137,7 +250,11 @@ impl ValueTree {
                         Ok(res)
                     })
                     .collect::<Result<Vec<_>, GenerateError>>()?;
-                Ok(FuncValues { func_name, args })
+                let mut func_vals = FuncValues { func_name, args };
+                if !func.body.stmts.is_empty() {
+                    func_vals.eval_outputs(types, func_idx)?;
+                }
+                Ok(func_vals)
             })
             .collect::<Result<Vec<_>, GenerateError>>()?;
 
@@ -148,7 +265,7 @@ impl ValueTree {
     }
 
     #[track_caller]
-    pub fn at_func(&self, func_idx: usize) -> FuncValuesIter {
+    pub fn at_func(&self, func_idx: usize) -> FuncValuesIter<'_> {
         assert!(
             func_idx < self.funcs.len(),
             "internal error: ValueTree func_idx exceeded"
@@ -161,6 +278,188 @@ impl ValueTree {
     }
 }
 
+impl FuncValues {
+    /// Replace the outputs of a function that has a body with what the body computes
+    /// from the inputs, so we can check that every language computes the same thing.
+    fn eval_outputs(
+        &mut self,
+        types: &TypedProgram,
+        func_idx: FuncIdx,
+    ) -> Result<(), GenerateError> {
+        let func = types.realize_func(func_idx);
+        let (inputs, outputs) = self.args.split_at_mut(func.inputs.len());
+        let inputs = inputs
+            .iter()
+            .map(|arg| input_val(types, arg.ty, &mut arg.vals.iter()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let results = types.eval_func(func_idx, inputs)?;
+        for (arg, val) in outputs.iter_mut().zip(&results) {
+            arg.vals.clear();
+            output_values(types, arg.ty, val, &mut arg.vals, arg.arg_name.clone());
+        }
+
+        let mut absolute_val_idx_start = 0;
+        for arg in &mut self.args {
+            arg.absolute_val_idx_start = absolute_val_idx_start;
+            absolute_val_idx_start += arg.vals.len();
+        }
+        Ok(())
+    }
+}
+
+/// Rebuild the value the caller will pass for an input (the way the backends
+/// initialize it), so the interpreter can run the body on it.
+fn input_val<'a>(
+    types: &TypedProgram,
+    ty_idx: TyIdx,
+    vals: &mut impl Iterator<Item = &'a Value>,
+) -> Result<Val, GenerateError> {
+    let val = match types.realize_ty(ty_idx) {
+        Ty::Primitive(prim) => {
+            let val = next_val(vals);
+            match prim {
+                PrimitiveTy::I8 | PrimitiveTy::U8 => Val::Int(*prim, val.generate_u8().into()),
+                PrimitiveTy::I16 | PrimitiveTy::U16 => Val::Int(*prim, val.generate_u16().into()),
+                PrimitiveTy::I32 | PrimitiveTy::U32 => Val::Int(*prim, val.generate_u32().into()),
+                PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::Ptr => {
+                    Val::Int(*prim, val.generate_u64().into())
+                }
+                PrimitiveTy::I128 | PrimitiveTy::U128 => Val::Int(*prim, val.generate_u128()),
+                PrimitiveTy::F32 => Val::Float(*prim, f32::from_bits(val.generate_u32()).into()),
+                PrimitiveTy::F64 => Val::Float(*prim, f64::from_bits(val.generate_u64())),
+                PrimitiveTy::Bool => Val::Bool(val.generate_bool()),
+                PrimitiveTy::I256 | PrimitiveTy::U256 | PrimitiveTy::F16 | PrimitiveTy::F128 => {
+                    Err(UnsupportedError::Other(format!(
+                        "function bodies don't support {}",
+                        types.format_ty(ty_idx)
+                    )))?
+                }
+            }
+        }
+        Ty::Enum(ty) => Val::Enum(ty_idx, next_val(vals).generate_idx(ty.variants.len())),
+        Ty::Empty => Val::Empty,
+        Ty::Alias(ty) => input_val(types, ty.real, vals)?,
+        Ty::Ref(ty) => Val::Ref(Box::new(input_val(types, ty.pointee_ty, vals)?)),
+        Ty::Struct(ty) => Val::Struct(
+            ty_idx,
+            ty.fields
+                .iter()
+                .map(|field| input_val(types, field.ty, vals))
+                .collect::<Result<_, _>>()?,
+        ),
+        Ty::Array(ty) => Val::Array(
+            (0..ty.len)
+                .map(|_| input_val(types, ty.elem_ty, vals))
+                .collect::<Result<_, _>>()?,
+        ),
+        Ty::Union(ty) => {
+            let idx = next_val(vals).generate_idx(ty.fields.len());
+            let Some(field) = ty.fields.get(idx) else {
+                Err(UnsupportedError::Other(format!(
+                    "{} has no fields to pass",
+                    types.format_ty(ty_idx)
+                )))?
+            };
+            Val::Union(ty_idx, idx, Box::new(input_val(types, field.ty, vals)?))
+        }
+        Ty::Tagged(ty) => {
+            let idx = next_val(vals).generate_idx(ty.variants.len());
+            let fields = ty
+                .variants
+                .get(idx)
+                .and_then(|variant| variant.fields.as_deref())
+                .unwrap_or_default();
+            Val::Tagged(
+                ty_idx,
+                idx,
+                fields
+                    .iter()
+                    .map(|field| input_val(types, field.ty, vals))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Ty::Pun(_) => unreachable!("functions with bodies can't use puns"),
+    };
+    Ok(val)
+}
+
+fn next_val<'a>(vals: &mut impl Iterator<Item = &'a Value>) -> &'a Value {
+    vals.next()
+        .expect("internal error: ran out of values for an input")
+}
+
+/// Turn a value computed by the interpreter into the values the harness
+/// expects to see written (the same shape as [`ValueGeneratorBuilder::build_values`][]).
+fn output_values(
+    types: &TypedProgram,
+    ty_idx: TyIdx,
+    val: &Val,
+    vals: &mut Vec<Value>,
+    path: String,
+) {
+    let fixed = |bytes: &[u8], vals: &mut Vec<Value>, path: String| {
+        vals.push(Value {
+            val: ValueGenerator::Fixed {
+                bytes: bytes.to_vec(),
+            },
+            ty: ty_idx,
+            path,
+        })
+    };
+    let tag = |idx: usize| (idx as u32).to_le_bytes();
+    match (types.realize_ty(ty_idx), val) {
+        (Ty::Alias(ty), _) => output_values(types, ty.real, val, vals, path),
+        (Ty::Ref(ty), Val::Ref(pointee)) => {
+            output_values(types, ty.pointee_ty, pointee, vals, format!("{path}.*"))
+        }
+        (Ty::Primitive(prim), Val::Int(_, bits)) => {
+            let size = match prim {
+                PrimitiveTy::I8 | PrimitiveTy::U8 => 1,
+                PrimitiveTy::I16 | PrimitiveTy::U16 => 2,
+                PrimitiveTy::I32 | PrimitiveTy::U32 => 4,
+                PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::Ptr => 8,
+                _ => 16,
+            };
+            fixed(&bits.to_le_bytes()[..size], vals, path)
+        }
+        (Ty::Primitive(PrimitiveTy::F32), Val::Float(_, val)) => {
+            fixed(&(*val as f32).to_bits().to_le_bytes(), vals, path)
+        }
+        (Ty::Primitive(_), Val::Float(_, val)) => fixed(&val.to_bits().to_le_bytes(), vals, path),
+        (Ty::Primitive(_), Val::Bool(val)) => fixed(&[u8::from(*val)], vals, path),
+        (Ty::Enum(_), Val::Enum(_, idx)) => fixed(&tag(*idx), vals, path),
+        (Ty::Empty, _) => {}
+        (Ty::Struct(ty), Val::Struct(_, fields)) => {
+            for (field, val) in ty.fields.iter().zip(fields) {
+                let new_path = format!("{path}.{}", field.ident);
+                output_values(types, field.ty, val, vals, new_path);
+            }
+        }
+        (Ty::Array(ty), Val::Array(elems)) => {
+            for (idx, val) in elems.iter().enumerate() {
+                let new_path = format!("{path}[{idx}]");
+                output_values(types, ty.elem_ty, val, vals, new_path);
+            }
+        }
+        (Ty::Union(ty), Val::Union(_, idx, val)) => {
+            fixed(&tag(*idx), vals, path.clone());
+            let field = &ty.fields[*idx];
+            let new_path = format!("{path}.{}", field.ident);
+            output_values(types, field.ty, val, vals, new_path);
+        }
+        (Ty::Tagged(ty), Val::Tagged(_, idx, fields)) => {
+            fixed(&tag(*idx), vals, path.clone());
+            let variant = &ty.variants[*idx];
+            let variant_fields = variant.fields.as_deref().unwrap_or_default();
+            for (field, val) in variant_fields.iter().zip(fields) {
+                let new_path = format!("{path}.{}.{}", variant.name, field.ident);
+                output_values(types, field.ty, val, vals, new_path);
+            }
+        }
+        _ => unreachable!("internal error: the interpreter returned a value of the wrong type"),
+    }
+}
+
 impl<'a> FuncValuesIter<'a> {
     #[track_caller]
     pub fn next_arg(&mut self) -> ArgValuesIter<'a> {
@@ -274,15 +573,228 @@ impl ValueGeneratorKind {
             // This isn't to "increase randomness" or anything, but instead to create N
             // random streams of bytes that can be repeatably and independently queried,
             // while still having them all deterministically derived from the root seed.
-            ValueGeneratorKind::Random { seed } => ValueGeneratorBuilder::Random {
-                rng: RngImpl::seed_from_u64(*seed),
+            ValueGeneratorKind::Random { seed }
+            | ValueGeneratorKind::RandomRange { start: seed, .. } => {
+                ValueGeneratorBuilder::Random {
+                    rng: RngImpl::seed_from_u64(*seed),
+                }
+            }
+            // Every primitive cycles through its own edge cases, so `edgesN` just
+            // starts all of those cycles N steps in.
+            ValueGeneratorKind::Edges { start } => ValueGeneratorBuilder::Edges {
+                start: *start as usize,
+                prims: HashMap::new(),
+                tags: *start as usize,
             },
+            ValueGeneratorKind::Variants { combo } => ValueGeneratorBuilder::Variants {
+                idx: 0,
+                combo: *combo,
+                choices: HashMap::new(),
+            },
+            // Should have been expanded, but the first combination is a fine fallback
+            ValueGeneratorKind::Exhaustive => ValueGeneratorBuilder::Variants {
+                idx: 0,
+                combo: 0,
+                choices: HashMap::new(),
+            },
+            ValueGeneratorKind::Explicit => ValueGeneratorBuilder::Explicit {
+                idx: 0,
+                vals: HashMap::new(),
+            },
+        }
+    }
+
+    /// Expand this into the generators that should actually be run for a test.
+    ///
+    /// A seed range becomes each of its seeds, and `exhaustive` becomes one `variantsN`
+    /// for each combination of variants the test's biggest function has (up to
+    /// [`MAX_VARIANT_COMBOS`][], with a warning if there are more; functions with fewer
+    /// just wrap around).
+    pub fn expand(&self, test: &Test) -> Vec<Self> {
+        match self {
+            ValueGeneratorKind::RandomRange { start, end } => (*start..=*end)
+                .map(|seed| ValueGeneratorKind::Random { seed })
+                .collect(),
+            ValueGeneratorKind::Exhaustive => {
+                let types = &test.types;
+                let combos = types
+                    .all_funcs()
+                    .map(|func_idx| func_variant_combos(types, func_idx))
+                    .max()
+                    .unwrap_or(1);
+                if combos > MAX_VARIANT_COMBOS {
+                    warn!(
+                        "{} has {combos} combinations of variants, only running the first {MAX_VARIANT_COMBOS}",
+                        test.name
+                    );
+                }
+                (0..combos.min(MAX_VARIANT_COMBOS))
+                    .map(|combo| ValueGeneratorKind::Variants { combo })
+                    .collect()
+            }
+            _ => vec![*self],
+        }
+    }
+
+    /// Whether this expands into several runs that should be reported together.
+    pub fn is_sweep(&self) -> bool {
+        matches!(
+            self,
+            ValueGeneratorKind::RandomRange { .. } | ValueGeneratorKind::Exhaustive
+        )
+    }
+}
+
+/// How many different combinations of variants (and bools) the args of a function have.
+pub fn func_variant_combos(types: &TypedProgram, func_idx: FuncIdx) -> u64 {
+    let func = types.realize_func(func_idx);
+    func.inputs.iter().chain(&func.outputs).fold(1, |acc, arg| {
+        acc.saturating_mul(variant_combos(types, arg.ty))
+    })
+}
+
+/// How many different combinations of variants (and bools) a value of this type has.
+///
+/// Every tagged union contributes each of its variants' payloads separately, so
+/// iterating through all of these exercises every layout the type can have.
+/// Puns aren't expanded, as their blocks can disagree about what's inside them.
+fn variant_combos(types: &TypedProgram, ty_idx: TyIdx) -> u64 {
+    let product = |tys: &mut dyn Iterator<Item = TyIdx>| {
+        tys.fold(1u64, |acc, ty| {
+            acc.saturating_mul(variant_combos(types, ty))
+        })
+    };
+    match types.realize_ty(ty_idx) {
+        Ty::Primitive(PrimitiveTy::Bool) => 2,
+        Ty::Primitive(_) | Ty::Empty | Ty::Pun(_) => 1,
+        Ty::Enum(ty) => ty.variants.len().max(1) as u64,
+        Ty::Alias(ty) => variant_combos(types, ty.real),
+        Ty::Ref(ty) => variant_combos(types, ty.pointee_ty),
+        Ty::Struct(ty) => product(&mut ty.fields.iter().map(|field| field.ty)),
+        Ty::Array(ty) => variant_combos(types, ty.elem_ty).saturating_pow(ty.len as u32),
+        Ty::Union(ty) => ty
+            .fields
+            .iter()
+            .fold(0u64, |acc, field| {
+                acc.saturating_add(variant_combos(types, field.ty))
+            })
+            .max(1),
+        Ty::Tagged(ty) => ty
+            .variants
+            .iter()
+            .fold(0u64, |acc, variant| {
+                let fields = variant.fields.as_deref().unwrap_or_default();
+                acc.saturating_add(product(&mut fields.iter().map(|field| field.ty)))
+            })
+            .max(1),
+    }
+}
+
+/// Pick the variant (or bool) for every value of a function, keyed by the value's path.
+///
+/// `combo` is decoded like a mixed-radix number, with each arg (and struct field)
+/// getting its own digit, and each variant of a union getting its own range.
+fn func_variant_choices(
+    types: &TypedProgram,
+    func_idx: FuncIdx,
+    combo: u64,
+) -> HashMap<String, usize> {
+    let func = types.realize_func(func_idx);
+    let mut combo = combo % func_variant_combos(types, func_idx);
+    let mut choices = HashMap::new();
+    for arg in func.inputs.iter().chain(&func.outputs) {
+        let count = variant_combos(types, arg.ty);
+        let path = arg.name.to_string();
+        choose_variants(types, arg.ty, combo % count, path, &mut choices);
+        combo /= count;
+    }
+    choices
+}
+
+fn choose_variants(
+    types: &TypedProgram,
+    ty_idx: TyIdx,
+    mut combo: u64,
+    path: String,
+    choices: &mut HashMap<String, usize>,
+) {
+    match types.realize_ty(ty_idx) {
+        Ty::Primitive(PrimitiveTy::Bool) | Ty::Enum(_) => {
+            choices.insert(path, combo as usize);
+        }
+        Ty::Primitive(_) | Ty::Empty | Ty::Pun(_) => {}
+        Ty::Alias(ty) => choose_variants(types, ty.real, combo, path, choices),
+        Ty::Ref(ty) => choose_variants(types, ty.pointee_ty, combo, format!("{path}.*"), choices),
+        Ty::Struct(ty) => {
+            for field in &ty.fields {
+                let count = variant_combos(types, field.ty);
+                let new_path = format!("{path}.{}", field.ident);
+                choose_variants(types, field.ty, combo % count, new_path, choices);
+                combo /= count;
+            }
+        }
+        Ty::Array(ty) => {
+            let count = variant_combos(types, ty.elem_ty);
+            for idx in 0..ty.len {
+                let new_path = format!("{path}[{idx}]");
+                choose_variants(types, ty.elem_ty, combo % count, new_path, choices);
+                combo /= count;
+            }
+        }
+        Ty::Union(ty) => {
+            for (idx, field) in ty.fields.iter().enumerate() {
+                let count = variant_combos(types, field.ty);
+                if combo < count {
+                    let new_path = format!("{path}.{}", field.ident);
+                    choose_variants(types, field.ty, combo, new_path, choices);
+                    choices.insert(path, idx);
+                    return;
+                }
+                combo -= count;
+            }
+        }
+        Ty::Tagged(ty) => {
+            for (idx, variant) in ty.variants.iter().enumerate() {
+                let fields = variant.fields.as_deref().unwrap_or_default();
+                let count = fields.iter().fold(1u64, |acc, field| {
+                    acc.saturating_mul(variant_combos(types, field.ty))
+                });
+                if combo < count {
+                    for field in fields {
+                        let count = variant_combos(types, field.ty);
+                        let new_path = format!("{path}.{}.{}", variant.name, field.ident);
+                        choose_variants(types, field.ty, combo % count, new_path, choices);
+                        combo /= count;
+                    }
+                    choices.insert(path, idx);
+                    return;
+                }
+                combo -= count;
+            }
         }
     }
 }
 
 impl ValueGeneratorBuilder {
-    fn next(&mut self, ty: TyIdx, path: String) -> Value {
+    /// Get ready to make the values for a new function.
+    fn start_func(&mut self, types: &TypedProgram, func_idx: FuncIdx) {
+        match self {
+            ValueGeneratorBuilder::Variants { combo, choices, .. } => {
+                *choices = func_variant_choices(types, func_idx, *combo);
+            }
+            ValueGeneratorBuilder::Explicit { vals, .. } => {
+                *vals = types
+                    .realize_func(func_idx)
+                    .vals
+                    .iter()
+                    .map(|val| (val.path.clone(), val.kind.clone()))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    fn next(&mut self, types: &TypedProgram, ty: TyIdx, path: String) -> Value {
         let val = match self {
             ValueGeneratorBuilder::Graffiti { idx } => {
                 let res = ValueGenerator::Graffiti { idx: *idx };
@@ -292,6 +804,67 @@ impl ValueGeneratorBuilder {
             ValueGeneratorBuilder::Random { rng } => ValueGenerator::Random {
                 seed: rng.next_u64(),
             },
+            ValueGeneratorBuilder::Edges { start, prims, tags } => {
+                let bytes = match types.realize_ty(ty) {
+                    Ty::Primitive(prim) => {
+                        let edges = prim_edges(*prim);
+                        let idx = prims.entry(*prim).or_insert(*start);
+                        let bytes = edges[*idx % edges.len()].clone();
+                        *idx += 1;
+                        bytes
+                    }
+                    // Tags just cycle through the variants
+                    ty => {
+                        let len = match ty {
+                            Ty::Enum(ty) => ty.variants.len(),
+                            Ty::Union(ty) => ty.fields.len(),
+                            Ty::Tagged(ty) => ty.variants.len(),
+                            _ => unreachable!("only leaves and tags should get values"),
+                        };
+                        let idx = *tags % len.max(1);
+                        *tags += 1;
+                        (idx as u32).to_le_bytes().to_vec()
+                    }
+                };
+                ValueGenerator::Fixed { bytes }
+            }
+            ValueGeneratorBuilder::Variants {
+                idx,
+                combo: _,
+                choices,
+            } => match types.realize_ty(ty) {
+                Ty::Primitive(PrimitiveTy::Bool) => {
+                    let choice = choices.get(&path).copied().unwrap_or(0);
+                    ValueGenerator::Fixed {
+                        bytes: vec![choice as u8],
+                    }
+                }
+                Ty::Enum(_) | Ty::Union(_) | Ty::Tagged(_) => {
+                    let choice = choices.get(&path).copied().unwrap_or(0);
+                    ValueGenerator::Fixed {
+                        bytes: (choice as u32).to_le_bytes().to_vec(),
+                    }
+                }
+                _ => {
+                    let res = ValueGenerator::Graffiti { idx: *idx };
+                    *idx += 1;
+                    res
+                }
+            },
+            // Unspecified values get the same graffiti they would've had anyway
+            ValueGeneratorBuilder::Explicit { idx, vals } => {
+                let res = match vals.get(&path) {
+                    Some(ExplicitValKind::Bytes(bytes)) => ValueGenerator::Fixed {
+                        bytes: bytes.clone(),
+                    },
+                    Some(ExplicitValKind::Variant(variant)) => ValueGenerator::Fixed {
+                        bytes: (*variant as u32).to_le_bytes().to_vec(),
+                    },
+                    None => ValueGenerator::Graffiti { idx: *idx },
+                };
+                *idx += 1;
+                res
+            }
         };
         Value { val, ty, path }
     }
@@ -306,8 +879,8 @@ impl ValueGeneratorBuilder {
         let ty = types.realize_ty(ty_idx);
         match ty {
             // Primitives and enums just have the one value
-            Ty::Primitive(_) => vals.push(self.next(ty_idx, path)),
-            Ty::Enum(_) => vals.push(self.next(ty_idx, path)),
+            Ty::Primitive(_) => vals.push(self.next(types, ty_idx, path)),
+            Ty::Enum(_) => vals.push(self.next(types, ty_idx, path)),
 
             // Empty has no values
             Ty::Empty => {}
@@ -337,7 +910,7 @@ impl ValueGeneratorBuilder {
             // Union and Tagged need an implicit "tag" field for selecting the active variant
             Ty::Union(ty) => {
                 // generate the tag value
-                let tag_generator = self.next(ty_idx, path.clone());
+                let tag_generator = self.next(types, ty_idx, path.clone());
                 let active_variant_idx = tag_generator.generate_idx(ty.fields.len());
                 vals.push(tag_generator);
 
@@ -350,7 +923,7 @@ impl ValueGeneratorBuilder {
             }
             Ty::Tagged(ty) => {
                 // generate the tag value
-                let tag_generator = self.next(ty_idx, path.clone());
+                let tag_generator = self.next(types, ty_idx, path.clone());
                 let active_variant_idx = tag_generator.generate_idx(ty.variants.len());
                 vals.push(tag_generator);
 
@@ -413,6 +986,71 @@ impl ValueGeneratorBuilder {
     }
 }
 
+/// The interesting boundary values of a primitive (as little-endian bytes).
+fn prim_edges(prim: PrimitiveTy) -> Vec<Vec<u8>> {
+    match prim {
+        PrimitiveTy::I8 | PrimitiveTy::U8 => int_edges(1),
+        PrimitiveTy::I16 | PrimitiveTy::U16 => int_edges(2),
+        PrimitiveTy::I32 | PrimitiveTy::U32 => int_edges(4),
+        PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::Ptr => int_edges(8),
+        PrimitiveTy::I128 | PrimitiveTy::U128 => int_edges(16),
+        PrimitiveTy::I256 | PrimitiveTy::U256 => int_edges(32),
+        PrimitiveTy::F16 => float_edges(5, 10),
+        PrimitiveTy::F32 => float_edges(8, 23),
+        PrimitiveTy::F64 => float_edges(11, 52),
+        PrimitiveTy::F128 => float_edges(15, 112),
+        PrimitiveTy::Bool => vec![vec![0], vec![1]],
+    }
+}
+
+/// 0, -1 (all ones), 1, and the signed max/min (which are also the
+/// unsigned halfway points), for an integer of the given size.
+fn int_edges(size: usize) -> Vec<Vec<u8>> {
+    let zeros = vec![0; size];
+    let ones = vec![0xFF; size];
+    let mut one = zeros.clone();
+    one[0] = 1;
+    let mut max = ones.clone();
+    max[size - 1] = 0x7F;
+    let mut min = zeros.clone();
+    min[size - 1] = 0x80;
+    vec![zeros, ones, max, min, one]
+}
+
+/// Zeros, ones, subnormals, extremes, infinities, and NaNs (quiet, signalling,
+/// negative, and with payloads), for an IEEE float with the given layout.
+fn float_edges(exp_bits: u32, mant_bits: u32) -> Vec<Vec<u8>> {
+    let size = (1 + exp_bits + mant_bits) as usize / 8;
+    let sign = 1u128 << (exp_bits + mant_bits);
+    let exp = ((1u128 << exp_bits) - 1) << mant_bits;
+    let mant = (1u128 << mant_bits) - 1;
+    let quiet = 1u128 << (mant_bits - 1);
+    let one = ((1u128 << (exp_bits - 1)) - 1) << mant_bits;
+    let max = exp - 1;
+    [
+        0,
+        sign,
+        one,
+        sign | one,
+        // smallest and largest subnormals, then the smallest normal
+        1,
+        mant,
+        mant + 1,
+        max,
+        sign | max,
+        exp,
+        sign | exp,
+        exp | quiet,
+        exp | quiet | 1,
+        exp | 1,
+        sign | exp | quiet,
+        sign | exp | mant,
+    ]
+    .iter()
+    .map(|bits| bits.to_le_bytes()[..size].to_vec())
+    .collect()
+}
+
 impl ValueGenerator {
     pub fn fill_bytes(&self, output: &mut [u8]) {
         match self {
@@ -421,7 +1059,7 @@ impl ValueGenerator {
                 // high nibble is the field index (wrapping)
                 // low nibble is the byte index (wrapping)
                 for (byte_idx, byte) in output.iter_mut().enumerate() {
-                    *byte = ((*idx as u8) << 4) | ((byte_idx as u8) & 0b1111);
+                    *byte = graffiti_byte(*idx, byte_idx);
                 }
             }
             ValueGenerator::Random { seed } => {
@@ -429,6 +1067,19 @@ impl ValueGenerator {
                 let mut rng = RngImpl::seed_from_u64(*seed);
                 rng.fill_bytes(output);
             }
+            ValueGenerator::Fixed { bytes } => {
+                let len = bytes.len().min(output.len());
+                output[..len].copy_from_slice(&bytes[..len]);
+                output[len..].fill(0);
+            }
+        }
+    }
+
+    /// The exact bytes this value should have, if it was computed rather than generated.
+    pub fn fixed_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ValueGenerator::Fixed { bytes } => Some(bytes),
+            _ => None,
         }
     }
 
@@ -451,9 +1102,18 @@ impl ValueGenerator {
                 RngImpl::seed_from_u64(*idx)
             }
             ValueGenerator::Random { seed } => RngImpl::seed_from_u64(*seed),
+            ValueGenerator::Fixed { bytes } => {
+                let mut buf = [0; 8];
+                let n = bytes.len().min(8);
+                buf[..n].copy_from_slice(&bytes[..n]);
+                return (u64::from_le_bytes(buf) as usize).min(len - 1);
+            }
         };
         rng.gen_range(0..len)
     }
+    pub fn generate_bool(&self) -> bool {
+        self.generate_idx(2) == 1
+    }
     pub fn generate_u8(&self) -> u8 {
         let mut buf = [0; 1];
         self.fill_bytes(&mut buf);
@@ -1,4 +1,5 @@
 mod cli;
+mod corpus;
 mod error;
 mod files;
 mod fivemat;
@@ -21,7 +22,7 @@ use std::error::Error;
 use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::OnceCell;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 pub type SortedMap<K, V> = std::collections::BTreeMap<K, V>;
 
@@ -73,9 +74,14 @@ pub struct Config {
     pub run_values: Vec<ValueGeneratorKind>,
     pub run_writers: Vec<WriteImpl>,
     pub run_selections: Vec<FunctionSelector>,
+    pub float_compare: Vec<FloatCompareRule>,
     pub minimizing_write_impl: WriteImpl,
     pub rustc_codegen_backends: Vec<(String, String)>,
     pub disable_builtin_tests: bool,
+    pub deny_warnings: bool,
+    pub dump_procgen: bool,
+    pub use_corpus: bool,
+    pub prune_corpus: bool,
     pub paths: Paths,
 }
 
@@ -93,9 +99,12 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Grab all the tests
     let test_sources = harness::find_tests(&cfg)?;
-    let read_tasks = test_sources
-        .into_iter()
-        .map(|(test, test_file)| harness::spawn_read_test(&rt, test, test_file));
+    let procgen_dump_dir = cfg
+        .dump_procgen
+        .then(|| cfg.paths.generated_src_dir.join("procgen"));
+    let read_tasks = test_sources.into_iter().map(|(test, test_file)| {
+        harness::spawn_read_test(&rt, test, test_file, procgen_dump_dir.clone())
+    });
 
     // We could async pipeline this harder but it's nice to know all the tests upfront
     // Also we want it to be a hard error for any test to fail to load, as this indicates
@@ -106,6 +115,15 @@ fn main() -> Result<(), Box<dyn Error>> {
         let res = rt.block_on(task).expect("failed to join on task");
         match res {
             Ok(test) => {
+                for warning in test.types.warnings() {
+                    let warning = miette::Report::new(warning.clone());
+                    if cfg.deny_warnings {
+                        test_read_fails = true;
+                        error!("{:?}", warning);
+                    } else {
+                        warn!("{:?}", warning);
+                    }
+                }
                 tests.insert(test.name.clone(), test);
             }
             Err(e) => {
@@ -122,6 +140,20 @@ fn main() -> Result<(), Box<dyn Error>> {
     let harness = Arc::new(TestHarness::new(tests, &cfg));
     debug!("initialized test harness!");
 
+    // Seeds that failed in previous runs get replayed alongside everything else
+    let mut corpus = if cfg.use_corpus {
+        Some(corpus::Corpus::load(&cfg.paths)?)
+    } else {
+        None
+    };
+    if let Some(corpus) = corpus.as_ref().filter(|corpus| corpus.len() > 0) {
+        info!(
+            "replaying failing seeds from {} ({} recorded)",
+            corpus.path(),
+            corpus.len()
+        );
+    }
+
     // Run the tests
     use TestConclusion::*;
 
@@ -132,6 +164,23 @@ fn main() -> Result<(), Box<dyn Error>> {
         if !cfg.run_tests.is_empty() && !cfg.run_tests.contains(&test.name) {
             continue;
         }
+        // `exhaustive` turns into a different number of runs for each test,
+        // and we remember which sweep each run came from to report them together
+        // (pruning the corpus only runs the corpus)
+        let requested_values = if cfg.prune_corpus {
+            &[][..]
+        } else {
+            &cfg.run_values[..]
+        };
+        let run_values = requested_values
+            .iter()
+            .flat_map(|vals| {
+                let sweep = vals.is_sweep().then_some(*vals);
+                vals.expand(&test)
+                    .into_iter()
+                    .map(move |vals| (vals, sweep))
+            })
+            .collect::<Vec<_>>();
         for &convention in &cfg.run_conventions {
             if !test.has_convention(convention) {
                 continue;
@@ -144,7 +193,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                     continue;
                 }
                 for &repr in &cfg.run_reprs {
-                    for &val_generator in &cfg.run_values {
+                    let replays = corpus
+                        .iter()
+                        .flat_map(|corpus| {
+                            corpus.seeds(&test.name, caller_id, callee_id, convention, repr)
+                        })
+                        .map(|seed| (ValueGeneratorKind::Random { seed }, None))
+                        .filter(|replay| !run_values.iter().any(|(vals, _)| *vals == replay.0))
+                        .collect::<Vec<_>>();
+                    for &(val_generator, sweep) in run_values.iter().chain(&replays) {
                         for &val_writer in &cfg.run_writers {
                             for functions in &cfg.run_selections {
                                 // Run the test!
@@ -167,7 +224,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                                     test_key.clone(),
                                 );
 
-                                tasks.push(task);
+                                tasks.push((task, sweep));
                             }
                         }
                     }
@@ -178,12 +235,20 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Join on all the tasks, and compute their results
     let reports = tasks
         .into_iter()
-        .map(|task| {
+        .map(|(task, sweep)| {
             let results = rt.block_on(task).expect("failed to join task");
-            report_test(results)
+            let mut report = report_test(results);
+            report.sweep = sweep;
+            report
         })
         .collect::<Vec<_>>();
 
+    if let Some(corpus) = &mut corpus {
+        if corpus.update(&reports, cfg.prune_corpus) {
+            corpus.save()?;
+        }
+    }
+
     // Compute the final report
     let mut num_tests = 0;
     let mut num_passed = 0;
@@ -242,27 +307,16 @@ fn generate_minimized_failures(
             .subtest_checks
             .iter()
             .filter_map(|func_result| {
-                let Err(failure) = func_result else {
+                // The first failure is the one we want to minimize
+                let Err(failures) = func_result else {
                     return None;
                 };
-                let functions = match *failure {
-                    CheckFailure::ValMismatch {
-                        func_idx,
-                        arg_idx,
-                        val_idx,
-                        ..
-                    }
-                    | CheckFailure::TagMismatch {
-                        func_idx,
-                        arg_idx,
-                        val_idx,
-                        ..
-                    } => FunctionSelector::One {
-                        idx: func_idx,
-                        args: ArgSelector::One {
-                            idx: arg_idx,
-                            vals: ValSelector::One { idx: val_idx },
-                        },
+                let (func_idx, arg_idx, val_idx) = failures.first()?.val_idx();
+                let functions = FunctionSelector::One {
+                    idx: func_idx,
+                    args: ArgSelector::One {
+                        idx: arg_idx,
+                        vals: ValSelector::One { idx: val_idx },
                     },
                 };
 
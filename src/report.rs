@@ -18,6 +18,7 @@ pub fn get_test_rules(test: &TestKey, caller: &dyn Toolchain, callee: &dyn Toolc
     let mut result = TestRules {
         run: Check,
         check: Pass(Check),
+        float_compare: FloatCompare::Bitwise,
     };
 
     // Now apply specific custom expectations for platforms/suites
@@ -164,6 +165,7 @@ pub fn report_test(results: TestRunResults) -> TestReport {
         rules: results.rules.clone(),
         conclusion,
         results,
+        sweep: None,
     }
 }
 
@@ -180,6 +182,8 @@ pub struct TestReport {
     pub rules: TestRules,
     pub results: TestRunResults,
     pub conclusion: TestConclusion,
+    /// The value generator this run was expanded from (like `random1..=500`), if any
+    pub sweep: Option<ValueGeneratorKind>,
 }
 
 #[derive(Debug, Serialize)]
@@ -213,6 +217,8 @@ impl TestKey {
 pub struct TestRules {
     pub run: TestRunMode,
     pub check: TestCheckMode,
+    /// How float values are compared (see also `--float-compare`)
+    pub float_compare: FloatCompare,
 }
 
 /// How far the test should be executed
@@ -220,7 +226,6 @@ pub struct TestRules {
 /// Each case implies all the previous cases.
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize)]
 #[allow(dead_code)]
-
 pub enum TestRunMode {
     /// Don't run the test at all (marked as skipped)
     Skip,
@@ -303,7 +308,10 @@ pub struct LinkOutput {
 pub struct CheckOutput {
     pub all_passed: bool,
     pub subtest_names: Vec<String>,
-    pub subtest_checks: Vec<Result<(), CheckFailure>>,
+    /// Every value that differed in each subtest, in the order they were checked
+    pub subtest_checks: Vec<Result<(), Vec<CheckFailure>>>,
+    /// The layout of every aggregate argument that had a mismatch in each subtest
+    pub subtest_diffs: Vec<Vec<ArgDiff>>,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -320,123 +328,38 @@ impl FullReport {
         harness: &TestHarness,
         mut f: impl std::io::Write,
     ) -> Result<(), std::io::Error> {
-        use TestCheckMode::*;
-        use TestConclusion::*;
         writeln!(f, "Final Results:")?;
 
         let red = Style::new().red();
         let green = Style::new().green();
         let blue = Style::new().blue();
-        let mut sorted_tests = self.tests.iter().collect::<Vec<_>>();
-        sorted_tests.sort_by_key(|t| t.conclusion);
-        for test in sorted_tests {
-            if let Skipped = test.conclusion {
-                continue;
-            }
-            let pretty_test_name = harness.full_test_name(&test.key);
-            write!(f, "{pretty_test_name:<64} ")?;
-            match (&test.conclusion, &test.rules.check) {
-                (Skipped, _) => {
-                    // Don't mention these, too many
-                    // write!(f, "skipped")?
-                }
-
-                (Passed, Pass(_)) => write!(f, "passed")?,
-                (Passed, Random) => write!(f, "passed (random, result ignored)")?,
-                (Passed, Fail(_)) => write!(f, "passed (failed as expected)")?,
-
-                (Failed, Pass(_)) => {
-                    write!(f, "{}", red.apply_to("failed"))?;
-                    if test.results.ran_to < TestRunMode::Check {
-                        let (msg, err) = match &test.results.ran_to {
-                            TestRunMode::Generate => (
-                                "generate source code",
-                                format!(
-                                    "{}",
-                                    test.results
-                                        .source
-                                        .as_ref()
-                                        .unwrap()
-                                        .as_ref()
-                                        .err()
-                                        .unwrap()
-                                ),
-                            ),
-                            TestRunMode::Build => (
-                                "compile source code",
-                                format!(
-                                    "{}",
-                                    test.results.build.as_ref().unwrap().as_ref().err().unwrap()
-                                ),
-                            ),
-                            TestRunMode::Link => (
-                                "link both sides together",
-                                format!(
-                                    "{}",
-                                    test.results.link.as_ref().unwrap().as_ref().err().unwrap()
-                                ),
-                            ),
-                            TestRunMode::Run => (
-                                "run the program",
-                                format!(
-                                    "{}",
-                                    test.results.run.as_ref().unwrap().as_ref().err().unwrap()
-                                ),
-                            ),
-                            TestRunMode::Skip | TestRunMode::Check => ("", String::new()),
-                        };
-                        write!(f, "{}", red.apply_to(" to "))?;
-                        writeln!(f, "{}", red.apply_to(msg))?;
-                        writeln!(f, "  {}", red.apply_to(err))?;
-                    }
-                }
-                (Failed, Random) => {
-                    write!(f, "{}", red.apply_to("failed!? (failed but random!?)"))?
-                }
-                (Failed, Fail(_)) => {
-                    write!(f, "{}", red.apply_to("failed (passed unexpectedly!)"))?
-                }
-                (Failed, TestCheckMode::Busted(_)) => write!(
-                    f,
-                    "{}",
-                    green.apply_to("fixed (test was busted, congrats!)")
-                )?,
-
-                (TestConclusion::Busted, _) | (Passed, TestCheckMode::Busted(_)) => {
-                    write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?
-                }
-            }
-
-            let be_detailed = test.results.ran_to >= TestRunMode::Check;
-            if !be_detailed {
-                writeln!(f)?;
-                continue;
-            }
-            let check_result = test.results.check.as_ref().unwrap();
-            let sub_results = &check_result.subtest_checks;
-            let num_passed = sub_results.iter().filter(|r| r.is_ok()).count();
-
-            writeln!(f, " ({num_passed:>3}/{:<3} passed)", sub_results.len())?;
-            // If all the subtests pass, don't bother with a breakdown.
-            if check_result.all_passed {
+        // Runs expanded from the same sweep (like random1..=500) are reported together
+        let mut entries = vec![];
+        let mut sweeps = SortedMap::<String, usize>::new();
+        for test in &self.tests {
+            let Some(sweep) = test.sweep else {
+                entries.push(HumanEntry::Test(test));
                 continue;
+            };
+            let mut key = test.key.clone();
+            key.options.val_generator = sweep;
+            let name = harness.full_test_name(&key);
+            let idx = *sweeps.entry(name.clone()).or_insert_with(|| {
+                entries.push(HumanEntry::Sweep { name, runs: vec![] });
+                entries.len() - 1
+            });
+            if let HumanEntry::Sweep { runs, .. } = &mut entries[idx] {
+                runs.push(test);
             }
-
-            let max_name_len = check_result
-                .subtest_names
-                .iter()
-                .fold(0, |max, name| max.max(name.len()));
-            for (subtest_name, result) in check_result.subtest_names.iter().zip(sub_results.iter())
-            {
-                write!(f, "  {:width$} ", subtest_name, width = max_name_len)?;
-                if let Err(e) = result {
-                    writeln!(f, "{}", red.apply_to("failed!"))?;
-                    writeln!(f, "{}", red.apply_to(e))?;
-                } else {
-                    writeln!(f)?;
+        }
+        entries.sort_by_key(|entry| entry.conclusion());
+        for entry in entries {
+            match entry {
+                HumanEntry::Test(test) => print_human_test(harness, &mut f, test)?,
+                HumanEntry::Sweep { name, runs } => {
+                    print_human_sweep(harness, &mut f, &name, &runs)?
                 }
             }
-            writeln!(f)?;
         }
         writeln!(f)?;
         let summary_style = if self.summary.num_failed > 0 {
@@ -463,8 +386,7 @@ impl FullReport {
         _harness: &TestHarness,
         f: impl std::io::Write,
     ) -> Result<(), std::io::Error> {
-        serde_json::to_writer_pretty(f, self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
     }
     pub fn print_rustc_json(
         &self,
@@ -479,7 +401,7 @@ impl FullReport {
                 "test_count": self.summary.num_tests - self.summary.num_skipped,
             }),
         )
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
         writeln!(&mut f)?;
 
         for test in &self.tests {
@@ -498,7 +420,7 @@ impl FullReport {
                     "name": &test_name,
                 }),
             )
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
             writeln!(&mut f)?;
             serde_json::to_writer(
                 &mut f,
@@ -509,7 +431,7 @@ impl FullReport {
                     "stdout": status_message,
                 }),
             )
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
             writeln!(&mut f)?;
         }
 
@@ -527,7 +449,7 @@ impl FullReport {
                 "exec_time": 0.0,
             }),
         )
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
         writeln!(&mut f)?;
 
         Ok(())
@@ -537,3 +459,404 @@ impl FullReport {
         self.summary.num_failed > 0
     }
 }
+
+/// An entry in the human-readable report.
+enum HumanEntry<'a> {
+    Test(&'a TestReport),
+    /// All the runs of a sweep (like `random1..=500`)
+    Sweep {
+        name: String,
+        runs: Vec<&'a TestReport>,
+    },
+}
+
+impl HumanEntry<'_> {
+    fn conclusion(&self) -> TestConclusion {
+        match self {
+            HumanEntry::Test(test) => test.conclusion,
+            // A sweep is as bad as its worst run
+            HumanEntry::Sweep { runs, .. } => runs
+                .iter()
+                .map(|test| test.conclusion)
+                .max()
+                .unwrap_or(TestConclusion::Skipped),
+        }
+    }
+}
+
+fn print_human_test(
+    harness: &TestHarness,
+    f: &mut impl std::io::Write,
+    test: &TestReport,
+) -> Result<(), std::io::Error> {
+    use TestCheckMode::*;
+    use TestConclusion::*;
+    let red = Style::new().red();
+    let green = Style::new().green();
+    let blue = Style::new().blue();
+    if let Skipped = test.conclusion {
+        return Ok(());
+    }
+    let pretty_test_name = harness.full_test_name(&test.key);
+    write!(f, "{pretty_test_name:<64} ")?;
+    match (&test.conclusion, &test.rules.check) {
+        (Skipped, _) => {
+            // Don't mention these, too many
+            // write!(f, "skipped")?
+        }
+
+        (Passed, Pass(_)) => write!(f, "passed")?,
+        (Passed, Random) => write!(f, "passed (random, result ignored)")?,
+        (Passed, Fail(_)) => write!(f, "passed (failed as expected)")?,
+
+        (Failed, Pass(_)) => {
+            write!(f, "{}", red.apply_to("failed"))?;
+            if test.results.ran_to < TestRunMode::Check {
+                let (msg, err) = match &test.results.ran_to {
+                    TestRunMode::Generate => (
+                        "generate source code",
+                        format!(
+                            "{}",
+                            test.results
+                                .source
+                                .as_ref()
+                                .unwrap()
+                                .as_ref()
+                                .err()
+                                .unwrap()
+                        ),
+                    ),
+                    TestRunMode::Build => (
+                        "compile source code",
+                        format!(
+                            "{}",
+                            test.results.build.as_ref().unwrap().as_ref().err().unwrap()
+                        ),
+                    ),
+                    TestRunMode::Link => (
+                        "link both sides together",
+                        format!(
+                            "{}",
+                            test.results.link.as_ref().unwrap().as_ref().err().unwrap()
+                        ),
+                    ),
+                    TestRunMode::Run => (
+                        "run the program",
+                        format!(
+                            "{}",
+                            test.results.run.as_ref().unwrap().as_ref().err().unwrap()
+                        ),
+                    ),
+                    TestRunMode::Skip | TestRunMode::Check => ("", String::new()),
+                };
+                write!(f, "{}", red.apply_to(" to "))?;
+                writeln!(f, "{}", red.apply_to(msg))?;
+                writeln!(f, "  {}", red.apply_to(err))?;
+            }
+        }
+        (Failed, Random) => write!(f, "{}", red.apply_to("failed!? (failed but random!?)"))?,
+        (Failed, Fail(_)) => write!(f, "{}", red.apply_to("failed (passed unexpectedly!)"))?,
+        (Failed, TestCheckMode::Busted(_)) => write!(
+            f,
+            "{}",
+            green.apply_to("fixed (test was busted, congrats!)")
+        )?,
+
+        (TestConclusion::Busted, _) | (Passed, TestCheckMode::Busted(_)) => {
+            write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?
+        }
+    }
+
+    let be_detailed = test.results.ran_to >= TestRunMode::Check;
+    if !be_detailed {
+        writeln!(f)?;
+        return Ok(());
+    }
+    let check_result = test.results.check.as_ref().unwrap();
+    let sub_results = &check_result.subtest_checks;
+    let num_passed = sub_results.iter().filter(|r| r.is_ok()).count();
+
+    writeln!(f, " ({num_passed:>3}/{:<3} passed)", sub_results.len())?;
+    // If all the subtests pass, don't bother with a breakdown.
+    if check_result.all_passed {
+        return Ok(());
+    }
+
+    let max_name_len = check_result
+        .subtest_names
+        .iter()
+        .fold(0, |max, name| max.max(name.len()));
+    for (idx, (subtest_name, result)) in check_result
+        .subtest_names
+        .iter()
+        .zip(sub_results.iter())
+        .enumerate()
+    {
+        write!(f, "  {:width$} ", subtest_name, width = max_name_len)?;
+        if let Err(failures) = result {
+            writeln!(
+                f,
+                "{}",
+                red.apply_to(format!("failed! ({})", values_differed(failures.len())))
+            )?;
+            // The first mismatch gets the full explanation, the rest get a table row
+            let (first, _) = failures
+                .split_first()
+                .expect("failed without any failures!?");
+            writeln!(f, "{}", red.apply_to(first))?;
+            if failures.len() > 1 {
+                print_mismatch_table(f, failures)?;
+            }
+            for diff in check_result.subtest_diffs.get(idx).into_iter().flatten() {
+                print_arg_diff(f, diff)?;
+            }
+        } else {
+            writeln!(f)?;
+        }
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Print every mismatch of a subtest as one row of a table, with the first highlighted.
+fn print_mismatch_table(
+    f: &mut impl std::io::Write,
+    failures: &[CheckFailure],
+) -> Result<(), std::io::Error> {
+    let red = Style::new().red();
+    let header = ["value", "type", "expect", "caller", "callee"].map(String::from);
+    let rows = failures
+        .iter()
+        .map(|failure| {
+            let (path, ty) = failure.val_path();
+            let [expected, caller, callee] = failure.short_vals();
+            [path.to_owned(), ty.to_owned(), expected, caller, callee]
+        })
+        .collect::<Vec<_>>();
+    let mut widths = header.clone().map(|col| col.len());
+    for row in &rows {
+        for (width, col) in widths.iter_mut().zip(row) {
+            *width = (*width).max(col.len());
+        }
+    }
+    let fmt_row = |row: &[String; 5]| {
+        row.iter()
+            .zip(widths)
+            .map(|(col, width)| format!("{col:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    writeln!(f, "    every value that differed:")?;
+    writeln!(f, "      {}", fmt_row(&header))?;
+    for (idx, row) in rows.iter().enumerate() {
+        if idx == 0 {
+            writeln!(f, "    > {}", red.apply_to(fmt_row(row)))?;
+        } else {
+            writeln!(f, "      {}", fmt_row(row))?;
+        }
+    }
+    // (the first one's explanation was already printed with it)
+    for failure in &failures[1..] {
+        for explanation in failure.misplaced() {
+            writeln!(f, "    {explanation}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the bytes of an argument where they live in memory, with the ones
+/// that differ from what we expected highlighted.
+fn print_arg_diff(f: &mut impl std::io::Write, diff: &ArgDiff) -> Result<(), std::io::Error> {
+    let red = Style::new().red();
+    let dim = Style::new().dim();
+    writeln!(
+        f,
+        "    layout of {}: {} (host target)",
+        diff.arg_name, diff.arg_ty_name
+    )?;
+    if let Some(e) = &diff.layout_error {
+        writeln!(f, "      {}", dim.apply_to(format!("unknown ({e})")))?;
+        return Ok(());
+    }
+
+    // Each cell is (text, styled text), so we can pad by what's actually visible
+    type Cell = (String, String);
+    let plain = |text: String| (text.clone(), text);
+    let hex = |bytes: &[u8], expected: &[u8]| -> Cell {
+        let mut text = vec![];
+        let mut styled = vec![];
+        for (idx, byte) in bytes.iter().enumerate() {
+            let byte_text = format!("{byte:02X}");
+            if expected.get(idx) != Some(byte) {
+                styled.push(red.apply_to(&byte_text).to_string());
+            } else {
+                styled.push(byte_text.clone());
+            }
+            text.push(byte_text);
+        }
+        (text.join(" "), styled.join(" "))
+    };
+    let named = |name: &str, expected: &str| -> Cell {
+        if name == expected {
+            plain(name.to_owned())
+        } else {
+            (name.to_owned(), red.apply_to(name).to_string())
+        }
+    };
+
+    for block in &diff.blocks {
+        let header = ["offset", "size", "contents", "expect", "caller", "callee"];
+        let mut rows: Vec<[Cell; 6]> = vec![header.map(|col| plain(col.to_owned()))];
+        let mut differs = vec![false];
+        for region in &block.regions {
+            differs.push(region.contents.differs());
+            let offset = plain(format!("{:#04x}", region.offset));
+            let size = plain(region.size.to_string());
+            let row = match &region.contents {
+                RegionContents::Padding => [
+                    offset,
+                    size,
+                    (
+                        "(padding)".to_owned(),
+                        dim.apply_to("(padding)").to_string(),
+                    ),
+                    plain(String::new()),
+                    plain(String::new()),
+                    plain(String::new()),
+                ],
+                RegionContents::Pointer { pointee } => [
+                    offset,
+                    size,
+                    plain(format!("(pointer to {pointee})")),
+                    plain(String::new()),
+                    plain(String::new()),
+                    plain(String::new()),
+                ],
+                RegionContents::Bytes {
+                    path,
+                    ty_name,
+                    expected,
+                    caller,
+                    callee,
+                } => [
+                    offset,
+                    size,
+                    plain(format!("{path}: {ty_name}")),
+                    hex(expected, expected),
+                    hex(caller, expected),
+                    hex(callee, expected),
+                ],
+                RegionContents::Variant {
+                    path,
+                    ty_name,
+                    expected,
+                    caller,
+                    callee,
+                } => [
+                    offset,
+                    size,
+                    plain(format!("{path}: {ty_name}")),
+                    plain(expected.clone()),
+                    named(caller, expected),
+                    named(callee, expected),
+                ],
+            };
+            rows.push(row);
+        }
+
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, (text, _)) in widths.iter_mut().zip(row) {
+                *width = (*width).max(text.len());
+            }
+        }
+        writeln!(
+            f,
+            "      {}: {} ({} bytes, align {})",
+            block.path, block.ty_name, block.size, block.align
+        )?;
+        for (row, differs) in rows.iter().zip(differs) {
+            let mut line = String::new();
+            for (col, ((text, styled), width)) in row.iter().zip(widths).enumerate() {
+                if col > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(styled);
+                line.push_str(&" ".repeat(width - text.len()));
+            }
+            let marker = if differs { ">" } else { " " };
+            writeln!(f, "      {marker} {}", line.trim_end())?;
+        }
+    }
+    Ok(())
+}
+
+/// "1 value differed" / "3 values differed"
+pub fn values_differed(count: usize) -> String {
+    if count == 1 {
+        format!("{count} value differed")
+    } else {
+        format!("{count} values differed")
+    }
+}
+
+fn print_human_sweep(
+    harness: &TestHarness,
+    f: &mut impl std::io::Write,
+    name: &str,
+    runs: &[&TestReport],
+) -> Result<(), std::io::Error> {
+    use TestConclusion::*;
+    let red = Style::new().red();
+    let blue = Style::new().blue();
+    let ran = runs
+        .iter()
+        .filter(|test| test.conclusion != Skipped)
+        .collect::<Vec<_>>();
+    let failed = ran
+        .iter()
+        .filter(|test| test.conclusion == Failed)
+        .collect::<Vec<_>>();
+    let Some(conclusion) = ran.iter().map(|test| test.conclusion).max() else {
+        return Ok(());
+    };
+    write!(f, "{name:<64} ")?;
+    match conclusion {
+        Skipped => unreachable!("skipped runs were filtered out"),
+        Failed => write!(f, "{}", red.apply_to("failed"))?,
+        Busted => write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?,
+        Passed => write!(f, "passed")?,
+    }
+    let count = |conclusion| {
+        runs.iter()
+            .filter(|test| test.conclusion == conclusion)
+            .count()
+    };
+    writeln!(
+        f,
+        " ({} passed, {} busted, {} failed, {} skipped)",
+        count(Passed),
+        count(Busted),
+        count(Failed),
+        count(Skipped)
+    )?;
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    // Give the details of every failing run, and how to rerun just those
+    for test in &failed {
+        print_human_test(harness, f, test)?;
+    }
+    let rerun = failed
+        .iter()
+        .map(|test| test.key.options.val_generator.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(f, "  rerun the failures with --gen-vals={rerun}")?;
+    writeln!(f)?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use super::*;
+use kdl_script::body::{resolve_alias, ExprKind, TypedExpr, TypedStmt};
+use kdl_script::emit::body_var_name;
+use kdl_script::types::{FieldTy, Func, PrimitiveTy, Ty, TyIdx};
+use std::fmt::Write;
+
+impl CcToolchain {
+    /// Run a function's body to compute its output (which is bound to the output's name).
+    pub fn lower_body(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        function: &Func,
+    ) -> Result<(), GenerateError> {
+        let body = &function.body;
+        for stmt in &body.stmts {
+            match stmt {
+                TypedStmt::Let(Some(var), expr) => {
+                    let (pre, post) = self.body_tyname(state, expr.ty)?;
+                    let var_name = state
+                        .decls
+                        .local_ident(function, &body_var_name(function, *var))
+                        .into_owned();
+                    let val = self.lower_value(state, function, expr)?;
+                    writeln!(f, "{pre}{var_name}{post} = {val};")?;
+                }
+                TypedStmt::Let(None, expr) => {
+                    let val = self.lower_value(state, function, expr)?;
+                    writeln!(f, "(void)({val});")?;
+                }
+                TypedStmt::Return(expr) => {
+                    let output = &function.outputs[0];
+                    let (pre, post) = self.body_tyname(state, output.ty)?;
+                    let val = self.lower_value(state, function, expr)?;
+                    let output_name = state.decls.local_ident(function, &output.name);
+                    writeln!(f, "{pre}{output_name}{post} = {val};")?;
+                }
+                // The callee has nowhere to print to
+                TypedStmt::Print(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Lower an expression that's used as a value.
+    fn lower_value(
+        &self,
+        state: &TestState,
+        function: &Func,
+        expr: &TypedExpr,
+    ) -> Result<String, GenerateError> {
+        let types = &state.types;
+        let real_ty = types.realize_ty(resolve_alias(types, expr.ty));
+        if let Ty::Array(_) = real_ty {
+            Err(UnsupportedError::Other(
+                "c can't copy arrays around in function bodies".to_owned(),
+            ))?
+        }
+        let fields = |fields: &[FieldTy], vals: &[TypedExpr]| {
+            let fields = fields
+                .iter()
+                .zip(vals)
+                .map(|(field, val)| {
+                    let val = self.lower_value(state, function, val)?;
+                    Ok(format!(
+                        ".{} = {val}",
+                        state.decls.field_ident(fields, field.idx)
+                    ))
+                })
+                .collect::<Result<Vec<_>, GenerateError>>()?;
+            Ok::<_, GenerateError>(fields.join(", "))
+        };
+
+        let val = match &expr.kind {
+            ExprKind::Var(_)
+            | ExprKind::Field(..)
+            | ExprKind::UnionField(..)
+            | ExprKind::Index(..)
+            | ExprKind::Deref(_) => self.lower_place(state, function, expr)?,
+            ExprKind::Ref(place) => format!("(&{})", self.lower_place(state, function, place)?),
+            ExprKind::Int(prim, bits) => {
+                let (tyname, _) = self.body_tyname(state, expr.ty)?;
+                match prim {
+                    PrimitiveTy::I128 | PrimitiveTy::U128 => {
+                        let lower = bits & 0xFFFF_FFFF_FFFF_FFFF;
+                        let higher = bits >> 64;
+                        format!(
+                            "(({tyname})(((__uint128_t){lower:#X}ull) | (((__uint128_t){higher:#X}ull) << 64)))"
+                        )
+                    }
+                    _ => format!("(({tyname}){bits:#X}ull)"),
+                }
+            }
+            ExprKind::Float(prim, val) => match prim {
+                PrimitiveTy::F32 => {
+                    let val = *val as f32;
+                    if val.fract() == 0.0 {
+                        format!("({val}.0f)")
+                    } else {
+                        format!("({val}f)")
+                    }
+                }
+                _ => {
+                    if val.fract() == 0.0 {
+                        format!("({val}.0)")
+                    } else {
+                        format!("({val})")
+                    }
+                }
+            },
+            ExprKind::Bool(val) => val.to_string(),
+            ExprKind::EnumCase(idx) => {
+                let Ty::Enum(enum_ty) = real_ty else {
+                    unreachable!("enum case of non-enum type");
+                };
+                self.body_tyname(state, expr.ty)?;
+                state
+                    .decls
+                    .variant_ident(&enum_ty.name, &enum_ty.variants[*idx].name)
+            }
+            ExprKind::Struct(vals) => {
+                let Ty::Struct(struct_ty) = real_ty else {
+                    unreachable!("struct literal of non-struct type");
+                };
+                let (tyname, _) = self.body_tyname(state, expr.ty)?;
+                format!("(({tyname}){{ {} }})", fields(&struct_ty.fields, vals)?)
+            }
+            ExprKind::Union(idx, val) => {
+                let Ty::Union(union_ty) = real_ty else {
+                    unreachable!("union literal of non-union type");
+                };
+                let (tyname, _) = self.body_tyname(state, expr.ty)?;
+                let field = &union_ty.fields[*idx..][..1];
+                let val = std::slice::from_ref(&**val);
+                format!("(({tyname}){{ {} }})", fields(field, val)?)
+            }
+            ExprKind::Arith(op, lhs, rhs) => {
+                let (tyname, _) = self.body_tyname(state, expr.ty)?;
+                let lhs = self.lower_value(state, function, lhs)?;
+                let rhs = self.lower_value(state, function, rhs)?;
+                let op = op.name();
+                match real_ty {
+                    Ty::Primitive(PrimitiveTy::F32 | PrimitiveTy::F64) => {
+                        format!("(({tyname})({lhs} {op} {rhs}))")
+                    }
+                    // Do the math unsigned (and wide enough to not get promoted to int)
+                    // so that it wraps instead of overflowing
+                    Ty::Primitive(PrimitiveTy::I128 | PrimitiveTy::U128) => {
+                        format!("(({tyname})((__uint128_t){lhs} {op} (__uint128_t){rhs}))")
+                    }
+                    _ => format!("(({tyname})((uint64_t){lhs} {op} (uint64_t){rhs}))"),
+                }
+            }
+            ExprKind::VariantField(..) | ExprKind::Tagged(..) => Err(UnsupportedError::Other(
+                "c doesn't have tagged unions impled yet".to_owned(),
+            ))?,
+            ExprKind::Call(..) => Err(UnsupportedError::Other(
+                "function bodies that call other functions aren't supported yet".to_owned(),
+            ))?,
+            ExprKind::Array(_) => unreachable!("arrays were rejected above"),
+        };
+        Ok(val)
+    }
+
+    /// Lower an expression that names a place (something that can be borrowed).
+    fn lower_place(
+        &self,
+        state: &TestState,
+        function: &Func,
+        expr: &TypedExpr,
+    ) -> Result<String, GenerateError> {
+        let types = &state.types;
+        let base_ty = |base: &TypedExpr| types.realize_ty(resolve_alias(types, base.ty));
+        let place = match &expr.kind {
+            ExprKind::Var(var) => state
+                .decls
+                .local_ident(function, &body_var_name(function, *var))
+                .into_owned(),
+            ExprKind::Field(base, idx) => {
+                let Ty::Struct(struct_ty) = base_ty(base) else {
+                    unreachable!("field of non-struct type");
+                };
+                let base = self.lower_place(state, function, base)?;
+                format!(
+                    "{base}.{}",
+                    state.decls.field_ident(&struct_ty.fields, *idx)
+                )
+            }
+            ExprKind::UnionField(base, idx) => {
+                let Ty::Union(union_ty) = base_ty(base) else {
+                    unreachable!("field of non-union type");
+                };
+                let base = self.lower_place(state, function, base)?;
+                format!("{base}.{}", state.decls.field_ident(&union_ty.fields, *idx))
+            }
+            ExprKind::Index(base, idx) => {
+                let base = self.lower_place(state, function, base)?;
+                format!("{base}[{idx}]")
+            }
+            ExprKind::Deref(base) => format!("(*{})", self.lower_place(state, function, base)?),
+            ExprKind::VariantField(..) => Err(UnsupportedError::Other(
+                "c doesn't have tagged unions impled yet".to_owned(),
+            ))?,
+            _ => unreachable!("only paths are places"),
+        };
+        Ok(place)
+    }
+
+    /// The name of a type a function body uses, which the test needs to have declared
+    /// (primitives are always available).
+    fn body_tyname(&self, state: &TestState, ty: TyIdx) -> Result<(String, String), GenerateError> {
+        if let Some(tyname) = state.decls.tynames.get(&ty) {
+            return Ok(tyname.clone());
+        }
+        let tyname = match state.types.realize_ty(ty) {
+            Ty::Primitive(prim) => match prim {
+                PrimitiveTy::I8 => "int8_t ",
+                PrimitiveTy::I16 => "int16_t ",
+                PrimitiveTy::I32 => "int32_t ",
+                PrimitiveTy::I64 => "int64_t ",
+                PrimitiveTy::I128 => "__int128_t ",
+                PrimitiveTy::U8 => "uint8_t ",
+                PrimitiveTy::U16 => "uint16_t ",
+                PrimitiveTy::U32 => "uint32_t ",
+                PrimitiveTy::U64 => "uint64_t ",
+                PrimitiveTy::U128 => "__uint128_t ",
+                PrimitiveTy::F32 => "float ",
+                PrimitiveTy::F64 => "double ",
+                PrimitiveTy::Bool => "bool ",
+                PrimitiveTy::Ptr => "void *",
+                _ => unreachable!("function bodies don't support {prim:?}"),
+            },
+            _ => Err(UnsupportedError::Other(format!(
+                "function body uses {}, which this test doesn't declare",
+                state.types.format_ty(ty)
+            )))?,
+        };
+        Ok((tyname.to_owned(), String::new()))
+    }
+}
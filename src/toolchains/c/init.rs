@@ -17,8 +17,15 @@ impl CcToolchain {
             Ty::Primitive(prim) => match prim {
                 PrimitiveTy::I8 => write!(f, "{}", val.generate_i8())?,
                 PrimitiveTy::I16 => write!(f, "{}", val.generate_i16())?,
-                PrimitiveTy::I32 => write!(f, "{}", val.generate_i32())?,
-                PrimitiveTy::I64 => write!(f, "{}", val.generate_i64())?,
+                // The minimums can't be written as a literal (the negation of a too-big positive one)
+                PrimitiveTy::I32 => match val.generate_i32() {
+                    i32::MIN => write!(f, "({} - 1)", i32::MIN + 1)?,
+                    val => write!(f, "{val}")?,
+                },
+                PrimitiveTy::I64 => match val.generate_i64() {
+                    i64::MIN => write!(f, "({}ll - 1)", i64::MIN + 1)?,
+                    val => write!(f, "{val}")?,
+                },
                 PrimitiveTy::U8 => write!(f, "{}", val.generate_u8())?,
                 PrimitiveTy::U16 => write!(f, "{}", val.generate_u16())?,
                 PrimitiveTy::U32 => write!(f, "{}", val.generate_u32())?,
@@ -42,23 +49,36 @@ impl CcToolchain {
                     )?
                 }
 
+                // Infinities and NaNs don't have literals, so punt them through their bits
                 PrimitiveTy::F32 => {
-                    let val = f32::from_bits(val.generate_u32());
-                    if val.fract() == 0.0 {
+                    let bits = val.generate_u32();
+                    let val = f32::from_bits(bits);
+                    if !val.is_finite() {
+                        write!(
+                            f,
+                            "(((union {{ uint32_t bits; float value; }}){{ .bits = {bits:#X}u }}).value)"
+                        )?
+                    } else if val.fract() == 0.0 {
                         write!(f, "{val}.0f")?
                     } else {
                         write!(f, "{val}f")?
                     }
                 }
                 PrimitiveTy::F64 => {
-                    let val = f64::from_bits(val.generate_u64());
-                    if val.fract() == 0.0 {
+                    let bits = val.generate_u64();
+                    let val = f64::from_bits(bits);
+                    if !val.is_finite() {
+                        write!(
+                            f,
+                            "(((union {{ uint64_t bits; double value; }}){{ .bits = {bits:#X}ull }}).value)"
+                        )?
+                    } else if val.fract() == 0.0 {
                         write!(f, "{val}.0")?
                     } else {
                         write!(f, "{val}")?
                     }
                 }
-                PrimitiveTy::Bool => write!(f, "true")?,
+                PrimitiveTy::Bool => write!(f, "{}", val.generate_bool())?,
                 PrimitiveTy::Ptr => {
                     if true {
                         write!(f, "(void*){:#X}ull", val.generate_u64())?
@@ -90,8 +110,7 @@ impl CcToolchain {
             Ty::Enum(enum_ty) => {
                 let name = alias.unwrap_or(&enum_ty.name);
                 if let Some(variant) = val.select_val(&enum_ty.variants) {
-                    let variant_name = &variant.name;
-                    write!(f, "{name}_{variant_name}")?;
+                    write!(f, "{}", state.decls.variant_ident(name, &variant.name))?;
                 }
             }
             _ => unreachable!("only primitives and enums should be passed to generate_leaf_value"),
@@ -123,7 +142,7 @@ impl CcToolchain {
                 // Now do the rest of the recursion on constructing the temporary
                 let mut ref_temp = String::new();
                 let mut ref_temp_f = Fivemat::new(&mut ref_temp, INDENT);
-                let (pre, post) = &state.tynames[pointee_ty];
+                let (pre, post) = &state.decls.tynames[pointee_ty];
                 write!(&mut ref_temp_f, "{pre}{ref_temp_name}{post} = ")?;
                 let ref_temp_name = format!("{ref_temp_name}_");
                 self.init_value(
@@ -157,7 +176,11 @@ impl CcToolchain {
                         write!(f, ", ")?;
                     }
                     let field_name = &field.ident;
-                    write!(f, ".{field_name} = ")?;
+                    write!(
+                        f,
+                        ".{} = ",
+                        state.decls.field_ident(&struct_ty.fields, field.idx)
+                    )?;
                     let ref_temp_name = format!("{ref_temp_name}{field_name}_");
                     self.init_value(f, state, field.ty, vals, alias, &ref_temp_name, extra_decls)?;
                 }
@@ -168,7 +191,11 @@ impl CcToolchain {
                 let tag_val = vals.next_val();
                 if let Some(field) = tag_val.select_val(&union_ty.fields) {
                     let field_name = &field.ident;
-                    write!(f, ".{field_name} = ")?;
+                    write!(
+                        f,
+                        ".{} = ",
+                        state.decls.field_ident(&union_ty.fields, field.idx)
+                    )?;
                     let ref_temp_name = format!("{ref_temp_name}{field_name}_");
                     self.init_value(f, state, field.ty, vals, alias, &ref_temp_name, extra_decls)?;
                 }
@@ -242,7 +269,7 @@ impl CcToolchain {
         let mut real_var_decl = String::new();
         let mut real_var_decl_f = Fivemat::new(&mut real_var_decl, INDENT);
         let mut extra_decls = Vec::new();
-        let (pre, post) = &state.tynames[&var_ty];
+        let (pre, post) = &state.decls.tynames[&var_ty];
         write!(&mut real_var_decl_f, "{pre}{var_name}{post} = ")?;
         let ref_temp_name = format!("{var_name}_");
         self.init_value(
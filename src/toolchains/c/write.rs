@@ -78,10 +78,9 @@ impl CcToolchain {
                 let tag_generator = vals.next_val();
                 let tag_idx = tag_generator.generate_idx(enum_ty.variants.len());
                 if let Some(variant) = enum_ty.variants.get(tag_idx) {
-                    let enum_name = &enum_ty.name;
-                    let variant_name = &variant.name;
+                    let variant_name = state.decls.variant_ident(&enum_ty.name, &variant.name);
                     if tag_generator.should_write_val(&state.options) {
-                        writeln!(f, "if ({enum_name}_{variant_name} == {from}) {{")?;
+                        writeln!(f, "if ({variant_name} == {from}) {{")?;
                         f.add_indent(1);
                         self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
                         f.sub_indent(1);
@@ -115,7 +114,7 @@ impl CcToolchain {
             Ty::Struct(struct_ty) => {
                 // recurse into each field
                 for field in &struct_ty.fields {
-                    let field_name = &field.ident;
+                    let field_name = state.decls.field_ident(&struct_ty.fields, field.idx);
                     let base = format!("{from}.{field_name}");
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
                 }
@@ -208,7 +207,7 @@ impl CcToolchain {
                     self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
                 }
                 if let Some(field) = union_ty.fields.get(tag_idx) {
-                    let field_name = &field.ident;
+                    let field_name = state.decls.field_ident(&union_ty.fields, tag_idx);
                     let base = format!("{from}.{field_name}");
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
                 }
@@ -21,6 +21,22 @@ pub const TOOLCHAIN_MSVC: &str = "msvc";
 
 const C_TOOLCHAINS: &[&str] = &[TOOLCHAIN_CC, TOOLCHAIN_GCC, TOOLCHAIN_CLANG, TOOLCHAIN_MSVC];
 
+/// Names the harness defines in generated code, which tests' identifiers
+/// get renamed to avoid (see `CDecls::ident` and `RustDecls::ident`).
+const HARNESS_SYMBOLS: &[&str] = &[
+    "CALLER_VALS",
+    "CALLEE_VALS",
+    "WRITE_VAL",
+    "SET_FUNC",
+    "WriteBuffer",
+    "WriteValCallback",
+    "SetFuncCallback",
+    "write_val",
+    "set_func",
+    "do_test",
+    "test_start",
+];
+
 /// A compiler/language toolchain!
 pub trait Toolchain {
     fn lang(&self) -> &'static str;
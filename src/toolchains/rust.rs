@@ -1,13 +1,14 @@
 //! Rust(c) codegen backend backend
 
+mod body;
 mod declare;
 mod init;
 mod write;
 
 use camino::Utf8Path;
+use kdl_script::emit::rust::RustDecls;
 use kdl_script::types::*;
 use kdl_script::PunEnv;
-use std::collections::HashMap;
 use std::fmt::Write;
 use std::sync::Arc;
 
@@ -24,8 +25,7 @@ pub struct TestState {
     pub inner: TestImpl,
     // interning state
     pub desired_funcs: Vec<FuncIdx>,
-    pub tynames: HashMap<TyIdx, String>,
-    pub borrowed_tynames: HashMap<TyIdx, String>,
+    pub decls: RustDecls,
 }
 impl std::ops::Deref for TestState {
     type Target = TestImpl;
@@ -34,13 +34,16 @@ impl std::ops::Deref for TestState {
     }
 }
 impl TestState {
-    fn new(inner: TestImpl) -> Self {
+    fn new(inner: TestImpl, toolchain: &RustcToolchain) -> Self {
         let desired_funcs = inner.options.functions.active_funcs(&inner.types);
+        let mut decls = RustDecls::new(inner.types.clone(), inner.env.clone());
+        decls.repr = inner.options.repr;
+        decls.allow_unstable = toolchain.is_nightly;
+        decls.reserved = HARNESS_SYMBOLS;
         Self {
             inner,
             desired_funcs,
-            tynames: Default::default(),
-            borrowed_tynames: Default::default(),
+            decls,
         }
     }
 }
@@ -110,13 +113,13 @@ impl Toolchain for RustcToolchain {
 
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
         let mut f = Fivemat::new(f, INDENT);
-        let mut state = TestState::new(test);
+        let mut state = TestState::new(test, self);
         self.generate_callee_impl(&mut f, &mut state)
     }
 
     fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
         let mut f = Fivemat::new(f, INDENT);
-        let mut state = TestState::new(test);
+        let mut state = TestState::new(test, self);
         self.generate_caller_impl(&mut f, &mut state)
     }
 }
@@ -160,9 +163,10 @@ impl RustcToolchain {
         let mut func_vals = state.vals.at_func(func);
         for arg in &function.inputs {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
+            let arg_name = state.decls.ident(&arg.name);
             // Create and report the input
-            self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+            self.init_var(f, state, &arg_name, arg.ty, arg_vals.clone())?;
+            self.write_var(f, state, &arg_name, arg.ty, arg_vals, CALLER_VALS)?;
         }
 
         // Call the function
@@ -171,8 +175,8 @@ impl RustcToolchain {
         // Report all the outputs
         for arg in &function.outputs {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
-
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+            let arg_name = state.decls.ident(&arg.name);
+            self.write_var(f, state, &arg_name, arg.ty, arg_vals, CALLER_VALS)?;
         }
 
         f.sub_indent(1);
@@ -186,12 +190,12 @@ impl RustcToolchain {
         state: &TestState,
         function: &Func,
     ) -> Result<(), GenerateError> {
-        let func_name = &function.name;
+        let func_name = state.decls.func_ident(&function.name);
 
         // make sure the outputs aren't weird
         self.check_returns(state, function)?;
         if let Some(output) = function.outputs.first() {
-            write!(f, "let {} = ", output.name)?;
+            write!(f, "let {} = ", state.decls.ident(&output.name))?;
         }
 
         // Call the function
@@ -202,7 +206,7 @@ impl RustcToolchain {
             if arg_idx > 0 {
                 write!(f, ", ")?;
             }
-            write!(f, "{}", arg.name)?;
+            write!(f, "{}", state.decls.ident(&arg.name))?;
         }
         writeln!(f, ");")?;
         writeln!(f)?;
@@ -249,21 +253,30 @@ impl RustcToolchain {
         let mut func_vals = state.vals.at_func(func);
         for arg in &function.inputs {
             let arg_vals = func_vals.next_arg();
-            let arg_name = &arg.name;
-            self.write_var(f, state, arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
+            let arg_name = state.decls.ident(&arg.name);
+            self.write_var(f, state, &arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
+        }
+
+        // If the function has a body, run it to compute the outputs
+        self.check_returns(state, function)?;
+        let has_body = !function.body.stmts.is_empty();
+        if has_body {
+            self.lower_body(f, state, function)?;
         }
 
         // Create outputs and report them
         for arg in &function.outputs {
             let arg_vals = func_vals.next_arg();
-            self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+            let arg_name = state.decls.ident(&arg.name);
+            if !has_body {
+                self.init_var(f, state, &arg_name, arg.ty, arg_vals.clone())?;
+            }
+            self.write_var(f, state, &arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
 
         // Return the outputs
-        self.check_returns(state, function)?;
         if let Some(arg) = function.outputs.first() {
-            writeln!(f, "{}", arg.name)?;
+            writeln!(f, "{}", state.decls.ident(&arg.name))?;
         }
         f.sub_indent(1);
         writeln!(f, "}}")?;
@@ -289,20 +302,7 @@ impl RustcToolchain {
     }
 
     fn check_returns(&self, state: &TestState, function: &Func) -> Result<(), GenerateError> {
-        let has_outparams = function
-            .outputs
-            .iter()
-            .any(|arg| state.types.ty_contains_ref(arg.ty));
-        if has_outparams {
-            return Err(UnsupportedError::Other(
-                "outparams (outputs containing references) aren't supported".to_owned(),
-            ))?;
-        }
-        if function.outputs.len() > 1 {
-            return Err(UnsupportedError::Other(
-                "multiple returns (should this be a struct?)".to_owned(),
-            ))?;
-        }
+        kdl_script::emit::check_returns(&state.types, function)?;
         Ok(())
     }
 }
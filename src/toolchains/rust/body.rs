@@ -0,0 +1,315 @@
+use super::*;
+use kdl_script::body::{resolve_alias, ExprKind, TypedBody, TypedExpr, TypedStmt, VarIdx};
+use kdl_script::emit::body_var_name;
+use kdl_script::types::{Func, PrimitiveTy, Ty};
+use std::fmt::Write;
+
+impl RustcToolchain {
+    /// Run a function's body to compute its output (which is bound to the output's name).
+    pub fn lower_body(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        function: &Func,
+    ) -> Result<(), GenerateError> {
+        let body = &function.body;
+        let borrowed = borrowed_body_vars(body);
+        for (var, arg) in function.inputs.iter().enumerate() {
+            if borrowed.contains(&var) {
+                writeln!(f, "let mut {0} = {0};", state.decls.ident(&arg.name))?;
+            }
+        }
+        for stmt in &body.stmts {
+            match stmt {
+                TypedStmt::Let(Some(var), expr) => {
+                    let let_mut = if borrowed.contains(var) {
+                        "let mut"
+                    } else {
+                        "let"
+                    };
+                    let var_name = state
+                        .decls
+                        .ident(&body_var_name(function, *var))
+                        .into_owned();
+                    let val = self.lower_value(state, function, expr)?;
+                    writeln!(f, "{let_mut} {var_name} = {val};")?;
+                }
+                TypedStmt::Let(None, expr) => {
+                    let val = self.lower_value(state, function, expr)?;
+                    writeln!(f, "let _ = {val};")?;
+                }
+                TypedStmt::Return(expr) => {
+                    let val = self.lower_value(state, function, expr)?;
+                    let output_name = state.decls.ident(&function.outputs[0].name);
+                    writeln!(f, "let {output_name} = {val};")?;
+                }
+                // The callee has nowhere to print to
+                TypedStmt::Print(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Lower an expression that's used as a value.
+    fn lower_value(
+        &self,
+        state: &TestState,
+        function: &Func,
+        expr: &TypedExpr,
+    ) -> Result<String, GenerateError> {
+        let types = &state.types;
+        let nominal_name = |ty| -> Result<String, GenerateError> {
+            if !state.decls.tynames.contains_key(&ty) {
+                Err(UnsupportedError::Other(format!(
+                    "function body builds {}, which this test doesn't declare",
+                    types.format_ty(ty)
+                )))?
+            }
+            Ok(state.decls.tynames[&ty].clone())
+        };
+        let fields = |fields: &[kdl_script::types::FieldTy], vals: &[TypedExpr]| {
+            let fields = fields
+                .iter()
+                .zip(vals)
+                .map(|(field, val)| {
+                    let val = self.lower_value(state, function, val)?;
+                    let (wrap_pre, wrap_post) = state.decls.field_val_wrapping(field);
+                    let field_name = state.decls.ident(&field.ident);
+                    Ok(format!("{field_name}: {wrap_pre}{val}{wrap_post}"))
+                })
+                .collect::<Result<Vec<_>, GenerateError>>()?;
+            Ok::<_, GenerateError>(fields.join(", "))
+        };
+
+        let val = match &expr.kind {
+            ExprKind::Var(_)
+            | ExprKind::Field(..)
+            | ExprKind::UnionField(..)
+            | ExprKind::VariantField(..)
+            | ExprKind::Index(..)
+            | ExprKind::Deref(_) => {
+                let place = self.lower_place(state, function, expr, false)?;
+                let is_ref = matches!(types.realize_ty(resolve_alias(types, expr.ty)), Ty::Ref(_));
+                if is_ref {
+                    // Reborrow so the reference can be used again
+                    format!("&mut *{place}")
+                } else if types.ty_contains_ref(expr.ty) {
+                    Err(UnsupportedError::Other(format!(
+                        "function body copies {}, which contains references",
+                        types.format_ty(expr.ty)
+                    )))?
+                } else {
+                    place
+                }
+            }
+            ExprKind::Ref(place) => {
+                format!("&mut {}", self.lower_place(state, function, place, true)?)
+            }
+            ExprKind::Int(prim, bits) => {
+                // Negative literals get parens so a method call doesn't bind tighter than the `-`
+                let signed = |val: i128, suffix: &str| {
+                    if val < 0 {
+                        format!("({val}{suffix})")
+                    } else {
+                        format!("{val}{suffix}")
+                    }
+                };
+                match prim {
+                    PrimitiveTy::I8 => signed((*bits as u8 as i8).into(), "i8"),
+                    PrimitiveTy::I16 => signed((*bits as u16 as i16).into(), "i16"),
+                    PrimitiveTy::I32 => signed((*bits as u32 as i32).into(), "i32"),
+                    PrimitiveTy::I64 => signed((*bits as u64 as i64).into(), "i64"),
+                    PrimitiveTy::I128 => signed(*bits as i128, "i128"),
+                    PrimitiveTy::U8 => format!("{bits}u8"),
+                    PrimitiveTy::U16 => format!("{bits}u16"),
+                    PrimitiveTy::U32 => format!("{bits}u32"),
+                    PrimitiveTy::U64 => format!("{bits}u64"),
+                    PrimitiveTy::U128 => format!("{bits}u128"),
+                    PrimitiveTy::Ptr => format!("({bits:#X}u64 as *mut ())"),
+                    _ => unreachable!("integer literal of non-integer type"),
+                }
+            }
+            ExprKind::Float(prim, val) => match prim {
+                PrimitiveTy::F32 => format!("f32::from_bits({:#X})", (*val as f32).to_bits()),
+                _ => format!("f64::from_bits({:#X})", val.to_bits()),
+            },
+            ExprKind::Bool(val) => val.to_string(),
+            ExprKind::EnumCase(idx) => {
+                let Ty::Enum(enum_ty) = types.realize_ty(expr.ty) else {
+                    unreachable!("enum case of non-enum type");
+                };
+                let name = nominal_name(expr.ty)?;
+                let variant_name = state.decls.ident(&enum_ty.variants[*idx].name);
+                format!("{name}::{variant_name}")
+            }
+            ExprKind::Struct(vals) => {
+                let Ty::Struct(struct_ty) = types.realize_ty(expr.ty) else {
+                    unreachable!("struct literal of non-struct type");
+                };
+                let name = nominal_name(expr.ty)?;
+                format!("{name} {{ {} }}", fields(&struct_ty.fields, vals)?)
+            }
+            ExprKind::Union(idx, val) => {
+                let Ty::Union(union_ty) = types.realize_ty(expr.ty) else {
+                    unreachable!("union literal of non-union type");
+                };
+                let name = nominal_name(expr.ty)?;
+                let field = &union_ty.fields[*idx..][..1];
+                let val = std::slice::from_ref(&**val);
+                format!("{name} {{ {} }}", fields(field, val)?)
+            }
+            ExprKind::Tagged(idx, vals) => {
+                let Ty::Tagged(tagged_ty) = types.realize_ty(expr.ty) else {
+                    unreachable!("tagged literal of non-tagged type");
+                };
+                let name = nominal_name(expr.ty)?;
+                let variant = &tagged_ty.variants[*idx];
+                let variant_name = state.decls.ident(&variant.name);
+                match &variant.fields {
+                    Some(variant_fields) => format!(
+                        "{name}::{variant_name} {{ {} }}",
+                        fields(variant_fields, vals)?
+                    ),
+                    None => format!("{name}::{variant_name}"),
+                }
+            }
+            ExprKind::Array(elems) => {
+                let elems = elems
+                    .iter()
+                    .map(|elem| self.lower_value(state, function, elem))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", elems.join(", "))
+            }
+            ExprKind::Arith(op, lhs, rhs) => {
+                let lhs = self.lower_value(state, function, lhs)?;
+                let rhs = self.lower_value(state, function, rhs)?;
+                let is_float = matches!(
+                    types.realize_ty(resolve_alias(types, expr.ty)),
+                    Ty::Primitive(PrimitiveTy::F32 | PrimitiveTy::F64)
+                );
+                if is_float {
+                    format!("({lhs} {} {rhs})", op.name())
+                } else {
+                    let method = match op {
+                        kdl_script::body::ArithOp::Add => "wrapping_add",
+                        kdl_script::body::ArithOp::Sub => "wrapping_sub",
+                        kdl_script::body::ArithOp::Mul => "wrapping_mul",
+                    };
+                    format!("{lhs}.{method}({rhs})")
+                }
+            }
+            ExprKind::Call(..) => Err(UnsupportedError::Other(
+                "function bodies that call other functions aren't supported yet".to_owned(),
+            ))?,
+        };
+        Ok(val)
+    }
+
+    /// Lower an expression that names a place (something that can be borrowed).
+    fn lower_place(
+        &self,
+        state: &TestState,
+        function: &Func,
+        expr: &TypedExpr,
+        borrowing: bool,
+    ) -> Result<String, GenerateError> {
+        let types = &state.types;
+        let base_ty = |base: &TypedExpr| types.realize_ty(resolve_alias(types, base.ty));
+        let place = match &expr.kind {
+            ExprKind::Var(var) => state
+                .decls
+                .ident(&body_var_name(function, *var))
+                .into_owned(),
+            ExprKind::Field(base, idx) => {
+                let Ty::Struct(struct_ty) = base_ty(base) else {
+                    unreachable!("field of non-struct type");
+                };
+                let base = self.lower_place(state, function, base, borrowing)?;
+                state.decls.field_place(&base, &struct_ty.fields[*idx])
+            }
+            ExprKind::UnionField(base, idx) => {
+                let Ty::Union(union_ty) = base_ty(base) else {
+                    unreachable!("field of non-union type");
+                };
+                let base = self.lower_place(state, function, base, borrowing)?;
+                state.decls.field_place(&base, &union_ty.fields[*idx])
+            }
+            ExprKind::VariantField(base, variant, idx) => {
+                let Ty::Tagged(tagged_ty) = base_ty(base) else {
+                    unreachable!("variant of non-tagged type");
+                };
+                let name = state.decls.ident(&tagged_ty.name);
+                let variant = &tagged_ty.variants[*variant];
+                let variant_name = state.decls.ident(&variant.name);
+                let field = &variant.fields.as_deref().unwrap_or_default()[*idx].ident;
+                let field = state.decls.ident(field);
+                let base = self.lower_place(state, function, base, borrowing)?;
+                let borrow = if borrowing { "&mut" } else { "&" };
+                format!(
+                    "(*match {borrow} {base} {{ {name}::{variant_name} {{ {field}, .. }} => {field}, _ => unreachable!() }})"
+                )
+            }
+            ExprKind::Index(base, idx) => {
+                let base = self.lower_place(state, function, base, borrowing)?;
+                format!("{base}[{idx}]")
+            }
+            ExprKind::Deref(base) => {
+                format!("(*{})", self.lower_place(state, function, base, borrowing)?)
+            }
+            _ => unreachable!("only paths are places"),
+        };
+        Ok(place)
+    }
+}
+
+/// The variables a function body takes a reference to (directly, not through another reference).
+fn borrowed_body_vars(body: &TypedBody) -> Vec<VarIdx> {
+    fn visit(expr: &TypedExpr, borrowed: &mut Vec<VarIdx>) {
+        match &expr.kind {
+            ExprKind::Ref(place) => {
+                let mut place = &**place;
+                loop {
+                    match &place.kind {
+                        ExprKind::Var(var) => {
+                            borrowed.push(*var);
+                            break;
+                        }
+                        ExprKind::Field(base, _)
+                        | ExprKind::UnionField(base, _)
+                        | ExprKind::VariantField(base, ..)
+                        | ExprKind::Index(base, _) => place = base,
+                        _ => break,
+                    }
+                }
+            }
+            ExprKind::Field(base, _)
+            | ExprKind::UnionField(base, _)
+            | ExprKind::VariantField(base, ..)
+            | ExprKind::Index(base, _)
+            | ExprKind::Deref(base)
+            | ExprKind::Union(_, base) => visit(base, borrowed),
+            ExprKind::Struct(exprs)
+            | ExprKind::Tagged(_, exprs)
+            | ExprKind::Array(exprs)
+            | ExprKind::Call(_, exprs) => exprs.iter().for_each(|expr| visit(expr, borrowed)),
+            ExprKind::Arith(_, lhs, rhs) => {
+                visit(lhs, borrowed);
+                visit(rhs, borrowed);
+            }
+            ExprKind::Var(_)
+            | ExprKind::Int(..)
+            | ExprKind::Float(..)
+            | ExprKind::Bool(_)
+            | ExprKind::EnumCase(_) => {}
+        }
+    }
+    let mut borrowed = vec![];
+    for stmt in &body.stmts {
+        match stmt {
+            TypedStmt::Let(_, expr) | TypedStmt::Return(expr) | TypedStmt::Print(expr) => {
+                visit(expr, &mut borrowed)
+            }
+        }
+    }
+    borrowed
+}
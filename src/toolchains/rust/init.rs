@@ -28,7 +28,7 @@ impl RustcToolchain {
 
                 PrimitiveTy::F32 => write!(f, "f32::from_bits({})", val.generate_u32())?,
                 PrimitiveTy::F64 => write!(f, "f64::from_bits({})", val.generate_u64())?,
-                PrimitiveTy::Bool => write!(f, "true")?,
+                PrimitiveTy::Bool => write!(f, "{}", val.generate_bool())?,
                 PrimitiveTy::Ptr => {
                     if true {
                         write!(f, "{:#X}u64 as *mut ()", val.generate_u64())?
@@ -46,9 +46,9 @@ impl RustcToolchain {
                 PrimitiveTy::F128 => write!(f, "f128::from_bits({})", val.generate_u128())?,
             },
             Ty::Enum(enum_ty) => {
-                let name = alias.unwrap_or(&enum_ty.name);
+                let name = state.decls.ident(alias.unwrap_or(&enum_ty.name));
                 if let Some(variant) = val.select_val(&enum_ty.variants) {
-                    let variant_name = &variant.name;
+                    let variant_name = state.decls.ident(&variant.name);
                     write!(f, "{name}::{variant_name}")?;
                 }
             }
@@ -110,37 +110,41 @@ impl RustcToolchain {
             }
             // Nominal types we need to emit a decl for
             Ty::Struct(struct_ty) => {
-                let name = alias.unwrap_or(&struct_ty.name);
+                let name = state.decls.ident(alias.unwrap_or(&struct_ty.name));
                 write!(f, "{name} {{ ")?;
                 for (field_idx, field) in struct_ty.fields.iter().enumerate() {
                     if field_idx > 0 {
                         write!(f, ", ")?;
                     }
                     let field_name = &field.ident;
-                    write!(f, "{field_name}: ")?;
+                    let (wrap_pre, wrap_post) = state.decls.field_val_wrapping(field);
+                    write!(f, "{}: {wrap_pre}", state.decls.ident(field_name))?;
                     let ref_temp_name = format!("{ref_temp_name}{field_name}_");
                     self.init_value(f, state, field.ty, vals, alias, &ref_temp_name, extra_decls)?;
+                    write!(f, "{wrap_post}")?;
                 }
                 write!(f, " }}")?;
             }
             Ty::Union(union_ty) => {
-                let name = alias.unwrap_or(&union_ty.name);
+                let name = state.decls.ident(alias.unwrap_or(&union_ty.name));
                 write!(f, "{name} {{ ")?;
                 let tag_val = vals.next_val();
                 if let Some(field) = tag_val.select_val(&union_ty.fields) {
                     let field_name = &field.ident;
-                    write!(f, "{field_name}: ")?;
+                    let (wrap_pre, wrap_post) = state.decls.field_val_wrapping(field);
+                    write!(f, "{}: {wrap_pre}", state.decls.ident(field_name))?;
                     let ref_temp_name = format!("{ref_temp_name}{field_name}_");
                     self.init_value(f, state, field.ty, vals, alias, &ref_temp_name, extra_decls)?;
+                    write!(f, "{wrap_post}")?;
                 }
                 write!(f, " }}")?;
             }
 
             Ty::Tagged(tagged_ty) => {
-                let name = alias.unwrap_or(&tagged_ty.name);
+                let name = state.decls.ident(alias.unwrap_or(&tagged_ty.name));
                 let tag_val = vals.next_val();
                 if let Some(variant) = tag_val.select_val(&tagged_ty.variants) {
-                    let variant_name = &variant.name;
+                    let variant_name = state.decls.ident(&variant.name);
                     write!(f, "{name}::{variant_name}")?;
                     if let Some(fields) = &variant.fields {
                         write!(f, " {{ ")?;
@@ -149,7 +153,7 @@ impl RustcToolchain {
                                 write!(f, ", ")?;
                             }
                             let field_name = &field.ident;
-                            write!(f, "{field_name}: ")?;
+                            write!(f, "{}: ", state.decls.ident(field_name))?;
                             let ref_temp_name = format!("{ref_temp_name}{field_name}_");
                             self.init_value(
                                 f,
@@ -195,7 +199,8 @@ impl RustcToolchain {
         let mut real_var_decl_f = Fivemat::new(&mut real_var_decl, INDENT);
         let mut extra_decls = Vec::new();
         write!(&mut real_var_decl_f, "{let_mut} {var_name} = ")?;
-        let ref_temp_name = format!("{var_name}_");
+        // Temporaries are always suffixed, so they don't need to be raw identifiers
+        let ref_temp_name = format!("{}_", var_name.trim_start_matches("r#"));
         self.init_value(
             &mut real_var_decl_f,
             state,
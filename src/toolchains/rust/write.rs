@@ -98,8 +98,8 @@ impl RustcToolchain {
                 let tag_generator = vals.next_val();
                 let tag_idx = tag_generator.generate_idx(enum_ty.variants.len());
                 if let Some(variant) = enum_ty.variants.get(tag_idx) {
-                    let enum_name = &enum_ty.name;
-                    let variant_name = &variant.name;
+                    let enum_name = state.decls.ident(&enum_ty.name);
+                    let variant_name = state.decls.ident(&variant.name);
                     if tag_generator.should_write_val(&state.options) {
                         writeln!(f, "if let {enum_name}::{variant_name} = &{from} {{")?;
                         f.add_indent(1);
@@ -135,8 +135,7 @@ impl RustcToolchain {
             Ty::Struct(struct_ty) => {
                 // recurse into each field
                 for field in &struct_ty.fields {
-                    let field_name = &field.ident;
-                    let base = format!("{from}.{field_name}");
+                    let base = state.decls.field_value(from, field);
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
                 }
             }
@@ -145,14 +144,14 @@ impl RustcToolchain {
                 let tag_generator = vals.next_val();
                 let tag_idx = tag_generator.generate_idx(tagged_ty.variants.len());
                 if let Some(variant) = tagged_ty.variants.get(tag_idx) {
-                    let tagged_name = &tagged_ty.name;
-                    let variant_name = &variant.name;
+                    let tagged_name = state.decls.ident(&tagged_ty.name);
+                    let variant_name = state.decls.ident(&variant.name);
                     let pat = match &variant.fields {
                         Some(fields) => {
                             // Variant with fields, recurse into them
                             let field_list = fields
                                 .iter()
-                                .map(|f| f.ident.to_string())
+                                .map(|f| state.decls.ident(&f.ident))
                                 .collect::<Vec<_>>()
                                 .join(", ");
                             format!("{tagged_name}::{variant_name} {{ {field_list} }}")
@@ -177,7 +176,7 @@ impl RustcToolchain {
                         if let Some(fields) = &variant.fields {
                             for field in fields {
                                 // Do the ugly deref thing to deal with pattern autoref
-                                let base = format!("(*{})", field.ident);
+                                let base = format!("(*{})", state.decls.ident(&field.ident));
                                 self.write_fields(f, state, to, &base, field.ty, vals)?;
                             }
                         }
@@ -223,8 +222,7 @@ impl RustcToolchain {
                     self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
                 }
                 if let Some(field) = union_ty.fields.get(tag_idx) {
-                    let field_name = &field.ident;
-                    let base = format!("{from}.{field_name}");
+                    let base = state.decls.field_value(from, field);
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
                 }
             }